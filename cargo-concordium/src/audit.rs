@@ -0,0 +1,337 @@
+//! Static checks for common vulnerabilities in smart contract modules.
+//!
+//! The checks are heuristics based on the host functions imported by the
+//! module, the names of the exported entrypoints and the schema. They are
+//! meant to point at code that deserves a closer look, and both false
+//! positives and false negatives are to be expected.
+//!
+//! Checks of what an entrypoint does are based on the host functions it can
+//! reach through the calls between the functions of the module. A reachable
+//! host function is not necessarily called, e.g., the sender might only be
+//! inspected in a branch that is never taken, and an indirect call is assumed
+//! to reach every function in the table.
+use crate::{
+    build::{get_module_imports, parse_and_validate_module, ModuleExports},
+    call_graph::CallGraph,
+    versions,
+};
+use ansi_term::{Color, Style};
+use concordium_contracts_common::schema::{SizeLength, Type, VersionedModuleSchema};
+use concordium_smart_contract_engine::utils::WasmVersion;
+use concordium_wasm::types::{ExportDescription, Module};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
+
+/// Names of entrypoints that by convention are only meant to be invoked by
+/// privileged addresses. Names are compared case-insensitively.
+const PRIVILEGED_ENTRYPOINTS: &[&str] = &[
+    "upgrade",
+    "setadmin",
+    "updateadmin",
+    "transferownership",
+    "setowner",
+    "pause",
+    "unpause",
+    "setpaused",
+    "withdraw",
+    "mint",
+    "setimplementors",
+    "setmetadataurl",
+    "updaterevocationkeys",
+];
+
+/// Host functions that allow a contract to inspect who is calling it.
+const SENDER_CHECK_IMPORTS: &[&str] = &[
+    "get_receive_sender",
+    "get_receive_invoker",
+    "get_receive_owner",
+];
+
+/// The severity of a finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+}
+
+impl FromStr for Severity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            _ => anyhow::bail!(
+                "Unsupported severity '{}'. Must be one of 'info', 'low', 'medium' or 'high'.",
+                s
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Low => write!(f, "low"),
+            Severity::Medium => write!(f, "medium"),
+            Severity::High => write!(f, "high"),
+        }
+    }
+}
+
+/// A potential problem found in a module.
+#[derive(Debug)]
+pub struct Finding {
+    pub severity:   Severity,
+    /// The entrypoint the finding concerns, formatted as
+    /// `<contract>.<entrypoint>`, if any.
+    pub entrypoint: Option<String>,
+    pub message:    String,
+}
+
+/// Run all the checks on the given module and return the findings, sorted
+/// with the most severe first.
+pub fn audit_module(
+    module: &[u8],
+    version: WasmVersion,
+    exports: &ModuleExports,
+    schema: Option<&VersionedModuleSchema>,
+) -> anyhow::Result<Vec<Finding>> {
+    let parsed = parse_and_validate_module(module, version)?;
+    let imports = get_module_imports(&parsed);
+    let reachable = reachable_imports(&parsed);
+    let mut findings = Vec::new();
+    check_transfers(version, &imports, exports, &mut findings);
+    check_sender_checks(&reachable, exports, &mut findings);
+    check_upgrade(version, &imports, &reachable, &mut findings);
+    if let Some(schema) = schema {
+        check_parameter_types(schema, &mut findings);
+    } else {
+        findings.push(Finding {
+            severity:   Severity::Info,
+            entrypoint: None,
+            message:    "The module has no schema. Checks of parameter types were skipped.".into(),
+        });
+    }
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity));
+    Ok(findings)
+}
+
+/// A contract that can never transfer CCD locks any CCD it receives forever.
+fn check_transfers(
+    version: WasmVersion,
+    imports: &BTreeSet<String>,
+    exports: &ModuleExports,
+    findings: &mut Vec<Finding>,
+) {
+    let can_transfer = versions::descriptor(version)
+        .transfer_imports
+        .iter()
+        .any(|import| imports.contains(*import));
+    if !can_transfer && !exports.entrypoints.is_empty() {
+        findings.push(Finding {
+            severity:   Severity::Medium,
+            entrypoint: None,
+            message:    "The module cannot transfer CCD. Any CCD accepted by a payable entrypoint \
+                         is locked in the contract forever."
+                .into(),
+        });
+    }
+}
+
+/// The host functions that each exported receive function can reach, by the
+/// name of the export, i.e., `<contract>.<entrypoint>`.
+fn reachable_imports(module: &Module) -> BTreeMap<String, BTreeSet<String>> {
+    let call_graph = CallGraph::new(module);
+    module
+        .export
+        .exports
+        .iter()
+        .filter_map(|export| match export.description {
+            ExportDescription::Func { index } if export.name.as_ref().contains('.') => Some((
+                export.name.as_ref().to_string(),
+                call_graph.reachable_imports(module, index),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Privileged entrypoints that cannot reach any of the host functions for
+/// inspecting the sender are most likely callable by anyone.
+fn check_sender_checks(
+    reachable: &BTreeMap<String, BTreeSet<String>>,
+    exports: &ModuleExports,
+    findings: &mut Vec<Finding>,
+) {
+    for (contract, entrypoints) in exports.entrypoints.iter() {
+        for entrypoint in entrypoints.iter() {
+            let name = format!("{}.{}", contract, entrypoint);
+            let checks_sender = reachable.get(&name).map_or(false, has_sender_checks);
+            if PRIVILEGED_ENTRYPOINTS.contains(&entrypoint.to_lowercase().as_str())
+                && !checks_sender
+            {
+                findings.push(Finding {
+                    severity:   Severity::High,
+                    entrypoint: Some(name),
+                    message:    "The entrypoint looks privileged, but none of the functions it \
+                                 can reach checks the sender, invoker or owner of a call."
+                        .into(),
+                });
+            }
+        }
+    }
+}
+
+/// Whether the host functions include any of those for inspecting the sender
+/// of a call. The names are the same for V0 and V1 modules.
+fn has_sender_checks(imports: &BTreeSet<String>) -> bool {
+    SENDER_CHECK_IMPORTS
+        .iter()
+        .any(|name| imports.contains(*name))
+}
+
+/// Upgradable contracts must restrict who can upgrade them, so each
+/// entrypoint that can upgrade the contract must be able to check the sender.
+fn check_upgrade(
+    version: WasmVersion,
+    imports: &BTreeSet<String>,
+    reachable: &BTreeMap<String, BTreeSet<String>>,
+    findings: &mut Vec<Finding>,
+) {
+    if !versions::descriptor(version).supports_upgrade || !imports.contains("upgrade") {
+        return;
+    }
+    findings.push(Finding {
+        severity:   Severity::Info,
+        entrypoint: None,
+        message:    "The module is upgradable. Make sure upgrades are restricted to the owner of \
+                     the contract."
+            .into(),
+    });
+    for (name, host_functions) in reachable.iter() {
+        if host_functions.contains("upgrade") && !has_sender_checks(host_functions) {
+            findings.push(Finding {
+                severity:   Severity::High,
+                entrypoint: Some(name.clone()),
+                message:    "The entrypoint can upgrade the contract, but none of the functions \
+                             it can reach checks the sender, invoker or owner of a call. Anyone \
+                             might be able to upgrade the contract."
+                    .into(),
+            });
+        }
+    }
+}
+
+/// Check the parameter types of all entrypoints for collections that are only
+/// bounded by the maximum parameter size.
+fn check_parameter_types(schema: &VersionedModuleSchema, findings: &mut Vec<Finding>) {
+    let mut check = |contract: &str, entrypoint: &str, ty: Option<&Type>| {
+        if let Some(ty) = ty {
+            if has_unbounded_collection(ty) {
+                findings.push(Finding {
+                    severity:   Severity::Low,
+                    entrypoint: Some(format!("{}.{}", contract, entrypoint)),
+                    message:    "The parameter contains a collection whose length is only bounded \
+                                 by the maximum parameter size. Make sure that iterating over it \
+                                 or storing it cannot exhaust the energy or grow the state \
+                                 unboundedly."
+                        .into(),
+                });
+            }
+        }
+    };
+    match schema {
+        VersionedModuleSchema::V0(module_schema) => {
+            for (contract, contract_schema) in module_schema.contracts.iter() {
+                for (entrypoint, ty) in contract_schema.receive.iter() {
+                    check(contract, entrypoint, Some(ty));
+                }
+            }
+        }
+        VersionedModuleSchema::V1(module_schema) => {
+            for (contract, contract_schema) in module_schema.contracts.iter() {
+                for (entrypoint, func) in contract_schema.receive.iter() {
+                    check(contract, entrypoint, func.parameter());
+                }
+            }
+        }
+        VersionedModuleSchema::V2(module_schema) => {
+            for (contract, contract_schema) in module_schema.contracts.iter() {
+                for (entrypoint, func) in contract_schema.receive.iter() {
+                    check(contract, entrypoint, func.parameter());
+                }
+            }
+        }
+        VersionedModuleSchema::V3(module_schema) => {
+            for (contract, contract_schema) in module_schema.contracts.iter() {
+                for (entrypoint, func) in contract_schema.receive.iter() {
+                    check(contract, entrypoint, func.parameter());
+                }
+            }
+        }
+    }
+}
+
+/// Whether the type contains a list, set, map or string with a length that
+/// is encoded using more than two bytes.
+fn has_unbounded_collection(ty: &Type) -> bool {
+    let is_large = |size_len: &SizeLength| matches!(size_len, SizeLength::U32 | SizeLength::U64);
+    match ty {
+        Type::List(size_len, elem) | Type::Set(size_len, elem) => {
+            is_large(size_len) || has_unbounded_collection(elem)
+        }
+        Type::Map(size_len, key, value) => {
+            is_large(size_len) || has_unbounded_collection(key) || has_unbounded_collection(value)
+        }
+        Type::String(size_len) | Type::ByteList(size_len) => is_large(size_len),
+        Type::Array(_, elem) => has_unbounded_collection(elem),
+        Type::Pair(left, right) => {
+            has_unbounded_collection(left) || has_unbounded_collection(right)
+        }
+        Type::Struct(fields) => fields_have_unbounded_collection(fields),
+        Type::Enum(variants) => variants
+            .iter()
+            .any(|(_, fields)| fields_have_unbounded_collection(fields)),
+        Type::TaggedEnum(variants) => variants
+            .values()
+            .any(|(_, fields)| fields_have_unbounded_collection(fields)),
+        _ => false,
+    }
+}
+
+fn fields_have_unbounded_collection(fields: &concordium_contracts_common::schema::Fields) -> bool {
+    use concordium_contracts_common::schema::Fields;
+    match fields {
+        Fields::Named(fields) => fields.iter().any(|(_, ty)| has_unbounded_collection(ty)),
+        Fields::Unnamed(fields) => fields.iter().any(has_unbounded_collection),
+        Fields::None => false,
+    }
+}
+
+/// Print the findings.
+pub fn print_findings(findings: &[Finding]) {
+    if findings.is_empty() {
+        eprintln!("{}", Color::Green.bold().paint("No findings."));
+        return;
+    }
+    for finding in findings {
+        let label = match finding.severity {
+            Severity::Info => Style::new().bold().paint("info"),
+            Severity::Low => Color::Blue.bold().paint("low"),
+            Severity::Medium => Color::Yellow.bold().paint("medium"),
+            Severity::High => Color::Red.bold().paint("high"),
+        };
+        match &finding.entrypoint {
+            Some(entrypoint) => eprintln!("[{}] {}: {}", label, entrypoint, finding.message),
+            None => eprintln!("[{}] {}", label, finding.message),
+        }
+    }
+}
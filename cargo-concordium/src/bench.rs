@@ -0,0 +1,369 @@
+//! Benchmarks of contracts using scenarios.
+//!
+//! `bench compare` runs the same scenario against two versions of a module
+//! and reports the differences in energy, state growth and module size, e.g.,
+//! to show the effect of an optimization.
+//!
+//! `run --bench <n>` executes a single invocation repeatedly against the same
+//! initial state and reports the spread of the wall time and energy, e.g., to
+//! compare optimization attempts on a hot entrypoint.
+use crate::{
+    context::{InitContextOpt, ReceiveContextV1Opt},
+    report::{Cell, HtmlReport},
+    scenario::Scenario,
+    schema_types::ContractSchemaView,
+    sim::{CallResult, Instance, Simulator},
+};
+use ansi_term::Style;
+use anyhow::ensure;
+use concordium_contracts_common::{Amount, ContractAddress};
+use concordium_smart_contract_engine::{v1::trie::PersistentState, InterpreterEnergy};
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// A version of a module to compare.
+pub struct BenchModule<'a> {
+    pub sim:    &'a Simulator,
+    pub schema: &'a ContractSchemaView<'a>,
+    /// The size of the versioned module in bytes.
+    pub size:   usize,
+}
+
+/// The accumulated measurements of the calls with the same name.
+#[derive(Default)]
+struct Measurement {
+    calls:            u64,
+    energy:           u64,
+    additional_state: u64,
+    failures:         u64,
+}
+
+impl Measurement {
+    fn add(&mut self, result: &CallResult) {
+        self.calls += 1;
+        self.energy += result.energy_used;
+        self.additional_state += result.additional_state;
+        if !result.outcome.is_success() {
+            self.failures += 1;
+        }
+    }
+
+    fn average_energy(&self) -> f64 { self.energy as f64 / self.calls as f64 }
+
+    fn average_state(&self) -> f64 { self.additional_state as f64 / self.calls as f64 }
+}
+
+/// Run the scenario and measure the initialization and the calls, grouped by
+/// name in the order they first appear.
+fn measure(
+    scenario: &Scenario,
+    module: &BenchModule,
+) -> anyhow::Result<Vec<(String, Measurement)>> {
+    let mut measurements: Vec<(String, Measurement)> = Vec::new();
+    let (result, mut instance) = scenario.init(module.sim, module.schema)?;
+    let mut init = Measurement::default();
+    init.add(&result);
+    measurements.push(("init".to_string(), init));
+    for call in scenario.setup.iter().chain(scenario.calls.iter()) {
+        let result = scenario.call(module.sim, module.schema, &mut instance, call)?;
+        match measurements
+            .iter_mut()
+            .find(|(name, _)| name == call.name())
+        {
+            Some((_, measurement)) => measurement.add(&result),
+            None => {
+                let mut measurement = Measurement::default();
+                measurement.add(&result);
+                measurements.push((call.name().to_string(), measurement));
+            }
+        }
+    }
+    Ok(measurements)
+}
+
+/// Format the relative change from old to new.
+fn delta(old: f64, new: f64) -> String {
+    if old == 0.0 {
+        if new == 0.0 {
+            "0.0%".to_string()
+        } else {
+            "new".to_string()
+        }
+    } else {
+        format!("{:+.1}%", 100.0 * (new - old) / old)
+    }
+}
+
+/// Run the scenario against both modules and print the differences. If
+/// `report_html` is set, an HTML report of the differences is also written to
+/// that directory.
+pub fn compare(
+    scenario: &Scenario,
+    old: &BenchModule,
+    new: &BenchModule,
+    report_html: Option<&Path>,
+) -> anyhow::Result<()> {
+    let old_measurements = measure(scenario, old)?;
+    let new_measurements = measure(scenario, new)?;
+
+    eprintln!(
+        "{}",
+        Style::new()
+            .bold()
+            .paint("Average interpreter energy and additional state per call:")
+    );
+    eprintln!(
+        "  {:<24} {:>6} {:>12} {:>12} {:>9}   {:>10} {:>10} {:>9}",
+        "call", "calls", "old energy", "new energy", "change", "old state", "new state", "change"
+    );
+    for ((name, old_m), (_, new_m)) in old_measurements.iter().zip(new_measurements.iter()) {
+        eprintln!(
+            "  {:<24} {:>6} {:>12.0} {:>12.0} {:>9}   {:>9.0}B {:>9.0}B {:>9}",
+            name,
+            old_m.calls,
+            old_m.average_energy(),
+            new_m.average_energy(),
+            delta(old_m.average_energy(), new_m.average_energy()),
+            old_m.average_state(),
+            new_m.average_state(),
+            delta(old_m.average_state(), new_m.average_state()),
+        );
+        if old_m.failures != new_m.failures {
+            eprintln!(
+                "{}",
+                crate::WARNING_STYLE.paint(format!(
+                    "    {} of the calls failed with the old module and {} with the new one, so \
+                     the measurements are not comparable.",
+                    old_m.failures, new_m.failures
+                ))
+            );
+        }
+    }
+
+    let total = |ms: &[(String, Measurement)]| ms.iter().map(|(_, m)| m.energy).sum::<u64>();
+    let (old_total, new_total) = (total(&old_measurements), total(&new_measurements));
+    eprintln!(
+        "\n  Total energy: {} -> {} ({})",
+        old_total,
+        new_total,
+        delta(old_total as f64, new_total as f64)
+    );
+    eprintln!(
+        "  Module size:  {}B -> {}B ({})",
+        old.size,
+        new.size,
+        delta(old.size as f64, new.size as f64)
+    );
+
+    if let Some(dir) = report_html {
+        let mut report = HtmlReport::new(&format!("Benchmark of {}", scenario.contract));
+        report.paragraph(&format!(
+            "Total energy: {} -> {} ({}). Module size: {}B -> {}B ({}).",
+            old_total,
+            new_total,
+            delta(old_total as f64, new_total as f64),
+            old.size,
+            new.size,
+            delta(old.size as f64, new.size as f64)
+        ));
+        report.heading("Average interpreter energy and additional state per call");
+        let rows = old_measurements
+            .iter()
+            .zip(new_measurements.iter())
+            .map(|((name, old_m), (_, new_m))| {
+                vec![
+                    Cell::Text(name.clone()),
+                    Cell::Number(old_m.calls.to_string()),
+                    Cell::Number(format!("{:.0}", old_m.average_energy())),
+                    Cell::Number(format!("{:.0}", new_m.average_energy())),
+                    Cell::Number(delta(old_m.average_energy(), new_m.average_energy())),
+                    Cell::Number(format!("{:.0}B", old_m.average_state())),
+                    Cell::Number(format!("{:.0}B", new_m.average_state())),
+                    Cell::Number(delta(old_m.average_state(), new_m.average_state())),
+                    Cell::Status(old_m.failures == new_m.failures),
+                ]
+            })
+            .collect::<Vec<_>>();
+        report.table(
+            &[
+                "call",
+                "calls",
+                "old energy",
+                "new energy",
+                "change",
+                "old state",
+                "new state",
+                "change",
+                "same failures",
+            ],
+            &rows,
+        );
+        report.heading("Average interpreter energy");
+        let bars = old_measurements
+            .iter()
+            .zip(new_measurements.iter())
+            .flat_map(|((name, old_m), (_, new_m))| {
+                vec![
+                    (format!("{} (old)", name), old_m.average_energy()),
+                    (format!("{} (new)", name), new_m.average_energy()),
+                ]
+            })
+            .collect::<Vec<_>>();
+        report.bar_chart(&bars);
+        report.write(dir)?;
+    }
+    Ok(())
+}
+
+/// The measurements of repeated executions of an invocation.
+#[derive(Default)]
+struct Runs {
+    instantiation: Vec<Duration>,
+    execution:     Vec<Duration>,
+    energy:        Vec<u64>,
+    failures:      usize,
+}
+
+/// The minimum, median and maximum of the values.
+fn spread<T: Ord + Copy>(values: &mut [T]) -> (T, T, T) {
+    values.sort_unstable();
+    (
+        values[0],
+        values[values.len() / 2],
+        values[values.len() - 1],
+    )
+}
+
+fn millis(duration: Duration) -> String { format!("{:.3} ms", duration.as_secs_f64() * 1000.0) }
+
+impl Runs {
+    /// Instantiate the module once per run, returning the last simulator.
+    fn instantiate(
+        &mut self,
+        module: &[u8],
+        energy: InterpreterEnergy,
+        runs: usize,
+    ) -> anyhow::Result<Simulator> {
+        ensure!(runs > 0, "The number of benchmark runs must be positive.");
+        let mut sim = None;
+        for _ in 0..runs {
+            let start = Instant::now();
+            sim = Some(Simulator::new(module, energy)?);
+            self.instantiation.push(start.elapsed());
+        }
+        Ok(sim.expect("There is at least one run."))
+    }
+
+    fn add(&mut self, result: &CallResult, duration: Duration) {
+        self.execution.push(duration);
+        self.energy.push(result.energy_used);
+        if !result.outcome.is_success() {
+            self.failures += 1;
+        }
+    }
+
+    fn print(mut self, execution: &str) {
+        eprintln!(
+            "{}",
+            Style::new()
+                .bold()
+                .paint(format!("Benchmark of {} runs:", self.execution.len()))
+        );
+        eprintln!("  {:<32} {:>12} {:>12} {:>12}", "", "min", "median", "max");
+        let (min, median, max) = spread(&mut self.instantiation);
+        eprintln!(
+            "  {:<32} {:>12} {:>12} {:>12}",
+            "instantiation",
+            millis(min),
+            millis(median),
+            millis(max)
+        );
+        let (min, median, max) = spread(&mut self.execution);
+        eprintln!(
+            "  {:<32} {:>12} {:>12} {:>12}",
+            execution,
+            millis(min),
+            millis(median),
+            millis(max)
+        );
+        let (min, median, max) = spread(&mut self.energy);
+        eprintln!(
+            "  {:<32} {:>12} {:>12} {:>12}",
+            "interpreter energy", min, median, max
+        );
+        if self.failures > 0 {
+            eprintln!(
+                "{}",
+                crate::WARNING_STYLE
+                    .paint(format!("  {} of the runs did not succeed.", self.failures))
+            );
+        }
+    }
+}
+
+/// Execute an initialization the given number of times and print the spread
+/// of the measurements. The engine instantiates the module as part of each
+/// initialization, so the execution time includes the instantiation.
+pub fn invocation_init(
+    module: &[u8],
+    energy: InterpreterEnergy,
+    runs: usize,
+    contract_name: &str,
+    ctx: &InitContextOpt,
+    amount: Amount,
+    parameter: &[u8],
+) -> anyhow::Result<()> {
+    let mut measurements = Runs::default();
+    let sim = measurements.instantiate(module, energy, runs)?;
+    for _ in 0..runs {
+        let start = Instant::now();
+        let (result, _) = sim.init(
+            contract_name,
+            ContractAddress::new(0, 0),
+            ctx.clone(),
+            amount,
+            parameter,
+        )?;
+        measurements.add(&result, start.elapsed());
+    }
+    measurements.print("execution (with instantiation)");
+    Ok(())
+}
+
+/// Execute an update of an instance with the given state the given number of
+/// times, each time starting from the same state, and print the spread of
+/// the measurements.
+#[allow(clippy::too_many_arguments)]
+pub fn invocation_update(
+    module: &[u8],
+    energy: InterpreterEnergy,
+    runs: usize,
+    contract_name: &str,
+    entrypoint: &str,
+    state: &PersistentState,
+    ctx: &ReceiveContextV1Opt,
+    amount: Amount,
+    parameter: &[u8],
+) -> anyhow::Result<()> {
+    let mut measurements = Runs::default();
+    let sim = measurements.instantiate(module, energy, runs)?;
+    let instance = Instance {
+        contract_name: contract_name.to_string(),
+        address:       ctx
+            .common
+            .self_address
+            .unwrap_or_else(|| ContractAddress::new(0, 0)),
+        state:         state.clone(),
+        balance:       ctx.common.self_balance.unwrap_or_else(Amount::zero),
+    };
+    for _ in 0..runs {
+        let mut instance = instance.clone();
+        let start = Instant::now();
+        let result = sim.update(&mut instance, entrypoint, ctx.clone(), amount, parameter)?;
+        measurements.add(&result, start.elapsed());
+    }
+    measurements.print("execution");
+    Ok(())
+}
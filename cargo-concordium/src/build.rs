@@ -1,7 +1,25 @@
+use crate::{
+    build_info::{
+        build_info, contract_metadata, BUILD_INFO_SECTION_NAME, CONTRACT_METADATA_SECTION_NAME,
+    },
+    cache::BuildCache,
+    protocol::ProtocolVersion,
+    report::{Cell, HtmlReport},
+    sbom::{generate_sbom, SbomFormat, SBOM_SECTION_NAME},
+    schema_types::schema_gaps,
+    size_report::SizeReport,
+    strict::suspicious_constructs,
+    test_coverage,
+    test_energy::TestEnergy,
+    test_report::{TestOutcome, TestReport, TestRun},
+    test_runner::{TestLimits, TestRunner},
+    verify::{self, SOURCE_SECTION_NAME},
+    versions::{self, VersionDescriptor},
+};
 use ansi_term::{Color, Style};
 use anyhow::Context;
 use base64::{engine::general_purpose, Engine as _};
-use cargo_metadata::MetadataCommand;
+use cargo_metadata::{Metadata, MetadataCommand, Package};
 use concordium_contracts_common::{
     schema::{
         ContractV0, ContractV1, ContractV2, ContractV3, FunctionV1, FunctionV2,
@@ -9,33 +27,161 @@ use concordium_contracts_common::{
     },
     *,
 };
-use concordium_smart_contract_engine::{
-    utils::{self, WasmVersion},
-    v0, v1, ExecResult,
-};
+use concordium_smart_contract_engine::utils::{self, WasmVersion};
 use concordium_wasm::{
     output::{write_custom_section, Output},
-    parse::parse_skeleton,
-    types::{CustomSection, ExportDescription, Module},
-    utils::strip,
-    validate::validate_module,
+    parse::{parse_custom, parse_skeleton, Skeleton},
+    types::{CustomSection, ExportDescription, FunctionType, Module, Name},
+    validate::{validate_module, ValidateImportExport},
 };
-use rand::{thread_rng, Rng};
+use rand::{rngs::SmallRng, seq::SliceRandom, thread_rng, Rng, SeedableRng};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::{
     cmp::Ordering,
     collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
     env, fs,
     path::{Path, PathBuf},
     process::{Command, Stdio},
+    time::{Duration, Instant},
 };
+use structopt::StructOpt;
 
 /// Encode all base64 strings using the standard alphabet and no padding.
 /// Padding is not useful since strings are just put as JSON strings.
-const ENCODER: base64::engine::GeneralPurpose = general_purpose::STANDARD_NO_PAD;
+pub const ENCODER: base64::engine::GeneralPurpose = general_purpose::STANDARD_NO_PAD;
 
 fn to_snake_case(string: &str) -> String { string.to_lowercase().replace('-', "_") }
 
+/// Selection of the package to compile, e.g., a member of a workspace.
+#[derive(Debug, Default, StructOpt)]
+pub struct PackageOptions {
+    #[structopt(
+        name = "package",
+        long = "package",
+        short = "p",
+        help = "The package to compile, which is required in workspaces with multiple members. \
+                Defaults to the package in the current directory."
+    )]
+    pub package:       Option<String>,
+    #[structopt(
+        name = "manifest-path",
+        long = "manifest-path",
+        help = "Path to the Cargo.toml of the package to compile. Cargo is run in the directory \
+                of the manifest, so that its `.cargo/config` applies. Defaults to the package in \
+                the current directory."
+    )]
+    pub manifest_path: Option<PathBuf>,
+}
+
+impl PackageOptions {
+    /// The directory of the manifest, if a manifest path is given.
+    pub fn directory(&self) -> Option<&Path> {
+        self.manifest_path
+            .as_deref()
+            .and_then(Path::parent)
+            .filter(|dir| !dir.as_os_str().is_empty())
+    }
+
+    /// Create a command that runs cargo in the directory of the manifest.
+    pub fn cargo_command(&self) -> Command {
+        let mut command = Command::new("cargo");
+        if let Some(dir) = self.directory() {
+            command.current_dir(dir);
+        }
+        command
+    }
+
+    /// Get the cargo metadata, without the dependencies if `no_deps` is set.
+    pub fn metadata(&self, no_deps: bool) -> anyhow::Result<Metadata> {
+        let mut command = MetadataCommand::new();
+        if let Some(manifest_path) = &self.manifest_path {
+            command.manifest_path(manifest_path);
+        }
+        if no_deps {
+            command.no_deps();
+        }
+        command.exec().context("Could not access cargo metadata.")
+    }
+
+    /// Get the selected package, which must be a member of the workspace.
+    pub fn package<'a>(&self, metadata: &'a Metadata) -> anyhow::Result<&'a Package> {
+        let name = match &self.package {
+            Some(name) => name,
+            None => {
+                return metadata.root_package().context(
+                    "Unable to determine package. Use `--package` to select a member of the \
+                     workspace.",
+                )
+            }
+        };
+        let members = metadata
+            .packages
+            .iter()
+            .filter(|p| metadata.workspace_members.contains(&p.id))
+            .collect::<Vec<_>>();
+        match members.iter().find(|p| p.name == *name) {
+            Some(package) => Ok(package),
+            None => anyhow::bail!(
+                "The workspace has no member '{}'. The members are: {}.",
+                name,
+                members
+                    .iter()
+                    .map(|p| p.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    /// The arguments of a cargo command created by `cargo_command` that select
+    /// the package.
+    pub fn cargo_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(file_name) = self.manifest_path.as_deref().and_then(Path::file_name) {
+            args.push("--manifest-path".to_string());
+            args.push(file_name.to_string_lossy().into_owned());
+        }
+        if let Some(name) = &self.package {
+            args.push("--package".to_string());
+            args.push(name.clone());
+        }
+        args
+    }
+}
+
+/// The cargo arguments that build the standard library together with the
+/// contract, which requires a nightly toolchain.
+pub const BUILD_STD_ARGS: [&str; 2] = ["-Z", "build-std=std,panic_abort"];
+
+/// The cargo arguments that pass the given flags, separated by whitespace, to
+/// rustc. They are added to the flags configured for the package, e.g., in
+/// `.cargo/config`, and do not apply to build scripts.
+pub fn rustflags_args(rustflags: &str) -> Vec<String> {
+    rustflag_list_args(&rustflags.split_whitespace().collect::<Vec<_>>())
+}
+
+/// Like [`rustflags_args`], but for a list of flags, which may contain
+/// whitespace.
+pub fn rustflag_list_args(flags: &[impl AsRef<str>]) -> Vec<String> {
+    let flags = flags.iter().map(AsRef::as_ref).collect::<Vec<&str>>();
+    // A JSON array of strings is also a TOML array.
+    let flags = serde_json::to_string(&flags).unwrap_or_default();
+    vec!["--config".to_string(), format!("build.rustflags={}", flags)]
+}
+
+/// A module written by [`build_contract`].
+pub struct BuiltModule {
+    /// The file the versioned module was written to.
+    pub path:       PathBuf,
+    /// The size of the versioned module.
+    pub size:       usize,
+    pub module_ref: ModuleReference,
+    /// The schema, if it was built.
+    pub schema:     Option<schema::VersionedModuleSchema>,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum SchemaBuildOptions {
     DoNotBuild,
@@ -56,172 +202,461 @@ impl SchemaBuildOptions {
     pub fn embed(self) -> bool { matches!(self, SchemaBuildOptions::BuildAndEmbed) }
 }
 
+/// The settings of [`build_contract`], which are the same for every module
+/// built by one invocation of `cargo concordium build`.
+#[derive(Clone, Copy)]
+pub struct BuildOptions<'a> {
+    pub version:              WasmVersion,
+    pub build_schema:         SchemaBuildOptions,
+    /// The format of the software bill of materials to embed, if any.
+    pub sbom:                 Option<SbomFormat>,
+    /// The URL of the git repository to embed a link to the source for.
+    pub source_url:           Option<&'a str>,
+    pub optimize:             bool,
+    /// Whether to keep the names of the functions for debugging.
+    pub debug_names:          bool,
+    pub keep_custom_sections: &'a [String],
+    pub size_report:          bool,
+    pub max_module_size:      Option<usize>,
+    pub strict:               bool,
+    pub use_cache:            bool,
+    /// The extra arguments given to cargo.
+    pub cargo_args:           &'a [String],
+}
+
 /// Build a contract and its schema.
 /// If build_schema is set then the return value will contain the schema of the
-/// version specified.
+/// version specified. If sbom is set then a software bill of materials in the
+/// given format is embedded into the module, and if source_url is set then the
+/// link to the source is embedded.
 pub fn build_contract(
-    version: WasmVersion,
-    build_schema: SchemaBuildOptions,
+    options: &BuildOptions,
     out: Option<PathBuf>,
-    cargo_args: &[String],
-) -> anyhow::Result<(usize, Option<schema::VersionedModuleSchema>)> {
-    #[allow(unused_assignments)]
-    // This assignment is not actually unused. It is used via the custom_section which retains a
-    // reference to this vector, which is why it has to be here. This is a bit ugly, but not as
-    // ugly as alternatives.
-    let mut schema_bytes = Vec::new();
-    // if none do not build. If Some(true) then embed, otherwise
-    // just build and return
-    let schema = match version {
-        WasmVersion::V0 => {
-            if build_schema.build() {
-                let schema = build_contract_schema(cargo_args, utils::generate_contract_schema_v0)
-                    .context("Could not build module schema.")?;
-                if build_schema.embed() {
-                    schema_bytes = to_bytes(&schema);
-                    let custom_section = CustomSection {
-                        name:     "concordium-schema".into(),
-                        contents: &schema_bytes,
-                    };
-                    Some((Some(custom_section), schema))
-                } else {
-                    Some((None, schema))
-                }
-            } else {
-                None
-            }
-        }
-        WasmVersion::V1 => {
-            if build_schema.build() {
-                let schema = build_contract_schema(cargo_args, utils::generate_contract_schema_v3)
-                    .context("Could not build module schema.")?;
-                if build_schema.embed() {
-                    schema_bytes = to_bytes(&schema);
-                    let custom_section = CustomSection {
-                        name:     "concordium-schema".into(),
-                        contents: &schema_bytes,
-                    };
-                    Some((Some(custom_section), schema))
-                } else {
-                    Some((None, schema))
-                }
-            } else {
-                None
-            }
-        }
+    out_raw: Option<PathBuf>,
+    package_options: &PackageOptions,
+) -> anyhow::Result<BuiltModule> {
+    let BuildOptions {
+        version,
+        build_schema,
+        sbom,
+        source_url,
+        optimize,
+        debug_names,
+        keep_custom_sections,
+        size_report,
+        max_module_size,
+        strict,
+        use_cache,
+        cargo_args,
+    } = *options;
+    let descriptor = versions::descriptor(version);
+    if strict && !build_schema.embed() {
+        anyhow::bail!(
+            "Strict builds require the schema to be embedded using `--schema-embed`, so that the \
+             module can be used by wallets and dApps."
+        );
+    }
+    let source = source_url
+        .map(|url| {
+            verify::source_link(
+                url.to_string(),
+                version,
+                build_schema.embed(),
+                sbom,
+                optimize,
+                package_options,
+                cargo_args,
+            )
+        })
+        .transpose()?;
+    let metadata = package_options.metadata(true)?;
+    let package = package_options.package(&metadata)?;
+    let filename = wasm_filename(&metadata, package, cargo_args)?;
+    let build_info = build_info(package_options, package, cargo_args);
+
+    let cache = if use_cache {
+        let settings = serde_json::json!({
+            "version": descriptor.name,
+            "buildSchema": build_schema.build(),
+            "embedSchema": build_schema.embed(),
+            "sbom": sbom.map(|format| format.to_string()),
+            "source": source,
+            "optimize": optimize,
+            "debugNames": debug_names,
+            "keepCustomSections": keep_custom_sections,
+            "strict": strict,
+            "package": package.name,
+            "buildInfo": build_info,
+            "cargoArgs": cargo_args,
+        });
+        Some(BuildCache::new(
+            package_options,
+            &metadata,
+            package,
+            descriptor,
+            &settings,
+        )?)
+    } else {
+        None
     };
-
-    let metadata = MetadataCommand::new()
-        .no_deps()
-        .exec()
-        .context("Could not access cargo metadata.")?;
-
-    let package = metadata
-        .root_package()
-        .context("Unable to determine package.")?;
-
-    let target_dir = format!("{}/concordium", metadata.target_directory);
-
-    let result = Command::new("cargo")
-        .arg("build")
-        .args(&["--target", "wasm32-unknown-unknown"])
-        .args(&["--release"])
-        .args(&["--target-dir", target_dir.as_str()])
-        .args(cargo_args)
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
-        .output()
-        .context("Could not use cargo build.")?;
-
-    if !result.status.success() {
-        anyhow::bail!("Compilation failed.")
+    if let Some((output_bytes, module_schema)) = cache.as_ref().and_then(BuildCache::load) {
+        eprintln!("   Reusing the module built from unchanged sources.");
+        if size_report {
+            let module = parse_and_validate_module(&output_bytes[8..], version)?;
+            SizeReport::new(&output_bytes[8..], &module)?.print();
+        }
+        return write_module(
+            &output_bytes,
+            module_schema,
+            descriptor,
+            max_module_size,
+            out,
+            out_raw,
+            &filename,
+        );
     }
 
-    let filename = format!(
-        "{}/wasm32-unknown-unknown/release/{}.wasm",
-        target_dir,
-        to_snake_case(package.name.as_str())
-    );
+    // The schema is generated from the same build as the module. The schema
+    // functions are then no longer exported, and are removed by wasm-opt along
+    // with everything else only they use if the module is optimized.
+    let mut wasm = compile(package_options, cargo_args, build_schema.build())?;
+    let module_schema = if build_schema.build() {
+        let schema = (descriptor.generate_schema)(&wasm)
+            .context("Could not generate module schema from Wasm module.")?;
+        wasm = remove_exports(&wasm, is_schema_export)
+            .context("Could not remove the schema functions from the module.")?;
+        Some(schema)
+    } else {
+        None
+    };
 
-    let wasm = fs::read(&filename).context("Could not read cargo build Wasm output.")?;
+    // The optimized module, which must outlive the skeleton referring to it.
+    let optimized;
 
     let mut skeleton =
         parse_skeleton(&wasm).context("Could not parse the skeleton of the module.")?;
 
-    // Remove all custom sections to reduce the size of the module
-    strip(&mut skeleton);
-    match version {
-        WasmVersion::V0 => {
-            let module = validate_module(&v0::ConcordiumAllowedImports, &skeleton)
-                .context("Could not validate resulting smart contract module as a V0 contract.")?;
-            check_exports(&module, WasmVersion::V0)
-                .context("Contract and entrypoint validation failed for a V0 contract.")?;
-            module
-        }
-        WasmVersion::V1 => {
-            let module = validate_module(
-                &v1::ConcordiumAllowedImports {
-                    support_upgrade: true,
-                },
-                &skeleton,
+    // Remove the custom sections to reduce the size of the module, except the
+    // function names if they are requested for debugging and the sections the
+    // user asked to keep.
+    let mut keep_sections = keep_custom_sections
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if debug_names {
+        keep_sections.push("name");
+    }
+    if let Some(reserved) = keep_sections
+        .iter()
+        .find(|name| name.starts_with("concordium-"))
+    {
+        anyhow::bail!(
+            "The custom section '{}' cannot be kept, since sections starting with `concordium-` \
+             are written by cargo-concordium.",
+            reserved
+        );
+    }
+    strip_except(&mut skeleton, &keep_sections);
+    if optimize {
+        let mut stripped = Vec::new();
+        skeleton.output(&mut stripped)?;
+        optimized = optimize_module(&stripped, debug_names)?;
+        eprintln!(
+            "   Optimized the module from {} B to {} B.",
+            stripped.len(),
+            optimized.len()
+        );
+        skeleton = parse_skeleton(&optimized)
+            .context("Could not parse the skeleton of the optimized module.")?;
+        strip_except(&mut skeleton, &keep_sections);
+    }
+    let kept = skeleton
+        .custom
+        .iter()
+        .filter_map(|section| parse_custom(section).ok())
+        .map(|section| section.name.as_ref().to_string())
+        .collect::<BTreeSet<_>>();
+    for name in keep_custom_sections
+        .iter()
+        .filter(|name| !kept.contains(*name))
+    {
+        eprintln!(
+            "{}",
+            crate::WARNING_STYLE.paint(format!(
+                "   The module does not contain a custom section '{}'.",
+                name
+            ))
+        );
+    }
+    if debug_names {
+        eprintln!(
+            "{}",
+            crate::WARNING_STYLE.paint(
+                "   The module contains the names of its functions for debugging. Build it \
+                 without `--debug-names` for deployment."
             )
-            .context("Could not validate resulting smart contract module as a V1 contract.")?;
-            check_exports(&module, WasmVersion::V1)
-                .context("Contract and entrypoint validation failed for a V1 contract.")?;
-            module
+        );
+    }
+    let module = (descriptor.validate)(&skeleton).with_context(|| {
+        format!(
+            "Could not validate resulting smart contract module as a {} contract.",
+            descriptor.name
+        )
+    })?;
+    check_exports(&module, version).with_context(|| {
+        format!(
+            "Contract and entrypoint validation failed for a {} contract.",
+            descriptor.name
+        )
+    })?;
+
+    if let Some(schema) = &module_schema {
+        let gaps = schema_gaps(schema, &module_exports(&module));
+        if !gaps.is_empty() {
+            let gaps = gaps.join("\n     - ");
+            if strict {
+                anyhow::bail!("The schema is incomplete:\n     - {}", gaps);
+            }
+            eprintln!(
+                "{}",
+                crate::WARNING_STYLE
+                    .paint(format!("   The schema is incomplete:\n     - {}", gaps))
+            );
         }
-    };
+    }
 
     // We output a versioned module that can be directly deployed to the chain,
     // i.e., the exact data that needs to go into the transaction. This starts with
     // the version number in big endian. The remaining 4 bytes are a placeholder for
     // length.
-    let mut output_bytes = match version {
-        WasmVersion::V0 => vec![0, 0, 0, 0, 0, 0, 0, 0],
-        WasmVersion::V1 => vec![0, 0, 0, 1, 0, 0, 0, 0],
-    };
-    // Embed schema custom section
+    let mut output_bytes = descriptor.tag.to_be_bytes().to_vec();
+    output_bytes.extend_from_slice(&[0, 0, 0, 0]);
     skeleton.output(&mut output_bytes)?;
-    let return_schema = if let Some((custom_section, schema)) = schema {
-        if let Some(custom_section) = custom_section {
-            write_custom_section(&mut output_bytes, &custom_section)?;
+    // Embed schema custom section
+    if build_schema.embed() {
+        if let Some(schema) = &module_schema {
+            write_custom_section(&mut output_bytes, &CustomSection {
+                name:     "concordium-schema".into(),
+                contents: &to_bytes(schema),
+            })?;
         }
-        Some(schema)
-    } else {
-        None
-    };
+    }
+    // Embed the SBOM custom section
+    if let Some(format) = sbom {
+        let sbom_bytes =
+            generate_sbom(format, package_options).context("Could not generate the SBOM.")?;
+        write_custom_section(&mut output_bytes, &CustomSection {
+            name:     SBOM_SECTION_NAME.into(),
+            contents: &sbom_bytes,
+        })?;
+    }
+    // Embed the source link custom section
+    if let Some(source) = &source {
+        let source_bytes = serde_json::to_vec(source)?;
+        write_custom_section(&mut output_bytes, &CustomSection {
+            name:     SOURCE_SECTION_NAME.into(),
+            contents: &source_bytes,
+        })?;
+    }
+    // Embed the build information custom section
+    let build_info_bytes = serde_json::to_vec(&build_info)?;
+    write_custom_section(&mut output_bytes, &CustomSection {
+        name:     BUILD_INFO_SECTION_NAME.into(),
+        contents: &build_info_bytes,
+    })?;
+    // Embed the contract metadata custom section
+    if let Some(metadata) = contract_metadata(package)? {
+        let metadata_bytes = serde_json::to_vec(&metadata)?;
+        write_custom_section(&mut output_bytes, &CustomSection {
+            name:     CONTRACT_METADATA_SECTION_NAME.into(),
+            contents: &metadata_bytes,
+        })?;
+    }
     // write the size of the actual module to conform to serialization expected on
     // the chain
     let data_size = (output_bytes.len() - 8) as u32;
     (&mut output_bytes[4..8]).copy_from_slice(&data_size.to_be_bytes());
+    if strict {
+        let findings = suspicious_constructs(&output_bytes[8..], &module)?;
+        if !findings.is_empty() {
+            anyhow::bail!(
+                "The module contains suspicious constructs:\n     - {}",
+                findings.join("\n     - ")
+            );
+        }
+    }
+    if size_report {
+        SizeReport::new(&output_bytes[8..], &module)?.print();
+    }
+    if let Some(cache) = &cache {
+        cache.store(&output_bytes, module_schema.as_ref())?;
+    }
+    write_module(
+        &output_bytes,
+        module_schema,
+        descriptor,
+        max_module_size,
+        out,
+        out_raw,
+        &filename,
+    )
+}
+
+/// Check the size of the versioned module against the limit and write it to
+/// `out`, or next to the Wasm output of cargo at `filename` by default.
+fn write_module(
+    output_bytes: &[u8],
+    schema: Option<schema::VersionedModuleSchema>,
+    descriptor: &VersionDescriptor,
+    max_module_size: Option<usize>,
+    out: Option<PathBuf>,
+    out_raw: Option<PathBuf>,
+    filename: &str,
+) -> anyhow::Result<BuiltModule> {
+    let data_size = output_bytes.len() - 8;
+    let max_module_size = max_module_size.unwrap_or(descriptor.max_module_size);
+    if data_size > max_module_size {
+        anyhow::bail!(
+            "The module is {} B, which is {} B over the limit of {} B for {} modules. Try `--opt` \
+             or `--size-report` to see where the bytes go.",
+            data_size,
+            data_size - max_module_size,
+            max_module_size,
+            descriptor.name
+        );
+    }
 
+    let default_filename = PathBuf::from(format!("{}.{}", filename, descriptor.extension));
     let out_filename = match out {
-        Some(out) => {
-            // A path and a filename need to be provided when using the `--out` flag.
-            if out.file_name().is_none() || out.is_dir() {
-                anyhow::bail!(
-                    "The `--out` flag requires a path and a filename (expected input: \
-                     `./my/path/my_smart_contract.wasm.v1`)"
-                );
-            }
-            out
-        }
-        None => {
-            let extension = match version {
-                WasmVersion::V0 => "v0",
-                WasmVersion::V1 => "v1",
-            };
-            PathBuf::from(format!("{}.{}", filename, extension))
-        }
+        Some(out) => out_path(out, &default_filename),
+        None => default_filename,
     };
 
-    let total_module_len = output_bytes.len();
     if let Some(out_dir) = out_filename.parent() {
         fs::create_dir_all(out_dir)
             .context("Unable to create directory for the resulting smart contract module.")?;
     }
-    fs::write(out_filename, output_bytes)?;
-    Ok((total_module_len, return_schema))
+    fs::write(&out_filename, output_bytes)?;
+    if let Some(out_raw) = out_raw {
+        write_raw_module(&out_raw, output_bytes)?;
+    }
+    Ok(BuiltModule {
+        path: out_filename,
+        size: output_bytes.len(),
+        module_ref: module_reference(output_bytes),
+        schema,
+    })
+}
+
+/// The path to write a module to when `--out` is given. If `out` is a
+/// directory, or ends with a path separator, the module is written to it with
+/// the file name of `default_filename`.
+pub fn out_path(out: PathBuf, default_filename: &Path) -> PathBuf {
+    let is_dir = out.is_dir()
+        || out
+            .as_os_str()
+            .to_string_lossy()
+            .ends_with(std::path::is_separator)
+        || out.file_name().is_none();
+    match default_filename.file_name() {
+        Some(name) if is_dir => out.join(name),
+        _ => out,
+    }
+}
+
+/// A temporary directory, which is removed with its contents when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(purpose: &str) -> anyhow::Result<Self> {
+        let path = env::temp_dir().join(format!(
+            "cargo-concordium-{}-{}",
+            purpose,
+            std::process::id()
+        ));
+        fs::create_dir_all(&path).context("Could not create a temporary directory.")?;
+        Ok(TempDir(path))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) { let _ = fs::remove_dir_all(&self.0); }
+}
+
+/// Optimize the (stripped) Wasm module for size using wasm-opt. Only the
+/// features of the Wasm MVP are enabled, since the chain does not support any
+/// others. The files passed to wasm-opt are placed in a temporary directory.
+fn optimize_module(module: &[u8], debug_names: bool) -> anyhow::Result<Vec<u8>> {
+    let dir = TempDir::new("optimize")?;
+    let input = dir.0.join("module.wasm");
+    let output = dir.0.join("module.opt.wasm");
+    fs::write(&input, module).context("Could not write the module to optimize.")?;
+    wasm_opt::OptimizationOptions::new_optimize_for_size()
+        .mvp_features_only()
+        .debug_info(debug_names)
+        .run(&input, &output)
+        .context("Could not optimize the module using wasm-opt.")?;
+    fs::read(&output).context("Could not read the optimized module.")
+}
+
+/// Write the module reference as hex to a file, for use in scripts.
+pub fn write_module_reference(path: &Path, module_ref: ModuleReference) -> anyhow::Result<()> {
+    if let Some(out_dir) = path.parent() {
+        fs::create_dir_all(out_dir)
+            .context("Unable to create directory for the module reference.")?;
+    }
+    fs::write(path, format!("{}\n", module_ref)).context("Could not write the module reference.")
+}
+
+/// Write the Wasm module of a versioned module, i.e., without the version and
+/// length prefix, for use with other Wasm tools.
+pub fn write_raw_module(path: &Path, versioned_module: &[u8]) -> anyhow::Result<()> {
+    if let Some(out_dir) = path.parent() {
+        fs::create_dir_all(out_dir)
+            .context("Unable to create directory for the unversioned module.")?;
+    }
+    fs::write(path, &versioned_module[8..]).context("Could not write the unversioned module.")
+}
+
+/// Remove the custom sections of a module except those with the given names.
+fn strip_except(skeleton: &mut Skeleton, keep: &[&str]) {
+    skeleton.custom.retain(|section| {
+        parse_custom(section).map_or(false, |section| keep.contains(&section.name.as_ref()))
+    });
+}
+
+/// Append a custom section to a versioned module and update the length of the
+/// module in its header.
+pub fn append_custom_section(
+    versioned_module: &mut Vec<u8>,
+    name: &str,
+    contents: &[u8],
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        versioned_module.len() >= 8,
+        "The module is too short to be versioned."
+    );
+    write_custom_section(versioned_module, &CustomSection {
+        name: name.into(),
+        contents,
+    })?;
+    let data_size = (versioned_module.len() - 8) as u32;
+    (&mut versioned_module[4..8]).copy_from_slice(&data_size.to_be_bytes());
+    Ok(())
+}
+
+/// Get the name and contents of the custom sections of a Wasm module, in the
+/// order they appear in the module.
+pub fn get_custom_sections(module: &[u8]) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let skeleton = parse_skeleton(module).context("Could not parse the skeleton of the module.")?;
+    skeleton
+        .custom
+        .iter()
+        .map(|section| {
+            let section = parse_custom(section).context("Could not parse custom section.")?;
+            Ok((section.name.as_ref().to_string(), section.contents.to_vec()))
+        })
+        .collect()
 }
 
 /// Check that exports of module conform to the specification so that they will
@@ -240,15 +675,16 @@ fn check_exports(module: &Module, version: WasmVersion) -> anyhow::Result<()> {
                     .or_insert_with(BTreeSet::new)
                     .insert(rn.entrypoint_name().into());
             } else {
-                // for V0 contracts we do not allow any other functions.
-                match version {
-                    WasmVersion::V0 => anyhow::bail!(
+                // for some versions, e.g., V0, we do not allow any other functions.
+                let descriptor = versions::descriptor(version);
+                if descriptor.only_contract_exports {
+                    anyhow::bail!(
                         "The module has '{}' as an exposed function, which is neither a valid \
-                         init or receive method.\nV0 contracts do not allow any exported \
+                         init or receive method.\n{} contracts do not allow any exported \
                          functions that are neither init or receive methods.\n",
-                        export.name.as_ref()
-                    ),
-                    WasmVersion::V1 => (),
+                        export.name.as_ref(),
+                        descriptor.name
+                    );
                 }
             }
         }
@@ -286,6 +722,94 @@ fn check_exports(module: &Module, version: WasmVersion) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// The contracts and entrypoints exported by a module.
+#[derive(Debug, Default)]
+pub struct ModuleExports {
+    /// Names of the contracts in the module, i.e., the names of the exported
+    /// `init_` functions without the prefix.
+    pub contracts:   BTreeSet<String>,
+    /// The entrypoints exported for each contract.
+    pub entrypoints: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl ModuleExports {
+    /// Get the entrypoints of the given contract. Returns an empty set if the
+    /// contract has no entrypoints.
+    pub fn entrypoints_of(&self, contract_name: &str) -> BTreeSet<String> {
+        self.entrypoints
+            .get(contract_name)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// Parse and validate the given (unversioned) Wasm module as a contract of the
+/// given version.
+pub fn parse_and_validate_module(module: &[u8], version: WasmVersion) -> anyhow::Result<Module> {
+    let skeleton = parse_skeleton(module).context("Could not parse the skeleton of the module.")?;
+    let descriptor = versions::descriptor(version);
+    let module = (descriptor.validate)(&skeleton).with_context(|| {
+        format!(
+            "Could not validate the module as a {} contract.",
+            descriptor.name
+        )
+    })?;
+    Ok(module)
+}
+
+/// Parse and validate the given (unversioned) Wasm module and collect the
+/// contracts and entrypoints it exports.
+pub fn get_module_exports(module: &[u8], version: WasmVersion) -> anyhow::Result<ModuleExports> {
+    let module = parse_and_validate_module(module, version)?;
+    Ok(module_exports(&module))
+}
+
+/// Collect the contracts and entrypoints a parsed module exports.
+pub fn module_exports(module: &Module) -> ModuleExports {
+    let mut exports = ModuleExports::default();
+    for export in &module.export.exports {
+        if let ExportDescription::Func { .. } = export.description {
+            if let Ok(cn) = ContractName::new(export.name.as_ref()) {
+                exports.contracts.insert(cn.contract_name().to_string());
+            } else if let Ok(rn) = ReceiveName::new(export.name.as_ref()) {
+                exports
+                    .entrypoints
+                    .entry(rn.contract_name().to_string())
+                    .or_insert_with(BTreeSet::new)
+                    .insert(rn.entrypoint_name().to_string());
+            }
+        }
+    }
+    exports
+}
+
+/// Prefix the (unversioned) Wasm module with its version and length, which is
+/// the format in which modules are deployed to the chain.
+pub fn versioned_module_bytes(module: &[u8], version: WasmVersion) -> Vec<u8> {
+    let mut out = Vec::with_capacity(module.len() + 8);
+    out.extend_from_slice(&versions::descriptor(version).tag.to_be_bytes());
+    out.extend_from_slice(&(module.len() as u32).to_be_bytes());
+    out.extend_from_slice(module);
+    out
+}
+
+/// Compute the module reference of a versioned module, i.e., the SHA256 hash
+/// of the module in the format it is deployed in.
+pub fn module_reference(versioned_module: &[u8]) -> ModuleReference {
+    let hash: [u8; 32] = Sha256::digest(versioned_module).into();
+    ModuleReference::new(hash)
+}
+
+/// Get the names of the host functions imported by the module.
+pub fn get_module_imports(module: &Module) -> BTreeSet<String> {
+    module
+        .import
+        .imports
+        .iter()
+        .map(|import| import.item_name.as_ref().to_string())
+        .collect()
+}
+
 /// Find the string closest to the list of strings. If an exact match is found
 /// return `None`, otherwise return `Some` with a list of strings that are
 /// closest according to the [optimal string alignment metric](https://en.wikipedia.org/wiki/Damerau%E2%80%93Levenshtein_distance distance).
@@ -317,50 +841,213 @@ fn find_closest<'a>(
     Some(out)
 }
 
-/// Generates the contract schema by compiling with the 'build-schema' feature
-/// Then extracts the schema from the schema build
-pub fn build_contract_schema<A>(
+/// The kind and name of the target selected with `--example` or `--bin` in the
+/// cargo arguments, if any.
+fn selected_target(cargo_args: &[String]) -> Option<(&'static str, &str)> {
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--example" => return args.next().map(|name| ("example", name.as_str())),
+            "--bin" => return args.next().map(|name| ("bin", name.as_str())),
+            _ => {
+                if let Some(name) = arg.strip_prefix("--example=") {
+                    return Some(("example", name));
+                }
+                if let Some(name) = arg.strip_prefix("--bin=") {
+                    return Some(("bin", name));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The path of the Wasm module that cargo builds for the package. This is the
+/// cdylib library of the package, unless an example or a binary is selected
+/// with `--example` or `--bin` in the cargo arguments.
+fn wasm_filename(
+    metadata: &Metadata,
+    package: &Package,
     cargo_args: &[String],
-    generate_schema: impl FnOnce(&[u8]) -> ExecResult<A>,
-) -> anyhow::Result<A> {
-    let metadata = MetadataCommand::new()
-        .no_deps()
-        .exec()
-        .context("Could not access cargo metadata.")?;
+) -> anyhow::Result<String> {
+    let release_dir = format!(
+        "{}/concordium/wasm32-unknown-unknown/release",
+        metadata.target_directory
+    );
+    let (kind, name) = match selected_target(cargo_args) {
+        Some(selected) => selected,
+        None => {
+            let name = package
+                .targets
+                .iter()
+                .find(|target| target.kind.iter().any(|kind| kind == "cdylib"))
+                .map_or(package.name.as_str(), |target| target.name.as_str());
+            return Ok(format!("{}/{}.wasm", release_dir, to_snake_case(name)));
+        }
+    };
+    let target = package
+        .targets
+        .iter()
+        .find(|target| target.name == name && target.kind.iter().any(|k| k == kind))
+        .with_context(|| format!("The package '{}' has no {} '{}'.", package.name, kind, name))?;
+    // Cargo names libraries after the crate, in which dashes are replaced by
+    // underscores, and binaries after the target.
+    let file_name = if target
+        .crate_types
+        .iter()
+        .any(|crate_type| crate_type == "cdylib")
+    {
+        to_snake_case(name)
+    } else {
+        name.to_string()
+    };
+    if kind == "example" {
+        Ok(format!("{}/examples/{}.wasm", release_dir, file_name))
+    } else {
+        Ok(format!("{}/{}.wasm", release_dir, file_name))
+    }
+}
 
-    let package = metadata
-        .root_package()
-        .context("Unable to determine package.")?;
+/// Run `cargo build` for the Wasm target and return the resulting module. If
+/// `build_schema` is set, the module is built with the `build-schema` feature
+/// of concordium-std, so that it exports the schema functions.
+fn compile(
+    package_options: &PackageOptions,
+    cargo_args: &[String],
+    build_schema: bool,
+) -> anyhow::Result<Vec<u8>> {
+    let metadata = package_options.metadata(true)?;
+    let package = package_options.package(&metadata)?;
 
     let target_dir = format!("{}/concordium", metadata.target_directory);
 
-    let result = Command::new("cargo")
+    let mut command = package_options.cargo_command();
+    command
         .arg("build")
         .args(&["--target", "wasm32-unknown-unknown"])
         .arg("--release")
-        .args(&["--features", "concordium-std/build-schema"])
-        .args(&["--target-dir", target_dir.as_str()])
+        .args(&["--target-dir", target_dir.as_str()]);
+    if build_schema {
+        command.args(&["--features", "concordium-std/build-schema"]);
+    }
+    let result = command
+        .args(package_options.cargo_args())
         .args(cargo_args)
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
         .output()
-        .context("Could not run cargo build.")?;
+        .context("Could not use cargo build.")?;
 
     if !result.status.success() {
-        anyhow::bail!("Compilation failed.");
+        anyhow::bail!("Compilation failed.")
     }
 
-    let filename = format!(
-        "{}/wasm32-unknown-unknown/release/{}.wasm",
-        target_dir,
-        to_snake_case(package.name.as_str())
-    );
+    fs::read(wasm_filename(&metadata, package, cargo_args)?)
+        .context("Could not read cargo build Wasm output.")
+}
+
+/// The prefix of the names under which concordium-std exports test functions.
+pub const TEST_EXPORT_PREFIX: &str = "concordium_test ";
+
+/// A validator that accepts all imports and exports, for reading the exports
+/// of modules that are not contract modules, such as test modules and modules
+/// built with the `build-schema` feature.
+struct AnyImportExport;
+
+impl ValidateImportExport for AnyImportExport {
+    fn validate_import_function(
+        &self,
+        _duplicate: bool,
+        _mod_name: &Name,
+        _item_name: &Name,
+        _ty: &FunctionType,
+    ) -> bool {
+        true
+    }
 
-    let wasm =
-        std::fs::read(filename).context("Could not read cargo build contract schema output.")?;
-    let schema =
-        generate_schema(&wasm).context("Could not generate module schema from Wasm module.")?;
-    Ok(schema)
+    fn validate_export_function(&self, _item_name: &Name, _ty: &FunctionType) -> bool { true }
+}
+
+/// Parse and validate a Wasm module that is not necessarily a contract module.
+fn parse_any_module(wasm: &[u8]) -> anyhow::Result<(Skeleton, Module)> {
+    let skeleton = parse_skeleton(wasm).context("Could not parse the skeleton of the module.")?;
+    let module =
+        validate_module(&AnyImportExport, &skeleton).context("Could not validate the module.")?;
+    Ok((skeleton, module))
+}
+
+/// The names of the tests in a test Wasm module with the indices of their
+/// functions, sorted by name.
+pub fn test_exports(wasm: &[u8]) -> anyhow::Result<Vec<(String, u32)>> {
+    let (_, module) = parse_any_module(wasm)?;
+    let mut tests = module
+        .export
+        .exports
+        .iter()
+        .filter_map(|export| {
+            let test = export.name.as_ref().strip_prefix(TEST_EXPORT_PREFIX)?;
+            match export.description {
+                ExportDescription::Func { index } => Some((test.to_string(), index)),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    tests.sort();
+    Ok(tests)
+}
+
+/// Whether an export is one of the functions that concordium-std generates
+/// with the `build-schema` feature.
+fn is_schema_export(name: &str) -> bool {
+    name.starts_with("concordium_schema_") || name.starts_with("concordium_event_schema_")
+}
+
+/// Read an unsigned LEB128 encoded integer at the position, advancing it.
+pub fn read_leb128_u32(bytes: &[u8], pos: &mut usize) -> anyhow::Result<u32> {
+    let mut rest = bytes.get(*pos..).context("Unexpected end of the module.")?;
+    let len = rest.len();
+    let value = leb128::read::unsigned(&mut rest).context("Malformed integer in the module.")?;
+    *pos += len - rest.len();
+    u32::try_from(value).context("Malformed integer in the module.")
+}
+
+/// Remove the exports whose name satisfies `remove` from a Wasm module. The
+/// functions themselves are left in the module.
+fn remove_exports(wasm: &[u8], remove: impl Fn(&str) -> bool) -> anyhow::Result<Vec<u8>> {
+    let (mut skeleton, module) = parse_any_module(wasm)?;
+    let kept = module
+        .export
+        .exports
+        .iter()
+        .filter(|export| !remove(export.name.as_ref()))
+        .collect::<Vec<_>>();
+    let mut section = Vec::new();
+    leb128::write::unsigned(&mut section, kept.len() as u64)?;
+    for export in kept {
+        let name = export.name.as_ref().as_bytes();
+        leb128::write::unsigned(&mut section, name.len() as u64)?;
+        section.extend_from_slice(name);
+        let (kind, index) = match export.description {
+            ExportDescription::Func { index } => (0, index),
+            ExportDescription::Table => (1, 0),
+            ExportDescription::Memory => (2, 0),
+            ExportDescription::Global { index } => (3, index),
+        };
+        section.push(kind);
+        leb128::write::unsigned(&mut section, u64::from(index))?;
+    }
+    // The new export section is parsed from a module with only that section,
+    // the export section (7), and replaces the export section of the module.
+    let mut exports_only = wasm[..8].to_vec();
+    exports_only.push(7);
+    leb128::write::unsigned(&mut exports_only, section.len() as u64)?;
+    exports_only.extend_from_slice(&section);
+    skeleton.export = parse_skeleton(&exports_only)
+        .context("Could not parse the new export section.")?
+        .export;
+    let mut out = Vec::new();
+    skeleton.output(&mut out)?;
+    Ok(out)
 }
 
 /// Create a new Concordium smart contract project from a template, or there
@@ -662,24 +1349,18 @@ pub fn write_json_schema_to_file_v3(
     write_schema_json(path_of_out, contract_name, contract_counter, schema_json)
 }
 
-/// Build tests and run them. If errors occur in building the tests, or there
-/// are runtime exceptions that are not expected then this function returns
-/// Err(...).
-///
-/// Otherwise a boolean is returned, signifying whether the tests succeeded or
-/// failed.
-///
-/// The `seed` argument allows for providing the seed to instantiate a random
-/// number generator. If `None` is given, a random seed will be sampled.
-pub fn build_and_run_wasm_test(extra_args: &[String], seed: Option<u64>) -> anyhow::Result<bool> {
-    let metadata = MetadataCommand::new()
-        .no_deps()
-        .exec()
-        .context("Could not access cargo metadata.")?;
-
-    let package = metadata
-        .root_package()
-        .context("Unable to determine package.")?;
+/// Build the test Wasm module of the package with the given features, which
+/// default to the `wasm-test` feature of concordium-std, and with the given
+/// protocol version in [`PROTOCOL_VERSION_ENV`]. Returns the path of the
+/// module and the name of the package.
+fn build_wasm_test(
+    package_options: &PackageOptions,
+    extra_args: &[String],
+    features: Option<&str>,
+    protocol_version: Option<ProtocolVersion>,
+) -> anyhow::Result<(PathBuf, String)> {
+    let metadata = package_options.metadata(true)?;
+    let package = package_options.package(&metadata)?;
 
     let target_dir = format!("{}/concordium", metadata.target_directory);
 
@@ -689,18 +1370,22 @@ pub fn build_and_run_wasm_test(extra_args: &[String], seed: Option<u64>) -> anyh
         "--target",
         "wasm32-unknown-unknown",
         "--features",
-        "concordium-std/wasm-test",
+        features.unwrap_or("concordium-std/wasm-test"),
         "--target-dir",
         target_dir.as_str(),
     ];
 
     // Output what we are doing so that it is easier to debug if the user
     // has their own features or options.
+    let package_args = package_options.cargo_args();
     eprint!(
         "{} cargo {}",
         Color::Green.bold().paint("Running"),
         cargo_args.join(" ")
     );
+    if !package_args.is_empty() {
+        eprint!(" {}", package_args.join(" "));
+    }
     if extra_args.is_empty() {
         // This branch is just to avoid the extra trailing space in the case when
         // there are no extra arguments.
@@ -708,8 +1393,13 @@ pub fn build_and_run_wasm_test(extra_args: &[String], seed: Option<u64>) -> anyh
     } else {
         eprintln!(" {}", extra_args.join(" "));
     }
-    let result = Command::new("cargo")
+    let mut command = package_options.cargo_command();
+    if let Some(protocol_version) = protocol_version {
+        command.env(PROTOCOL_VERSION_ENV, protocol_version.number().to_string());
+    }
+    let result = command
         .args(cargo_args)
+        .args(&package_args)
         .args(extra_args)
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit())
@@ -721,15 +1411,163 @@ pub fn build_and_run_wasm_test(extra_args: &[String], seed: Option<u64>) -> anyh
         Color::Red.bold().paint("Could not build contract tests.")
     );
 
-    // If we compiled successfully the artifact is in the place listed below.
-    // So we load it, and try to run it.s
-    let filename = format!(
-        "{}/wasm32-unknown-unknown/release/{}.wasm",
-        target_dir,
-        to_snake_case(package.name.as_str())
+    // If we compiled successfully the artifact is in the same place as the
+    // module built by `build`, named after the selected target.
+    let filename = wasm_filename(&metadata, package, extra_args)?;
+    Ok((PathBuf::from(filename), package.name.to_string()))
+}
+
+/// The environment variable with the path of the module built for
+/// integration tests.
+pub const INTEGRATION_MODULE_ENV: &str = "CARGO_CONCORDIUM_MODULE";
+
+/// The environment variable with the number of the protocol version selected
+/// with `--protocol-version`, which is set when building the test module and
+/// when running the integration tests.
+pub const PROTOCOL_VERSION_ENV: &str = "CARGO_CONCORDIUM_PROTOCOL_VERSION";
+
+/// Build the contract module of the given version of the package with the
+/// given extra cargo arguments, and run its host-side integration tests with
+/// `cargo test`, with the path of the module in the environment variable
+/// [`INTEGRATION_MODULE_ENV`] and the protocol version, if any, in
+/// [`PROTOCOL_VERSION_ENV`]. Returns whether the tests succeeded.
+pub fn run_integration_tests(
+    package_options: &PackageOptions,
+    version: WasmVersion,
+    protocol_version: Option<ProtocolVersion>,
+    cargo_args: &[String],
+) -> anyhow::Result<bool> {
+    eprintln!(
+        "\n{}",
+        Color::Green
+            .bold()
+            .paint("Building module for integration tests ...")
+    );
+    let options = BuildOptions {
+        version,
+        build_schema: SchemaBuildOptions::BuildAndEmbed,
+        sbom: None,
+        source_url: None,
+        optimize: false,
+        debug_names: false,
+        keep_custom_sections: &[],
+        size_report: false,
+        max_module_size: None,
+        strict: false,
+        use_cache: true,
+        cargo_args,
+    };
+    let built = build_contract(&options, None, None, package_options)
+        .context("Could not build the module for integration tests.")?;
+    eprintln!(
+        "\n{} cargo test with {}={}",
+        Color::Green.bold().paint("Running"),
+        INTEGRATION_MODULE_ENV,
+        built.path.display()
     );
+    let mut command = package_options.cargo_command();
+    if let Some(protocol_version) = protocol_version {
+        command.env(PROTOCOL_VERSION_ENV, protocol_version.number().to_string());
+    }
+    let status = command
+        .arg("test")
+        .args(package_options.cargo_args())
+        .env(INTEGRATION_MODULE_ENV, &built.path)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("Failed running integration tests.")?;
+    Ok(status.success())
+}
+
+/// The settings of [`build_and_run_wasm_test`].
+#[derive(Clone, Copy)]
+pub struct TestOptions<'a> {
+    /// The extra arguments given to cargo when building the tests.
+    pub extra_args:       &'a [String],
+    /// The seed of the random number generator. If `None` is given, a random
+    /// seed will be sampled.
+    pub seed:             Option<u64>,
+    pub report_html:      Option<&'a Path>,
+    pub reports:          &'a [TestReport],
+    /// Only build the test module and print its path.
+    pub no_run:           bool,
+    /// Only list the tests of the module.
+    pub list:             bool,
+    pub coverage:         bool,
+    pub energy_report:    Option<&'a Path>,
+    /// Run the tests one at a time and skip the remaining tests after the
+    /// first failure.
+    pub fail_fast:        bool,
+    /// The limits of each test. A test that exceeds them fails.
+    pub limits:           TestLimits,
+    /// A test module built elsewhere to run instead of building the package.
+    pub module:           Option<&'a Path>,
+    /// The features to build the tests with instead of the `wasm-test`
+    /// feature of concordium-std.
+    pub features:         Option<&'a str>,
+    /// Run the tests one at a time in an order determined by the seed instead
+    /// of the order of their names.
+    pub shuffle:          bool,
+    /// The protocol version to build the tests for, which they can read from
+    /// [`PROTOCOL_VERSION_ENV`] at compile time.
+    pub protocol_version: Option<ProtocolVersion>,
+}
+
+/// Build tests and run them. If errors occur in building the tests, or there
+/// are runtime exceptions that are not expected then this function returns
+/// Err(...).
+///
+/// Otherwise a boolean is returned, signifying whether the tests succeeded or
+/// failed.
+pub fn build_and_run_wasm_test(
+    package_options: &PackageOptions,
+    options: &TestOptions,
+) -> anyhow::Result<bool> {
+    let TestOptions {
+        extra_args,
+        seed,
+        report_html,
+        reports,
+        no_run,
+        list,
+        coverage,
+        energy_report,
+        fail_fast,
+        limits,
+        module,
+        features,
+        shuffle,
+        protocol_version,
+    } = *options;
+    // The name of the test suite is the package name, or the name of the
+    // module file if it was built elsewhere.
+    let (filename, suite) = match module {
+        Some(path) => {
+            let name = path
+                .file_stem()
+                .context("The path of the test module has no file name.")?;
+            (path.to_path_buf(), name.to_string_lossy().into_owned())
+        }
+        None => build_wasm_test(package_options, extra_args, features, protocol_version)?,
+    };
 
-    let wasm = std::fs::read(filename).context("Failed reading contract test output artifact.")?;
+    if no_run {
+        eprintln!("{} test module", Color::Green.bold().paint("Built"));
+        println!("{}", filename.display());
+        return Ok(true);
+    }
+
+    let wasm = std::fs::read(&filename).context("Failed reading contract test output artifact.")?;
+
+    if list {
+        let tests = test_exports(&wasm)?;
+        for (name, _) in tests.iter() {
+            println!("{}", name);
+        }
+        eprintln!("{} tests", tests.len());
+        return Ok(true);
+    }
 
     eprintln!("\n{}", Color::Green.bold().paint("Running tests ..."));
 
@@ -743,13 +1581,108 @@ pub fn build_and_run_wasm_test(extra_args: &[String], seed: Option<u64>) -> anyh
         }
     };
 
-    let results = utils::run_module_tests(&wasm, seed_u64)?;
+    let start = Instant::now();
+    let mut skipped = 0;
+    // The functions executed by each test when measuring coverage.
+    let mut executed = Vec::new();
+    let mut measured = Vec::new();
+    // The interpreter energy used by each test, including the energy used
+    // until a failing test stopped.
+    let mut energy = BTreeMap::new();
+    // The time taken by each test.
+    let mut durations = BTreeMap::new();
+    let results = if shuffle
+        || fail_fast
+        || coverage
+        || energy_report.is_some()
+        || !reports.is_empty()
+        || limits.energy.is_some()
+        || limits.timeout.is_some()
+    {
+        let runner = if coverage {
+            // The functions of the crate are named after the crate, which is
+            // the name of the artifact with dashes replaced by underscores.
+            let artifact = filename
+                .file_stem()
+                .context("The path of the test module has no file name.")?;
+            let crate_name = to_snake_case(&artifact.to_string_lossy());
+            let (runner, functions) =
+                TestRunner::with_coverage(&wasm, seed_u64, limits, &crate_name)?;
+            measured = functions;
+            runner
+        } else {
+            TestRunner::new(&wasm, seed_u64, limits)?
+        };
+        let mut order = runner.tests.clone();
+        if shuffle {
+            order.shuffle(&mut SmallRng::seed_from_u64(seed_u64));
+            eprintln!(
+                "  Running the tests in random order with seed {}.",
+                seed_u64
+            );
+        }
+        let mut results = Vec::new();
+        for name in order.iter() {
+            let test_start = Instant::now();
+            let result = runner.run(name);
+            durations.insert(name.clone(), test_start.elapsed());
+            let failed = result.error.is_some();
+            if coverage {
+                executed.push((name.clone(), result.covered));
+            }
+            energy.insert(name.clone(), result.energy);
+            results.push((
+                name.clone(),
+                result.error.map(|err| (err, result.randomized)),
+            ));
+            if failed && fail_fast {
+                skipped = runner.tests.len() - results.len();
+                break;
+            }
+        }
+        results
+    } else {
+        let mut results = utils::run_module_tests(&wasm, seed_u64)?
+            .into_iter()
+            .map(|(name, result)| {
+                (
+                    name.to_string(),
+                    result.map(|(err, randomized)| (err.to_string(), randomized)),
+                )
+            })
+            .collect::<Vec<_>>();
+        // The engine runs the tests in the order of the exports of the module,
+        // which changes between builds.
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        results
+    };
+    let duration = start.elapsed();
     let mut num_failed = 0;
+    let mut report_rows = Vec::new();
+    let mut outcomes = Vec::new();
     for result in results {
         let test_name = result.0;
         match result.1 {
             Some((err, is_randomized)) => {
                 num_failed += 1;
+                outcomes.push(TestOutcome {
+                    name:     test_name.to_string(),
+                    error:    Some(err.to_string()),
+                    seed:     is_randomized.then(|| seed_u64),
+                    duration: durations.get(&test_name).map(Duration::as_secs_f64),
+                });
+                report_rows.push(vec![
+                    Cell::Text(test_name.to_string()),
+                    Cell::Status(false),
+                    Cell::Text(err.to_string()),
+                    Cell::Text(
+                        if is_randomized {
+                            seed_u64.to_string()
+                        } else {
+                            String::new()
+                        },
+                    ),
+                ]);
                 eprintln!(
                     "  - {} ... {}",
                     test_name,
@@ -770,9 +1703,60 @@ pub fn build_and_run_wasm_test(extra_args: &[String], seed: Option<u64>) -> anyh
             }
             None => {
                 eprintln!("  - {} ... {}", test_name, Color::Green.bold().paint("ok"));
+                outcomes.push(TestOutcome {
+                    name:     test_name.to_string(),
+                    error:    None,
+                    seed:     None,
+                    duration: durations.get(&test_name).map(Duration::as_secs_f64),
+                });
+                report_rows.push(vec![
+                    Cell::Text(test_name.to_string()),
+                    Cell::Status(true),
+                    Cell::Text(String::new()),
+                    Cell::Text(String::new()),
+                ]);
             }
         }
     }
+    if skipped > 0 {
+        eprintln!(
+            "  Skipped the remaining {} tests after the first failure.",
+            skipped
+        );
+    }
+
+    if let Some(dir) = report_html {
+        let mut report = HtmlReport::new(&format!("Tests of {}", suite));
+        report.result(
+            num_failed == 0,
+            &format!("{} of {} tests failed.", num_failed, report_rows.len()),
+        );
+        report.table(&["test", "result", "error", "seed"], &report_rows);
+        report.write(dir)?;
+    }
+    if !reports.is_empty() {
+        let run = TestRun::new(&suite, seed_u64, duration, outcomes);
+        for report in reports {
+            run.write(report)?;
+        }
+    }
+    if coverage {
+        let functions = test_coverage::coverage(
+            &measured,
+            executed
+                .iter()
+                .map(|(name, covered)| (name.as_str(), covered)),
+        );
+        test_coverage::print_report(&functions);
+    }
+    if let Some(path) = energy_report {
+        let energy = TestEnergy {
+            seed:  seed_u64,
+            tests: energy,
+        };
+        energy.print();
+        energy.write(path)?;
+    }
 
     if num_failed == 0 {
         eprintln!("Test result: {}", Color::Green.bold().paint("ok"));
@@ -0,0 +1,130 @@
+//! Information about how a module was built.
+//!
+//! Every module built by `cargo concordium build` contains a
+//! `concordium-build-info` custom section with the versions of rustc and
+//! cargo-concordium, the enabled features of the package and the git commit of
+//! the source tree, so that anyone inspecting the module later can tell how it
+//! was produced.
+//!
+//! The `[package.metadata.concordium]` table of the Cargo.toml of the package
+//! can describe the contract for wallets and explorers:
+//!
+//! ```toml
+//! [package.metadata.concordium]
+//! version = "1.2.0"
+//! license = "MPL-2.0"
+//! homepage = "https://example.com/my-contract"
+//! description = "A token with a fixed supply."
+//! ```
+//!
+//! It is embedded in a `concordium-metadata` custom section.
+use crate::{build::PackageOptions, toolchain, verify::command_output};
+use anyhow::Context;
+use cargo_metadata::Package;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, process::Command};
+
+/// Name of the custom section that contains the build information.
+pub const BUILD_INFO_SECTION_NAME: &str = "concordium-build-info";
+
+/// Name of the custom section that contains the metadata of the contract.
+pub const CONTRACT_METADATA_SECTION_NAME: &str = "concordium-metadata";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    /// The version of rustc, e.g., `1.62.0`.
+    pub rustc_version:            Option<String>,
+    /// The version of cargo-concordium that built the module.
+    pub cargo_concordium_version: String,
+    /// The features of the package that were enabled.
+    pub features:                 Vec<String>,
+    /// The commit of the source tree, if it is a git repository.
+    pub git_commit:               Option<String>,
+}
+
+/// Collect the build information of a package built with the given extra
+/// cargo arguments. Information that cannot be determined is left out.
+pub fn build_info(
+    package_options: &PackageOptions,
+    package: &Package,
+    cargo_args: &[String],
+) -> BuildInfo {
+    let mut git = Command::new("git");
+    if let Some(dir) = package_options.directory() {
+        git.current_dir(dir);
+    }
+    BuildInfo {
+        rustc_version:            toolchain::rustc_version(package_options).ok(),
+        cargo_concordium_version: env!("CARGO_PKG_VERSION").to_string(),
+        features:                 enabled_features(package, cargo_args),
+        git_commit:               command_output(git.args(&["rev-parse", "HEAD"])).ok(),
+    }
+}
+
+/// The features of the package enabled by the cargo arguments, including the
+/// features they enable in turn. Features of dependencies are not included.
+fn enabled_features(package: &Package, cargo_args: &[String]) -> Vec<String> {
+    let mut requested = Vec::new();
+    let mut default = true;
+    let mut args = cargo_args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--all-features" => requested.extend(package.features.keys().cloned()),
+            "--no-default-features" => default = false,
+            "--features" | "-F" => requested.extend(args.next().cloned()),
+            _ => {
+                if let Some(list) = arg.strip_prefix("--features=") {
+                    requested.push(list.to_string());
+                }
+            }
+        }
+    }
+    if default {
+        requested.push("default".to_string());
+    }
+    let mut enabled = BTreeSet::new();
+    let mut pending: Vec<String> = requested
+        .iter()
+        .flat_map(|list| list.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|feature| !feature.is_empty())
+        .map(String::from)
+        .collect();
+    while let Some(feature) = pending.pop() {
+        if let Some(implied) = package.features.get(&feature) {
+            if enabled.insert(feature) {
+                pending.extend(implied.iter().filter(|f| !f.contains(['/', ':'])).cloned());
+            }
+        }
+    }
+    enabled.into_iter().collect()
+}
+
+/// The description of a contract given in the Cargo.toml of its package.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContractMetadata {
+    pub version:     Option<String>,
+    pub license:     Option<String>,
+    pub homepage:    Option<String>,
+    pub description: Option<String>,
+}
+
+/// Read the metadata of the contract from the `[package.metadata.concordium]`
+/// table of the package. Returns `None` if the table contains none of the
+/// fields.
+pub fn contract_metadata(package: &Package) -> anyhow::Result<Option<ContractMetadata>> {
+    let table = match package.metadata.get("concordium") {
+        Some(table) => table,
+        None => return Ok(None),
+    };
+    let metadata: ContractMetadata = serde_json::from_value(table.clone())
+        .context("Could not parse [package.metadata.concordium] of the package.")?;
+    if metadata.version.is_none()
+        && metadata.license.is_none()
+        && metadata.homepage.is_none()
+        && metadata.description.is_none()
+    {
+        return Ok(None);
+    }
+    Ok(Some(metadata))
+}
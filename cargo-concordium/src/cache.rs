@@ -0,0 +1,191 @@
+//! Reuse of previously built modules when nothing changed.
+//!
+//! `cargo concordium build` fingerprints everything that determines the
+//! module: the sources of all local packages of the workspace, `Cargo.lock`,
+//! the cargo configuration and toolchain files that apply to the build, the
+//! environment variables that set flags of rustc, the build options, the
+//! toolchain and the version of cargo-concordium. If the
+//! fingerprint matches that of the last build of the package, the module and
+//! the schema are taken from the cache in `target/concordium/cache` instead of
+//! invoking cargo and validating the module again.
+use crate::{build::PackageOptions, versions::VersionDescriptor};
+use anyhow::Context;
+use cargo_metadata::{Metadata, Package};
+use concordium_contracts_common::{from_bytes, schema::VersionedModuleSchema, to_bytes};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The files, relative to the directory cargo is run in or any of its
+/// ancestors, that configure cargo or select the toolchain.
+const CONFIG_FILES: [&str; 4] = [
+    ".cargo/config",
+    ".cargo/config.toml",
+    "rust-toolchain",
+    "rust-toolchain.toml",
+];
+
+/// The environment variables that pass flags to rustc.
+const FLAG_VARIABLES: [&str; 4] = [
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "CARGO_BUILD_RUSTFLAGS",
+    "CARGO_TARGET_WASM32_UNKNOWN_UNKNOWN_RUSTFLAGS",
+];
+
+/// The cached build of a package.
+pub struct BuildCache {
+    /// The directory of the cached build.
+    dir:         PathBuf,
+    /// The fingerprint of the current build.
+    fingerprint: String,
+}
+
+impl BuildCache {
+    /// Fingerprint the build of a module of the package with the given
+    /// settings, which must include everything other than the sources that
+    /// affects the module. One build is cached per package and module version.
+    pub fn new(
+        package_options: &PackageOptions,
+        metadata: &Metadata,
+        package: &Package,
+        descriptor: &VersionDescriptor,
+        settings: &impl Serialize,
+    ) -> anyhow::Result<Self> {
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_vec(settings)?);
+        hash_sources(package_options, &mut hasher).context("Could not hash the sources.")?;
+        hash_configuration(package_options, &mut hasher)
+            .context("Could not hash the cargo configuration.")?;
+        Ok(BuildCache {
+            dir:         metadata
+                .target_directory
+                .as_std_path()
+                .join("concordium")
+                .join("cache")
+                .join(&package.name)
+                .join(descriptor.extension),
+            fingerprint: hex::encode(hasher.finalize()),
+        })
+    }
+
+    /// Get the versioned module and the schema of the last build if its
+    /// fingerprint matches.
+    pub fn load(&self) -> Option<(Vec<u8>, Option<VersionedModuleSchema>)> {
+        let fingerprint = fs::read_to_string(self.dir.join("fingerprint")).ok()?;
+        if fingerprint != self.fingerprint {
+            return None;
+        }
+        let module = fs::read(self.dir.join("module")).ok()?;
+        let schema = match fs::read(self.dir.join("schema")) {
+            Ok(bytes) => Some(from_bytes(&bytes).ok()?),
+            Err(_) => None,
+        };
+        Some((module, schema))
+    }
+
+    /// Store the versioned module and the schema of the current build,
+    /// replacing the previous build.
+    pub fn store(
+        &self,
+        module: &[u8],
+        schema: Option<&VersionedModuleSchema>,
+    ) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir).context("Could not create the build cache.")?;
+        // The fingerprint is removed first and written last, so that an
+        // interrupted store is not mistaken for a complete one.
+        let fingerprint = self.dir.join("fingerprint");
+        if fingerprint.exists() {
+            fs::remove_file(&fingerprint).context("Could not update the build cache.")?;
+        }
+        fs::write(self.dir.join("module"), module).context("Could not cache the module.")?;
+        let schema_path = self.dir.join("schema");
+        match schema {
+            Some(schema) => {
+                fs::write(&schema_path, to_bytes(schema)).context("Could not cache the schema.")?
+            }
+            None if schema_path.exists() => {
+                fs::remove_file(&schema_path).context("Could not update the build cache.")?
+            }
+            None => {}
+        }
+        fs::write(fingerprint, &self.fingerprint).context("Could not update the build cache.")
+    }
+}
+
+/// Hash `Cargo.lock` and the files of all packages of the workspace that are
+/// not from a registry or a git repository, including path dependencies.
+fn hash_sources(package_options: &PackageOptions, hasher: &mut Sha256) -> anyhow::Result<()> {
+    let metadata = package_options.metadata(false)?;
+    let lock_file = metadata.workspace_root.as_std_path().join("Cargo.lock");
+    if lock_file.exists() {
+        hasher.update(fs::read(lock_file)?);
+    }
+    let target_dir = metadata.target_directory.as_std_path();
+    for package in metadata
+        .packages
+        .iter()
+        .filter(|package| package.source.is_none())
+    {
+        if let Some(dir) = package.manifest_path.parent() {
+            hash_dir(dir.as_std_path(), target_dir, hasher)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hash the configuration files of cargo and the toolchain that apply when
+/// cargo is run in the directory of the manifest, which are looked up in the
+/// directory and all its ancestors, the configuration in the cargo home
+/// directory, and the environment variables that set flags of rustc.
+fn hash_configuration(package_options: &PackageOptions, hasher: &mut Sha256) -> io::Result<()> {
+    let dir = match package_options.directory() {
+        Some(dir) => dir.canonicalize()?,
+        None => env::current_dir()?,
+    };
+    let mut files = dir
+        .ancestors()
+        .flat_map(|ancestor| CONFIG_FILES.iter().map(move |file| ancestor.join(file)))
+        .collect::<Vec<_>>();
+    let cargo_home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")));
+    if let Some(cargo_home) = cargo_home {
+        files.push(cargo_home.join("config"));
+        files.push(cargo_home.join("config.toml"));
+    }
+    for file in files.iter().filter(|file| file.is_file()) {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(fs::read(file)?);
+    }
+    for variable in FLAG_VARIABLES.iter() {
+        hasher.update(variable.as_bytes());
+        if let Some(value) = env::var_os(variable) {
+            hasher.update(value.to_string_lossy().as_bytes());
+        }
+    }
+    Ok(())
+}
+
+/// Hash the paths and contents of the files in a directory recursively, in
+/// sorted order. The `.git` directory and the target directory are skipped.
+fn hash_dir(dir: &Path, target_dir: &Path, hasher: &mut Sha256) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_name() == ".git" || path == target_dir {
+            continue;
+        }
+        if path.is_dir() {
+            hash_dir(&path, target_dir, hasher)?;
+        } else {
+            hasher.update(path.to_string_lossy().as_bytes());
+            hasher.update(fs::read(&path)?);
+        }
+    }
+    Ok(())
+}
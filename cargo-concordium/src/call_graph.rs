@@ -0,0 +1,101 @@
+//! The calls between the functions of a Wasm module.
+//!
+//! The graph is used to find the functions, and in particular the host
+//! functions, that an exported function can reach. An indirect call is assumed
+//! to reach any function in the table, since its target is only known at run
+//! time.
+use concordium_wasm::types::{Module, OpCode};
+use std::collections::BTreeSet;
+
+/// The functions a function calls directly, and whether it contains loops or
+/// indirect calls.
+#[derive(Default)]
+pub struct FunctionCalls {
+    pub callees:  BTreeSet<u32>,
+    /// The number of loops in the function.
+    pub loops:    usize,
+    pub indirect: bool,
+}
+
+/// The calls of each function defined in a module.
+pub struct CallGraph {
+    num_imports: usize,
+    /// The calls of the functions defined in the module, in the order of the
+    /// code section.
+    functions:   Vec<FunctionCalls>,
+    /// The functions in the table, which indirect calls can reach.
+    table:       Vec<u32>,
+}
+
+impl CallGraph {
+    /// Collect the calls of the functions of the module.
+    pub fn new(module: &Module) -> Self {
+        let functions = module
+            .code
+            .impls
+            .iter()
+            .map(|code| {
+                let mut calls = FunctionCalls::default();
+                for instr in code.expr.instrs.iter() {
+                    match instr {
+                        OpCode::Call(index) => {
+                            calls.callees.insert(*index);
+                        }
+                        OpCode::CallIndirect(_) => calls.indirect = true,
+                        OpCode::Loop(_) => calls.loops += 1,
+                        _ => (),
+                    }
+                }
+                calls
+            })
+            .collect();
+        let table = module
+            .element
+            .elements
+            .iter()
+            .flat_map(|element| element.inits.iter().copied())
+            .collect();
+        CallGraph {
+            num_imports: module.import.imports.len(),
+            functions,
+            table,
+        }
+    }
+
+    /// The calls of the function with the given index, if it is defined in the
+    /// module rather than imported.
+    pub fn calls(&self, index: u32) -> Option<&FunctionCalls> {
+        (index as usize)
+            .checked_sub(self.num_imports)
+            .and_then(|i| self.functions.get(i))
+    }
+
+    /// The functions that can be reached from the function with the given
+    /// index, including itself.
+    pub fn reachable(&self, index: u32) -> BTreeSet<u32> {
+        let mut reachable = BTreeSet::new();
+        let mut pending = vec![index];
+        while let Some(index) = pending.pop() {
+            if !reachable.insert(index) {
+                continue;
+            }
+            if let Some(function) = self.calls(index) {
+                pending.extend(function.callees.iter().copied());
+                if function.indirect {
+                    pending.extend(self.table.iter().copied());
+                }
+            }
+        }
+        reachable
+    }
+
+    /// The names of the host functions that can be reached from the function
+    /// with the given index.
+    pub fn reachable_imports(&self, module: &Module, index: u32) -> BTreeSet<String> {
+        self.reachable(index)
+            .into_iter()
+            .filter_map(|index| module.import.imports.get(index as usize))
+            .map(|import| import.item_name.as_ref().to_string())
+            .collect()
+    }
+}
@@ -0,0 +1,199 @@
+//! A local chain of contract instances in a directory, used by
+//! `cargo concordium run` with `--chain-dir`.
+//!
+//! `run init` records the created instance with its module, state, balance
+//! and address, and `run update` loads the latest state of the instance at
+//! the given address and records the resulting state and balance. The slot
+//! time given with `--slot-time` is recorded as well, so that later
+//! invocations can advance it with `--slot-time +<duration>`. The directory is
+//! laid out as
+//!
+//! ```text
+//! chain.json
+//! modules/<module reference>.wasm.v1
+//! instances/<index>-<subindex>/instance.json
+//! instances/<index>-<subindex>/state.bin
+//! ```
+use crate::build::module_reference;
+use anyhow::Context;
+use concordium_contracts_common::{AccountAddress, Amount, ContractAddress, SlotTime};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// An instance recorded in the local chain.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceRecord {
+    pub address:  ContractAddress,
+    /// The reference of the module of the instance, hex encoded.
+    pub module:   String,
+    pub contract: String,
+    pub balance:  Amount,
+    /// The account that initialized the instance, if known.
+    pub owner:    Option<AccountAddress>,
+}
+
+/// The state of the local chain that is not specific to an instance.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChainRecord {
+    /// The slot time of the last recorded invocation that gave one.
+    slot_time: Option<SlotTime>,
+}
+
+/// A local chain stored in a directory.
+pub struct LocalChain {
+    dir: PathBuf,
+}
+
+impl LocalChain {
+    /// Open the local chain in the directory, creating it if needed.
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        for sub_dir in ["modules", "instances"] {
+            fs::create_dir_all(dir.join(sub_dir)).with_context(|| {
+                format!("Could not create the local chain in {}.", dir.display())
+            })?;
+        }
+        Ok(LocalChain {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn chain_record(&self) -> anyhow::Result<ChainRecord> {
+        let path = self.dir.join("chain.json");
+        if !path.exists() {
+            return Ok(ChainRecord::default());
+        }
+        let bytes = fs::read(&path).context("Could not read the local chain.")?;
+        serde_json::from_slice(&bytes)
+            .with_context(|| format!("Could not parse {}.", path.display()))
+    }
+
+    /// The slot time of the last recorded invocation that gave one.
+    pub fn slot_time(&self) -> anyhow::Result<Option<SlotTime>> {
+        Ok(self.chain_record()?.slot_time)
+    }
+
+    /// Record the slot time of an invocation.
+    pub fn set_slot_time(&self, slot_time: SlotTime) -> anyhow::Result<()> {
+        let record = ChainRecord {
+            slot_time: Some(slot_time),
+        };
+        fs::write(
+            self.dir.join("chain.json"),
+            serde_json::to_vec_pretty(&record)?,
+        )
+        .context("Could not store the slot time of the local chain.")
+    }
+
+    fn instance_dir(&self, address: ContractAddress) -> PathBuf {
+        self.dir
+            .join("instances")
+            .join(format!("{}-{}", address.index, address.subindex))
+    }
+
+    /// The path of the state of the instance.
+    pub fn state_path(&self, address: ContractAddress) -> PathBuf {
+        self.instance_dir(address).join("state.bin")
+    }
+
+    /// The path that the state produced by an invocation is written to until
+    /// the invocation is known to have succeeded.
+    pub fn pending_state_path(&self, address: ContractAddress) -> PathBuf {
+        self.instance_dir(address).join("state.bin.pending")
+    }
+
+    /// The path of a module given by its reference.
+    pub fn module_path(&self, module_ref: &str) -> PathBuf {
+        self.dir
+            .join("modules")
+            .join(format!("{}.wasm.v1", module_ref))
+    }
+
+    /// Store a versioned module, returning its reference.
+    pub fn add_module(&self, versioned_module: &[u8]) -> anyhow::Result<String> {
+        let module_ref = module_reference(versioned_module).to_string();
+        let path = self.module_path(&module_ref);
+        if !path.exists() {
+            fs::write(&path, versioned_module).context("Could not store the module.")?;
+        }
+        Ok(module_ref)
+    }
+
+    /// The instance at the address.
+    pub fn instance(&self, address: ContractAddress) -> anyhow::Result<InstanceRecord> {
+        let path = self.instance_dir(address).join("instance.json");
+        let bytes = fs::read(&path).with_context(|| {
+            format!(
+                "There is no instance at {} in the local chain {}.",
+                address,
+                self.dir.display()
+            )
+        })?;
+        serde_json::from_slice(&bytes)
+            .with_context(|| format!("Could not parse the instance {}.", path.display()))
+    }
+
+    /// The address the next instance is created at, which follows the highest
+    /// index in use.
+    pub fn next_address(&self) -> anyhow::Result<ContractAddress> {
+        let mut next_index = 0;
+        for entry in fs::read_dir(self.dir.join("instances"))
+            .context("Could not read the instances of the local chain.")?
+        {
+            let name = entry?.file_name();
+            let index = name
+                .to_str()
+                .and_then(|name| name.split('-').next()?.parse::<u64>().ok());
+            if let Some(index) = index {
+                next_index = next_index.max(index + 1);
+            }
+        }
+        Ok(ContractAddress::new(next_index, 0))
+    }
+
+    /// Prepare a new invocation of the instance at the address by removing
+    /// state left over by earlier invocations that did not succeed.
+    pub fn prepare(&self, address: ContractAddress) -> anyhow::Result<()> {
+        fs::create_dir_all(self.instance_dir(address))
+            .context("Could not create the directory of the instance.")?;
+        let pending = self.pending_state_path(address);
+        if pending.exists() {
+            fs::remove_file(pending).context("Could not remove the pending state.")?;
+        }
+        Ok(())
+    }
+
+    /// Record the instance after a successful invocation. The pending state
+    /// becomes the state of the instance if the invocation produced one.
+    pub fn commit(&self, record: &InstanceRecord) -> anyhow::Result<()> {
+        let pending = self.pending_state_path(record.address);
+        if pending.exists() {
+            fs::rename(pending, self.state_path(record.address))
+                .context("Could not store the state of the instance.")?;
+        }
+        fs::write(
+            self.instance_dir(record.address).join("instance.json"),
+            serde_json::to_vec_pretty(record)?,
+        )
+        .context("Could not store the instance.")
+    }
+
+    /// Discard the state of an invocation that did not succeed. The directory
+    /// of an instance that was not created is removed.
+    pub fn discard(&self, address: ContractAddress) -> anyhow::Result<()> {
+        let dir = self.instance_dir(address);
+        if dir.join("instance.json").exists() {
+            let pending = self.pending_state_path(address);
+            if pending.exists() {
+                fs::remove_file(pending).context("Could not remove the pending state.")?;
+            }
+            Ok(())
+        } else {
+            fs::remove_dir_all(dir).context("Could not remove the directory of the instance.")
+        }
+    }
+}
@@ -0,0 +1,269 @@
+//! The state of V1 contracts written with `concordium-std`, grouped by the
+//! collections it is made of.
+//!
+//! `concordium-std` stores the root of the state at the empty key and the next
+//! free collection prefix at the key `0u64`. Each `StateMap`, `StateSet` and
+//! `StateBox` is given a prefix, a little endian `u64`, and stores its entries
+//! at keys that start with the prefix: a map at the prefix followed by the
+//! serialized key, a set at the prefix followed by the serialized element, and
+//! a box at the prefix alone. Entries are grouped by these prefixes, and with a
+//! file giving the schema types of the root and of each collection, the keys
+//! and values are decoded into JSON instead of being shown as hex.
+//!
+//! The types file is JSON with the types as base64, like the types in the
+//! output of `cargo concordium build --schema-json-out`:
+//!
+//! ```json
+//! {
+//!   "root": "<type of the root>",
+//!   "collections": {
+//!     "2": { "name": "balances", "key": "<type>", "value": "<type>" },
+//!     "3": { "name": "operators", "key": "<type>" }
+//!   }
+//! }
+//! ```
+use crate::{build::ENCODER, state_json};
+use anyhow::{ensure, Context};
+use base64::Engine as _;
+use concordium_contracts_common::{from_bytes, schema::Type};
+use concordium_smart_contract_engine::v1::trie::PersistentState;
+use ptree::TreeBuilder;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::BTreeMap, convert::TryInto, path::Path};
+
+/// The prefix of the key at which the next free collection prefix is stored.
+const NEXT_PREFIX_KEY: [u8; 8] = [0; 8];
+
+/// The schema types of a collection, as given in the types file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CollectionSpec {
+    name:  Option<String>,
+    /// The type of the keys of a map or the elements of a set.
+    key:   Option<String>,
+    /// The type of the values of a map or the value of a box.
+    value: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TypesFile {
+    root:        Option<String>,
+    #[serde(default)]
+    collections: BTreeMap<u64, CollectionSpec>,
+}
+
+/// The name and the decoded types of a collection.
+#[derive(Debug, Default)]
+struct CollectionTypes {
+    name:  Option<String>,
+    key:   Option<Type>,
+    value: Option<Type>,
+}
+
+/// The schema types of the root and the collections of a state.
+#[derive(Debug, Default)]
+pub struct StateTypes {
+    root:        Option<Type>,
+    collections: BTreeMap<u64, CollectionTypes>,
+}
+
+/// Decode a schema type given as base64.
+fn decode_type(base64: &str, what: &str) -> anyhow::Result<Type> {
+    let bytes = ENCODER
+        .decode(base64.trim().trim_end_matches('='))
+        .with_context(|| format!("The type of {} is not valid base64.", what))?;
+    from_bytes(&bytes).map_err(|_| anyhow::anyhow!("The type of {} is not a valid type.", what))
+}
+
+impl StateTypes {
+    /// Read the types from a file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Could not read the types file {}.", path.display()))?;
+        let file: TypesFile = serde_json::from_slice(&bytes)
+            .with_context(|| format!("Could not parse the types file {}.", path.display()))?;
+        let root = file
+            .root
+            .as_deref()
+            .map(|ty| decode_type(ty, "the root"))
+            .transpose()?;
+        let mut collections = BTreeMap::new();
+        for (prefix, spec) in file.collections {
+            ensure!(
+                prefix > 1,
+                "Collections have a prefix of 2 or more, not {}.",
+                prefix
+            );
+            let what = |part: &str| format!("the {} of collection {}", part, prefix);
+            collections.insert(prefix, CollectionTypes {
+                name:  spec.name,
+                key:   spec
+                    .key
+                    .as_deref()
+                    .map(|ty| decode_type(ty, &what("key")))
+                    .transpose()?,
+                value: spec
+                    .value
+                    .as_deref()
+                    .map(|ty| decode_type(ty, &what("value")))
+                    .transpose()?,
+            });
+        }
+        Ok(StateTypes { root, collections })
+    }
+
+    /// The type of the value at the key, if it is known.
+    pub fn value_type(&self, key: &[u8]) -> Option<&Type> {
+        if key.is_empty() {
+            return self.root.as_ref();
+        }
+        self.collections
+            .get(&collection_prefix(key)?)?
+            .value
+            .as_ref()
+    }
+}
+
+/// The collection prefix of a key, if the key belongs to a collection.
+fn collection_prefix(key: &[u8]) -> Option<u64> {
+    let prefix = u64::from_le_bytes(key.get(..8)?.try_into().ok()?);
+    if prefix > 1 {
+        Some(prefix)
+    } else {
+        None
+    }
+}
+
+/// The bytes decoded using the type, or as hex if there is no type or the
+/// bytes are not exactly a value of the type.
+pub fn decode(bytes: &[u8], ty: Option<&Type>) -> Value {
+    if let Some(ty) = ty {
+        let mut cursor = std::io::Cursor::new(bytes);
+        if let Ok(value) = ty.to_json(&mut cursor) {
+            if cursor.position() as usize == bytes.len() {
+                return value;
+            }
+        }
+    }
+    Value::String(hex::encode(bytes))
+}
+
+/// An entry of the state, decoded if possible.
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    pub key:   Value,
+    pub value: Value,
+}
+
+/// The entries of a collection.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Collection {
+    pub prefix:  u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name:    Option<String>,
+    /// The entries with the keys without the prefix.
+    pub entries: Vec<Entry>,
+}
+
+/// The state grouped by collections.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupedState {
+    /// The value at the empty key.
+    pub root:                   Option<Value>,
+    pub next_collection_prefix: Option<u64>,
+    pub collections:            Vec<Collection>,
+    /// The entries that do not follow the conventions of `concordium-std`.
+    pub other:                  Vec<Entry>,
+}
+
+/// Group the entries of the state with keys that start with the prefix by the
+/// collections they belong to, and decode them using the types.
+pub fn group(
+    state: &PersistentState,
+    prefix: &[u8],
+    types: &StateTypes,
+) -> anyhow::Result<GroupedState> {
+    let mut grouped = GroupedState::default();
+    let mut collections = BTreeMap::<u64, Collection>::new();
+    state_json::for_each_entry(state, prefix, |key, value| {
+        if key.is_empty() {
+            grouped.root = Some(decode(value, types.root.as_ref()));
+        } else if key == NEXT_PREFIX_KEY && value.len() == 8 {
+            grouped.next_collection_prefix = value.try_into().ok().map(u64::from_le_bytes);
+        } else if let Some(collection_prefix) = collection_prefix(key) {
+            let collection_types = types.collections.get(&collection_prefix);
+            let collection = collections
+                .entry(collection_prefix)
+                .or_insert_with(|| Collection {
+                    prefix:  collection_prefix,
+                    name:    collection_types.and_then(|types| types.name.clone()),
+                    entries: Vec::new(),
+                });
+            collection.entries.push(Entry {
+                key:   decode(
+                    &key[8..],
+                    collection_types.and_then(|types| types.key.as_ref()),
+                ),
+                value: decode(
+                    value,
+                    collection_types.and_then(|types| types.value.as_ref()),
+                ),
+            });
+        } else {
+            grouped.other.push(Entry {
+                key:   Value::String(hex::encode(key)),
+                value: Value::String(hex::encode(value)),
+            });
+        }
+        Ok(())
+    })?;
+    grouped.collections = collections.into_values().collect();
+    Ok(grouped)
+}
+
+/// The label of an entry in the tree.
+fn entry_label(entry: &Entry) -> String {
+    match &entry.key {
+        Value::String(key) if key.is_empty() => entry.value.to_string(),
+        key => format!("{} = {}", key, entry.value),
+    }
+}
+
+/// The grouped state as a tree like the one printed by `display-state`.
+pub fn tree(grouped: &GroupedState) -> ptree::item::StringItem {
+    let mut builder = TreeBuilder::new("StateRoot".into());
+    if let Some(root) = &grouped.root {
+        builder.add_empty_child(format!("root = {}", root));
+    }
+    if let Some(next) = grouped.next_collection_prefix {
+        builder.add_empty_child(format!("next collection prefix = {}", next));
+    }
+    for collection in grouped.collections.iter() {
+        let name = match &collection.name {
+            Some(name) => format!(" ({})", name),
+            None => String::new(),
+        };
+        builder.begin_child(format!(
+            "collection {}{}: {} entries",
+            collection.prefix,
+            name,
+            collection.entries.len()
+        ));
+        for entry in collection.entries.iter() {
+            builder.add_empty_child(entry_label(entry));
+        }
+        builder.end_child();
+    }
+    if !grouped.other.is_empty() {
+        builder.begin_child(format!("other: {} entries", grouped.other.len()));
+        for entry in grouped.other.iter() {
+            builder.add_empty_child(entry_label(entry));
+        }
+        builder.end_child();
+    }
+    builder.build()
+}
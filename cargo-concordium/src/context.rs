@@ -1,21 +1,44 @@
-use anyhow::anyhow;
+use crate::schema_types::TEMPLATE_ACCOUNT_ADDRESS;
+use anyhow::{anyhow, Context};
 use concordium_contracts_common::{
-    AccountAddress, Address, Amount, ContractAddress, EntrypointName, OwnedEntrypointName,
-    OwnedPolicy, Serial, SlotTime,
+    AccountAddress, Address, Amount, ContractAddress, Duration, EntrypointName,
+    OwnedEntrypointName, OwnedPolicy, Serial, SlotTime,
 };
 use concordium_smart_contract_engine::{v0, v1, ExecResult};
 use serde::Deserialize;
+use std::str::FromStr;
+use structopt::StructOpt;
 
 /// A chain metadata with an optional field.
 /// Used when simulating contracts to allow the user to only specify the
 /// necessary context fields.
 /// The default value is `None` for all `Option` fields.
-#[derive(serde::Deserialize, Default)]
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct ChainMetadataOpt {
     slot_time: Option<SlotTime>,
 }
 
+impl ChainMetadataOpt {
+    /// Set the slot time given on the command line or in a scenario, and
+    /// return it. A duration advances the current slot time.
+    fn advance_slot_time(&mut self, slot_time: SlotTimeArg) -> anyhow::Result<SlotTime> {
+        let slot_time = match slot_time {
+            SlotTimeArg::At(slot_time) => slot_time,
+            SlotTimeArg::Advance(duration) => self
+                .slot_time
+                .context(
+                    "The slot time can only be advanced by a duration if the context has a slot \
+                     time.",
+                )?
+                .checked_add(duration)
+                .context("The advanced slot time is out of range.")?,
+        };
+        self.slot_time = Some(slot_time);
+        Ok(slot_time)
+    }
+}
+
 impl v0::HasChainMetadata for ChainMetadataOpt {
     fn slot_time(&self) -> ExecResult<SlotTime> {
         unwrap_ctx_field(self.slot_time, "metadata.slotTime")
@@ -27,16 +50,33 @@ impl v0::HasChainMetadata for ChainMetadataOpt {
 /// context fields used by the contract.
 /// The default value is `None` for all `Option` fields and the default of
 /// `ChainMetadataOpt` for `metadata`.
-#[derive(serde::Deserialize, Default)]
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct InitContextOpt {
     #[serde(default)]
     metadata:        ChainMetadataOpt,
     init_origin:     Option<AccountAddress>,
-    #[serde(default, deserialize_with = "deserialize_policy_bytes_from_json")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_policy_bytes_from_json",
+        serialize_with = "serialize_policy_bytes_as_hex",
+        rename(serialize = "senderPoliciesHex")
+    )]
     sender_policies: Option<Vec<u8>>,
 }
 
+impl InitContextOpt {
+    /// Set the account that initializes the instance.
+    pub(crate) fn set_init_origin(&mut self, init_origin: AccountAddress) {
+        self.init_origin = Some(init_origin);
+    }
+
+    /// Set or advance the slot time, returning the new slot time.
+    pub(crate) fn advance_slot_time(&mut self, slot_time: SlotTimeArg) -> anyhow::Result<SlotTime> {
+        self.metadata.advance_slot_time(slot_time)
+    }
+}
+
 impl v0::HasInitContext for InitContextOpt {
     type MetadataType = ChainMetadataOpt;
 
@@ -54,6 +94,25 @@ impl v0::HasInitContext for InitContextOpt {
     }
 }
 
+/// Read an amount in a scenario, rehearsal or model file, given in CCD as a
+/// string, such as `"12.5"`, which is parsed like `--amount`.
+pub(crate) fn ccd<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+    let amount = String::deserialize(deserializer)?;
+    amount.parse().map_err(|_| {
+        serde::de::Error::custom(format!(
+            "invalid amount '{}', give it in CCD with at most 6 decimals, such as \"12.5\"",
+            amount
+        ))
+    })
+}
+
+/// Read an optional amount given in CCD.
+pub(crate) fn optional_ccd<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Amount>, D::Error> {
+    ccd(deserializer).map(Some)
+}
+
 /// Serde deserializer for Option<Address>.
 /// Introduced to avoid breaking changes when the serde implementation for
 /// Address was changed to match the node.
@@ -76,12 +135,32 @@ fn deserialize_optional_address<'de, D: serde::de::Deserializer<'de>>(
     Ok(option)
 }
 
+/// Serde serializer for Option<Address> in the format read by
+/// [`deserialize_optional_address`].
+fn serialize_optional_address<S: serde::Serializer>(
+    address: &Option<Address>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(serde::Serialize)]
+    #[serde(tag = "type", content = "address", rename_all = "lowercase")]
+    enum AddressWrapper<'a> {
+        Account(&'a AccountAddress),
+        Contract(&'a ContractAddress),
+    }
+
+    let wrapped = address.as_ref().map(|address| match address {
+        Address::Account(address) => AddressWrapper::Account(address),
+        Address::Contract(address) => AddressWrapper::Contract(address),
+    });
+    serde::Serialize::serialize(&wrapped, serializer)
+}
+
 /// A receive context with optional fields.
 /// Used when simulating contracts to allow the user to only specify the
 /// context fields used by the contract.
 /// The default value is `None` for all `Option` fields and the default of
 /// `ChainMetadataOpt` for `metadata`.
-#[derive(serde::Deserialize, Default)]
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct ReceiveContextOpt {
     #[serde(default)]
@@ -90,10 +169,18 @@ pub(crate) struct ReceiveContextOpt {
     self_address:            Option<ContractAddress>,
     // This is pub(crate) because it is overwritten when `--balance` is used.
     pub(crate) self_balance: Option<Amount>,
-    #[serde(deserialize_with = "deserialize_optional_address")]
+    #[serde(
+        deserialize_with = "deserialize_optional_address",
+        serialize_with = "serialize_optional_address"
+    )]
     sender:                  Option<Address>,
     owner:                   Option<AccountAddress>,
-    #[serde(default, deserialize_with = "deserialize_policy_bytes_from_json")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_policy_bytes_from_json",
+        serialize_with = "serialize_policy_bytes_as_hex",
+        rename(serialize = "senderPoliciesHex")
+    )]
     sender_policies:         Option<Vec<u8>>,
 }
 
@@ -145,7 +232,7 @@ fn unwrap_ctx_field<A>(opt: Option<A>, name: &str) -> ExecResult<A> {
 /// context fields used by the contract.
 /// The default value is `None` for all `Option` fields and the default of
 /// `ChainMetadataOpt` for `metadata`.
-#[derive(serde::Deserialize, Default)]
+#[derive(serde::Deserialize, serde::Serialize, Default, Clone)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct ReceiveContextV1Opt {
     #[serde(flatten)]
@@ -186,6 +273,50 @@ impl v0::HasReceiveContext for ReceiveContextV1Opt {
     }
 }
 
+impl ReceiveContextV1Opt {
+    /// Set the fields describing the instance that is invoked.
+    pub(crate) fn set_instance(
+        &mut self,
+        self_address: ContractAddress,
+        self_balance: Amount,
+        entrypoint: OwnedEntrypointName,
+    ) {
+        self.common.self_address = Some(self_address);
+        self.common.self_balance = Some(self_balance);
+        self.entrypoint = Some(entrypoint);
+    }
+
+    /// Set the account that sends the transaction, which is both the sender
+    /// and the invoker of the call.
+    pub(crate) fn set_sender_account(&mut self, account: AccountAddress) {
+        self.common.sender = Some(Address::Account(account));
+        self.common.invoker = Some(account);
+    }
+
+    /// Set the owner of the invoked instance.
+    pub(crate) fn set_owner(&mut self, owner: AccountAddress) { self.common.owner = Some(owner); }
+
+    /// Set or advance the slot time, returning the new slot time.
+    pub(crate) fn advance_slot_time(&mut self, slot_time: SlotTimeArg) -> anyhow::Result<SlotTime> {
+        self.common.metadata.advance_slot_time(slot_time)
+    }
+
+    /// The context of a call that the invoked instance makes to another
+    /// instance with the given owner, or the same owner if none is given.
+    pub(crate) fn for_call(&self, owner: Option<AccountAddress>) -> anyhow::Result<Self> {
+        let caller = self
+            .common
+            .self_address
+            .context("The context needs the address of the invoked contract to call instances.")?;
+        let mut ctx = self.clone();
+        ctx.common.sender = Some(Address::Contract(caller));
+        if let Some(owner) = owner {
+            ctx.common.owner = Some(owner);
+        }
+        Ok(ctx)
+    }
+}
+
 impl v1::HasReceiveContext for ReceiveContextV1Opt {
     fn entrypoint(&self) -> ExecResult<EntrypointName> {
         let ep = unwrap_ctx_field(self.entrypoint.as_ref(), "entrypoint")?;
@@ -218,3 +349,270 @@ fn deserialize_policy_bytes_from_json<'de, D: serde::de::Deserializer<'de>>(
         Ok(None)
     }
 }
+
+/// Serde serializer for the policy bytes produced by
+/// [`deserialize_policy_bytes_from_json`]. The policies cannot be turned back
+/// into JSON, so they are written as hex encoded bytes.
+fn serialize_policy_bytes_as_hex<S: serde::Serializer>(
+    policies: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(&policies.as_ref().map(hex::encode), serializer)
+}
+
+/// Parse a value given on the command line in the same JSON representation as
+/// in context files.
+fn from_json_string<A: serde::de::DeserializeOwned>(s: &str) -> anyhow::Result<A> {
+    Ok(serde_json::from_value(serde_json::Value::String(
+        s.to_string(),
+    ))?)
+}
+
+/// A contract address given on the command line as `<index,subindex>`. The
+/// brackets and the subindex can be omitted.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ContractAddressArg(pub(crate) ContractAddress);
+
+impl FromStr for ContractAddressArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let inner = s.trim().trim_start_matches('<').trim_end_matches('>');
+        let (index, subindex) = inner.split_once(',').unwrap_or((inner, "0"));
+        let index = index
+            .trim()
+            .parse()
+            .context("Invalid index of contract address.")?;
+        let subindex = subindex
+            .trim()
+            .parse()
+            .context("Invalid subindex of contract address.")?;
+        Ok(ContractAddressArg(ContractAddress::new(index, subindex)))
+    }
+}
+
+/// An address given on the command line, either an account address or a
+/// contract address of the form `<index,subindex>`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AddressArg(Address);
+
+impl FromStr for AddressArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with('<') || s.contains(',') || s.parse::<u64>().is_ok() {
+            Ok(AddressArg(Address::Contract(
+                s.parse::<ContractAddressArg>()?.0,
+            )))
+        } else {
+            Ok(AddressArg(Address::Account(
+                s.parse::<AccountAddressArg>()?.0,
+            )))
+        }
+    }
+}
+
+/// An account address given on the command line.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AccountAddressArg(AccountAddress);
+
+impl FromStr for AccountAddressArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(AccountAddressArg(
+            from_json_string(s).context("Invalid account address.")?,
+        ))
+    }
+}
+
+/// A slot time given on the command line or in a scenario, either in RFC 3339
+/// format, as milliseconds since the Unix epoch, or as a duration such as
+/// `+1d 2h` that advances the current slot time.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum SlotTimeArg {
+    At(SlotTime),
+    Advance(Duration),
+}
+
+impl FromStr for SlotTimeArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(duration) = s.strip_prefix('+') {
+            return Ok(SlotTimeArg::Advance(
+                from_json_string(duration.trim())
+                    .context("Invalid duration. Use a duration such as `+1d 2h 30m 15s`.")?,
+            ));
+        }
+        match s.parse::<u64>() {
+            Ok(millis) => Ok(SlotTimeArg::At(SlotTime::from_timestamp_millis(millis))),
+            Err(_) => Ok(SlotTimeArg::At(from_json_string(s).context(
+                "Invalid slot time. Use RFC 3339, milliseconds or a duration such as `+1h`.",
+            )?)),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SlotTimeArg {
+    fn deserialize<D: serde::de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Context fields given on the command line. They override the fields of the
+/// context file, so that simple simulations do not need one.
+#[derive(Debug, Clone, Default, StructOpt)]
+pub(crate) struct ContextOverrides {
+    #[structopt(
+        name = "sender",
+        long = "sender",
+        help = "The sender of the invocation, either an account address or a contract address of \
+                the form `<index,subindex>`. For init functions, this is the account that \
+                initializes the contract."
+    )]
+    sender:          Option<AddressArg>,
+    #[structopt(
+        name = "sender-contract",
+        long = "sender-contract",
+        conflicts_with = "sender",
+        help = "A contract address of the form `<index,subindex>` that is the sender of the \
+                update, as when the instance is called by another contract. Unless an invoker is \
+                given, the owner of the instance is used as the invoker. This only applies to \
+                updates."
+    )]
+    sender_contract: Option<ContractAddressArg>,
+    #[structopt(
+        name = "invoker",
+        long = "invoker",
+        help = "The account that invoked the transaction. This only applies to updates."
+    )]
+    invoker:         Option<AccountAddressArg>,
+    #[structopt(
+        name = "owner",
+        long = "owner",
+        help = "The owner of the contract instance. This only applies to updates."
+    )]
+    owner:           Option<AccountAddressArg>,
+    #[structopt(
+        name = "self-address",
+        long = "self-address",
+        help = "The address of the contract instance, of the form `<index,subindex>`. This only \
+                applies to updates."
+    )]
+    self_address:    Option<ContractAddressArg>,
+    #[structopt(
+        name = "slot-time",
+        long = "slot-time",
+        help = "The slot time of the block, in RFC 3339 format, as milliseconds since the Unix \
+                epoch, or as a duration such as `+1d 2h` that advances the slot time of the \
+                context file, or of the last invocation recorded in --chain-dir."
+    )]
+    slot_time:       Option<SlotTimeArg>,
+}
+
+impl ContextOverrides {
+    /// The address of the invoked instance, if given.
+    pub(crate) fn self_address(&self) -> Option<ContractAddress> {
+        self.self_address.map(|address| address.0)
+    }
+
+    /// The sender if it is an account, which is the owner of instances it
+    /// initializes.
+    pub(crate) fn sender_account(&self) -> Option<AccountAddress> {
+        if self.sender_contract.is_some() {
+            return None;
+        }
+        match self.sender {
+            Some(AddressArg(Address::Account(address))) => Some(address),
+            _ => None,
+        }
+    }
+
+    /// Use the owner unless one is given.
+    pub(crate) fn default_owner(&mut self, owner: AccountAddress) {
+        self.owner.get_or_insert(AccountAddressArg(owner));
+    }
+
+    /// The slot time, if it is given as a point in time.
+    pub(crate) fn slot_time(&self) -> Option<SlotTime> {
+        match self.slot_time {
+            Some(SlotTimeArg::At(slot_time)) => Some(slot_time),
+            _ => None,
+        }
+    }
+
+    /// Turn a slot time given as a duration into the point in time that
+    /// follows the previous slot time by the duration.
+    pub(crate) fn advance_slot_time_from(&mut self, previous: SlotTime) -> anyhow::Result<()> {
+        if let Some(slot_time) = self.slot_time.as_mut() {
+            let mut metadata = ChainMetadataOpt {
+                slot_time: Some(previous),
+            };
+            *slot_time = SlotTimeArg::At(metadata.advance_slot_time(*slot_time)?);
+        }
+        Ok(())
+    }
+}
+
+impl InitContextOpt {
+    /// Override fields with those given on the command line. Returns an error
+    /// if the sender is a contract, since only accounts initialize contracts.
+    pub(crate) fn apply(&mut self, overrides: &ContextOverrides) -> anyhow::Result<()> {
+        if let Some(slot_time) = overrides.slot_time {
+            self.metadata.advance_slot_time(slot_time)?;
+        }
+        match overrides.sender {
+            Some(AddressArg(Address::Account(address))) => self.init_origin = Some(address),
+            Some(AddressArg(Address::Contract(_))) => {
+                anyhow::bail!("The sender of an init function must be an account.")
+            }
+            None => {}
+        }
+        anyhow::ensure!(
+            overrides.sender_contract.is_none(),
+            "The sender of an init function must be an account."
+        );
+        Ok(())
+    }
+}
+
+impl ReceiveContextOpt {
+    /// Override fields with those given on the command line. Returns an error
+    /// if the slot time is advanced but the context has none.
+    pub(crate) fn apply(&mut self, overrides: &ContextOverrides) -> anyhow::Result<()> {
+        if let Some(slot_time) = overrides.slot_time {
+            self.metadata.advance_slot_time(slot_time)?;
+        }
+        if let Some(sender) = overrides.sender {
+            self.sender = Some(sender.0);
+        }
+        if let Some(invoker) = overrides.invoker {
+            self.invoker = Some(invoker.0);
+        }
+        if let Some(owner) = overrides.owner {
+            self.owner = Some(owner.0);
+        }
+        if let Some(self_address) = overrides.self_address {
+            self.self_address = Some(self_address.0);
+        }
+        if let Some(sender) = overrides.sender_contract {
+            self.sender = Some(Address::Contract(sender.0));
+            // The call by the contract is part of a transaction sent by an
+            // account, which is taken to be the owner if none is given.
+            if self.invoker.is_none() {
+                self.invoker = match self.owner {
+                    Some(owner) => Some(owner),
+                    None => Some(
+                        TEMPLATE_ACCOUNT_ADDRESS
+                            .parse()
+                            .map_err(|_| anyhow!("Invalid template account address."))?,
+                    ),
+                };
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,405 @@
+//! Projection of the costs of using a contract from a usage profile.
+//!
+//! Representative calls of each entrypoint in the profile are simulated on an
+//! instance of the contract, and the measured energy and state growth are
+//! extrapolated to the number of calls per day given by the profile.
+//!
+//! The cost of a single invocation is also estimated after each `run`.
+use crate::{
+    context::{optional_ccd, InitContextOpt, ReceiveContextV1Opt},
+    schema_types::{encode_parameter, ContractSchemaView},
+    sim::Simulator,
+};
+use ansi_term::Style;
+use anyhow::{ensure, Context};
+use concordium_contracts_common::{Amount, ContractAddress};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+/// The number of interpreter energy units that make up one NRG.
+pub const INTERPRETER_ENERGY_PER_NRG: u64 = 1000;
+/// The NRG charged per signature on a transaction.
+const NRG_PER_SIGNATURE: u64 = 100;
+/// The NRG charged per byte of a transaction, including the header.
+const NRG_PER_TRANSACTION_BYTE: u64 = 1;
+/// The size of a transaction header in bytes.
+const TRANSACTION_HEADER_SIZE: u64 = 60;
+/// The NRG charged per byte of additional contract state.
+const NRG_PER_STATE_BYTE: u64 = 1;
+/// The base NRG of an `Update` transaction, which is charged in addition to the
+/// execution, the state and the size of the transaction.
+const UPDATE_BASE_NRG: u64 = 300;
+/// The NRG charged per byte of the Wasm module of a `DeployModule`
+/// transaction is one tenth.
+const DEPLOY_BYTES_PER_NRG: u64 = 10;
+
+/// The NRG of a `DeployModule` transaction with a single signature for the
+/// versioned module of the given size, i.e., including the version and the
+/// length of the module.
+pub fn deploy_module_nrg(versioned_module_len: usize) -> u64 {
+    let versioned_module_len = versioned_module_len as u64;
+    // The payload consists of a tag followed by the versioned module.
+    let payload_size = 1 + versioned_module_len;
+    NRG_PER_SIGNATURE
+        + NRG_PER_TRANSACTION_BYTE * (TRANSACTION_HEADER_SIZE + payload_size)
+        + versioned_module_len.saturating_sub(8) / DEPLOY_BYTES_PER_NRG
+}
+
+/// The NRG of an `Update` transaction with the given number of signatures,
+/// calling the entrypoint of the contract with a parameter of the given size,
+/// other than the execution and the additional state.
+fn update_transaction_nrg(
+    signatures: u64,
+    contract: &str,
+    entrypoint: &str,
+    parameter_len: usize,
+) -> u64 {
+    // The payload of an update consists of a tag, the amount, the contract
+    // address, and the length prefixed receive name and parameter.
+    let receive_name_len = (contract.len() + 1 + entrypoint.len()) as u64;
+    let payload_size = 1 + 8 + 16 + 2 + receive_name_len + 2 + parameter_len as u64;
+    UPDATE_BASE_NRG
+        + NRG_PER_SIGNATURE * signatures
+        + NRG_PER_TRANSACTION_BYTE * (TRANSACTION_HEADER_SIZE + payload_size)
+}
+
+/// Convert NRG to CCD with the exchange rates from NRG to euro and from euro
+/// to microCCD.
+pub fn nrg_to_ccd(nrg: f64, euro_per_nrg: f64, micro_ccd_per_euro: f64) -> f64 {
+    nrg * euro_per_nrg * micro_ccd_per_euro / 1_000_000.0
+}
+
+/// The estimated cost of a single invocation of a contract, excluding the
+/// cost of the transaction itself, such as its size and signatures.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InvocationCost {
+    /// The NRG charged for the interpreter energy used.
+    pub execution_nrg: u64,
+    /// The NRG charged for the additional state.
+    pub state_nrg:     u64,
+    pub total_nrg:     u64,
+    /// The cost in microCCD, if the price of NRG is known.
+    pub micro_ccd:     Option<f64>,
+}
+
+impl InvocationCost {
+    /// The cost of an invocation that used the given interpreter energy and
+    /// produced the given number of bytes of additional state, with the price
+    /// of one NRG in microCCD if known.
+    pub fn new(energy_used: u64, additional_state: u64, micro_ccd_per_nrg: Option<f64>) -> Self {
+        let execution_nrg = energy_used / INTERPRETER_ENERGY_PER_NRG;
+        let state_nrg = additional_state.saturating_mul(NRG_PER_STATE_BYTE);
+        let total_nrg = execution_nrg.saturating_add(state_nrg);
+        InvocationCost {
+            execution_nrg,
+            state_nrg,
+            total_nrg,
+            micro_ccd: micro_ccd_per_nrg.map(|price| total_nrg as f64 * price),
+        }
+    }
+
+    pub fn print(&self) {
+        eprintln!(
+            "Estimated cost is {} NRG: {} NRG for execution and {} NRG for additional state.",
+            self.total_nrg, self.execution_nrg, self.state_nrg
+        );
+        match self.micro_ccd {
+            Some(micro_ccd) => eprintln!("That is {:.0} microCCD.", micro_ccd),
+            None => eprintln!(
+                "Provide `--nrg-price` or `--euro-per-energy` and `--micro-ccd-per-euro` to get \
+                 the cost in microCCD."
+            ),
+        }
+    }
+}
+
+/// A parameter given as JSON, chosen with probability proportional to its
+/// weight.
+#[derive(Deserialize)]
+pub struct WeightedParameter {
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    pub value:  Json,
+}
+
+/// A parameter of random bytes of the given size, chosen with probability
+/// proportional to its weight.
+#[derive(Deserialize)]
+pub struct WeightedSize {
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    pub size:   usize,
+}
+
+fn default_weight() -> u32 { 1 }
+
+/// The expected use of an entrypoint.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntrypointUsage {
+    pub entrypoint:      String,
+    /// The expected number of calls per day.
+    pub per_day:         f64,
+    /// Representative parameters given as JSON.
+    #[serde(default)]
+    pub parameters:      Vec<WeightedParameter>,
+    /// The distribution of sizes of parameters, which are then random bytes.
+    /// This is used if no JSON parameters are given.
+    #[serde(default)]
+    pub parameter_sizes: Vec<WeightedSize>,
+    #[serde(default, deserialize_with = "optional_ccd")]
+    pub amount:          Option<Amount>,
+    pub context:         Option<ReceiveContextV1Opt>,
+}
+
+/// The initialization of the instance the calls are made on.
+#[derive(Default, Deserialize)]
+pub struct ProfileInit {
+    pub parameter: Option<Json>,
+    #[serde(default, deserialize_with = "optional_ccd")]
+    pub amount:    Option<Amount>,
+    #[serde(default)]
+    pub context:   InitContextOpt,
+}
+
+/// A usage profile of a contract.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageProfile {
+    pub contract:           String,
+    #[serde(default)]
+    pub init:               ProfileInit,
+    /// The context used for calls that do not specify one.
+    #[serde(default)]
+    pub context:            ReceiveContextV1Opt,
+    pub calls:              Vec<EntrypointUsage>,
+    /// The number of signatures on each transaction.
+    #[serde(default = "default_signatures")]
+    pub signatures:         u64,
+    /// The exchange rate from NRG to euro, used for computing costs in CCD.
+    pub euro_per_nrg:       Option<f64>,
+    /// The exchange rate from euro to microCCD, used for computing costs in
+    /// CCD.
+    pub micro_ccd_per_euro: Option<f64>,
+}
+
+fn default_signatures() -> u64 { 1 }
+
+/// Choose an index with probability proportional to the weights.
+fn choose_weighted(rng: &mut SmallRng, weights: &[u32]) -> usize {
+    let total: u64 = weights.iter().map(|w| u64::from(*w)).sum();
+    if total == 0 {
+        return 0;
+    }
+    let mut point = rng.gen_range(0, total);
+    for (i, weight) in weights.iter().enumerate() {
+        if point < u64::from(*weight) {
+            return i;
+        }
+        point -= u64::from(*weight);
+    }
+    weights.len() - 1
+}
+
+/// The measured cost of an entrypoint.
+struct EntrypointCost<'a> {
+    usage:            &'a EntrypointUsage,
+    /// Average NRG per call of the transaction, i.e., the base cost, the size
+    /// and the signatures.
+    transaction_nrg:  f64,
+    /// Average NRG per call for the execution.
+    execution_nrg:    f64,
+    /// Average NRG per call for the additional state.
+    state_nrg:        f64,
+    /// Average additional state per call in bytes.
+    additional_state: f64,
+    /// The number of sampled calls that did not succeed.
+    failures:         usize,
+}
+
+impl<'a> EntrypointCost<'a> {
+    /// Average NRG per call in total.
+    fn nrg(&self) -> f64 { self.transaction_nrg + self.execution_nrg + self.state_nrg }
+}
+
+/// Simulate the usage profile and print the projected costs over the given
+/// number of days.
+pub fn estimate_costs(
+    profile: &UsageProfile,
+    sim: &Simulator,
+    schema: &ContractSchemaView,
+    samples: usize,
+    days: u64,
+    seed: u64,
+) -> anyhow::Result<()> {
+    ensure!(
+        samples > 0,
+        "At least one sample per entrypoint is required."
+    );
+    let init_parameter = encode_parameter(
+        profile.init.parameter.as_ref(),
+        schema.init.and_then(|f| f.parameter),
+    )?;
+    let (result, instance) = sim.init(
+        &profile.contract,
+        ContractAddress::new(0, 0),
+        profile.init.context.clone(),
+        profile.init.amount.unwrap_or_else(Amount::zero),
+        &init_parameter,
+    )?;
+    let mut instance =
+        instance.with_context(|| format!("Initialization of the contract {}.", result.outcome))?;
+    let mut rng = SmallRng::seed_from_u64(seed);
+
+    let mut costs = Vec::new();
+    for usage in profile.calls.iter() {
+        let parameter_type = schema
+            .receive
+            .get(usage.entrypoint.as_str())
+            .and_then(|f| f.parameter);
+        let mut transaction_nrg = 0;
+        let mut execution_nrg = 0;
+        let mut state_nrg = 0;
+        let mut additional_state = 0;
+        let mut failures = 0;
+        for _ in 0..samples {
+            let parameter = if !usage.parameters.is_empty() {
+                let weights = usage
+                    .parameters
+                    .iter()
+                    .map(|p| p.weight)
+                    .collect::<Vec<_>>();
+                let chosen = &usage.parameters[choose_weighted(&mut rng, &weights)];
+                encode_parameter(Some(&chosen.value), parameter_type)?
+            } else if !usage.parameter_sizes.is_empty() {
+                let weights = usage
+                    .parameter_sizes
+                    .iter()
+                    .map(|p| p.weight)
+                    .collect::<Vec<_>>();
+                let size = usage.parameter_sizes[choose_weighted(&mut rng, &weights)].size;
+                (0..size).map(|_| rng.gen()).collect()
+            } else {
+                Vec::new()
+            };
+            let result = sim.update(
+                &mut instance,
+                &usage.entrypoint,
+                usage
+                    .context
+                    .clone()
+                    .unwrap_or_else(|| profile.context.clone()),
+                usage.amount.unwrap_or_else(Amount::zero),
+                &parameter,
+            )?;
+            if !result.outcome.is_success() {
+                failures += 1;
+            }
+            let cost = InvocationCost::new(result.energy_used, result.additional_state, None);
+            transaction_nrg += update_transaction_nrg(
+                profile.signatures,
+                &profile.contract,
+                &usage.entrypoint,
+                parameter.len(),
+            );
+            execution_nrg += cost.execution_nrg;
+            state_nrg += cost.state_nrg;
+            additional_state += result.additional_state;
+        }
+        let average = |total: u64| total as f64 / samples as f64;
+        costs.push(EntrypointCost {
+            usage,
+            transaction_nrg: average(transaction_nrg),
+            execution_nrg: average(execution_nrg),
+            state_nrg: average(state_nrg),
+            additional_state: average(additional_state),
+            failures,
+        });
+    }
+
+    let to_ccd = match (profile.euro_per_nrg, profile.micro_ccd_per_euro) {
+        (Some(euro_per_nrg), Some(micro_ccd_per_euro)) => {
+            Some(move |nrg: f64| nrg_to_ccd(nrg, euro_per_nrg, micro_ccd_per_euro))
+        }
+        _ => None,
+    };
+
+    eprintln!("{}", Style::new().bold().paint("Cost per entrypoint:"));
+    eprintln!(
+        "  {:<24} {:>10} {:>12} {:>14} {:>14} {:>14}",
+        "entrypoint", "calls/day", "NRG/call", "NRG/day", "state B/day", "state NRG/day"
+    );
+    let mut nrg_per_day = 0.0;
+    let mut state_per_day = 0.0;
+    let mut state_nrg_per_day = 0.0;
+    for cost in costs.iter() {
+        let daily_nrg = cost.nrg() * cost.usage.per_day;
+        let daily_state = cost.additional_state * cost.usage.per_day;
+        let daily_state_nrg = cost.state_nrg * cost.usage.per_day;
+        nrg_per_day += daily_nrg;
+        state_per_day += daily_state;
+        state_nrg_per_day += daily_state_nrg;
+        eprintln!(
+            "  {:<24} {:>10} {:>12.1} {:>14.0} {:>14.0} {:>14.0}",
+            cost.usage.entrypoint,
+            cost.usage.per_day,
+            cost.nrg(),
+            daily_nrg,
+            daily_state,
+            daily_state_nrg
+        );
+        eprintln!(
+            "    {:.1} NRG for the transaction, {:.1} NRG for execution and {:.1} NRG for \
+             additional state per call",
+            cost.transaction_nrg, cost.execution_nrg, cost.state_nrg
+        );
+        if cost.failures > 0 {
+            eprintln!(
+                "{}",
+                crate::WARNING_STYLE.paint(format!(
+                    "    {} of {} sampled calls did not succeed, so the estimate may be off.",
+                    cost.failures, samples
+                ))
+            );
+        }
+        if let Some(to_ccd) = to_ccd {
+            eprintln!("    {:.6} CCD per call", to_ccd(cost.nrg()));
+        }
+    }
+
+    eprintln!("\n{}", Style::new().bold().paint("Projection:"));
+    let mut checkpoints = [1, 7, 30, 90, 365]
+        .iter()
+        .copied()
+        .filter(|d| *d < days)
+        .collect::<Vec<_>>();
+    checkpoints.push(days);
+    for day in checkpoints {
+        let nrg = nrg_per_day * day as f64;
+        let state = state_per_day * day as f64;
+        let state_nrg = state_nrg_per_day * day as f64;
+        match to_ccd {
+            Some(to_ccd) => eprintln!(
+                "  after {:>4} days: {:>16.0} NRG ({:.2} CCD) paid by users, {:>12.0} B of \
+                 additional contract state costing {:.0} NRG ({:.2} CCD)",
+                day,
+                nrg,
+                to_ccd(nrg),
+                state,
+                state_nrg,
+                to_ccd(state_nrg)
+            ),
+            None => eprintln!(
+                "  after {:>4} days: {:>16.0} NRG paid by users, {:>12.0} B of additional \
+                 contract state costing {:.0} NRG",
+                day, nrg, state, state_nrg
+            ),
+        }
+    }
+    if to_ccd.is_none() {
+        eprintln!("Provide `euroPerNrg` and `microCcdPerEuro` in the profile to get costs in CCD.");
+    }
+    Ok(())
+}
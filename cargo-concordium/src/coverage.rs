@@ -0,0 +1,211 @@
+//! Behavioral coverage of contracts: which contracts and entrypoints were
+//! invoked, and which event and error variants were observed.
+//!
+//! Coverage data is accumulated in a JSON file across invocations, so that a
+//! flow consisting of several invocations can be measured as a whole, and is
+//! reported against the contracts exported by a module and its schema.
+use crate::{
+    build::ModuleExports,
+    schema_types::{variant_names, variant_of, ContractSchemaView},
+};
+use ansi_term::{Color, Style};
+use anyhow::Context;
+use concordium_contracts_common::schema::{Type, VersionedModuleSchema};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+/// Coverage observed for a single contract.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractCoverage {
+    /// The number of times the contract was initialized.
+    pub init:        u64,
+    /// The number of times each entrypoint was invoked.
+    pub entrypoints: BTreeMap<String, u64>,
+    /// The names of the event variants that were logged.
+    pub events:      BTreeSet<String>,
+    /// The names of the error variants that were returned on rejection.
+    pub errors:      BTreeSet<String>,
+}
+
+/// Coverage observed for all contracts, by contract name.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CoverageData {
+    pub contracts: BTreeMap<String, ContractCoverage>,
+}
+
+impl CoverageData {
+    /// Load coverage data from a file. A file that does not exist is treated as
+    /// empty coverage data.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let bytes = fs::read(path).context("Could not read coverage data.")?;
+        serde_json::from_slice(&bytes).context("Could not parse coverage data.")
+    }
+
+    /// Write the coverage data to a file.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Unable to create directory for coverage data.")?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?).context("Could not write coverage data.")
+    }
+
+    fn contract(&mut self, contract_name: &str) -> &mut ContractCoverage {
+        self.contracts.entry(contract_name.to_string()).or_default()
+    }
+
+    /// Record an initialization of the contract.
+    pub fn record_init(&mut self, contract_name: &str) { self.contract(contract_name).init += 1; }
+
+    /// Record an invocation of an entrypoint of the contract.
+    pub fn record_entrypoint(&mut self, contract_name: &str, entrypoint: &str) {
+        *self
+            .contract(contract_name)
+            .entrypoints
+            .entry(entrypoint.to_string())
+            .or_insert(0) += 1;
+    }
+
+    /// Record the variants of logged events, if an event schema is available.
+    pub fn record_events<'a>(
+        &mut self,
+        contract_name: &str,
+        events: impl Iterator<Item = &'a [u8]>,
+        event_schema: Option<&Type>,
+    ) {
+        if let Some(schema) = event_schema {
+            for event in events {
+                if let Some(variant) = variant_of(schema, event) {
+                    self.contract(contract_name)
+                        .events
+                        .insert(variant.to_string());
+                }
+            }
+        }
+    }
+
+    /// Record the variant of an error returned on rejection, if an error schema
+    /// is available.
+    pub fn record_error(&mut self, contract_name: &str, error: &[u8], error_schema: Option<&Type>) {
+        if let Some(variant) = error_schema.and_then(|schema| variant_of(schema, error)) {
+            self.contract(contract_name)
+                .errors
+                .insert(variant.to_string());
+        }
+    }
+}
+
+/// Print how many of the items were covered and list the ones that were not.
+/// Returns the number of covered and total items.
+fn report_items<'a>(
+    label: &str,
+    all: impl Iterator<Item = &'a str>,
+    is_covered: impl Fn(&str) -> bool,
+) -> (usize, usize) {
+    let mut covered = 0;
+    let mut uncovered = Vec::new();
+    for item in all {
+        if is_covered(item) {
+            covered += 1;
+        } else {
+            uncovered.push(item);
+        }
+    }
+    let total = covered + uncovered.len();
+    if total == 0 {
+        return (0, 0);
+    }
+    eprintln!("    {:<12} {}/{}", label, covered, total);
+    for item in uncovered {
+        eprintln!("      {} {}", Color::Red.paint("not covered:"), item);
+    }
+    (covered, total)
+}
+
+/// Print a coverage report for the contracts of a module. Event and error
+/// variants are only reported if a schema is available. Returns the overall
+/// coverage as a percentage.
+pub fn print_report(
+    data: &CoverageData,
+    exports: &ModuleExports,
+    schema: Option<&VersionedModuleSchema>,
+) -> f64 {
+    let empty = ContractCoverage::default();
+    let mut covered = 0;
+    let mut total = 0;
+    for contract_name in exports.contracts.iter() {
+        let coverage = data.contracts.get(contract_name).unwrap_or(&empty);
+        let view = schema.and_then(|s| ContractSchemaView::new(s, contract_name));
+        eprintln!(
+            "{}",
+            Style::new()
+                .bold()
+                .paint(format!("Contract '{}':", contract_name))
+        );
+
+        let mut add = |(c, t): (usize, usize)| {
+            covered += c;
+            total += t;
+        };
+        add(report_items("init", std::iter::once("init"), |_| {
+            coverage.init > 0
+        }));
+        let entrypoints = exports.entrypoints_of(contract_name);
+        add(report_items(
+            "entrypoints",
+            entrypoints.iter().map(String::as_str),
+            |e| coverage.entrypoints.contains_key(e),
+        ));
+        if let Some(event) = view.as_ref().and_then(|v| v.event) {
+            add(report_items(
+                "events",
+                variant_names(event).into_iter(),
+                |v| coverage.events.contains(v),
+            ));
+        }
+        // Error types are typically shared between entrypoints, so each variant
+        // is only reported once.
+        let error_variants = view
+            .as_ref()
+            .map(|v| {
+                v.init
+                    .iter()
+                    .chain(v.receive.values())
+                    .filter_map(|f| f.error)
+                    .flat_map(variant_names)
+                    .collect::<BTreeSet<_>>()
+            })
+            .unwrap_or_default();
+        add(report_items("errors", error_variants.into_iter(), |v| {
+            coverage.errors.contains(v)
+        }));
+    }
+    for contract_name in data.contracts.keys() {
+        if !exports.contracts.contains(contract_name) {
+            eprintln!(
+                "{}",
+                crate::WARNING_STYLE.paint(format!(
+                    "The coverage data contains contract '{}' which is not in the module.",
+                    contract_name
+                ))
+            );
+        }
+    }
+    let percentage = if total == 0 {
+        100.0
+    } else {
+        100.0 * covered as f64 / total as f64
+    };
+    eprintln!(
+        "\nBehavioral coverage: {}/{} ({:.1}%)",
+        covered, total, percentage
+    );
+    percentage
+}
@@ -0,0 +1,254 @@
+//! Overrides of the signature verification of V1 contracts in `cargo
+//! concordium run update`.
+//!
+//! Entrypoints gated by signatures, such as the `permit` entrypoint of CIS-3
+//! contracts, cannot be exercised locally without signatures made with the
+//! actual keys. The overrides force the result of verifying signatures, either
+//! of all signatures or of those by given public keys, so that such
+//! entrypoints can be run with made up signatures.
+//!
+//! The host functions are still executed by the engine, so the energy charged
+//! is the same as on the chain, and only their result is replaced. Hashing is
+//! not overridden, since its results can be computed locally. The engine cannot
+//! resume an execution with another host, so an invocation with overrides
+//! cannot be resumed after an interrupt.
+use crate::context::ReceiveContextV1Opt;
+use anyhow::{bail, ensure, Context};
+use concordium_contracts_common::{Amount, ReceiveName};
+use concordium_smart_contract_engine::{
+    constants, v0,
+    v1::{self, CommonFunc, ImportFunc, ProcessedImports},
+    InterpreterEnergy, OutOfEnergy,
+};
+use concordium_wasm::{
+    artifact::{Artifact, CompiledFunction},
+    machine::{ExecutionOutcome, Host, RunResult, RuntimeStack, Value},
+};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// How signatures are verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SignatureMode {
+    /// Signatures are verified, unless they are by an accepted key.
+    Real,
+    /// All signatures are valid.
+    Accept,
+    /// No signature is valid.
+    Reject,
+}
+
+impl Default for SignatureMode {
+    fn default() -> Self { SignatureMode::Real }
+}
+
+impl FromStr for SignatureMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "real" => Ok(SignatureMode::Real),
+            "accept" => Ok(SignatureMode::Accept),
+            "reject" => Ok(SignatureMode::Reject),
+            _ => bail!(
+                "Unknown signature mode '{}'. Use real, accept or reject.",
+                s
+            ),
+        }
+    }
+}
+
+/// A hex encoded public key, either an Ed25519 key of 32 bytes or a compressed
+/// secp256k1 key of 33 bytes.
+#[derive(Debug, Clone)]
+pub(crate) struct PublicKeyArg(Vec<u8>);
+
+impl FromStr for PublicKeyArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let key = hex::decode(s).context("Invalid public key, it must be hex encoded.")?;
+        ensure!(
+            key.len() == 32 || key.len() == 33,
+            "Invalid public key of {} bytes. Ed25519 keys have 32 bytes and secp256k1 keys 33.",
+            key.len()
+        );
+        Ok(PublicKeyArg(key))
+    }
+}
+
+/// Overrides of signature verification given on the command line.
+#[derive(Debug, Clone, Default, StructOpt)]
+pub(crate) struct CryptoOverrides {
+    #[structopt(
+        name = "signatures",
+        long = "signatures",
+        default_value = "real",
+        help = "How the signatures checked by the contract are verified: `real` verifies them, \
+                `accept` makes all of them valid and `reject` makes all of them invalid. This \
+                only applies to updates of V1 contracts, which cannot be interrupted unless the \
+                mode is `real`."
+    )]
+    signatures:    SignatureMode,
+    #[structopt(
+        name = "accept-signatures-of",
+        long = "accept-signatures-of",
+        number_of_values = 1,
+        help = "A hex encoded Ed25519 or secp256k1 public key whose signatures are valid \
+                regardless of what is signed. Can be given multiple times. This only applies to \
+                updates of V1 contracts, which cannot be interrupted with this option."
+    )]
+    accepted_keys: Vec<PublicKeyArg>,
+}
+
+impl CryptoOverrides {
+    /// Whether signature verification is overridden.
+    pub(crate) fn is_active(&self) -> bool {
+        self.signatures != SignatureMode::Real || !self.accepted_keys.is_empty()
+    }
+
+    /// The result of verifying a signature by the key, if it is forced.
+    fn forced_result(&self, key: &[u8]) -> Option<bool> {
+        match self.signatures {
+            SignatureMode::Accept => Some(true),
+            SignatureMode::Reject => Some(false),
+            SignatureMode::Real if self.accepted_keys.iter().any(|k| k.0 == key) => Some(true),
+            SignatureMode::Real => None,
+        }
+    }
+}
+
+/// A verification whose result was forced.
+pub struct ForcedVerification {
+    /// The hex encoded public key of the signature.
+    pub public_key: String,
+    /// Whether the signature was made valid.
+    pub valid:      bool,
+}
+
+/// A host that delegates to the host of the engine and replaces the results of
+/// signature verification as given by the overrides.
+struct OverridingHost<'o, H> {
+    inner:     H,
+    overrides: &'o CryptoOverrides,
+    forced:    Vec<ForcedVerification>,
+}
+
+impl<'o, H: Host<ProcessedImports>> Host<ProcessedImports> for OverridingHost<'o, H> {
+    type Interrupt = H::Interrupt;
+
+    fn tick_initial_memory(&mut self, num_pages: u32) -> RunResult<()> {
+        self.inner.tick_initial_memory(num_pages)
+    }
+
+    fn call(
+        &mut self,
+        f: &ProcessedImports,
+        memory: &mut Vec<u8>,
+        stack: &mut RuntimeStack,
+    ) -> RunResult<Option<Self::Interrupt>> {
+        let (arity, key_len) = match f.tag {
+            ImportFunc::Common(CommonFunc::VerifyEd25519) => (4, 32),
+            ImportFunc::Common(CommonFunc::VerifySecp256k1) => (3, 33),
+            _ => return self.inner.call(f, memory, stack),
+        };
+        // The public key is the first argument, so it is below the others on
+        // the stack. The values are pushed back unchanged for the engine.
+        let mut arguments = (0..arity)
+            .map(|_| unsafe { stack.pop_u64() })
+            .collect::<Vec<_>>();
+        arguments.reverse();
+        for argument in arguments.iter() {
+            stack.push_value(*argument);
+        }
+        let start = arguments[0] as u32 as usize;
+        let key = memory.get(start..start + key_len).map(<[u8]>::to_vec);
+        // The engine verifies the signature and charges for it, and traps if
+        // the arguments are out of bounds.
+        let result = self.inner.call(f, memory, stack)?;
+        let forced = key.and_then(|key| Some((self.overrides.forced_result(&key)?, key)));
+        if let Some((valid, key)) = forced {
+            unsafe { stack.pop_u64() };
+            stack.push_value(u64::from(valid));
+            self.forced.push(ForcedVerification {
+                public_key: hex::encode(key),
+                valid,
+            });
+        }
+        Ok(result)
+    }
+}
+
+/// Execute a receive function with the overrides applied to the state. The
+/// result is that of the engine, except that interrupts are reported as an
+/// error and successful calls are reported to change the state, since the
+/// engine does not expose whether they did. The verifications whose result
+/// was forced are returned as well.
+#[allow(clippy::too_many_arguments)]
+pub fn invoke_receive(
+    artifact: &Artifact<ProcessedImports, CompiledFunction>,
+    receive_ctx: ReceiveContextV1Opt,
+    receive_name: ReceiveName,
+    amount: Amount,
+    parameter: &[u8],
+    energy: InterpreterEnergy,
+    mutable_state: &mut v1::trie::MutableState,
+    params: v1::ReceiveParams,
+    overrides: &CryptoOverrides,
+) -> anyhow::Result<(
+    v1::ReceiveResult<CompiledFunction, ReceiveContextV1Opt>,
+    Vec<ForcedVerification>,
+)> {
+    let mut loader = v1::trie::Loader::new(&[][..]);
+    let inner = mutable_state.get_inner(&mut loader);
+    let instance_state = v1::InstanceState::new(loader, inner);
+    let mut host = OverridingHost {
+        inner: v1::ReceiveHost {
+            energy,
+            stateless: v1::StateLessReceiveHost {
+                activation_frames: constants::MAX_ACTIVATION_FRAMES,
+                logs: v0::Logs::new(),
+                return_value: Vec::new(),
+                parameters: vec![parameter],
+                receive_ctx,
+                params,
+            },
+            state: instance_state,
+        },
+        overrides,
+        forced: Vec::new(),
+    };
+    let outcome = artifact.run(&mut host, receive_name.get_chain_name(), &[Value::I64(
+        amount.micro_ccd() as i64,
+    )]);
+    let remaining_energy = host.inner.energy.energy;
+    let stateless = host.inner.stateless;
+    let result = match outcome {
+        Ok(ExecutionOutcome::Success { result, .. }) => match result {
+            Some(Value::I32(reason)) if reason < 0 => v1::ReceiveResult::Reject {
+                reason,
+                return_value: stateless.return_value,
+                remaining_energy,
+            },
+            Some(Value::I32(_)) => v1::ReceiveResult::Success {
+                logs: stateless.logs,
+                state_changed: true,
+                return_value: stateless.return_value,
+                remaining_energy,
+            },
+            _ => bail!("The receive function did not return an i32."),
+        },
+        Ok(ExecutionOutcome::Interrupted { .. }) => bail!(
+            "The invocation was interrupted, which is not supported when signature verification \
+             is overridden."
+        ),
+        Err(error) if error.downcast_ref::<OutOfEnergy>().is_some() => {
+            v1::ReceiveResult::OutOfEnergy
+        }
+        Err(error) => v1::ReceiveResult::Trap {
+            error,
+            remaining_energy,
+        },
+    };
+    Ok((result, host.forced))
+}
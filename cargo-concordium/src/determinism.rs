@@ -0,0 +1,128 @@
+//! Self-check that the simulation of an invocation is deterministic.
+//!
+//! The invocation is executed twice using the simulator, and the outcome,
+//! return value, events, energy, additional state, resulting state and
+//! balance are compared. The second execution starts from a copy of the state
+//! that is deserialized again, so that it uses a fresh backing store and
+//! loader. Any difference indicates nondeterminism in the contract or the
+//! simulation, which would be a consensus problem on the chain.
+use crate::{
+    context::{InitContextOpt, ReceiveContextV1Opt},
+    sim::{CallResult, Instance, Simulator},
+};
+use ansi_term::{Color, Style};
+use anyhow::Context;
+use concordium_contracts_common::{Amount, ContractAddress};
+use concordium_smart_contract_engine::v1::trie::PersistentState;
+
+/// The observable effects of an execution.
+struct Execution {
+    result:  CallResult,
+    state:   Option<Vec<u8>>,
+    balance: Option<Amount>,
+}
+
+impl Execution {
+    fn new(result: CallResult, instance: Option<&Instance>) -> anyhow::Result<Self> {
+        Ok(Execution {
+            result,
+            state: instance.map(Instance::state_bytes).transpose()?,
+            balance: instance.map(|instance| instance.balance),
+        })
+    }
+}
+
+/// Compare two executions and print the comparison. Returns whether they
+/// are identical.
+fn compare(first: &Execution, second: &Execution) -> bool {
+    let checks = [
+        (
+            "outcome, return value and events",
+            first.result.outcome == second.result.outcome,
+        ),
+        (
+            "interpreter energy",
+            first.result.energy_used == second.result.energy_used,
+        ),
+        (
+            "additional state",
+            first.result.additional_state == second.result.additional_state,
+        ),
+        ("resulting state", first.state == second.state),
+        ("resulting balance", first.balance == second.balance),
+    ];
+    eprintln!("{}", Style::new().bold().paint("Determinism check:"));
+    let mut deterministic = true;
+    for (name, same) in checks.iter() {
+        if *same {
+            eprintln!("  - {} ... {}", name, Color::Green.bold().paint("ok"));
+        } else {
+            deterministic = false;
+            eprintln!("  - {} ... {}", name, Color::Red.bold().paint("FAILED"));
+        }
+    }
+    if !deterministic {
+        eprintln!("  First execution:  {:?}", first.result);
+        eprintln!("  Second execution: {:?}", second.result);
+    }
+    deterministic
+}
+
+/// Execute an initialization twice and compare the executions.
+pub fn check_init(
+    sim: &Simulator,
+    contract_name: &str,
+    ctx: InitContextOpt,
+    amount: Amount,
+    parameter: &[u8],
+) -> anyhow::Result<bool> {
+    let mut executions = Vec::with_capacity(2);
+    for _ in 0..2 {
+        let (result, instance) = sim.init(
+            contract_name,
+            ContractAddress::new(0, 0),
+            ctx.clone(),
+            amount,
+            parameter,
+        )?;
+        executions.push(Execution::new(result, instance.as_ref())?);
+    }
+    Ok(compare(&executions[0], &executions[1]))
+}
+
+/// Execute an update of an instance with the given state twice and compare
+/// the executions.
+pub fn check_update(
+    sim: &Simulator,
+    contract_name: &str,
+    entrypoint: &str,
+    state: &PersistentState,
+    ctx: ReceiveContextV1Opt,
+    amount: Amount,
+    parameter: &[u8],
+) -> anyhow::Result<bool> {
+    let instance = Instance {
+        contract_name: contract_name.to_string(),
+        address:       ctx
+            .common
+            .self_address
+            .unwrap_or_else(|| ContractAddress::new(0, 0)),
+        state:         state.clone(),
+        balance:       ctx.common.self_balance.unwrap_or_else(Amount::zero),
+    };
+    let mut first = instance.clone();
+    let first_result = sim.update(&mut first, entrypoint, ctx.clone(), amount, parameter)?;
+
+    let state_bytes = instance.state_bytes()?;
+    let mut second = Instance {
+        state: PersistentState::deserialize(&mut std::io::Cursor::new(&state_bytes))
+            .context("Could not copy the state.")?,
+        ..instance
+    };
+    let second_result = sim.update(&mut second, entrypoint, ctx, amount, parameter)?;
+
+    Ok(compare(
+        &Execution::new(first_result, Some(&first))?,
+        &Execution::new(second_result, Some(&second))?,
+    ))
+}
@@ -0,0 +1,57 @@
+//! Conventional locations of the inputs of `cargo concordium run`.
+//!
+//! When no context or parameter is given, `run` uses the files at these
+//! locations relative to the root of the package, if they exist:
+//!
+//! - `concordium/init-context.json` for the context of init functions,
+//! - `concordium/receive-context.json` for the context of updates,
+//! - `concordium/params/<entrypoint>.json` for the JSON parameter of an
+//!   entrypoint, and `concordium/params/init_<contract>.json` for the JSON
+//!   parameter of an init function.
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// The directory of the conventional files, relative to the package root.
+const DIRECTORY: &str = "concordium";
+
+/// The root of the package, i.e., the closest ancestor of the current
+/// directory with a `Cargo.toml`.
+fn package_root() -> Option<PathBuf> {
+    let current = env::current_dir().ok()?;
+    current
+        .ancestors()
+        .find(|dir| dir.join("Cargo.toml").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// The file at the given path relative to the conventional directory, if it
+/// exists.
+fn find(relative: &Path) -> Option<PathBuf> {
+    let path = package_root()?.join(DIRECTORY).join(relative);
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// The conventional context file of init functions or updates.
+pub fn context(is_receive: bool) -> Option<PathBuf> {
+    if is_receive {
+        find(Path::new("receive-context.json"))
+    } else {
+        find(Path::new("init-context.json"))
+    }
+}
+
+/// The conventional JSON parameter file of an entrypoint, or of the init
+/// function of the contract if no entrypoint is given.
+pub fn parameter(contract_name: &str, entrypoint: Option<&str>) -> Option<PathBuf> {
+    let file = match entrypoint {
+        Some(entrypoint) => format!("{}.json", entrypoint),
+        None => format!("init_{}.json", contract_name),
+    };
+    find(&Path::new("params").join(file))
+}
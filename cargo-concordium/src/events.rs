@@ -0,0 +1,155 @@
+//! Output of contract events as newline delimited JSON (NDJSON), one record
+//! per event, for developing indexers against simulated executions.
+use anyhow::Context;
+use concordium_contracts_common::{schema::Type, ContractAddress, SlotTime};
+use concordium_smart_contract_engine::v0::{self, HasChainMetadata};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    net::TcpStream,
+};
+
+/// Where event records are written to.
+pub enum EventSink {
+    Stdout,
+    File(BufWriter<File>),
+    Socket(TcpStream),
+}
+
+/// Information about the execution that produced an event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventOrigin<'a> {
+    /// The step of a multi-step execution that produced the event. Single
+    /// invocations are step 0.
+    pub step:             usize,
+    /// The address of the contract instance, if known.
+    pub contract_address: Option<ContractAddress>,
+    pub contract_name:    &'a str,
+    /// The entrypoint that was invoked, or `None` for init functions.
+    pub entrypoint:       Option<&'a str>,
+    /// The slot time of the block the execution happened in, if known.
+    pub slot_time:        Option<SlotTime>,
+}
+
+impl<'a> EventOrigin<'a> {
+    /// Construct the origin of events produced by an init function.
+    pub fn init(contract_name: &'a str, ctx: &impl v0::HasInitContext) -> Self {
+        Self {
+            step: 0,
+            contract_address: None,
+            contract_name,
+            entrypoint: None,
+            slot_time: ctx.metadata().slot_time().ok(),
+        }
+    }
+
+    /// Construct the origin of events produced by an entrypoint.
+    pub fn receive(
+        contract_name: &'a str,
+        entrypoint: &'a str,
+        ctx: &impl v0::HasReceiveContext,
+    ) -> Self {
+        Self {
+            step: 0,
+            contract_address: ctx.self_address().ok().copied(),
+            contract_name,
+            entrypoint: Some(entrypoint),
+            slot_time: ctx.metadata().slot_time().ok(),
+        }
+    }
+}
+
+/// A single event record.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EventRecord<'a> {
+    #[serde(flatten)]
+    origin:      &'a EventOrigin<'a>,
+    /// The index of the event among the events produced by the execution.
+    event_index: usize,
+    /// The event as hex encoded bytes.
+    raw_hex:     String,
+    /// The event decoded using the event schema, if one is available and the
+    /// event could be decoded.
+    decoded:     Option<serde_json::Value>,
+}
+
+impl EventSink {
+    /// Open a sink. The target is either `-` for stdout, `tcp://<host>:<port>`
+    /// for a TCP socket, or a path to a file that will be created.
+    pub fn open(target: &str) -> anyhow::Result<Self> {
+        if target == "-" {
+            Ok(EventSink::Stdout)
+        } else if let Some(address) = target.strip_prefix("tcp://") {
+            let stream = TcpStream::connect(address).with_context(|| {
+                format!("Could not connect to {} for the event stream.", address)
+            })?;
+            Ok(EventSink::Socket(stream))
+        } else {
+            let file = File::create(target)
+                .with_context(|| format!("Could not create event stream file {}.", target))?;
+            Ok(EventSink::File(BufWriter::new(file)))
+        }
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        match self {
+            EventSink::Stdout => {
+                let stdout = std::io::stdout();
+                let mut lock = stdout.lock();
+                writeln!(lock, "{}", line)
+            }
+            EventSink::File(file) => writeln!(file, "{}", line),
+            EventSink::Socket(stream) => writeln!(stream, "{}", line),
+        }
+    }
+
+    /// Write a record for each of the logged events. If an event schema is
+    /// given, it is used to decode the events.
+    pub fn emit_logs(
+        &mut self,
+        origin: &EventOrigin,
+        logs: &v0::Logs,
+        event_schema: Option<&Type>,
+    ) -> anyhow::Result<()> {
+        self.emit_events(origin, logs.iterate(), event_schema)
+    }
+
+    /// Write a record for each of the events, like
+    /// [`emit_logs`](Self::emit_logs), for events that have been collected,
+    /// such as those of a simulated call.
+    pub fn emit_events<E: AsRef<[u8]>>(
+        &mut self,
+        origin: &EventOrigin,
+        events: impl IntoIterator<Item = E>,
+        event_schema: Option<&Type>,
+    ) -> anyhow::Result<()> {
+        for (event_index, item) in events.into_iter().enumerate() {
+            let item = item.as_ref();
+            let decoded = event_schema
+                .and_then(|schema| schema.to_json(&mut std::io::Cursor::new(item)).ok());
+            let record = EventRecord {
+                origin,
+                event_index,
+                raw_hex: hex::encode(item),
+                decoded,
+            };
+            let line = serde_json::to_string(&record).context("Could not encode event record.")?;
+            self.write_line(&line)
+                .context("Could not write to the event stream.")?;
+        }
+        Ok(())
+    }
+
+    /// Flush any buffered records.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        match self {
+            EventSink::Stdout => std::io::stdout().flush(),
+            EventSink::File(file) => file.flush(),
+            EventSink::Socket(stream) => stream.flush(),
+        }
+        .context("Could not flush the event stream.")
+    }
+}
@@ -0,0 +1,178 @@
+//! Expectations of a run given with `cargo concordium run --expect`.
+//!
+//! An expectation file records the outcome an invocation should have, such as
+//! the reject reason, the return value, the events and the maximum energy. The
+//! run fails with a diff of each field that does not match, so that a
+//! simulation can be repeated as a check in CI without writing a test harness.
+use crate::run_report::{ContractValue, Outcome, RunReport};
+use ansi_term::Color;
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::Value;
+use std::path::Path;
+
+/// The expected result of an invocation. The invocation is expected to
+/// succeed if no outcome is given, and the other fields are only checked if
+/// they are given.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Expectations {
+    #[serde(default = "Expectations::default_outcome")]
+    outcome:       Outcome,
+    reject_reason: Option<i32>,
+    /// The return value, or the error value of a rejection. It is compared
+    /// with the value decoded using the schema, or with the hex encoded value.
+    return_value:  Option<Value>,
+    /// All events in the order they are logged, compared like the return
+    /// value.
+    events:        Option<Vec<Value>>,
+    /// The maximum interpreter energy the invocation may use.
+    max_energy:    Option<u64>,
+}
+
+/// Read the expectations, as YAML if the file has a `.yaml` or `.yml`
+/// extension and as JSON otherwise.
+pub fn load(path: &Path) -> anyhow::Result<Expectations> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Could not read the expectations {}.", path.display()))?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_slice(&bytes)
+            .with_context(|| format!("Could not parse the expectations {}.", path.display())),
+        _ => serde_json::from_slice(&bytes)
+            .with_context(|| format!("Could not parse the expectations {}.", path.display())),
+    }
+}
+
+/// The value to compare with the expected value: the hex encoded value if
+/// that is what is expected, and otherwise the decoded value if there is one.
+fn actual_value(value: &ContractValue, expected: Option<&Value>) -> Value {
+    match (&value.decoded, expected) {
+        (_, Some(Value::String(hex))) if *hex == value.raw_hex => Value::String(hex.clone()),
+        (Some(decoded), _) => decoded.clone(),
+        (None, _) => Value::String(value.raw_hex.clone()),
+    }
+}
+
+/// A field whose value differs from the expected one.
+pub struct Mismatch {
+    field:    &'static str,
+    expected: Value,
+    actual:   Value,
+}
+
+impl Expectations {
+    fn default_outcome() -> Outcome { Outcome::Success }
+
+    /// The fields of the report that do not have the expected value.
+    pub fn check(&self, report: &RunReport) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        let mut compare = |field, expected: &Value, actual: Value| {
+            if *expected != actual {
+                mismatches.push(Mismatch {
+                    field,
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        };
+        compare(
+            "outcome",
+            &serde_json::json!(self.outcome),
+            serde_json::json!(report.outcome),
+        );
+        if let Some(reason) = self.reject_reason {
+            compare(
+                "rejectReason",
+                &reason.into(),
+                serde_json::json!(report.reject_reason),
+            );
+        }
+        if let Some(expected) = &self.return_value {
+            let actual = match &report.return_value {
+                Some(value) => actual_value(value, Some(expected)),
+                None => Value::Null,
+            };
+            compare("returnValue", expected, actual);
+        }
+        if let Some(expected) = &self.events {
+            let actual = report
+                .events
+                .iter()
+                .enumerate()
+                .map(|(i, event)| actual_value(event, expected.get(i)))
+                .collect();
+            compare(
+                "events",
+                &Value::Array(expected.clone()),
+                Value::Array(actual),
+            );
+        }
+        if let Some(max_energy) = self.max_energy {
+            match report.energy_used {
+                Some(used) if used <= max_energy => (),
+                used => compare(
+                    "energyUsed",
+                    &format!("at most {}", max_energy).into(),
+                    used.map_or("all of it".into(), |used| used.into()),
+                ),
+            }
+        }
+        mismatches
+    }
+}
+
+/// The lines of the expected and actual text, marked as removed with `-`,
+/// added with `+` or shared, based on their longest common subsequence.
+fn diff_lines<'a>(expected: &'a str, actual: &'a str) -> Vec<(char, &'a str)> {
+    let old = expected.lines().collect::<Vec<_>>();
+    let new = actual.lines().collect::<Vec<_>>();
+    // lengths[i][j] is the length of the longest common subsequence of the
+    // lines from i and from j on.
+    let mut lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = if old[i] == new[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            lines.push((' ', old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            lines.push(('-', old[i]));
+            i += 1;
+        } else {
+            lines.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    lines
+}
+
+/// Print a diff of the expected and actual value of each mismatching field.
+pub fn print_mismatches(mismatches: &[Mismatch]) -> anyhow::Result<()> {
+    eprintln!(
+        "\n{} (- expected, + actual):",
+        Color::Red.paint("The invocation did not meet the expectations")
+    );
+    for mismatch in mismatches {
+        eprintln!("{}:", mismatch.field);
+        let expected = serde_json::to_string_pretty(&mismatch.expected)?;
+        let actual = serde_json::to_string_pretty(&mismatch.actual)?;
+        for (mark, line) in diff_lines(&expected, &actual) {
+            match mark {
+                '-' => eprintln!("{}", Color::Red.paint(format!("- {}", line))),
+                '+' => eprintln!("{}", Color::Green.paint(format!("+ {}", line))),
+                _ => eprintln!("  {}", line),
+            }
+        }
+    }
+    Ok(())
+}
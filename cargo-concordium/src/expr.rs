@@ -0,0 +1,349 @@
+//! A small expression language over integers and booleans, used for
+//! describing models and expectations in declarative test files.
+//!
+//! Expressions consist of integer and boolean literals, variables, the
+//! arithmetic operators `+`, `-`, `*`, `/` and `%`, the comparisons `==`, `!=`,
+//! `<`, `<=`, `>` and `>=`, the boolean operators `&&`, `||` and `!`, and
+//! parentheses. Variables are names consisting of letters, digits, `_` and
+//! `.`, where `.` is used to access fields of JSON values. Like in Rust, `&&`
+//! and `||` only evaluate their right operand if the left one does not decide
+//! the result.
+use anyhow::{bail, ensure, Context};
+use serde_json::Value as Json;
+use std::{collections::BTreeMap, convert::TryFrom};
+
+/// The value of an expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    Int(i128),
+    Bool(bool),
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+impl Value {
+    /// Convert a JSON value to a value. Numbers must be integers, and strings
+    /// are accepted if they contain an integer, since large integers and
+    /// amounts are represented as strings in contract JSON.
+    pub fn from_json(json: &Json) -> Option<Self> {
+        match json {
+            Json::Bool(b) => Some(Value::Bool(*b)),
+            Json::Number(n) => n
+                .as_i64()
+                .map(i128::from)
+                .or_else(|| n.as_u64().map(i128::from))
+                .map(Value::Int),
+            Json::String(s) => s.parse().ok().map(Value::Int),
+            _ => None,
+        }
+    }
+
+    /// Convert the value to JSON.
+    pub fn to_json(self) -> Json {
+        match self {
+            Value::Int(i) => {
+                if let Ok(i) = i64::try_from(i) {
+                    Json::from(i)
+                } else {
+                    Json::from(i.to_string())
+                }
+            }
+            Value::Bool(b) => Json::from(b),
+        }
+    }
+
+    fn as_int(self) -> anyhow::Result<i128> {
+        match self {
+            Value::Int(i) => Ok(i),
+            Value::Bool(_) => bail!("Expected an integer, but got a boolean."),
+        }
+    }
+
+    fn as_bool(self) -> anyhow::Result<bool> {
+        match self {
+            Value::Bool(b) => Ok(b),
+            Value::Int(_) => bail!("Expected a boolean, but got an integer."),
+        }
+    }
+}
+
+/// The variables available when evaluating an expression.
+#[derive(Debug, Default, Clone)]
+pub struct Env {
+    vars: BTreeMap<String, Value>,
+}
+
+impl Env {
+    pub fn set(&mut self, name: &str, value: Value) { self.vars.insert(name.to_string(), value); }
+
+    /// Bind a JSON value to a name. Objects are bound field by field, using
+    /// `name.field` as the names of the fields, and values that cannot be
+    /// represented are skipped.
+    pub fn set_json(&mut self, name: &str, json: &Json) {
+        if let Json::Object(fields) = json {
+            for (field, value) in fields.iter() {
+                self.set_json(&format!("{}.{}", name, field), value);
+            }
+        } else if let Some(value) = Value::from_json(json) {
+            self.set(name, value);
+        }
+    }
+
+    pub fn vars(&self) -> &BTreeMap<String, Value> { &self.vars }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i128),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+const OPERATORS: [&str; 17] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "!", "(", ")", "=",
+];
+
+fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next().expect("rest is not empty");
+        if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            tokens.push(Token::Int(
+                rest[..end]
+                    .parse()
+                    .context("Integer literal is too large.")?,
+            ));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .with_context(|| format!("Unexpected character '{}' in expression.", c))?;
+            ensure!(*op != "=", "Use `==` for comparing values.");
+            tokens.push(match *op {
+                "(" => Token::LParen,
+                ")" => Token::RParen,
+                op => Token::Op(op),
+            });
+            rest = &rest[op.len()..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent evaluator. Precedence from lowest to highest is `||`,
+/// `&&`, comparisons, `+` and `-`, `*`, `/` and `%`, and unary operators.
+struct Evaluator<'a> {
+    tokens: Vec<Token>,
+    pos:    usize,
+    env:    &'a Env,
+    /// Whether the current operand is only parsed and not evaluated, since
+    /// the result of `&&` or `||` is already decided by the left operand.
+    skip:   bool,
+}
+
+impl<'a> Evaluator<'a> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(*op),
+            _ => None,
+        }
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[&str],
+        next: fn(&mut Self) -> anyhow::Result<Value>,
+        apply: fn(&str, Value, Value) -> anyhow::Result<Value>,
+    ) -> anyhow::Result<Value> {
+        let mut left = next(self)?;
+        while let Some(op) = self.peek_op().filter(|op| ops.contains(op)) {
+            self.pos += 1;
+            let right = next(self)?;
+            if !self.skip {
+                left = apply(op, left, right)?;
+            }
+        }
+        Ok(left)
+    }
+
+    /// A boolean operator whose result is `decisive` if the left operand is.
+    /// The right operand is then not evaluated, so that the left operand can
+    /// guard it, as in `x != 0 && 10 / x > 1`.
+    fn logical(
+        &mut self,
+        op: &str,
+        next: fn(&mut Self) -> anyhow::Result<Value>,
+        decisive: bool,
+    ) -> anyhow::Result<Value> {
+        let mut left = next(self)?;
+        while self.peek_op() == Some(op) {
+            self.pos += 1;
+            let decided = self.skip || left.as_bool()? == decisive;
+            let outer = std::mem::replace(&mut self.skip, decided);
+            let right = next(self);
+            self.skip = outer;
+            let right = right?;
+            if !decided {
+                left = Value::Bool(right.as_bool()?);
+            }
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> anyhow::Result<Value> { self.logical("||", Self::and, true) }
+
+    fn and(&mut self) -> anyhow::Result<Value> { self.logical("&&", Self::comparison, false) }
+
+    fn comparison(&mut self) -> anyhow::Result<Value> {
+        self.binary(
+            &["==", "!=", "<", "<=", ">", ">="],
+            Self::sum,
+            |op, l, r| {
+                let result = match op {
+                    "==" => l == r,
+                    "!=" => l != r,
+                    "<" => l.as_int()? < r.as_int()?,
+                    "<=" => l.as_int()? <= r.as_int()?,
+                    ">" => l.as_int()? > r.as_int()?,
+                    _ => l.as_int()? >= r.as_int()?,
+                };
+                Ok(Value::Bool(result))
+            },
+        )
+    }
+
+    fn sum(&mut self) -> anyhow::Result<Value> {
+        self.binary(&["+", "-"], Self::product, |op, l, r| {
+            let (l, r) = (l.as_int()?, r.as_int()?);
+            let result = if op == "+" {
+                l.checked_add(r)
+            } else {
+                l.checked_sub(r)
+            };
+            result
+                .map(Value::Int)
+                .context("Integer overflow in expression.")
+        })
+    }
+
+    fn product(&mut self) -> anyhow::Result<Value> {
+        self.binary(&["*", "/", "%"], Self::unary, |op, l, r| {
+            let (l, r) = (l.as_int()?, r.as_int()?);
+            let result = match op {
+                "*" => l.checked_mul(r),
+                "/" => l.checked_div(r),
+                _ => l.checked_rem(r),
+            };
+            result
+                .map(Value::Int)
+                .context("Integer overflow or division by zero in expression.")
+        })
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Value> {
+        match self.peek_op() {
+            Some("!") => {
+                self.pos += 1;
+                let value = self.unary()?;
+                if self.skip {
+                    return Ok(value);
+                }
+                Ok(Value::Bool(!value.as_bool()?))
+            }
+            Some("-") => {
+                self.pos += 1;
+                let value = self.unary()?;
+                if self.skip {
+                    return Ok(value);
+                }
+                value
+                    .as_int()?
+                    .checked_neg()
+                    .map(Value::Int)
+                    .context("Integer overflow in expression.")
+            }
+            _ => self.atom(),
+        }
+    }
+
+    fn atom(&mut self) -> anyhow::Result<Value> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .context("Unexpected end of expression.")?;
+        self.pos += 1;
+        match token {
+            Token::Int(i) => Ok(Value::Int(i)),
+            Token::Ident(name) => match name.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => match self.env.vars.get(&name) {
+                    Some(value) => Ok(*value),
+                    // Variables of operands that are not evaluated may be
+                    // unbound, e.g., in `has_owner && owner == 1`.
+                    None if self.skip => Ok(Value::Int(0)),
+                    None => bail!("Unknown variable '{}'.", name),
+                },
+            },
+            Token::LParen => {
+                let value = self.or()?;
+                ensure!(
+                    self.tokens.get(self.pos) == Some(&Token::RParen),
+                    "Expected a closing parenthesis."
+                );
+                self.pos += 1;
+                Ok(value)
+            }
+            token => bail!("Unexpected {:?} in expression.", token),
+        }
+    }
+}
+
+/// Evaluate an expression with the given variables.
+pub fn eval(expression: &str, env: &Env) -> anyhow::Result<Value> {
+    let tokens = tokenize(expression)?;
+    let mut evaluator = Evaluator {
+        tokens,
+        pos: 0,
+        env,
+        skip: false,
+    };
+    let value = evaluator
+        .or()
+        .with_context(|| format!("Could not evaluate expression `{}`.", expression))?;
+    ensure!(
+        evaluator.pos == evaluator.tokens.len(),
+        "Unexpected trailing input in expression `{}`.",
+        expression
+    );
+    Ok(value)
+}
+
+/// Evaluate an expression that must produce a boolean.
+pub fn eval_bool(expression: &str, env: &Env) -> anyhow::Result<bool> {
+    eval(expression, env)?
+        .as_bool()
+        .with_context(|| format!("Expression `{}` must be a boolean.", expression))
+}
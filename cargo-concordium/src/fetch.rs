@@ -0,0 +1,158 @@
+//! Download of the state of V1 contract instances and of deployed modules
+//! from a node.
+//!
+//! The node is queried through its V2 gRPC API. For `cargo concordium state
+//! fetch`, the node streams the key-value pairs of the state of the instance.
+//! The pairs are inserted into a new state, which is written in the same binary
+//! format as `--out-bin`, so that issues of deployed instances can be
+//! reproduced with `run update --state-bin` and inspected with
+//! `display-state`. For `cargo concordium verify-source --node`, the source of
+//! the deployed module is fetched, so that it can be compared with the rebuilt
+//! module.
+//!
+//! Querying a node requires the gRPC client of the Rust SDK, which is only
+//! built with the `node` feature, so that other installations do not include
+//! it. Without the feature, these commands fail with a hint to reinstall.
+use concordium_contracts_common::ContractAddress;
+use concordium_smart_contract_engine::{utils::WasmVersion, v1::trie::PersistentState};
+
+#[cfg(feature = "node")]
+pub use self::node::{fetch, fetch_module};
+#[cfg(not(feature = "node"))]
+pub use self::without_node::{fetch, fetch_module};
+
+/// The state of an instance and the block it was taken from.
+pub struct FetchedState {
+    pub state:      PersistentState,
+    pub entries:    usize,
+    /// The hash of the block as hex.
+    pub block_hash: String,
+}
+
+#[cfg(feature = "node")]
+mod node {
+    use super::*;
+    use crate::state_json;
+    use anyhow::Context;
+    use concordium_rust_sdk::{
+        types::{
+            hashes::BlockHash,
+            smart_contracts::{self, ModuleReference},
+        },
+        v2,
+    };
+    use futures::StreamExt;
+    use std::collections::BTreeMap;
+
+    /// A runtime for running the queries to the node from synchronous code.
+    fn runtime() -> anyhow::Result<tokio::runtime::Runtime> {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .context("Could not start the runtime for querying the node.")
+    }
+
+    /// Connect to the V2 gRPC API of the node.
+    async fn connect(node: &str) -> anyhow::Result<v2::Client> {
+        let endpoint = v2::Endpoint::from_shared(node.to_string())
+            .with_context(|| format!("Invalid node address {}.", node))?;
+        v2::Client::new(endpoint)
+            .await
+            .with_context(|| format!("Could not connect to the node at {}.", node))
+    }
+
+    /// Fetch the state of the instance from the node, as of the block with the
+    /// given hash or the last finalized block.
+    pub fn fetch(
+        node: &str,
+        address: ContractAddress,
+        block: Option<&str>,
+    ) -> anyhow::Result<FetchedState> {
+        let block = block
+            .map(|hash| {
+                hash.parse::<BlockHash>()
+                    .with_context(|| format!("Invalid block hash {}.", hash))
+            })
+            .transpose()?;
+        runtime()?.block_on(async {
+            let mut client = connect(node).await?;
+            let block = match block {
+                Some(hash) => v2::BlockIdentifier::Given(hash),
+                None => v2::BlockIdentifier::LastFinal,
+            };
+            let address =
+                concordium_rust_sdk::types::ContractAddress::new(address.index, address.subindex);
+            let response = client
+                .get_instance_state(address, block)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Could not query the state of <{},{}>. Only V1 instances have a state \
+                         that can be fetched.",
+                        address.index, address.subindex
+                    )
+                })?;
+            let block_hash = response.block_hash;
+            let mut stream = response.response;
+            let mut entries = BTreeMap::new();
+            while let Some(pair) = stream.next().await {
+                let (key, value) = pair.context("Could not receive the state from the node.")?;
+                entries.insert(key, value);
+            }
+            Ok(FetchedState {
+                entries:    entries.len(),
+                state:      state_json::from_entries(entries)?,
+                block_hash: block_hash.to_string(),
+            })
+        })
+    }
+
+    /// Fetch the source of the deployed module with the given reference from
+    /// the node, as of the last finalized block. Returns the version of the
+    /// module and the Wasm module, like `read_versioned_module`.
+    pub fn fetch_module(node: &str, module_ref: &str) -> anyhow::Result<(WasmVersion, Vec<u8>)> {
+        let reference: ModuleReference = module_ref
+            .parse()
+            .with_context(|| format!("Invalid module reference {}.", module_ref))?;
+        runtime()?.block_on(async {
+            let mut client = connect(node).await?;
+            let module = client
+                .get_module_source(&reference, v2::BlockIdentifier::LastFinal)
+                .await
+                .with_context(|| {
+                    format!("Could not fetch the module {} from the node.", module_ref)
+                })?
+                .response;
+            let version = match module.version {
+                smart_contracts::WasmVersion::V0 => WasmVersion::V0,
+                smart_contracts::WasmVersion::V1 => WasmVersion::V1,
+            };
+            Ok((version, module.source.as_ref().to_vec()))
+        })
+    }
+}
+
+#[cfg(not(feature = "node"))]
+mod without_node {
+    use super::*;
+
+    /// The error of the commands that query a node.
+    fn node_feature_missing() -> anyhow::Error {
+        anyhow::anyhow!(
+            "cargo-concordium was built without support for querying nodes. Install it with \
+             `cargo install cargo-concordium --features node` to use this command."
+        )
+    }
+
+    pub fn fetch(
+        _node: &str,
+        _address: ContractAddress,
+        _block: Option<&str>,
+    ) -> anyhow::Result<FetchedState> {
+        Err(node_feature_missing())
+    }
+
+    pub fn fetch_module(_node: &str, _module_ref: &str) -> anyhow::Result<(WasmVersion, Vec<u8>)> {
+        Err(node_feature_missing())
+    }
+}
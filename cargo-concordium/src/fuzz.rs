@@ -0,0 +1,437 @@
+//! Fuzzing of entrypoints with random parameters generated from the schema.
+//!
+//! Each run invokes the entrypoint on a copy of the given instance with a
+//! random parameter, amount and context. Calls that trap, run out of energy or
+//! use more energy than a threshold are findings, and a reproducer with the
+//! parameter and context is written for each of them. Findings with the same
+//! cause are only reported once. Optionally, the number of state entries each
+//! call adds is recorded to report unbounded state growth.
+use crate::{
+    context::ReceiveContextV1Opt,
+    growth::{count_entries, GrowthTracker},
+    sim::{Instance, Outcome, Simulator},
+};
+use ansi_term::{Color, Style};
+use anyhow::Context;
+use concordium_contracts_common::{
+    schema::{Fields, SizeLength, Type},
+    AccountAddress, Amount, ContractAddress, Timestamp,
+};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+/// The maximum number of elements generated for collections.
+const MAX_ELEMENTS: u32 = 4;
+
+/// The maximum length of generated strings and byte lists.
+const MAX_LENGTH: u32 = 32;
+
+/// The largest timestamp generated, the end of year 9999.
+const MAX_TIMESTAMP_MILLIS: u64 = 253_402_300_799_999;
+
+/// The number of accounts and contracts senders are chosen from, so that
+/// entrypoints that compare the sender to addresses in the state have a chance
+/// of seeing a known address.
+const NUM_ADDRESSES: u8 = 4;
+
+/// The options for fuzzing an entrypoint.
+pub struct FuzzOptions {
+    pub runs:             u64,
+    pub seed:             u64,
+    /// Calls that use more interpreter energy than this are findings.
+    pub energy_threshold: Option<u64>,
+    /// The largest amount sent with a call.
+    pub max_amount:       Amount,
+    /// The directory reproducers are written to.
+    pub out_dir:          PathBuf,
+    /// Whether to report if the entrypoint causes unbounded state growth.
+    pub state_growth:     bool,
+}
+
+/// A random integer in the inclusive range, biased towards the bounds and
+/// small values, which are the most likely to reveal bugs.
+fn gen_int(rng: &mut SmallRng, min: i128, max: i128) -> i128 {
+    match rng.gen_range(0, 8) {
+        0 => min,
+        1 => max,
+        2 => 0.max(min).min(max),
+        3 => 1.max(min).min(max),
+        _ => {
+            let span = max.wrapping_sub(min) as u128;
+            let offset = (u128::from(rng.gen::<u64>()) << 64 | u128::from(rng.gen::<u64>()))
+                % span.saturating_add(1);
+            min.wrapping_add(offset as i128)
+        }
+    }
+}
+
+fn size_length_max(size_len: &SizeLength) -> u32 {
+    match size_len {
+        SizeLength::U8 => u8::MAX.into(),
+        _ => u32::MAX,
+    }
+}
+
+fn gen_length(rng: &mut SmallRng, size_len: &SizeLength, max: u32) -> usize {
+    rng.gen_range(0, max.min(size_length_max(size_len)) + 1) as usize
+}
+
+fn gen_hex(rng: &mut SmallRng, len: usize) -> String {
+    hex::encode((0..len).map(|_| rng.gen::<u8>()).collect::<Vec<_>>())
+}
+
+fn gen_name(rng: &mut SmallRng) -> String {
+    let len = rng.gen_range(1, 12);
+    (0..len)
+        .map(|_| (b'a' + rng.gen_range(0, 26)) as char)
+        .collect()
+}
+
+/// One of a few fixed accounts.
+fn gen_account(rng: &mut SmallRng) -> AccountAddress {
+    AccountAddress([rng.gen_range(0, NUM_ADDRESSES); 32])
+}
+
+/// One of a few fixed contract addresses.
+fn gen_contract(rng: &mut SmallRng) -> ContractAddress {
+    ContractAddress::new(rng.gen_range(0, NUM_ADDRESSES).into(), 0)
+}
+
+fn gen_fields(rng: &mut SmallRng, fields: &Fields) -> Value {
+    match fields {
+        Fields::Named(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, ty)| (name.clone(), gen_value(rng, ty)))
+                .collect(),
+        ),
+        Fields::Unnamed(fields) => fields.iter().map(|ty| gen_value(rng, ty)).collect(),
+        Fields::None => json!([]),
+    }
+}
+
+fn gen_variant(rng: &mut SmallRng, variant: Option<&(String, Fields)>) -> Value {
+    let mut object = serde_json::Map::new();
+    if let Some((name, fields)) = variant {
+        object.insert(name.clone(), gen_fields(rng, fields));
+    }
+    Value::Object(object)
+}
+
+/// A random JSON value of the given type in the JSON format of the schema.
+/// Maps and sets may contain duplicate keys, which the schema refuses to
+/// encode.
+pub fn gen_value(rng: &mut SmallRng, ty: &Type) -> Value {
+    match ty {
+        Type::Unit => json!([]),
+        Type::Bool => json!(rng.gen::<bool>()),
+        Type::U8 => json!(gen_int(rng, 0, u8::MAX.into()) as u8),
+        Type::U16 => json!(gen_int(rng, 0, u16::MAX.into()) as u16),
+        Type::U32 => json!(gen_int(rng, 0, u32::MAX.into()) as u32),
+        Type::U64 => json!(gen_int(rng, 0, u64::MAX.into()) as u64),
+        Type::I8 => json!(gen_int(rng, i8::MIN.into(), i8::MAX.into()) as i8),
+        Type::I16 => json!(gen_int(rng, i16::MIN.into(), i16::MAX.into()) as i16),
+        Type::I32 => json!(gen_int(rng, i32::MIN.into(), i32::MAX.into()) as i32),
+        Type::I64 => json!(gen_int(rng, i64::MIN.into(), i64::MAX.into()) as i64),
+        Type::U128 => json!(gen_int(rng, 0, i128::MAX).to_string()),
+        Type::I128 => json!(gen_int(rng, i128::MIN, i128::MAX).to_string()),
+        Type::ULeb128(_) => json!(gen_int(rng, 0, u64::MAX.into()).to_string()),
+        Type::ILeb128(_) => json!(gen_int(rng, i64::MIN.into(), i64::MAX.into()).to_string()),
+        Type::Amount => json!((gen_int(rng, 0, u64::MAX.into()) as u64).to_string()),
+        Type::AccountAddress => json!(gen_account(rng)),
+        Type::ContractAddress => json!(gen_contract(rng)),
+        Type::Timestamp => {
+            json!(Timestamp::from_timestamp_millis(
+                gen_int(rng, 0, MAX_TIMESTAMP_MILLIS.into()) as u64
+            ))
+        }
+        Type::Duration => json!(format!("{}ms", gen_int(rng, 0, u64::MAX.into()))),
+        Type::Pair(left, right) => json!([gen_value(rng, left), gen_value(rng, right)]),
+        Type::List(size_len, elem) | Type::Set(size_len, elem) => {
+            let len = gen_length(rng, size_len, MAX_ELEMENTS);
+            (0..len).map(|_| gen_value(rng, elem)).collect()
+        }
+        Type::Map(size_len, key, value) => {
+            let len = gen_length(rng, size_len, MAX_ELEMENTS);
+            (0..len)
+                .map(|_| json!([gen_value(rng, key), gen_value(rng, value)]))
+                .collect()
+        }
+        Type::Array(len, elem) => (0..*len).map(|_| gen_value(rng, elem)).collect(),
+        Type::Struct(fields) => gen_fields(rng, fields),
+        Type::Enum(variants) => {
+            let variant = (!variants.is_empty()).then(|| rng.gen_range(0, variants.len()));
+            gen_variant(rng, variant.and_then(|i| variants.get(i)))
+        }
+        Type::TaggedEnum(variants) => {
+            let variants = variants.values().collect::<Vec<_>>();
+            let variant = (!variants.is_empty()).then(|| rng.gen_range(0, variants.len()));
+            gen_variant(rng, variant.map(|i| variants[i]))
+        }
+        Type::String(size_len) => {
+            let len = gen_length(rng, size_len, MAX_LENGTH);
+            json!((0..len)
+                .map(|_| rng.gen_range(0x20u8, 0x7f) as char)
+                .collect::<String>())
+        }
+        Type::ContractName(_) => json!({ "contract": gen_name(rng) }),
+        Type::ReceiveName(_) => json!({ "contract": gen_name(rng), "func": gen_name(rng) }),
+        Type::ByteList(size_len) => {
+            let len = gen_length(rng, size_len, MAX_LENGTH);
+            json!(gen_hex(rng, len))
+        }
+        Type::ByteArray(len) => json!(gen_hex(rng, *len as usize)),
+    }
+}
+
+/// A random context in the JSON format of context files. Fields of the base
+/// context are kept, and the slot time, sender, invoker and owner are
+/// generated if the base does not have them.
+fn gen_context(rng: &mut SmallRng, base: &Value) -> Value {
+    let mut context = base.as_object().cloned().unwrap_or_default();
+    let invoker = gen_account(rng);
+    let metadata = context.entry("metadata").or_insert_with(|| json!({}));
+    if let Some(metadata) = metadata.as_object_mut() {
+        metadata.entry("slotTime").or_insert_with(|| {
+            json!(Timestamp::from_timestamp_millis(
+                rng.gen_range(0, MAX_TIMESTAMP_MILLIS)
+            ))
+        });
+    }
+    let sender = if rng.gen_range(0, 4) == 0 {
+        json!({ "type": "contract", "address": gen_contract(rng) })
+    } else {
+        json!({ "type": "account", "address": invoker })
+    };
+    context.entry("sender").or_insert(sender);
+    context.entry("invoker").or_insert_with(|| json!(invoker));
+    context
+        .entry("owner")
+        .or_insert_with(|| json!(gen_account(rng)));
+    Value::Object(context)
+}
+
+/// The cause of a finding. Findings with the same cause are reported once.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Cause {
+    Trap(String),
+    OutOfEnergy,
+    ExcessiveEnergy,
+}
+
+impl std::fmt::Display for Cause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cause::Trap(error) => write!(f, "trapped: {}", error),
+            Cause::OutOfEnergy => write!(f, "ran out of energy"),
+            Cause::ExcessiveEnergy => write!(f, "used more energy than the threshold"),
+        }
+    }
+}
+
+/// The first input that caused a finding.
+struct Finding {
+    run:         u64,
+    parameter:   Option<Value>,
+    bytes:       Vec<u8>,
+    amount:      Amount,
+    context:     Value,
+    energy_used: u64,
+    occurrences: u64,
+}
+
+/// Write the reproducer of a finding to `<out_dir>/finding-<n>/`, with the
+/// parameter and context in files that `cargo concordium run update` accepts.
+fn write_reproducer(
+    options: &FuzzOptions,
+    index: usize,
+    entrypoint: &str,
+    cause: &Cause,
+    finding: &Finding,
+) -> anyhow::Result<PathBuf> {
+    let dir = options.out_dir.join(format!("finding-{}", index));
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Could not create the directory {}.", dir.display()))?;
+    fs::write(dir.join("parameter.bin"), &finding.bytes)
+        .context("Could not write the parameter of the finding.")?;
+    fs::write(
+        dir.join("context.json"),
+        serde_json::to_vec_pretty(&finding.context)?,
+    )
+    .context("Could not write the context of the finding.")?;
+    let micro_ccd = finding.amount.micro_ccd();
+    let command = format!(
+        "cargo concordium run update --entrypoint {} --parameter-bin {} --context {} --amount \
+         {}.{:06}",
+        entrypoint,
+        dir.join("parameter.bin").display(),
+        dir.join("context.json").display(),
+        micro_ccd / 1_000_000,
+        micro_ccd % 1_000_000
+    );
+    let description = json!({
+        "entrypoint": entrypoint,
+        "cause": cause.to_string(),
+        "run": finding.run,
+        "seed": options.seed,
+        "parameter": finding.parameter,
+        "amount": finding.amount,
+        "energyUsed": finding.energy_used,
+        "occurrences": finding.occurrences,
+        "command": command,
+    });
+    fs::write(
+        dir.join("finding.json"),
+        serde_json::to_vec_pretty(&description)?,
+    )
+    .context("Could not write the finding.")?;
+    Ok(dir)
+}
+
+/// Invoke the entrypoint of the instance with random inputs and report the
+/// findings. Returns whether there were no findings.
+pub fn fuzz_entrypoint(
+    sim: &Simulator,
+    instance: &Instance,
+    entrypoint: &str,
+    parameter_type: Option<&Type>,
+    base_context: &Value,
+    options: &FuzzOptions,
+) -> anyhow::Result<bool> {
+    eprintln!(
+        "Fuzzing '{}.{}' with {} runs (seed {}).",
+        instance.contract_name, entrypoint, options.runs, options.seed
+    );
+    if parameter_type.is_none() {
+        eprintln!(
+            "{}",
+            crate::WARNING_STYLE.paint(
+                "The schema has no parameter type for the entrypoint, so random bytes are used as \
+                 parameters."
+            )
+        );
+    }
+    let mut rng = SmallRng::seed_from_u64(options.seed);
+    let mut findings: BTreeMap<Cause, Finding> = BTreeMap::new();
+    let mut outcomes: BTreeMap<&'static str, u64> = BTreeMap::new();
+    let mut invalid = 0;
+    let mut growth = GrowthTracker::default();
+    // Every run starts from the given instance.
+    let entries_before = if options.state_growth {
+        count_entries(&instance.state)?
+    } else {
+        0
+    };
+    for run in 0..options.runs {
+        let (parameter, bytes) = match parameter_type {
+            Some(ty) => {
+                let json = gen_value(&mut rng, ty);
+                let mut bytes = Vec::new();
+                if ty.serial_value_into(&json, &mut bytes).is_err() {
+                    invalid += 1;
+                    continue;
+                }
+                (Some(json), bytes)
+            }
+            None => {
+                let len = rng.gen_range(0, MAX_LENGTH as usize + 1);
+                (None, (0..len).map(|_| rng.gen::<u8>()).collect())
+            }
+        };
+        let amount =
+            Amount::from_micro_ccd(
+                gen_int(&mut rng, 0, options.max_amount.micro_ccd().into()) as u64
+            );
+        let context = gen_context(&mut rng, base_context);
+        let ctx: ReceiveContextV1Opt = serde_json::from_value(context.clone())
+            .context("Could not parse the context used for fuzzing.")?;
+        let mut current = instance.clone();
+        let result = sim.update(&mut current, entrypoint, ctx, amount, &bytes)?;
+        *outcomes.entry(result.outcome.kind()).or_default() += 1;
+        if options.state_growth {
+            growth.record(entrypoint, entries_before, count_entries(&current.state)?);
+        }
+        let cause = match result.outcome {
+            Outcome::Trap(error) => Cause::Trap(error),
+            Outcome::OutOfEnergy => Cause::OutOfEnergy,
+            _ if options
+                .energy_threshold
+                .map_or(false, |t| result.energy_used > t) =>
+            {
+                Cause::ExcessiveEnergy
+            }
+            _ => continue,
+        };
+        let finding = Finding {
+            run,
+            parameter,
+            bytes,
+            amount,
+            context,
+            energy_used: result.energy_used,
+            occurrences: 1,
+        };
+        match findings.get_mut(&cause) {
+            // For excessive energy the most expensive input is kept.
+            Some(existing)
+                if cause == Cause::ExcessiveEnergy
+                    && finding.energy_used > existing.energy_used =>
+            {
+                let occurrences = existing.occurrences + 1;
+                *existing = Finding {
+                    occurrences,
+                    ..finding
+                };
+            }
+            Some(existing) => existing.occurrences += 1,
+            None => {
+                findings.insert(cause, finding);
+            }
+        }
+    }
+
+    eprintln!("\n{}", Style::new().bold().paint("Outcomes:"));
+    for (kind, count) in outcomes.iter() {
+        eprintln!("  {:<12} {:>8}", kind, count);
+    }
+    if invalid > 0 {
+        eprintln!(
+            "{} generated parameters could not be encoded, e.g., because of duplicate keys, and \
+             were skipped.",
+            invalid
+        );
+    }
+    if options.state_growth {
+        eprintln!();
+        growth.report();
+    }
+    if findings.is_empty() {
+        eprintln!("{}", Color::Green.paint("No findings."));
+        return Ok(true);
+    }
+    eprintln!(
+        "\n{}",
+        Color::Red
+            .bold()
+            .paint(format!("{} findings:", findings.len()))
+    );
+    for (index, (cause, finding)) in findings.iter().enumerate() {
+        let dir = write_reproducer(options, index, entrypoint, cause, finding)?;
+        eprintln!(
+            "  {}. The call {} in {} runs, first in run {} using {} energy. Reproducer written to \
+             {}.",
+            index,
+            cause,
+            finding.occurrences,
+            finding.run,
+            finding.energy_used,
+            dir.display()
+        );
+    }
+    eprintln!(
+        "Each finding.json contains the command reproducing the finding, which needs the module, \
+         contract, state and balance used for fuzzing in addition."
+    );
+    Ok(false)
+}
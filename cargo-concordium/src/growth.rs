@@ -0,0 +1,111 @@
+//! Heuristic detection of unbounded state growth.
+//!
+//! The number of entries in the state of an instance is recorded before and
+//! after each call of a simulation, i.e., of `model-test`, `run scenario` and
+//! `fuzz` with `--state-growth`. Entrypoints that add
+//! entries in most of their calls are flagged, in particular if no entrypoint
+//! ever removes entries, since the state of such a contract grows with every
+//! use, and whoever pays for the calls can make the state arbitrarily large.
+use crate::state_json;
+use ansi_term::Style;
+use concordium_smart_contract_engine::v1;
+use std::collections::BTreeMap;
+
+/// Entrypoints are only flagged after this many calls.
+const MIN_CALLS: u64 = 5;
+
+/// Entrypoints are flagged if at least this fraction of their calls add
+/// entries.
+const GROWING_FRACTION: f64 = 0.5;
+
+/// The number of entries in the state, counted by iterating over them.
+pub fn count_entries(state: &v1::trie::PersistentState) -> anyhow::Result<u64> {
+    let mut entries = 0;
+    state_json::for_each_entry(state, &[], |_, _| {
+        entries += 1;
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+/// The changes to the number of entries made by the calls of an entrypoint.
+#[derive(Default)]
+struct EntrypointGrowth {
+    calls:          u64,
+    /// The number of calls that added entries.
+    growing_calls:  u64,
+    /// The number of calls that removed entries.
+    removing_calls: u64,
+    added:          u64,
+}
+
+/// Records the changes to the number of entries made by each entrypoint.
+#[derive(Default)]
+pub struct GrowthTracker {
+    entrypoints: BTreeMap<String, EntrypointGrowth>,
+}
+
+impl GrowthTracker {
+    /// Record a call of the entrypoint and the number of entries before and
+    /// after it.
+    pub fn record(&mut self, entrypoint: &str, before: u64, after: u64) {
+        let growth = self.entrypoints.entry(entrypoint.to_string()).or_default();
+        growth.calls += 1;
+        if after > before {
+            growth.growing_calls += 1;
+            growth.added += after - before;
+        } else if after < before {
+            growth.removing_calls += 1;
+        }
+    }
+
+    /// Print the entrypoints that are suspected of causing unbounded state
+    /// growth.
+    pub fn report(&self) {
+        let removing = self
+            .entrypoints
+            .iter()
+            .filter(|(_, g)| g.removing_calls > 0)
+            .map(|(name, _)| format!("'{}'", name))
+            .collect::<Vec<_>>();
+        let growing = self
+            .entrypoints
+            .iter()
+            .filter(|(_, g)| {
+                g.calls >= MIN_CALLS && g.growing_calls as f64 >= GROWING_FRACTION * g.calls as f64
+            })
+            .collect::<Vec<_>>();
+        eprintln!("{}", Style::new().bold().paint("State growth:"));
+        if growing.is_empty() {
+            eprintln!("  No entrypoint adds state entries in most of its calls.");
+            return;
+        }
+        for (name, growth) in growing.iter() {
+            eprintln!(
+                "{}",
+                crate::WARNING_STYLE.paint(format!(
+                    "  '{}' added entries in {} of {} calls, {:.1} entries per call on average.",
+                    name,
+                    growth.growing_calls,
+                    growth.calls,
+                    growth.added as f64 / growth.calls as f64
+                ))
+            );
+        }
+        if removing.is_empty() {
+            eprintln!(
+                "{}",
+                crate::WARNING_STYLE.paint(
+                    "  No entrypoint removed entries, so the state grows with every use of the \
+                     entrypoints above. Consider bounding the number of entries, removing entries \
+                     that are no longer needed, or making callers pay for the storage."
+                )
+            );
+        } else {
+            eprintln!(
+                "  Entries were removed by {}. Check that every entry added is eventually removed.",
+                removing.join(", ")
+            );
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Rendering of binary values, such as the values of the state of V1
+//! contracts, for display in the console.
+use std::fmt::Write as _;
+
+/// The number of bytes shown on each line of a hexdump.
+const BYTES_PER_LINE: usize = 16;
+
+/// The bytes as the lines of a hexdump, each with the offset of its first
+/// byte, the bytes in hex, and the bytes that are printable ASCII characters.
+pub fn lines(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut line = format!("{:08x} ", i * BYTES_PER_LINE);
+            for j in 0..BYTES_PER_LINE {
+                if j % 8 == 0 {
+                    line.push(' ');
+                }
+                match chunk.get(j) {
+                    Some(byte) => {
+                        let _ = write!(line, "{:02x} ", byte);
+                    }
+                    None => line.push_str("   "),
+                }
+            }
+            line.push('|');
+            line.extend(chunk.iter().map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    char::from(byte)
+                } else {
+                    '.'
+                }
+            }));
+            line.push('|');
+            line
+        })
+        .collect()
+}
+
+/// The bytes decoded as UTF-8, with invalid sequences replaced and control
+/// characters escaped, in quotes.
+pub fn utf8_preview(bytes: &[u8]) -> String { format!("{:?}", String::from_utf8_lossy(bytes)) }
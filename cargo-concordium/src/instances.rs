@@ -0,0 +1,186 @@
+//! Contract instances that the calls of `cargo concordium run update` are
+//! executed on.
+//!
+//! An instances file lists contract instances with their module, contract and
+//! state. When the invoked contract calls one of them, the call is executed on
+//! the instance by the simulator, and the original call is resumed with its
+//! outcome. Changes to the state and balance of the called instances are kept
+//! for later calls in the same invocation.
+use crate::{
+    context::ReceiveContextV1Opt,
+    sim::{Instance, Outcome, Simulator},
+    versions,
+};
+use anyhow::{bail, ensure, Context};
+use concordium_contracts_common::{AccountAddress, Amount, ContractAddress};
+use concordium_smart_contract_engine::{
+    v1::{self, trie::PersistentState},
+    InterpreterEnergy,
+};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// An instance as described in the instances file.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceSpec {
+    address:  ContractAddress,
+    /// The versioned V1 module, relative to the instances file.
+    module:   PathBuf,
+    contract: String,
+    /// The state in binary, as written by `--out-bin`, relative to the
+    /// instances file.
+    state:    PathBuf,
+    #[serde(default = "Amount::zero")]
+    balance:  Amount,
+    /// The owner of the instance. The owner of the invoked instance is used if
+    /// absent.
+    owner:    Option<AccountAddress>,
+}
+
+/// An instance together with the simulator of its module.
+struct LocalInstance {
+    sim:      Simulator,
+    instance: Instance,
+    owner:    Option<AccountAddress>,
+}
+
+/// The instances that calls are executed on.
+pub struct Instances {
+    instances: Vec<LocalInstance>,
+}
+
+impl Instances {
+    /// Load the instances described in a file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Could not read the instances {}.", path.display()))?;
+        let specs: Vec<InstanceSpec> = serde_json::from_slice(&bytes)
+            .with_context(|| format!("Could not parse the instances {}.", path.display()))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let mut instances: Vec<LocalInstance> = Vec::with_capacity(specs.len());
+        for spec in specs {
+            ensure!(
+                instances.iter().all(|i| i.instance.address != spec.address),
+                "The instance {} is given more than once.",
+                spec.address
+            );
+            let (version, module) = crate::read_versioned_module(&dir.join(&spec.module))
+                .with_context(|| format!("Could not load the module of {}.", spec.address))?;
+            ensure!(
+                versions::descriptor(version).supports_simulation,
+                "The module of the instance {} is not a V1 module.",
+                spec.address
+            );
+            let state_file = std::fs::File::open(dir.join(&spec.state))
+                .with_context(|| format!("Could not read the state of {}.", spec.address))?;
+            let state = PersistentState::deserialize(&mut std::io::BufReader::new(state_file))
+                .with_context(|| format!("Could not deserialize the state of {}.", spec.address))?;
+            // The energy is set before each call.
+            let sim = Simulator::new(&module, InterpreterEnergy { energy: 0 })?;
+            instances.push(LocalInstance {
+                sim,
+                instance: Instance {
+                    contract_name: spec.contract,
+                    address: spec.address,
+                    state,
+                    balance: spec.balance,
+                },
+                owner: spec.owner,
+            });
+        }
+        Ok(Instances { instances })
+    }
+
+    /// Whether the interrupt is a call to one of the instances.
+    pub fn handles(&self, interrupt: &v1::Interrupt) -> bool {
+        match interrupt {
+            v1::Interrupt::Call { address, .. } => self
+                .instances
+                .iter()
+                .any(|i| i.instance.address == *address),
+            _ => false,
+        }
+    }
+
+    /// Execute the call of the interrupt if it calls one of the instances.
+    /// Returns the response to resume the caller with and the energy used by
+    /// the call. The balance of the caller is reduced by the amount sent if
+    /// the call succeeds.
+    pub fn call(
+        &mut self,
+        interrupt: &v1::Interrupt,
+        ctx: &ReceiveContextV1Opt,
+        remaining_energy: u64,
+        balance: &mut Amount,
+    ) -> anyhow::Result<Option<(v1::InvokeResponse, u64)>> {
+        let (address, parameter, name, amount) = match interrupt {
+            v1::Interrupt::Call {
+                address,
+                parameter,
+                name,
+                amount,
+            } => (address, parameter, name, amount),
+            _ => return Ok(None),
+        };
+        let local = match self
+            .instances
+            .iter_mut()
+            .find(|i| i.instance.address == *address)
+        {
+            Some(local) => local,
+            None => return Ok(None),
+        };
+        if amount.micro_ccd() > balance.micro_ccd() {
+            return Ok(Some((
+                v1::InvokeResponse::Failure {
+                    kind: v1::InvokeFailure::InsufficientAmount,
+                },
+                0,
+            )));
+        }
+        let call_ctx = ctx.for_call(local.owner)?;
+        local.sim.energy = InterpreterEnergy {
+            energy: remaining_energy,
+        };
+        let entrypoint = name.to_string();
+        let result = local.sim.update(
+            &mut local.instance,
+            &entrypoint,
+            call_ctx,
+            *amount,
+            parameter,
+        )?;
+        eprintln!(
+            "The call to '{}' on {} {} using {} interpreter energy.",
+            entrypoint, address, result.outcome, result.energy_used
+        );
+        let response = match result.outcome {
+            Outcome::Success { return_value, .. } => {
+                *balance = Amount::from_micro_ccd(balance.micro_ccd() - amount.micro_ccd());
+                v1::InvokeResponse::Success {
+                    new_balance: *balance,
+                    data:        Some(return_value),
+                }
+            }
+            Outcome::Reject {
+                reason,
+                return_value,
+            } => v1::InvokeResponse::Failure {
+                kind: v1::InvokeFailure::ContractReject {
+                    code: reason,
+                    data: return_value,
+                },
+            },
+            Outcome::Trap(_) => v1::InvokeResponse::Failure {
+                kind: v1::InvokeFailure::RuntimeError,
+            },
+            Outcome::OutOfEnergy => bail!("The call to {} ran out of energy.", address),
+            Outcome::Interrupted(_) | Outcome::Upgrade(_) => bail!(
+                "The call to {} was interrupted, which is not supported for calls to instances.",
+                address
+            ),
+        };
+        Ok(Some((response, result.energy_used)))
+    }
+}
@@ -0,0 +1,240 @@
+//! Responses to the interrupts of `cargo concordium run update`.
+//!
+//! A V1 contract is interrupted when it transfers CCD, calls another contract,
+//! upgrades itself or queries the chain. Without a response, `run` stops at
+//! the interrupt. With responses, given in a file or on the command line, the
+//! execution is resumed with the next response at each interrupt until the
+//! call completes or the responses run out.
+//!
+//! Upgrades to modules that are available locally are answered like on the
+//! chain, by checking that the new module is a V1 module with the contract.
+use crate::{
+    build::{get_module_exports, module_reference},
+    versions,
+};
+use anyhow::{bail, ensure, Context};
+use concordium_contracts_common::{to_bytes, Amount, ModuleReference};
+use concordium_smart_contract_engine::{utils::WasmVersion, v1};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+/// Why an operation of the contract failed.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FailureReason {
+    /// The contract does not have the amount it tried to send.
+    InsufficientAmount,
+    MissingAccount,
+    MissingContract,
+    MissingEntrypoint,
+    /// A message sent by a called V0 contract failed.
+    V0Failed,
+    /// The called contract trapped.
+    Trap,
+}
+
+/// A response to an interrupt that reports success. The fields needed depend
+/// on the interrupt.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Success {
+    /// The hex encoded return value of a called contract.
+    return_value:       Option<String>,
+    /// The balance reported by a balance query, in microCCD.
+    balance:            Option<u64>,
+    /// The staked amount of an account, in microCCD.
+    #[serde(default)]
+    staked:             u64,
+    /// The locked amount of an account, in microCCD.
+    #[serde(default)]
+    locked:             u64,
+    /// The exchange rates as numerator and denominator.
+    euro_per_energy:    Option<(u64, u64)>,
+    micro_ccd_per_euro: Option<(u64, u64)>,
+}
+
+/// The response to an interrupt.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum InterruptResponse {
+    Success(Success),
+    /// The called contract rejected.
+    #[serde(rename_all = "camelCase")]
+    Reject {
+        code:         i32,
+        /// The hex encoded error value.
+        return_value: Option<String>,
+    },
+    Failure {
+        reason: FailureReason,
+    },
+}
+
+fn decode_hex(value: &Option<String>) -> anyhow::Result<Vec<u8>> {
+    match value {
+        Some(value) => hex::decode(value)
+            .with_context(|| format!("Could not decode the return value '{}' as hex.", value)),
+        None => Ok(Vec::new()),
+    }
+}
+
+impl Success {
+    /// The data returned to the contract for the interrupt.
+    fn data(&self, interrupt: &v1::Interrupt) -> anyhow::Result<Option<Vec<u8>>> {
+        let balance = || {
+            self.balance
+                .context("The response to the query needs a balance.")
+        };
+        let data = match interrupt {
+            v1::Interrupt::Call { .. } => Some(decode_hex(&self.return_value)?),
+            v1::Interrupt::Transfer { .. } | v1::Interrupt::Upgrade { .. } => None,
+            v1::Interrupt::QueryAccountBalance { .. } => {
+                Some(to_bytes(&(balance()?, self.staked, self.locked)))
+            }
+            v1::Interrupt::QueryContractBalance { .. } => Some(to_bytes(&balance()?)),
+            v1::Interrupt::QueryExchangeRates => {
+                match (self.euro_per_energy, self.micro_ccd_per_euro) {
+                    (Some(euro_per_energy), Some(micro_ccd_per_euro)) => {
+                        Some(to_bytes(&(euro_per_energy, micro_ccd_per_euro)))
+                    }
+                    _ => bail!(
+                        "The response to the query needs the exchange rates 'euroPerEnergy' and \
+                         'microCcdPerEuro'."
+                    ),
+                }
+            }
+        };
+        Ok(data)
+    }
+}
+
+impl InterruptResponse {
+    /// The response to give to the contract for the interrupt, given the
+    /// current balance of the contract, which is reduced by amounts sent.
+    pub fn to_invoke_response(
+        &self,
+        interrupt: &v1::Interrupt,
+        balance: &mut Amount,
+    ) -> anyhow::Result<v1::InvokeResponse> {
+        let kind = match self {
+            InterruptResponse::Success(success) => {
+                if let v1::Interrupt::Transfer { amount, .. } | v1::Interrupt::Call { amount, .. } =
+                    interrupt
+                {
+                    ensure!(
+                        amount.micro_ccd() <= balance.micro_ccd(),
+                        "The contract cannot send {} with a balance of {}. Respond with the \
+                         failure 'insufficientAmount' instead.",
+                        amount,
+                        balance
+                    );
+                    *balance = Amount::from_micro_ccd(balance.micro_ccd() - amount.micro_ccd());
+                }
+                return Ok(v1::InvokeResponse::Success {
+                    new_balance: *balance,
+                    data:        success.data(interrupt)?,
+                });
+            }
+            InterruptResponse::Reject { code, return_value } => v1::InvokeFailure::ContractReject {
+                code: *code,
+                data: decode_hex(return_value)?,
+            },
+            InterruptResponse::Failure { reason } => match reason {
+                FailureReason::InsufficientAmount => v1::InvokeFailure::InsufficientAmount,
+                FailureReason::MissingAccount => v1::InvokeFailure::NonExistentAccount,
+                FailureReason::MissingContract => v1::InvokeFailure::NonExistentContract,
+                FailureReason::MissingEntrypoint => v1::InvokeFailure::NonExistentEntrypoint,
+                FailureReason::V0Failed => v1::InvokeFailure::SendingV0Failed,
+                FailureReason::Trap => v1::InvokeFailure::RuntimeError,
+            },
+        };
+        Ok(v1::InvokeResponse::Failure { kind })
+    }
+}
+
+/// Read responses from a file with a list of responses.
+pub fn load(path: &Path) -> anyhow::Result<Vec<InterruptResponse>> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Could not read the responses {}.", path.display()))?;
+    serde_json::from_slice(&bytes)
+        .with_context(|| format!("Could not parse the responses {}.", path.display()))
+}
+
+/// Parse a single response given on the command line.
+pub fn parse(json: &str) -> anyhow::Result<InterruptResponse> {
+    serde_json::from_str(json).with_context(|| format!("Could not parse the response '{}'.", json))
+}
+
+/// A module that contracts can upgrade to.
+struct UpgradeModule {
+    version:   WasmVersion,
+    contracts: BTreeSet<String>,
+}
+
+/// The modules that contracts can upgrade to, by module reference.
+#[derive(Default)]
+pub struct UpgradeModules {
+    modules:         BTreeMap<ModuleReference, UpgradeModule>,
+    /// The module the instance was upgraded to.
+    pub upgraded_to: Option<ModuleReference>,
+}
+
+impl UpgradeModules {
+    /// Load the versioned modules.
+    pub fn load(paths: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut modules = BTreeMap::new();
+        for path in paths {
+            let bytes = std::fs::read(path)
+                .with_context(|| format!("Could not read the module {}.", path.display()))?;
+            let (version, module) = crate::read_versioned_module(path)?;
+            let exports = get_module_exports(&module, version)
+                .with_context(|| format!("Could not load the module {}.", path.display()))?;
+            modules.insert(module_reference(&bytes), UpgradeModule {
+                version,
+                contracts: exports.contracts,
+            });
+        }
+        Ok(UpgradeModules {
+            modules,
+            upgraded_to: None,
+        })
+    }
+
+    /// Whether the interrupt is an upgrade to one of the modules.
+    pub fn handles(&self, interrupt: &v1::Interrupt) -> bool {
+        match interrupt {
+            v1::Interrupt::Upgrade { module_ref } => self.modules.contains_key(module_ref),
+            _ => false,
+        }
+    }
+
+    /// The response to the interrupt if it is an upgrade to one of the
+    /// modules. Successful upgrades are recorded.
+    pub fn respond(
+        &mut self,
+        interrupt: &v1::Interrupt,
+        contract_name: &str,
+        balance: Amount,
+    ) -> Option<v1::InvokeResponse> {
+        let module_ref = match interrupt {
+            v1::Interrupt::Upgrade { module_ref } => *module_ref,
+            _ => return None,
+        };
+        let module = self.modules.get(&module_ref)?;
+        let kind = if !versions::descriptor(module.version).supports_upgrade {
+            v1::InvokeFailure::UpgradeInvalidVersion
+        } else if !module.contracts.contains(contract_name) {
+            v1::InvokeFailure::UpgradeInvalidContractName
+        } else {
+            self.upgraded_to = Some(module_ref);
+            return Some(v1::InvokeResponse::Success {
+                new_balance: balance,
+                data:        None,
+            });
+        };
+        Some(v1::InvokeResponse::Failure { kind })
+    }
+}
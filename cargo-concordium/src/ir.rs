@@ -0,0 +1,276 @@
+//! Export of smart contract modules to an intermediate representation intended
+//! for consumption by verification and model-checking tools.
+//!
+//! The representation is a JSON document combining the information from the
+//! module, its schema, and the metering that applies when it is executed. The
+//! format is documented in the README, and the `irVersion` field is increased
+//! whenever the format changes in an incompatible way.
+//!
+//! The metering is obtained by injecting the metering of the chain into the
+//! module, as is done before it is executed, and collecting the charges of
+//! interpreter energy made by each function. The calls between functions are
+//! collected from the module, so that the host functions, loops and indirect
+//! calls an entrypoint can reach are known.
+use crate::{
+    build::{module_reference, parse_and_validate_module, versioned_module_bytes},
+    call_graph::CallGraph,
+    cost::INTERPRETER_ENERGY_PER_NRG,
+    schema_types::{contract_names, type_to_description, ContractSchemaView, FunctionTypes},
+    versions,
+};
+use anyhow::Context;
+use concordium_contracts_common::{schema::VersionedModuleSchema, ContractName, ReceiveName};
+use concordium_smart_contract_engine::utils::WasmVersion;
+use concordium_wasm::types::{ExportDescription, Module, OpCode};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The version of the intermediate representation produced.
+pub const IR_VERSION: u32 = 1;
+
+/// The charges of interpreter energy injected into a function by the metering.
+#[derive(Default)]
+struct FunctionMetering {
+    /// The number of charges, one for each block of instructions.
+    charges: usize,
+    /// The sum of the charges, i.e., the energy used if each block is executed
+    /// once.
+    energy:  u64,
+}
+
+/// The metering of each function defined in the module, in the order of the
+/// code section.
+fn function_metering(module: &[u8], version: WasmVersion) -> anyhow::Result<Vec<FunctionMetering>> {
+    let mut metered = parse_and_validate_module(module, version)?;
+    metered
+        .inject_metering()
+        .context("Could not inject the metering into the module.")?;
+    let account_energy = metered
+        .import
+        .imports
+        .iter()
+        .position(|import| {
+            import.mod_name.as_ref() == "concordium_metering"
+                && import.item_name.as_ref() == "account_energy"
+        })
+        .context("The metered module does not import 'account_energy'.")?
+        as u32;
+    // Each charge is made by pushing the energy and calling `account_energy`.
+    Ok(metered
+        .code
+        .impls
+        .iter()
+        .map(|code| {
+            let mut metering = FunctionMetering::default();
+            for pair in code.expr.instrs.windows(2) {
+                if let (OpCode::I64Const(energy), OpCode::Call(index)) = (&pair[0], &pair[1]) {
+                    if *index == account_energy {
+                        metering.charges += 1;
+                        metering.energy = metering.energy.saturating_add(*energy as u64);
+                    }
+                }
+            }
+            metering
+        })
+        .collect())
+}
+
+/// The information about a module used to describe its functions.
+struct FunctionInfo<'a> {
+    module:   &'a Module,
+    metering: Vec<FunctionMetering>,
+    calls:    CallGraph,
+}
+
+impl<'a> FunctionInfo<'a> {
+    /// The name of the host function with the given index, if it is imported.
+    fn import_name(&self, index: u32) -> Option<String> {
+        self.module
+            .import
+            .imports
+            .get(index as usize)
+            .map(|import| format!("{}.{}", import.mod_name.as_ref(), import.item_name.as_ref()))
+    }
+
+    /// Describe the function with the given index in the module.
+    fn describe(&self, index: u32) -> Value {
+        if let Some(import) = self.import_name(index) {
+            return json!({
+                "index": index,
+                "import": import,
+            });
+        }
+        let defined = index as usize - self.module.import.imports.len();
+        let (code, metering, calls) = match (
+            self.module.code.impls.get(defined),
+            self.metering.get(defined),
+            self.calls.calls(index),
+        ) {
+            (Some(code), Some(metering), Some(calls)) => (code, metering, calls),
+            _ => return json!({ "index": index }),
+        };
+        let reachable = self.calls.reachable(index);
+        let host_functions = reachable
+            .iter()
+            .filter_map(|index| self.import_name(*index))
+            .collect::<BTreeSet<_>>();
+        let reachable_defined = || {
+            reachable
+                .iter()
+                .filter_map(|index| self.calls.calls(*index))
+        };
+        json!({
+            "index": index,
+            "numLocals": code.num_locals,
+            "numInstructions": code.expr.instrs.len(),
+            "meteringCharges": metering.charges,
+            "meteredEnergy": metering.energy,
+            "calls": calls.callees,
+            "numLoops": calls.loops,
+            "callsIndirectly": calls.indirect,
+            "reachable": {
+                "numFunctions": reachable_defined().count(),
+                "hostFunctions": host_functions,
+                "numLoops": reachable_defined().map(|calls| calls.loops).sum::<usize>(),
+                "callsIndirectly": reachable_defined().any(|calls| calls.indirect),
+            },
+        })
+    }
+}
+
+/// Describe the types of a function, if they are present in the schema.
+fn function_types_description(types: Option<&FunctionTypes>) -> Value {
+    let describe = |ty: Option<&concordium_contracts_common::schema::Type>| match ty {
+        Some(ty) => type_to_description(ty),
+        None => Value::Null,
+    };
+    match types {
+        Some(types) => json!({
+            "parameter": describe(types.parameter),
+            "returnValue": describe(types.return_value),
+            "error": describe(types.error),
+        }),
+        None => Value::Null,
+    }
+}
+
+/// Export the (unversioned) Wasm module with the given version and optional
+/// schema to the intermediate representation.
+pub fn export_ir(
+    module: &[u8],
+    version: WasmVersion,
+    schema: Option<&VersionedModuleSchema>,
+) -> anyhow::Result<Value> {
+    let parsed = parse_and_validate_module(module, version)?;
+    let info = FunctionInfo {
+        module:   &parsed,
+        metering: function_metering(module, version)?,
+        calls:    CallGraph::new(&parsed),
+    };
+    let module_ref = module_reference(&versioned_module_bytes(module, version));
+
+    // Collect the init and receive functions of each contract.
+    let mut inits = BTreeMap::new();
+    let mut receives = BTreeMap::<String, BTreeMap<String, u32>>::new();
+    for export in &parsed.export.exports {
+        if let ExportDescription::Func { index } = export.description {
+            if let Ok(cn) = ContractName::new(export.name.as_ref()) {
+                inits.insert(cn.contract_name().to_string(), index);
+            } else if let Ok(rn) = ReceiveName::new(export.name.as_ref()) {
+                receives
+                    .entry(rn.contract_name().to_string())
+                    .or_default()
+                    .insert(rn.entrypoint_name().to_string(), index);
+            }
+        }
+    }
+
+    let mut contracts = Vec::new();
+    for (contract_name, init_index) in inits.iter() {
+        let view = schema.and_then(|s| ContractSchemaView::new(s, contract_name));
+        let entrypoints = receives
+            .get(contract_name)
+            .map(|entrypoints| {
+                entrypoints
+                    .iter()
+                    .map(|(name, index)| {
+                        json!({
+                            "name": name,
+                            "function": info.describe(*index),
+                            "types": function_types_description(
+                                view.as_ref().and_then(|v| v.receive.get(name.as_str()))
+                            ),
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        contracts.push(json!({
+            "name": contract_name,
+            "init": {
+                "function": info.describe(*init_index),
+                "types": function_types_description(view.as_ref().and_then(|v| v.init.as_ref())),
+            },
+            "entrypoints": entrypoints,
+            "state": view.as_ref().and_then(|v| v.state).map(type_to_description),
+            "event": view.as_ref().and_then(|v| v.event).map(type_to_description),
+        }));
+    }
+
+    // Warn about contracts described in the schema that the module does not
+    // define, since those are most likely the result of using a wrong schema.
+    if let Some(schema) = schema {
+        for name in contract_names(schema) {
+            if !inits.contains_key(name) {
+                eprintln!(
+                    "{}",
+                    crate::WARNING_STYLE.paint(format!(
+                        "The schema describes contract '{}' which is not in the module.",
+                        name
+                    ))
+                );
+            }
+        }
+    }
+
+    let imports = parsed
+        .import
+        .imports
+        .iter()
+        .map(|import| {
+            json!({
+                "module": import.mod_name.as_ref(),
+                "name": import.item_name.as_ref(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let num_imports = parsed.import.imports.len();
+    let functions = info
+        .metering
+        .iter()
+        .enumerate()
+        .map(|(i, metering)| {
+            json!({
+                "index": num_imports + i,
+                "meteringCharges": metering.charges,
+                "meteredEnergy": metering.energy,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok(json!({
+        "irVersion": IR_VERSION,
+        "moduleReference": module_ref.to_string(),
+        "wasmVersion": versions::descriptor(version).name,
+        "hasSchema": schema.is_some(),
+        "metering": {
+            "unit": "interpreterEnergy",
+            "interpreterEnergyPerNrg": INTERPRETER_ENERGY_PER_NRG,
+            "numFunctions": parsed.import.imports.len() + parsed.code.impls.len(),
+            "functions": functions,
+        },
+        "imports": imports,
+        "contracts": contracts,
+    }))
+}
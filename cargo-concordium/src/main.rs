@@ -1,13 +1,17 @@
 use crate::{
     build::*,
-    context::{InitContextOpt, ReceiveContextOpt, ReceiveContextV1Opt},
+    context::{
+        ContextOverrides, ContractAddressArg, InitContextOpt, ReceiveContextOpt,
+        ReceiveContextV1Opt,
+    },
+    crypto::CryptoOverrides,
 };
 use anyhow::{bail, ensure, Context};
 use clap::AppSettings;
 use concordium_contracts_common::{
     from_bytes,
     schema::{Type, VersionedModuleSchema},
-    to_bytes, Amount, OwnedParameter, OwnedReceiveName, ReceiveName,
+    to_bytes, Amount, ContractAddress, OwnedParameter, OwnedReceiveName, ReceiveName,
 };
 use concordium_smart_contract_engine::{
     utils::{self, WasmVersion},
@@ -20,10 +24,67 @@ use std::{
     fs::{self, File},
     io::Read,
     path::{Path, PathBuf},
+    time::Duration,
 };
 use structopt::StructOpt;
+mod audit;
+mod bench;
 mod build;
+mod build_info;
+mod cache;
+mod call_graph;
+mod chain;
+mod collections;
 mod context;
+mod cost;
+mod coverage;
+mod crypto;
+mod determinism;
+mod discovery;
+mod events;
+mod expect;
+mod expr;
+mod fetch;
+mod fuzz;
+mod growth;
+mod hexdump;
+mod instances;
+mod interrupt;
+mod ir;
+mod mock;
+mod model;
+mod order;
+mod profile;
+mod protocol;
+mod receipt;
+mod remap;
+mod report;
+mod reproducible;
+mod run_report;
+mod run_scenario;
+mod sbom;
+mod scenario;
+mod schema_diff;
+mod schema_types;
+mod sim;
+mod size_report;
+mod smoke;
+mod standards;
+mod state_diff;
+mod state_html;
+mod state_json;
+mod state_stats;
+mod strict;
+mod test_coverage;
+mod test_energy;
+mod test_report;
+mod test_runner;
+mod toolchain;
+mod trace;
+mod upgrade;
+mod verify;
+mod verify_build;
+mod versions;
 
 /// Versioned schemas always start with two fully set bytes.
 /// This is used to determine whether we are looking at a versioned or
@@ -44,29 +105,232 @@ enum Command {
         name = "run",
         about = "Locally simulate invocation method of a smart contract and inspect the state."
     )]
-    Run(Box<RunCommand>),
+    Run(Box<RunTarget>),
+    #[structopt(name = "bench", about = "Benchmark V1 contracts using scenarios.")]
+    Bench(BenchCommand),
     #[structopt(
         name = "display-state",
-        about = "Display the contract state as a tree."
+        about = "Display the contract state as a tree, or as JSON for V0 contracts."
     )]
     DisplayState {
         #[structopt(
             name = "state-bin",
             long = "state-bin",
-            help = "Path to the file with state that is to be displayed. The state must be for a \
-                    V1 contract."
+            help = "Path to the file with state that is to be displayed. The state is for a V1 \
+                    contract unless `--wasm-version V0` is given."
         )]
         state_bin_path: PathBuf,
+        #[structopt(
+            name = "wasm-version",
+            long = "wasm-version",
+            short = "v",
+            help = "The version of the contract the state is for. The state of a V0 contract is a \
+                    single value, which is displayed as JSON using the state type in the schema \
+                    given with `--schema`."
+        )]
+        wasm_version:   Option<WasmVersion>,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to the schema of the module, which is required for the state of V0 \
+                    contracts. Unversioned schemas are read as V0 schemas."
+        )]
+        schema_path:    Option<PathBuf>,
+        #[structopt(
+            name = "contract",
+            long = "contract",
+            short = "c",
+            requires = "schema",
+            help = "The contract whose state type is used. By default the only contract in the \
+                    schema with a state type is used."
+        )]
+        contract:       Option<String>,
+        #[structopt(
+            name = "format",
+            long = "format",
+            default_value = "tree",
+            help = "The format of the output, either `tree`, `dot`, `html` or `jsonl`. With \
+                    `dot`, the structure of the trie is printed as a Graphviz graph, with the key \
+                    fragment, the kind and the value size of each node. With `html`, a \
+                    self-contained page with a collapsible tree and a search over the keys is \
+                    written to --out. With `jsonl`, each entry is printed as a line of JSON as \
+                    the state is traversed, which keeps the memory use low for huge states."
+        )]
+        format:         StateFormat,
+        #[structopt(
+            name = "out",
+            long = "out",
+            short = "o",
+            help = "Where to write the output of the `dot`, `html` or `jsonl` format. This is \
+                    required for `html`, and the other formats are printed to stdout if it is not \
+                    given."
+        )]
+        out:            Option<PathBuf>,
+        #[structopt(
+            name = "collections",
+            long = "collections",
+            help = "Group the entries by the `concordium-std` collections they belong to. Given a \
+                    JSON file with the schema types of the root and the collections by prefix, \
+                    the keys and values are decoded. This only applies to the `tree` format."
+        )]
+        collections:    Option<Option<PathBuf>>,
+        #[structopt(flatten)]
+        options:        StateDisplayOptions,
     },
+    #[structopt(
+        name = "state",
+        about = "Convert and inspect the state of V1 contracts."
+    )]
+    State(StateCommand),
     #[structopt(name = "test", about = "Build and run tests using a Wasm interpreter.")]
     Test {
+        #[structopt(flatten)]
+        package:           PackageOptions,
         #[structopt(name = "seed", long = "seed", help = "Seed for randomized testing")]
-        seed: Option<u64>,
+        seed:              Option<u64>,
+        #[structopt(
+            name = "report-html",
+            long = "report-html",
+            help = "Write a self-contained HTML report of the test results to `index.html` in the \
+                    given directory."
+        )]
+        report_html:       Option<PathBuf>,
+        #[structopt(
+            name = "report",
+            long = "report",
+            number_of_values = 1,
+            help = "Write a report of the test results for CI systems, given as \
+                    `<format>:<path>`, where the format is `junit` for JUnit XML or `json`. Can \
+                    be given multiple times. The tests are then run one at a time, so that each \
+                    of them is timed."
+        )]
+        reports:           Vec<test_report::TestReport>,
+        #[structopt(
+            name = "no-run",
+            long = "no-run",
+            conflicts_with_all = &["seed", "report-html", "report"],
+            help = "Only build the test Wasm module and print its path, without running the \
+                    tests."
+        )]
+        no_run:            bool,
+        #[structopt(
+            name = "list",
+            long = "list",
+            conflicts_with_all = &["seed", "report-html", "report", "no-run"],
+            help = "Build the test Wasm module and list the names of its tests, without running \
+                    them."
+        )]
+        list:              bool,
+        #[structopt(
+            name = "coverage",
+            long = "coverage",
+            conflicts_with_all = &["no-run", "list"],
+            help = "Report which functions of the package are executed by the tests. The tests \
+                    are then run one at a time from a module with a counter in each function."
+        )]
+        coverage:          bool,
+        #[structopt(
+            name = "energy-report",
+            long = "energy-report",
+            conflicts_with_all = &["no-run", "list"],
+            help = "Report the interpreter energy used by each test and write it as JSON to the \
+                    given path. The tests are then run one at a time with metering."
+        )]
+        energy_report:     Option<PathBuf>,
+        #[structopt(
+            name = "fail-fast",
+            long = "fail-fast",
+            conflicts_with_all = &["no-run", "list"],
+            help = "Stop running tests after the first failure. The tests are then run one at a \
+                    time in alphabetical order."
+        )]
+        fail_fast:         bool,
+        #[structopt(
+            name = "test-energy",
+            long = "test-energy",
+            conflicts_with_all = &["no-run", "list"],
+            help = "Fail tests that use more than the given interpreter energy. The tests are \
+                    then run one at a time in alphabetical order."
+        )]
+        test_energy:       Option<u64>,
+        #[structopt(
+            name = "test-timeout",
+            long = "test-timeout",
+            conflicts_with_all = &["no-run", "list"],
+            help = "Fail tests that run for longer than the given number of seconds. The tests \
+                    are then run one at a time in alphabetical order."
+        )]
+        test_timeout:      Option<u64>,
+        #[structopt(
+            name = "module",
+            long = "module",
+            conflicts_with = "no-run",
+            help = "Run the tests of the given test Wasm module instead of building the package, \
+                    for instance a module built with `--no-run` on another machine."
+        )]
+        module:            Option<PathBuf>,
+        #[structopt(
+            name = "test-features",
+            long = "test-features",
+            conflicts_with = "module",
+            help = "Comma-separated list of features to build the test module with, instead of \
+                    `concordium-std/wasm-test`, for crates that rename concordium-std or gate \
+                    test code behind their own features."
+        )]
+        test_features:     Option<String>,
+        #[structopt(
+            name = "no-default-features",
+            long = "no-default-features",
+            conflicts_with = "module",
+            help = "Do not enable the default features of the package when building the test \
+                    module."
+        )]
+        no_default:        bool,
+        #[structopt(
+            name = "integration",
+            long = "integration",
+            conflicts_with_all = &["module", "no-run", "list"],
+            help = "After the Wasm tests, build the contract module and run the host-side \
+                    integration tests of the package with `cargo test`. The path of the module \
+                    is given to them in the `CARGO_CONCORDIUM_MODULE` environment variable."
+        )]
+        integration:       bool,
+        #[structopt(
+            name = "contract-version",
+            long = "contract-version",
+            alias = "wasm-version",
+            short = "v",
+            help = "The version of the module built for integration tests. `--wasm-version` is an \
+                    alias.",
+            default_value = "V1"
+        )]
+        version:           utils::WasmVersion,
+        #[structopt(
+            name = "shuffle",
+            long = "shuffle",
+            conflicts_with_all = &["no-run", "list"],
+            help = "Run the tests one at a time in a random order determined by the seed, to \
+                    detect tests that depend on each other."
+        )]
+        shuffle:           bool,
+        #[structopt(
+            name = "protocol-version",
+            long = "protocol-version",
+            number_of_values = 1,
+            conflicts_with = "module",
+            help = "Protocol version to build and run the tests for, whose number is given to the \
+                    tests in the `CARGO_CONCORDIUM_PROTOCOL_VERSION` environment variable at \
+                    compile time, and to integration tests at run time. Can be given multiple \
+                    times to run the tests once for each version."
+        )]
+        protocol_versions: Vec<protocol::ProtocolVersion>,
         #[structopt(
             raw = true,
-            help = "Extra arguments passed to `cargo build` when building the test Wasm module."
+            help = "Extra arguments passed to `cargo build` when building the test Wasm module \
+                    and the module for integration tests."
         )]
-        args: Vec<String>,
+        args:              Vec<String>,
     },
     #[structopt(
         name = "init",
@@ -219,408 +483,3891 @@ enum Command {
                     input: `./my/path/base64_schema.b64` or `-`)."
         )]
         schema_base64_out: Option<PathBuf>,
+        #[structopt(
+            name = "schema-compare",
+            long = "schema-compare",
+            help = "Build the schema and compare it against the schema embedded in the given \
+                    module, e.g., the deployed one, or in the given schema file, and print the \
+                    added, removed and changed contracts, entrypoints and types."
+        )]
+        schema_compare:    Option<PathBuf>,
         #[structopt(
             name = "out",
             long = "out",
             short = "o",
-            help = "Writes the resulting module to file at specified location."
+            help = "Writes the resulting module to file at specified location. If the location is \
+                    a directory, or ends with a path separator, the module is written to it using \
+                    the default file name, e.g., `my_contract.wasm.v1`."
         )]
         out:               Option<PathBuf>,
+        #[structopt(
+            name = "out-raw",
+            long = "out-raw",
+            help = "Also write the plain Wasm module, without the version and length prefix, to \
+                    file at specified location, e.g., for use with wasm2wat or wasm-objdump."
+        )]
+        out_raw:           Option<PathBuf>,
+        #[structopt(
+            name = "module-ref-out",
+            long = "module-ref-out",
+            help = "Write the reference of the resulting module, i.e., the hash it is deployed \
+                    under, in hex to file at specified location."
+        )]
+        module_ref_out:    Option<PathBuf>,
         #[structopt(
             name = "contract-version",
             long = "contract-version",
+            alias = "wasm-version",
             short = "v",
-            help = "Build a module of the given version.",
+            help = "Build a module of the given version. `--wasm-version` is an alias.",
             default_value = "V1"
         )]
         version:           utils::WasmVersion,
+        #[structopt(
+            name = "all-versions",
+            long = "all-versions",
+            conflicts_with_all = &[
+                "workspace",
+                "verifiable",
+                "out",
+                "out-raw",
+                "module-ref-out",
+                "schema-out",
+                "schema-base64-out",
+                "schema-compare",
+                "example",
+                "bin"
+            ],
+            help = "Build both a V0 and a V1 module, next to each other, instead of a module \
+                    of the version given by `--contract-version`. JSON schemas are \
+                    written to a subdirectory per version of the `--schema-json-out` directory."
+        )]
+        all_versions:      bool,
+        #[structopt(
+            name = "sbom",
+            long = "sbom",
+            help = "Embed a software bill of materials in the given format into the module. The \
+                    format is either `cyclonedx` or `spdx`. Use `cargo concordium inspect --sbom` \
+                    to extract it."
+        )]
+        sbom:              Option<sbom::SbomFormat>,
+        #[structopt(
+            name = "source-url",
+            long = "source-url",
+            help = "Embed a link to the source into the module, consisting of the given URL of \
+                    the git repository, the current commit and the build settings. The working \
+                    tree must be clean. Use `cargo concordium verify-source` to check that the \
+                    source reproduces the module."
+        )]
+        source_url:        Option<String>,
+        #[structopt(
+            name = "verifiable",
+            long = "verifiable",
+            conflicts_with_all = &[
+                "workspace",
+                "source-url",
+                "schema-out",
+                "schema-json-out",
+                "schema-base64-out",
+                "schema-compare"
+            ],
+            help = "Build the committed source in a container of a pinned image, so that the \
+                    module is reproducible byte for byte, and embed the build recipe with the \
+                    image and the hash of the source archive into the module. Requires docker."
+        )]
+        verifiable:        bool,
+        #[structopt(
+            name = "verifiable-image",
+            long = "verifiable-image",
+            requires = "verifiable",
+            help = "The image to use for a verifiable build instead of the default pinned image."
+        )]
+        verifiable_image:  Option<String>,
+        #[structopt(
+            name = "opt",
+            long = "opt",
+            help = "Optimize the module for size using wasm-opt before the schema and other \
+                    custom sections are embedded."
+        )]
+        opt:               bool,
+        #[structopt(
+            name = "debug-names",
+            long = "debug-names",
+            help = "Keep the `name` custom section with the names of the functions, so that traps \
+                    can be traced back to functions. The module should not be deployed."
+        )]
+        debug_names:       bool,
+        #[structopt(
+            name = "keep-custom-section",
+            long = "keep-custom-section",
+            number_of_values = 1,
+            help = "Keep the custom section with this name, e.g., `producers`, in the resulting \
+                    module instead of stripping it. Can be given multiple times."
+        )]
+        keep_sections:     Vec<String>,
+        #[structopt(
+            name = "size-report",
+            long = "size-report",
+            help = "Print the size of each section of the resulting module and the largest \
+                    functions in it."
+        )]
+        size_report:       bool,
+        #[structopt(
+            name = "max-module-size",
+            long = "max-module-size",
+            help = "Fail if the module, without the version prefix, is larger than this many \
+                    bytes. Defaults to the limit of the chain for the contract version, which is \
+                    65536 for V0 and 524288 for V1."
+        )]
+        max_module_size:   Option<usize>,
+        #[structopt(
+            name = "no-cache",
+            long = "no-cache",
+            help = "Always build the module, instead of reusing the module of the previous build \
+                    if the sources, the build options and the toolchain are unchanged."
+        )]
+        no_cache:          bool,
+        #[structopt(
+            name = "rustflags",
+            long = "rustflags",
+            help = "Flags, separated by whitespace, passed to rustc when compiling for the Wasm \
+                    target, in addition to those configured for the package, e.g., `--rustflags \
+                    \"-C opt-level=z\"`."
+        )]
+        rustflags:         Option<String>,
+        #[structopt(
+            name = "build-std",
+            long = "build-std",
+            help = "Build the standard library with the contract using `-Z \
+                    build-std=std,panic_abort`, which often produces smaller modules. Requires a \
+                    nightly toolchain with the `rust-src` component."
+        )]
+        build_std:         bool,
+        #[structopt(
+            name = "strict",
+            long = "strict",
+            help = "Fail on constructs that the chain accepts, but which are almost always \
+                    mistakes: a start function, data segments larger than 64 KiB, exported \
+                    functions that are neither init nor receive functions, a schema that is not \
+                    embedded, and init functions or entrypoints without types in the schema."
+        )]
+        strict:            bool,
+        #[structopt(
+            name = "locked-toolchain",
+            long = "locked-toolchain",
+            help = "Refuse to build unless the versions of rustc, the Wasm target and \
+                    concordium-std match those pinned in the \
+                    `[package.metadata.concordium.toolchain]` table of Cargo.toml."
+        )]
+        locked_toolchain:  bool,
+        #[structopt(
+            name = "format",
+            long = "format",
+            default_value = "human",
+            help = "The format of the output, either `human` or `json`. With `json`, a summary of \
+                    the built modules with their paths, sizes, module references and schema sizes \
+                    is printed to stdout instead of the human-readable output."
+        )]
+        format:            OutputFormat,
+        #[structopt(
+            name = "example",
+            long = "example",
+            conflicts_with = "bin",
+            help = "Build the contract from the given example of the package, which must have the \
+                    `cdylib` crate type, instead of from the library."
+        )]
+        example:           Option<String>,
+        #[structopt(
+            name = "bin",
+            long = "bin",
+            help = "Build the contract from the given binary target of the package instead of \
+                    from the library."
+        )]
+        bin:               Option<String>,
+        #[structopt(
+            name = "remap-paths",
+            long = "remap-paths",
+            conflicts_with_all = &["verifiable", "source-url"],
+            help = "Replace the workspace root, the cargo home and the sysroot of the toolchain \
+                    in paths embedded by rustc with fixed prefixes, and fail if the module still \
+                    contains paths of the build machine. Makes modules independent of the \
+                    directories they are built in."
+        )]
+        remap_paths:       bool,
+        #[structopt(
+            name = "euro-per-energy",
+            long = "euro-per-energy",
+            requires = "micro-ccd-per-euro",
+            help = "The exchange rate from NRG to euro of the chain, used together with \
+                    `--micro-ccd-per-euro` to estimate the cost of deploying the module in CCD."
+        )]
+        euro_per_energy:   Option<f64>,
+        #[structopt(
+            name = "micro-ccd-per-euro",
+            long = "micro-ccd-per-euro",
+            requires = "euro-per-energy",
+            help = "The exchange rate from euro to microCCD of the chain."
+        )]
+        micro_ccd_rate:    Option<f64>,
+        #[structopt(flatten)]
+        package:           PackageOptions,
+        #[structopt(
+            name = "workspace",
+            long = "workspace",
+            alias = "all",
+            conflicts_with_all = &[
+                "package",
+                "out",
+                "out-raw",
+                "module-ref-out",
+                "schema-out",
+                "schema-base64-out",
+                "schema-compare"
+            ],
+            help = "Build every member of the workspace that depends on concordium-std into its \
+                    own module and print a summary. JSON schemas are written to a subdirectory \
+                    per package of the `--schema-json-out` directory."
+        )]
+        workspace:         bool,
         #[structopt(
             raw = true,
             help = "Extra arguments passed to `cargo build` when building Wasm module."
         )]
         cargo_args:        Vec<String>,
     },
-}
-
-#[derive(Debug, StructOpt)]
-#[structopt(name = "runner")]
-struct Runner {
-    #[structopt(name = "module", long = "module", help = "Binary module source.")]
-    module:              PathBuf,
-    #[structopt(
-        name = "out-bin",
-        long = "out-bin",
-        help = "Where to write the new contract state to in binary format."
-    )]
-    out_bin:             Option<PathBuf>,
-    #[structopt(
-        name = "out-json",
-        long = "out-json",
-        help = "Where to write the new contract state to in JSON format, requiring the module to \
-                have an appropriate schema embedded or otherwise provided by --schema. This only \
-                applies to V0 contracts."
-    )]
-    out_json:            Option<PathBuf>,
-    #[structopt(
-        name = "ignore-state-schema",
-        long = "ignore-state-schema",
-        help = "Disable displaying the state as JSON when a schema for the state is present. This \
-                only applies to V0 contracts."
-    )]
-    ignore_state_schema: bool,
-    #[structopt(
-        name = "amount",
-        long = "amount",
-        help = "The amount of CCD to invoke the method with.",
-        default_value = "0"
-    )]
-    amount:              Amount,
-    #[structopt(
-        name = "schema",
-        long = "schema",
-        help = "Path to a file with a schema for parsing parameter (or state only for V0 \
-                contracts) in JSON."
-    )]
-    schema_path:         Option<PathBuf>,
-    #[structopt(
-        name = "parameter-bin",
-        long = "parameter-bin",
-        conflicts_with = "parameter-json",
-        help = "Path to a binary file with a parameter to invoke the method with. Parameter \
-                defaults to an empty array if this is not given."
-    )]
-    parameter_bin_path:  Option<PathBuf>,
-    #[structopt(
-        name = "parameter-json",
-        long = "parameter-json",
-        conflicts_with = "parameter-bin",
-        help = "Path to a JSON file with a parameter to invoke the method with. The JSON is \
-                parsed using a schema, requiring the module to have an appropriate schema \
-                embedded or otherwise provided by --schema."
-    )]
-    parameter_json_path: Option<PathBuf>,
     #[structopt(
-        name = "energy",
-        long = "energy",
-        help = "Initial amount of interpreter energy to invoke the contract call with. Note that \
-                interpreter energy is not the same as NRG, there is a conversion factor between \
-                them.",
-        default_value = "1000000"
+        name = "check-standard",
+        about = "Check that the contracts in a module expose the entrypoints, schema types and \
+                 events of one or more of the Concordium interface standards (CIS-0, CIS-2, ...). \
+                 The behavior of the entrypoints is not checked."
     )]
-    energy:              InterpreterEnergy,
-}
-
-#[derive(Debug, StructOpt)]
-enum RunCommand {
-    #[structopt(name = "init", about = "Initialize a module.")]
-    Init {
-        #[structopt(
-            name = "contract",
-            long = "contract",
-            short = "c",
-            help = "Name of the contract to instantiate."
-        )]
-        contract_name:        String,
+    CheckStandard {
         #[structopt(
-            name = "context",
-            long = "context",
-            short = "t",
-            help = "Path to the init context file."
+            name = "module",
+            long = "module",
+            short = "m",
+            required_unless = "list",
+            help = "Path and filename to a file with a smart contract module (expected input: \
+                    `./my/path/module.wasm.v1`)."
         )]
-        context:              Option<PathBuf>,
+        module_path: Option<PathBuf>,
         #[structopt(
-            name = "display-state",
-            long = "display-state",
-            help = "Pretty print the contract state at the end of execution."
+            name = "standard",
+            long = "standard",
+            number_of_values = 1,
+            required_unless = "list",
+            help = "Identifier of a standard to check, e.g., `CIS-2`. Can be used multiple times."
         )]
-        should_display_state: bool,
-        #[structopt(flatten)]
-        runner:               Runner,
-    },
-    #[structopt(name = "update", about = "Invoke a receive method of a module.")]
-    Receive {
+        standards:   Vec<String>,
         #[structopt(
             name = "contract",
             long = "contract",
             short = "c",
-            help = "Name of the contract to receive message."
+            help = "Only check the contract with this name. By default all contracts in the \
+                    module are checked."
         )]
-        contract_name: String,
+        contract:    Option<String>,
         #[structopt(
-            name = "entrypoint",
-            long = "entrypoint",
-            short = "f",
-            help = "Name of the entrypoint to invoke."
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with a schema to use instead of the schema embedded in the \
+                    module."
         )]
-        entrypoint:    String,
-
+        schema_path: Option<PathBuf>,
         #[structopt(
-            name = "state-json",
-            long = "state-json",
-            help = "File with existing state of the contract in JSON, requires a schema is \
-                    present either embedded or using --schema."
+            name = "definitions",
+            long = "definitions",
+            number_of_values = 1,
+            help = "Path to a JSON file with additional standard definitions. Definitions with \
+                    the same identifier as a built-in standard replace it. Can be used multiple \
+                    times."
         )]
-        state_json_path:      Option<PathBuf>,
+        definitions: Vec<PathBuf>,
         #[structopt(
-            name = "state-bin",
-            long = "state-bin",
-            help = "File with existing state of the contract in binary."
+            name = "list",
+            long = "list",
+            help = "List the known standards instead of checking a module."
         )]
-        state_bin_path:       Option<PathBuf>,
+        list:        bool,
+    },
+    #[structopt(
+        name = "audit",
+        about = "Check a smart contract module for common vulnerabilities using static heuristics."
+    )]
+    Audit {
         #[structopt(
-            name = "balance",
-            long = "balance",
-            help = "Balance on the contract at the time it is invoked. Overrides the balance in \
-                    the receive context."
+            name = "module",
+            long = "module",
+            short = "m",
+            help = "Path and filename to a file with a smart contract module (expected input: \
+                    `./my/path/module.wasm.v1`)."
         )]
-        balance:              Option<u64>,
+        module_path: PathBuf,
         #[structopt(
-            name = "context",
-            long = "context",
-            short = "t",
-            help = "Path to the receive context file."
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with a schema to use instead of the schema embedded in the \
+                    module."
         )]
-        context:              Option<PathBuf>,
+        schema_path: Option<PathBuf>,
         #[structopt(
-            name = "display-state",
-            long = "display-state",
-            help = "Pretty print the contract state at the end of execution."
+            name = "fail-on",
+            long = "fail-on",
+            default_value = "high",
+            help = "Exit with a non-zero exit code if there are findings of this severity or \
+                    above. One of `info`, `low`, `medium` or `high`."
         )]
-        should_display_state: bool,
-        #[structopt(flatten)]
-        runner:               Runner,
+        fail_on:     audit::Severity,
     },
-}
-
-const WARNING_STYLE: ansi_term::Color = ansi_term::Color::Yellow;
+    #[structopt(
+        name = "export-ir",
+        about = "Export a smart contract module, its schema and metering information to a JSON \
+                 intermediate representation for use by verification tools."
+    )]
+    ExportIr {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            help = "Path and filename to a file with a smart contract module (expected input: \
+                    `./my/path/module.wasm.v1`)."
+        )]
+        module_path: PathBuf,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with a schema to use instead of the schema embedded in the \
+                    module."
+        )]
+        schema_path: Option<PathBuf>,
+        #[structopt(
+            name = "out",
+            long = "out",
+            short = "o",
+            default_value = "-",
+            help = "Path and filename to write the intermediate representation to, or `-` to \
+                    print it to the console."
+        )]
+        out:         PathBuf,
+    },
+    #[structopt(
+        name = "coverage",
+        about = "Report the behavioral coverage recorded by `cargo concordium run --coverage` for \
+                 the contracts in a module."
+    )]
+    Coverage {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            help = "Path and filename to a file with a smart contract module (expected input: \
+                    `./my/path/module.wasm.v1`)."
+        )]
+        module_path: PathBuf,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with a schema to use instead of the schema embedded in the \
+                    module."
+        )]
+        schema_path: Option<PathBuf>,
+        #[structopt(
+            name = "data",
+            long = "data",
+            short = "d",
+            help = "Path to the file with the recorded coverage."
+        )]
+        data:        PathBuf,
+        #[structopt(
+            name = "fail-under",
+            long = "fail-under",
+            help = "Exit with a non-zero exit code if the coverage in percent is below this value."
+        )]
+        fail_under:  Option<f64>,
+    },
+    #[structopt(
+        name = "model-test",
+        about = "Run random sequences of commands described by a model against a V1 contract and \
+                 check that the contract behaves like the model."
+    )]
+    ModelTest {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            help = "Path and filename to a file with a smart contract module (expected input: \
+                    `./my/path/module.wasm.v1`)."
+        )]
+        module_path:  PathBuf,
+        #[structopt(name = "model", long = "model", help = "Path to the JSON model file.")]
+        model_path:   PathBuf,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with a schema to use instead of the schema embedded in the \
+                    module."
+        )]
+        schema_path:  Option<PathBuf>,
+        #[structopt(
+            name = "runs",
+            long = "runs",
+            default_value = "100",
+            help = "The number of random sequences to run."
+        )]
+        runs:         u64,
+        #[structopt(
+            name = "length",
+            long = "length",
+            default_value = "20",
+            help = "The maximum number of commands in a sequence."
+        )]
+        length:       usize,
+        #[structopt(
+            name = "seed",
+            long = "seed",
+            help = "Seed for generating the sequences. A random seed is used if not given."
+        )]
+        seed:         Option<u64>,
+        #[structopt(
+            name = "energy",
+            long = "energy",
+            default_value = "1000000",
+            help = "Interpreter energy to give each call."
+        )]
+        energy:       InterpreterEnergy,
+        #[structopt(
+            name = "state-growth",
+            long = "state-growth",
+            help = "Report entrypoints that add state entries in most of their calls, which \
+                    indicates unbounded state growth."
+        )]
+        state_growth: bool,
+        #[structopt(
+            name = "mock",
+            long = "mock",
+            number_of_values = 1,
+            help = "Path to a mock of a contract that is called, as generated by `cargo \
+                    concordium generate-mock`. Can be given multiple times."
+        )]
+        mocks:        Vec<PathBuf>,
+    },
+    #[structopt(
+        name = "fuzz",
+        about = "Invoke an entrypoint of a V1 contract with random parameters generated from the \
+                 schema, and random amounts and contexts, and report inputs that cause traps or \
+                 excessive energy use."
+    )]
+    Fuzz {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            help = "Path and filename to a file with a smart contract module (expected input: \
+                    `./my/path/module.wasm.v1`)."
+        )]
+        module_path:      PathBuf,
+        #[structopt(
+            name = "contract",
+            long = "contract",
+            short = "c",
+            help = "Contract name."
+        )]
+        contract:         String,
+        #[structopt(
+            name = "entrypoint",
+            long = "entrypoint",
+            short = "e",
+            help = "The entrypoint to fuzz."
+        )]
+        entrypoint:       String,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with a schema to use instead of the schema embedded in the \
+                    module."
+        )]
+        schema_path:      Option<PathBuf>,
+        #[structopt(
+            name = "state-bin",
+            long = "state-bin",
+            help = "Path to the file with the state of the instance in binary format."
+        )]
+        state_bin_path:   Option<PathBuf>,
+        #[structopt(
+            name = "state-json",
+            long = "state-json",
+            help = "Path to the file with the state of the instance in JSON format."
+        )]
+        state_json_path:  Option<PathBuf>,
+        #[structopt(
+            name = "balance",
+            long = "balance",
+            default_value = "0",
+            help = "Balance of the instance in CCD, such as `12.5`."
+        )]
+        balance:          Amount,
+        #[structopt(
+            name = "context",
+            long = "context",
+            short = "t",
+            help = "Path to a receive context file with fields to use in every call. The slot \
+                    time, sender, invoker and owner are generated unless given."
+        )]
+        context_path:     Option<PathBuf>,
+        #[structopt(
+            name = "runs",
+            long = "runs",
+            default_value = "1000",
+            help = "The number of calls to make."
+        )]
+        runs:             u64,
+        #[structopt(
+            name = "seed",
+            long = "seed",
+            help = "Seed for generating the inputs. A random seed is used if not given."
+        )]
+        seed:             Option<u64>,
+        #[structopt(
+            name = "energy",
+            long = "energy",
+            default_value = "1000000",
+            help = "Interpreter energy to give each call."
+        )]
+        energy:           InterpreterEnergy,
+        #[structopt(
+            name = "energy-threshold",
+            long = "energy-threshold",
+            help = "Report calls that use more interpreter energy than this."
+        )]
+        energy_threshold: Option<u64>,
+        #[structopt(
+            name = "max-amount",
+            long = "max-amount",
+            default_value = "1000",
+            help = "The largest amount of CCD to send with a call, such as `12.5`."
+        )]
+        max_amount:       Amount,
+        #[structopt(
+            name = "out",
+            long = "out",
+            short = "o",
+            default_value = "fuzz-findings",
+            help = "The directory to write a reproducer of each finding to."
+        )]
+        out_dir:          PathBuf,
+        #[structopt(
+            name = "mock",
+            long = "mock",
+            number_of_values = 1,
+            help = "Path to a mock of a contract that is called, as generated by `cargo \
+                    concordium generate-mock`. Can be given multiple times."
+        )]
+        mocks:            Vec<PathBuf>,
+        #[structopt(
+            name = "state-growth",
+            long = "state-growth",
+            help = "Report if the entrypoint adds state entries in most of its calls, which \
+                    indicates unbounded state growth."
+        )]
+        state_growth:     bool,
+    },
+    #[structopt(
+        name = "estimate-costs",
+        about = "Project the costs of using a V1 contract from a usage profile by simulating \
+                 representative calls."
+    )]
+    EstimateCosts {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            help = "Path and filename to a file with a smart contract module (expected input: \
+                    `./my/path/module.wasm.v1`)."
+        )]
+        module_path:  PathBuf,
+        #[structopt(
+            name = "profile",
+            long = "profile",
+            help = "Path to the JSON usage profile."
+        )]
+        profile_path: PathBuf,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with a schema to use instead of the schema embedded in the \
+                    module."
+        )]
+        schema_path:  Option<PathBuf>,
+        #[structopt(
+            name = "days",
+            long = "days",
+            default_value = "30",
+            help = "The number of days to project the costs over."
+        )]
+        days:         u64,
+        #[structopt(
+            name = "samples",
+            long = "samples",
+            default_value = "20",
+            help = "The number of calls to simulate for each entrypoint in the profile."
+        )]
+        samples:      usize,
+        #[structopt(
+            name = "seed",
+            long = "seed",
+            default_value = "0",
+            help = "Seed for choosing parameters from the distributions in the profile."
+        )]
+        seed:         u64,
+        #[structopt(
+            name = "energy",
+            long = "energy",
+            default_value = "1000000",
+            help = "Interpreter energy to give each call."
+        )]
+        energy:       InterpreterEnergy,
+        #[structopt(
+            name = "mock",
+            long = "mock",
+            number_of_values = 1,
+            help = "Path to a mock of a contract that is called, as generated by `cargo \
+                    concordium generate-mock`. Can be given multiple times."
+        )]
+        mocks:        Vec<PathBuf>,
+    },
+    #[structopt(
+        name = "explore-orders",
+        about = "Execute independent calls of a scenario in different orders on a V1 contract and \
+                 report orders that lead to different final states."
+    )]
+    ExploreOrders {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            help = "Path and filename to a file with a smart contract module (expected input: \
+                    `./my/path/module.wasm.v1`)."
+        )]
+        module_path:   PathBuf,
+        #[structopt(
+            name = "scenario",
+            long = "scenario",
+            help = "Path to the scenario, in YAML if it has the extension .yaml or .yml and in \
+                    JSON otherwise."
+        )]
+        scenario_path: PathBuf,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with a schema to use instead of the schema embedded in the \
+                    module."
+        )]
+        schema_path:   Option<PathBuf>,
+        #[structopt(
+            name = "max-orders",
+            long = "max-orders",
+            default_value = "5040",
+            help = "Explore all orders if there are at most this many, and this many random \
+                    orders otherwise."
+        )]
+        max_orders:    u64,
+        #[structopt(
+            name = "seed",
+            long = "seed",
+            help = "Seed for choosing random orders. A random seed is used if not given."
+        )]
+        seed:          Option<u64>,
+        #[structopt(
+            name = "energy",
+            long = "energy",
+            default_value = "1000000",
+            help = "Interpreter energy to give each call."
+        )]
+        energy:        InterpreterEnergy,
+        #[structopt(
+            name = "mock",
+            long = "mock",
+            number_of_values = 1,
+            help = "Path to a mock of a contract that is called, as generated by `cargo \
+                    concordium generate-mock`. Can be given multiple times."
+        )]
+        mocks:         Vec<PathBuf>,
+        #[structopt(
+            name = "report-html",
+            long = "report-html",
+            help = "Write a self-contained HTML report of the explored orders to `index.html` in \
+                    the given directory."
+        )]
+        report_html:   Option<PathBuf>,
+    },
+    #[structopt(
+        name = "generate-mock",
+        about = "Generate a mock of a contract from its schema, which can be edited and used in \
+                 place of the contract by the simulation commands."
+    )]
+    GenerateMock {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            conflicts_with = "schema",
+            required_unless = "schema",
+            help = "Path and filename to a file with a smart contract module with an embedded \
+                    schema (expected input: `./my/path/module.wasm.v1`)."
+        )]
+        module_path: Option<PathBuf>,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with the schema of the module."
+        )]
+        schema_path: Option<PathBuf>,
+        #[structopt(
+            name = "contract",
+            long = "contract",
+            short = "c",
+            help = "Contract name."
+        )]
+        contract:    String,
+        #[structopt(
+            name = "index",
+            long = "index",
+            default_value = "1",
+            help = "The index of the address of the mock."
+        )]
+        index:       u64,
+        #[structopt(
+            name = "subindex",
+            long = "subindex",
+            default_value = "0",
+            help = "The subindex of the address of the mock."
+        )]
+        subindex:    u64,
+        #[structopt(
+            name = "out",
+            long = "out",
+            short = "o",
+            help = "Where to write the mock."
+        )]
+        out:         PathBuf,
+    },
+    #[structopt(
+        name = "print-parameter",
+        about = "Print a template of the JSON parameter of an init function or entrypoint, as \
+                 described by the schema, to be filled in and used with `--parameter-json`."
+    )]
+    PrintParameter {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            conflicts_with = "schema",
+            required_unless = "schema",
+            help = "Path and filename to a file with a smart contract module with an embedded \
+                    schema (expected input: `./my/path/module.wasm.v1`)."
+        )]
+        module_path: Option<PathBuf>,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with the schema of the module."
+        )]
+        schema_path: Option<PathBuf>,
+        #[structopt(name = "contract", help = "Contract name.")]
+        contract:    String,
+        #[structopt(
+            name = "entrypoint",
+            help = "Name of the entrypoint. The parameter of the init function is printed if this \
+                    is not given."
+        )]
+        entrypoint:  Option<String>,
+    },
+    #[structopt(
+        name = "replay",
+        about = "Step forwards and backwards through a trace recorded by `cargo concordium run \
+                 update --record-trace`."
+    )]
+    Replay {
+        #[structopt(name = "trace", long = "trace", help = "Path to the recorded trace.")]
+        trace:     PathBuf,
+        #[structopt(
+            name = "print",
+            long = "print",
+            help = "Print all steps instead of stepping through them interactively."
+        )]
+        print_all: bool,
+    },
+    #[structopt(
+        name = "rehearse-upgrade",
+        about = "Upgrade an instance of a V1 contract to a new module, migrate its state and \
+                 check the migrated state using view entrypoints of the new module."
+    )]
+    RehearseUpgrade {
+        #[structopt(
+            name = "old-module",
+            long = "old-module",
+            help = "Path and filename to the module the instance currently uses."
+        )]
+        old_module_path: PathBuf,
+        #[structopt(
+            name = "new-module",
+            long = "new-module",
+            help = "Path and filename to the module the instance is upgraded to."
+        )]
+        new_module_path: PathBuf,
+        #[structopt(
+            name = "state-bin",
+            long = "state-bin",
+            help = "Path to the file with the current state of the instance in binary format."
+        )]
+        state_bin_path:  PathBuf,
+        #[structopt(
+            name = "balance",
+            long = "balance",
+            default_value = "0",
+            help = "Balance of the instance in CCD, such as `12.5`."
+        )]
+        balance:         Amount,
+        #[structopt(
+            name = "rehearsal",
+            long = "rehearsal",
+            help = "Path to the JSON file describing the upgrade and migration calls and the \
+                    checks of the migrated state."
+        )]
+        rehearsal_path:  PathBuf,
+        #[structopt(
+            name = "out-bin",
+            long = "out-bin",
+            help = "Where to write the migrated state in binary format."
+        )]
+        out_bin:         Option<PathBuf>,
+        #[structopt(
+            name = "energy",
+            long = "energy",
+            default_value = "1000000",
+            help = "Interpreter energy to give each call."
+        )]
+        energy:          InterpreterEnergy,
+        #[structopt(
+            name = "old-schema",
+            long = "old-schema",
+            help = "Path to a file with a schema to use instead of the schema embedded in the old \
+                    module."
+        )]
+        old_schema_path: Option<PathBuf>,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            short = "s",
+            help = "Path to a file with a schema to use instead of the schema embedded in the new \
+                    module."
+        )]
+        schema_path:     Option<PathBuf>,
+    },
+    #[structopt(
+        name = "verify-source",
+        about = "Check that a module is reproduced by rebuilding the source it was built from, as \
+                 embedded by `cargo concordium build --source-url`."
+    )]
+    VerifySource {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            required_unless = "node",
+            conflicts_with = "node",
+            help = "Path and filename to a file with a smart contract module (expected input: \
+                    `./my/path/module.wasm.v1`)."
+        )]
+        module_path: Option<PathBuf>,
+        #[structopt(
+            name = "module-ref",
+            long = "module-ref",
+            help = "The expected reference of the module, e.g., of a module deployed on chain."
+        )]
+        module_ref:  Option<String>,
+        #[structopt(
+            name = "node",
+            long = "node",
+            requires = "module-ref",
+            help = "The gRPC V2 endpoint of a node to fetch the module with the reference given \
+                    by --module-ref from, instead of reading it from a file."
+        )]
+        node:        Option<String>,
+        #[structopt(
+            name = "image",
+            long = "image",
+            help = "The container image to rebuild the source in instead of the default pinned \
+                    image of verifiable builds."
+        )]
+        image:       Option<String>,
+        #[structopt(
+            name = "attestation",
+            long = "attestation",
+            help = "Where to write the verification attestation in JSON format."
+        )]
+        attestation: Option<PathBuf>,
+    },
+    #[structopt(
+        name = "verify-build",
+        about = "Check that building the local source reproduces a given module byte for byte, \
+                 and show where the modules differ otherwise."
+    )]
+    VerifyBuild {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            required_unless = "module-ref",
+            help = "The module to reproduce (expected input: `./my/path/module.wasm.v1`). The \
+                    settings it was built with are taken from its build recipe or source link, if \
+                    it has one."
+        )]
+        module_path: Option<PathBuf>,
+        #[structopt(
+            name = "module-ref",
+            long = "module-ref",
+            help = "The reference of the module to reproduce, e.g., of a module deployed on chain."
+        )]
+        module_ref:  Option<String>,
+        #[structopt(flatten)]
+        package:     PackageOptions,
+        #[structopt(
+            raw = true,
+            help = "Arguments of `cargo concordium build` to rebuild with, other than `--out`, \
+                    e.g., `-- --schema-embed --opt`. They take precedence over the settings \
+                    recorded in the module."
+        )]
+        build_args:  Vec<String>,
+    },
+    #[structopt(
+        name = "inspect",
+        about = "Show information about a smart contract module, or extract data embedded into it."
+    )]
+    Inspect {
+        #[structopt(
+            name = "module",
+            long = "module",
+            short = "m",
+            help = "Path and filename to a file with a smart contract module (expected input: \
+                    `./my/path/module.wasm.v1`)."
+        )]
+        module_path: PathBuf,
+        #[structopt(
+            name = "sbom",
+            long = "sbom",
+            help = "Extract the software bill of materials embedded by `cargo concordium build \
+                    --sbom`."
+        )]
+        sbom:        bool,
+        #[structopt(
+            name = "out",
+            long = "out",
+            short = "o",
+            default_value = "-",
+            help = "Path and filename to write the extracted data to, or `-` to print it to the \
+                    console."
+        )]
+        out:         PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, StructOpt)]
+#[structopt(name = "runner")]
+struct Runner {
+    #[structopt(
+        name = "module",
+        long = "module",
+        help = "Binary module source. When updating an instance of --chain-dir, the module of the \
+                instance is used."
+    )]
+    module:              Option<PathBuf>,
+    #[structopt(
+        name = "out-bin",
+        long = "out-bin",
+        help = "Where to write the new contract state to in binary format."
+    )]
+    out_bin:             Option<PathBuf>,
+    #[structopt(
+        name = "out-json",
+        long = "out-json",
+        help = "Where to write the new contract state to in JSON format. For V0 contracts this \
+                requires the module to have an appropriate schema embedded or otherwise provided \
+                by --schema. For V1 contracts the state is written as a tree of hex encoded keys \
+                and values."
+    )]
+    out_json:            Option<PathBuf>,
+    #[structopt(
+        name = "receipt-out",
+        long = "receipt-out",
+        help = "Write a receipt of the invocation to the given file. The receipt is a JSON record \
+                of the module reference, the context, amount, parameter and energy used, the \
+                outcome, events, return value, energy spent and the hash of the resulting state. \
+                This is only supported for V1 contracts."
+    )]
+    receipt_out:         Option<PathBuf>,
+    #[structopt(
+        name = "expect",
+        long = "expect",
+        help = "Path to a JSON or YAML file with the expected outcome, reject reason, return \
+                value, events and maximum interpreter energy of the invocation. The command fails \
+                with a diff of the fields that do not match. This is only supported for V1 \
+                contracts."
+    )]
+    expect:              Option<PathBuf>,
+    #[structopt(
+        name = "ignore-state-schema",
+        long = "ignore-state-schema",
+        help = "Disable displaying the state as JSON when a schema for the state is present. This \
+                only applies to V0 contracts."
+    )]
+    ignore_state_schema: bool,
+    #[structopt(
+        name = "event-schema",
+        long = "event-schema",
+        help = "Path to a file with the schema type of the logs, either serialized or as base64, \
+                which is used to display the logs as JSON. This only applies to V0 contracts, \
+                since V0 schemas have no event type."
+    )]
+    event_schema:        Option<PathBuf>,
+    #[structopt(
+        name = "amount",
+        long = "amount",
+        help = "The amount of CCD to invoke the method with, such as `12.5`.",
+        default_value = "0"
+    )]
+    amount:              Amount,
+    #[structopt(
+        name = "amount-micro",
+        long = "amount-micro",
+        conflicts_with = "amount",
+        help = "The amount to invoke the method with in microCCD, instead of --amount."
+    )]
+    amount_micro:        Option<u64>,
+    #[structopt(
+        name = "schema",
+        long = "schema",
+        help = "Path to a file with a schema for parsing parameter (or state only for V0 \
+                contracts) in JSON."
+    )]
+    schema_path:         Option<PathBuf>,
+    #[structopt(
+        name = "parameter-bin",
+        long = "parameter-bin",
+        conflicts_with_all = &["parameter-json", "parameter-json-inline", "parameter-hex"],
+        help = "Path to a binary file with a parameter to invoke the method with. Parameter \
+                defaults to an empty array if this is not given."
+    )]
+    parameter_bin_path:  Option<PathBuf>,
+    #[structopt(
+        name = "parameter-json",
+        long = "parameter-json",
+        conflicts_with_all = &["parameter-bin", "parameter-json-inline", "parameter-hex"],
+        help = "Path to a JSON file with a parameter to invoke the method with. The JSON is \
+                parsed using a schema, requiring the module to have an appropriate schema \
+                embedded or otherwise provided by --schema."
+    )]
+    parameter_json_path: Option<PathBuf>,
+    #[structopt(
+        name = "parameter-json-inline",
+        long = "parameter-json-inline",
+        conflicts_with_all = &["parameter-bin", "parameter-json", "parameter-hex"],
+        help = "A parameter to invoke the method with, given as JSON on the command line. Like \
+                for --parameter-json, the JSON is parsed using the schema."
+    )]
+    parameter_inline:    Option<String>,
+    #[structopt(
+        name = "parameter-hex",
+        long = "parameter-hex",
+        conflicts_with_all = &["parameter-bin", "parameter-json", "parameter-json-inline"],
+        help = "A parameter to invoke the method with, given as hex encoded bytes on the command \
+                line."
+    )]
+    parameter_hex:       Option<String>,
+    #[structopt(
+        name = "energy",
+        long = "energy",
+        help = "Initial amount of interpreter energy to invoke the contract call with. Note that \
+                interpreter energy is not the same as NRG, there is a conversion factor between \
+                them. Use --energy-nrg to give the energy in NRG instead.",
+        default_value = "1000000"
+    )]
+    energy:              InterpreterEnergy,
+    #[structopt(
+        name = "energy-nrg",
+        long = "energy-nrg",
+        help = "Initial amount of energy in NRG to invoke the contract call with, as on the \
+                chain. It is converted to interpreter energy at 1000 interpreter energy per NRG \
+                and takes precedence over --energy."
+    )]
+    energy_nrg:          Option<u64>,
+    #[structopt(
+        name = "energy-breakdown",
+        long = "energy-breakdown",
+        help = "Print a breakdown of the interpreter energy spent on execution, memory, state \
+                operations, logging and invocations. This is only supported when updating V1 \
+                contracts, not for init or for tests, whose energy is all Wasm execution."
+    )]
+    energy_breakdown:    bool,
+    #[structopt(
+        name = "profile",
+        long = "profile",
+        help = "Print the interpreter energy spent in and the number of calls of each host \
+                function, such as state operations, logging and invocations, sorted by energy, \
+                together with the number of calls of contract functions. This is only supported \
+                when updating V1 contracts."
+    )]
+    profile:             bool,
+    #[structopt(
+        name = "events-ndjson",
+        long = "events-ndjson",
+        help = "Write every produced event as a JSON record on a separate line. The target is \
+                either a path to a file, '-' for stdout, or 'tcp://<host>:<port>' for a TCP \
+                socket. This is only supported for V1 contracts."
+    )]
+    events_ndjson:       Option<String>,
+    #[structopt(
+        name = "coverage",
+        long = "coverage",
+        help = "Record which contracts, entrypoints, event variants and error variants were \
+                exercised in the given file. Coverage is accumulated over multiple runs and can \
+                be reported using `cargo concordium coverage`. This is only supported for V1 \
+                contracts."
+    )]
+    coverage:            Option<PathBuf>,
+    #[structopt(
+        name = "record-trace",
+        long = "record-trace",
+        help = "Record a trace of the host function calls, memory and state changes of the \
+                execution to the given file, which can be stepped through using `cargo concordium \
+                replay`. This is only supported when updating V1 contracts."
+    )]
+    record_trace:        Option<PathBuf>,
+    #[structopt(
+        name = "trace",
+        long = "trace",
+        help = "Log every host function call with its arguments, the keys and data they refer to, \
+                its result and the energy at that point. The log is written to the given file, or \
+                to stderr if no file is given. This is only supported when updating V1 contracts."
+    )]
+    trace:               Option<Option<PathBuf>>,
+    #[structopt(
+        name = "check-determinism",
+        long = "check-determinism",
+        help = "Execute the invocation twice, the second time on a fresh copy of the state, and \
+                fail unless the outcome, return value, events, energy and resulting state are \
+                identical. This is only supported for V1 contracts."
+    )]
+    check_determinism:   bool,
+    #[structopt(
+        name = "bench",
+        long = "bench",
+        help = "Execute the invocation the given number of times against the same initial state \
+                and report the minimum, median and maximum wall time and interpreter energy, and \
+                the time to instantiate the module separately. This is only supported for V1 \
+                contracts."
+    )]
+    bench:               Option<usize>,
+    #[structopt(
+        name = "protocol-version",
+        long = "protocol-version",
+        number_of_values = 1,
+        help = "Protocol version whose interpreter settings to use, such as the limits on \
+                parameters, logs and return values and the support for upgrades and queries. Can \
+                be given multiple times to run the invocation once for each version. Defaults to \
+                the latest version."
+    )]
+    protocol_versions:   Vec<protocol::ProtocolVersion>,
+    #[structopt(
+        name = "format",
+        long = "format",
+        default_value = "human",
+        conflicts_with = "display-state",
+        help = "The format of the output, either `human` or `json`. With `json`, a JSON document \
+                with the outcome, return value, events, state change, energy and interrupt of the \
+                invocation is printed to stdout instead of the human-readable output. This is \
+                only supported for V1 contracts."
+    )]
+    format:              OutputFormat,
+    #[structopt(
+        name = "diff-values",
+        long = "diff-values",
+        help = "Show the start of the values in the changes to the state printed after updating \
+                V1 contracts."
+    )]
+    diff_values:         bool,
+    #[structopt(flatten)]
+    state_display:       StateDisplayOptions,
+    #[structopt(flatten)]
+    context:             ContextOverrides,
+    #[structopt(flatten)]
+    crypto:              CryptoOverrides,
+    #[structopt(
+        name = "nrg-price",
+        long = "nrg-price",
+        conflicts_with_all = &["euro-per-energy", "micro-ccd-per-euro"],
+        help = "The price of one NRG in microCCD, used to estimate the cost of the invocation in \
+                microCCD."
+    )]
+    nrg_price:           Option<f64>,
+    #[structopt(
+        name = "euro-per-energy",
+        long = "euro-per-energy",
+        requires = "micro-ccd-per-euro",
+        help = "The exchange rate from NRG to euro of the chain, used together with \
+                `--micro-ccd-per-euro` to estimate the cost of the invocation in microCCD."
+    )]
+    euro_per_energy:     Option<f64>,
+    #[structopt(
+        name = "micro-ccd-per-euro",
+        long = "micro-ccd-per-euro",
+        requires = "euro-per-energy",
+        help = "The exchange rate from euro to microCCD of the chain."
+    )]
+    micro_ccd_rate:      Option<f64>,
+    #[structopt(
+        name = "chain-dir",
+        long = "chain-dir",
+        help = "Directory of a local chain of V1 contract instances. `run init` records the \
+                created instance, and `run update` updates the instance at --self-address with \
+                its latest state and records the result."
+    )]
+    chain_dir:           Option<PathBuf>,
+}
+
+impl Runner {
+    /// The price of one NRG in microCCD, if given directly or by the exchange
+    /// rates.
+    fn micro_ccd_per_nrg(&self) -> Option<f64> {
+        self.nrg_price
+            .or_else(|| Some(self.euro_per_energy? * self.micro_ccd_rate?))
+    }
+}
+
+/// Limits on the part of the state of a V1 contract that is displayed, and how
+/// its values are rendered.
+#[derive(Debug, Clone, Default, StructOpt)]
+struct StateDisplayOptions {
+    #[structopt(
+        name = "display-state-depth",
+        long = "display-state-depth",
+        help = "Only display the nodes of the state up to the given depth below the root, and \
+                show the number of entries below deeper nodes instead. When running a contract, \
+                this implies --display-state."
+    )]
+    depth:           Option<usize>,
+    #[structopt(
+        name = "display-state-prefix",
+        long = "display-state-prefix",
+        help = "Only display the entries of the state with keys that start with the given hex \
+                encoded prefix. When running a contract, this implies --display-state."
+    )]
+    prefix:          Option<String>,
+    #[structopt(
+        name = "max-value-bytes",
+        long = "max-value-bytes",
+        help = "Only display the given number of bytes of longer values of the state, followed by \
+                their length. When running a contract, this implies --display-state."
+    )]
+    max_value_bytes: Option<usize>,
+    #[structopt(
+        name = "hexdump",
+        long = "hexdump",
+        help = "Display the values of the state as a hexdump with offsets and printable \
+                characters. When running a contract, this implies --display-state."
+    )]
+    hexdump:         bool,
+    #[structopt(
+        name = "utf8-preview",
+        long = "utf8-preview",
+        help = "Display the values of the state decoded as UTF-8 next to their bytes. When \
+                running a contract, this implies --display-state."
+    )]
+    utf8_preview:    bool,
+}
+
+impl StateDisplayOptions {
+    /// Whether the display of the state is limited.
+    fn is_limited(&self) -> bool { self.depth.is_some() || self.prefix.is_some() }
+
+    /// Whether the display of the state differs from the default tree, either
+    /// because it is limited or because values are rendered differently.
+    fn is_customized(&self) -> bool {
+        self.is_limited() || self.max_value_bytes.is_some() || self.hexdump || self.utf8_preview
+    }
+
+    /// The value as displayed in the label of its node.
+    fn value_label(&self, value: &[u8]) -> String {
+        let shown = match self.max_value_bytes {
+            Some(max_bytes) if value.len() > max_bytes => &value[..max_bytes],
+            _ => value,
+        };
+        let mut label = if self.hexdump {
+            format!("({} B)", value.len())
+        } else if shown.len() < value.len() {
+            format!("{}... ({} B)", hex::encode(shown), value.len())
+        } else {
+            hex::encode(shown)
+        };
+        if self.utf8_preview {
+            label.push(' ');
+            label.push_str(&hexdump::utf8_preview(shown));
+        }
+        label
+    }
+
+    /// The lines of the hexdump of the value, if values are displayed as
+    /// hexdumps.
+    fn value_lines(&self, value: &[u8]) -> Vec<String> {
+        if !self.hexdump {
+            return Vec::new();
+        }
+        match self.max_value_bytes {
+            Some(max_bytes) if value.len() > max_bytes => {
+                let mut lines = hexdump::lines(&value[..max_bytes]);
+                lines.push(format!("... {} more bytes", value.len() - max_bytes));
+                lines
+            }
+            _ => hexdump::lines(value),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum BenchCommand {
+    #[structopt(
+        name = "compare",
+        about = "Run a scenario against two versions of a module and compare the energy used, the \
+                 state growth and the module size."
+    )]
+    Compare {
+        #[structopt(name = "old", help = "Path to the old version of the module.")]
+        old_module_path: PathBuf,
+        #[structopt(name = "new", help = "Path to the new version of the module.")]
+        new_module_path: PathBuf,
+        #[structopt(
+            name = "scenario",
+            long = "scenario",
+            help = "Path to the scenario, in YAML if it has the extension .yaml or .yml and in \
+                    JSON otherwise. The setup calls and the calls are made in order."
+        )]
+        scenario_path:   PathBuf,
+        #[structopt(
+            name = "energy",
+            long = "energy",
+            default_value = "1000000",
+            help = "Interpreter energy to give each call."
+        )]
+        energy:          InterpreterEnergy,
+        #[structopt(
+            name = "mock",
+            long = "mock",
+            number_of_values = 1,
+            help = "Path to a mock of a contract that is called, as generated by `cargo \
+                    concordium generate-mock`. Can be given multiple times."
+        )]
+        mocks:           Vec<PathBuf>,
+        #[structopt(
+            name = "report-html",
+            long = "report-html",
+            help = "Write a self-contained HTML report of the comparison to `index.html` in the \
+                    given directory."
+        )]
+        report_html:     Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum StateCommand {
+    #[structopt(
+        name = "to-json",
+        about = "Convert the state of a V1 contract in binary into JSON, as a tree of hex encoded \
+                 keys and values."
+    )]
+    ToJson {
+        #[structopt(
+            name = "state-bin",
+            long = "state-bin",
+            help = "Path to the state in binary, e.g., as written by --out-bin."
+        )]
+        state_bin_path:  PathBuf,
+        #[structopt(
+            name = "out",
+            long = "out",
+            short = "o",
+            help = "Where to write the JSON. It is written to stdout if this is not given."
+        )]
+        out:             Option<PathBuf>,
+        #[structopt(
+            name = "prefix",
+            long = "prefix",
+            help = "Only include the entries with keys that start with the given hex encoded \
+                    prefix."
+        )]
+        prefix:          Option<String>,
+        #[structopt(
+            name = "truncate-values",
+            long = "truncate-values",
+            help = "Only include the given number of bytes of longer values, followed by `...`, \
+                    together with the length of the full value. The JSON then cannot be read back \
+                    as a state."
+        )]
+        truncate_values: Option<usize>,
+        #[structopt(
+            name = "collections",
+            long = "collections",
+            conflicts_with = "truncate-values",
+            help = "Write the entries grouped by the `concordium-std` collections they belong to \
+                    instead of as a tree. Given a JSON file with the schema types of the root and \
+                    the collections by prefix, the keys and values are decoded. The JSON then \
+                    cannot be read back as a state."
+        )]
+        collections:     Option<Option<PathBuf>>,
+    },
+    #[structopt(
+        name = "from-json",
+        about = "Build the state of a V1 contract in binary from JSON, either the tree written by \
+                 `state to-json` or a list of keys and values."
+    )]
+    FromJson {
+        #[structopt(
+            name = "state-json",
+            long = "state-json",
+            help = "Path to the state in JSON. Keys and values are hex or typed values such as \
+                    `{\"u64\": 5}`, as for `run update --state-json`."
+        )]
+        state_json_path: PathBuf,
+        #[structopt(
+            name = "out",
+            long = "out",
+            short = "o",
+            help = "Where to write the state in binary, which can be given to `run update \
+                    --state-bin`."
+        )]
+        out:             PathBuf,
+    },
+    #[structopt(
+        name = "stats",
+        about = "Print the number of entries, the bytes of keys and values, the shape of the trie \
+                 and the largest values of the state of a V1 contract."
+    )]
+    Stats {
+        #[structopt(
+            name = "state-bin",
+            long = "state-bin",
+            help = "Path to the state in binary, e.g., as written by --out-bin."
+        )]
+        state_bin_path: PathBuf,
+        #[structopt(
+            name = "top",
+            long = "top",
+            default_value = "10",
+            help = "The number of largest values to list."
+        )]
+        top:            usize,
+        #[structopt(
+            name = "format",
+            long = "format",
+            default_value = "human",
+            help = "The format of the output, either `human` or `json`."
+        )]
+        format:         OutputFormat,
+    },
+    #[structopt(
+        name = "get",
+        about = "Print the value at a single key of the state of a V1 contract."
+    )]
+    Get {
+        #[structopt(
+            name = "state-bin",
+            long = "state-bin",
+            help = "Path to the state in binary, e.g., as written by --out-bin."
+        )]
+        state_bin_path:    PathBuf,
+        #[structopt(name = "key", long = "key", help = "The hex encoded key.")]
+        key:               String,
+        #[structopt(
+            name = "value-schema",
+            long = "value-schema",
+            conflicts_with = "raw",
+            help = "Path to a file with the schema type of the value, either serialized or as \
+                    base64, which is used to print the value as JSON."
+        )]
+        value_schema_path: Option<PathBuf>,
+        #[structopt(
+            name = "collections",
+            long = "collections",
+            conflicts_with_all = &["value-schema", "raw"],
+            help = "Path to a JSON file with the schema types of the root and the \
+                    `concordium-std` collections by prefix, which is used to decode the value \
+                    using the type of the collection the key belongs to."
+        )]
+        collections:       Option<PathBuf>,
+        #[structopt(
+            name = "raw",
+            long = "raw",
+            help = "Write the bytes of the value to stdout instead of printing them as hex."
+        )]
+        raw:               bool,
+    },
+    #[structopt(
+        name = "fetch",
+        about = "Download the current state of a V1 contract instance from a node through its \
+                 gRPC V2 API and write it in binary, as for `state from-json`."
+    )]
+    Fetch {
+        #[structopt(
+            name = "node",
+            long = "node",
+            default_value = "http://localhost:20000",
+            help = "The gRPC V2 endpoint of the node."
+        )]
+        node:    String,
+        #[structopt(
+            name = "address",
+            long = "address",
+            help = "The address of the instance, of the form `<index,subindex>`."
+        )]
+        address: ContractAddressArg,
+        #[structopt(
+            name = "block",
+            long = "block",
+            help = "The hash of the block to take the state from instead of the last finalized \
+                    block."
+        )]
+        block:   Option<String>,
+        #[structopt(
+            name = "out",
+            long = "out",
+            short = "o",
+            help = "Where to write the state in binary, which can be given to `run update \
+                    --state-bin`."
+        )]
+        out:     PathBuf,
+    },
+    #[structopt(
+        name = "set",
+        about = "Insert or replace the value at a key of the state of a V1 contract."
+    )]
+    Set {
+        #[structopt(
+            name = "state-bin",
+            long = "state-bin",
+            help = "Path to the state in binary, e.g., as written by --out-bin."
+        )]
+        state_bin_path:    PathBuf,
+        #[structopt(name = "key", long = "key", help = "The hex encoded key.")]
+        key:               String,
+        #[structopt(
+            name = "value",
+            long = "value",
+            required_unless = "value-json",
+            conflicts_with = "value-json",
+            help = "The hex encoded value."
+        )]
+        value:             Option<String>,
+        #[structopt(
+            name = "value-json",
+            long = "value-json",
+            requires = "value-schema",
+            help = "The value as JSON, which is encoded using the type given with \
+                    `--value-schema`."
+        )]
+        value_json:        Option<String>,
+        #[structopt(
+            name = "value-schema",
+            long = "value-schema",
+            help = "Path to a file with the schema type of the value, either serialized or as \
+                    base64."
+        )]
+        value_schema_path: Option<PathBuf>,
+        #[structopt(
+            name = "out",
+            long = "out",
+            short = "o",
+            help = "Where to write the modified state instead of replacing the given state."
+        )]
+        out:               Option<PathBuf>,
+    },
+    #[structopt(
+        name = "delete",
+        about = "Remove the value at a key, or all values with keys that start with a prefix, \
+                 from the state of a V1 contract."
+    )]
+    Delete {
+        #[structopt(
+            name = "state-bin",
+            long = "state-bin",
+            help = "Path to the state in binary, e.g., as written by --out-bin."
+        )]
+        state_bin_path: PathBuf,
+        #[structopt(
+            name = "key",
+            long = "key",
+            required_unless = "prefix",
+            conflicts_with = "prefix",
+            help = "The hex encoded key to remove."
+        )]
+        key:            Option<String>,
+        #[structopt(
+            name = "prefix",
+            long = "prefix",
+            help = "Remove all entries with keys that start with the hex encoded prefix."
+        )]
+        prefix:         Option<String>,
+        #[structopt(
+            name = "out",
+            long = "out",
+            short = "o",
+            help = "Where to write the modified state instead of replacing the given state."
+        )]
+        out:            Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum RunTarget {
+    #[structopt(flatten)]
+    Invocation(RunCommand),
+    #[structopt(
+        name = "scenario",
+        about = "Run a scenario of init and update calls to several instances and check their \
+                 outcomes."
+    )]
+    Scenario {
+        #[structopt(
+            name = "scenario",
+            help = "Path to the scenario file, in YAML if it has the extension .yaml or .yml and \
+                    in JSON otherwise."
+        )]
+        scenario_path: PathBuf,
+        #[structopt(
+            name = "energy",
+            long = "energy",
+            default_value = "1000000",
+            help = "Interpreter energy to give each call."
+        )]
+        energy:        InterpreterEnergy,
+        #[structopt(
+            name = "state-growth",
+            long = "state-growth",
+            help = "Report entrypoints that add state entries in most of their calls, which \
+                    indicates unbounded state growth."
+        )]
+        state_growth:  bool,
+        #[structopt(
+            name = "events-ndjson",
+            long = "events-ndjson",
+            help = "Write the events of the steps as JSON records on separate lines, with the \
+                    number of the step. The target is either a path to a file, '-' for stdout, or \
+                    'tcp://<host>:<port>' for a TCP socket."
+        )]
+        events_ndjson: Option<String>,
+    },
+    #[structopt(
+        name = "smoke",
+        about = "Initialize each contract of a V1 module and call each of its entrypoints with a \
+                 template parameter, reporting which calls succeed, reject or trap."
+    )]
+    Smoke {
+        #[structopt(
+            name = "module",
+            long = "module",
+            help = "Path to the versioned module."
+        )]
+        module_path:       PathBuf,
+        #[structopt(
+            name = "schema",
+            long = "schema",
+            help = "Path to a schema to use instead of the schema embedded in the module. The \
+                    parameters are the templates of the types in the schema, and empty if there \
+                    is no type."
+        )]
+        schema_path:       Option<PathBuf>,
+        #[structopt(
+            name = "init-context",
+            long = "init-context",
+            help = "Path to the init context file. Fields that are not given are filled in."
+        )]
+        init_context_path: Option<PathBuf>,
+        #[structopt(
+            name = "context",
+            long = "context",
+            help = "Path to the receive context file. Fields that are not given are filled in."
+        )]
+        context_path:      Option<PathBuf>,
+        #[structopt(
+            name = "energy",
+            long = "energy",
+            default_value = "1000000",
+            help = "Interpreter energy to give each call."
+        )]
+        energy:            InterpreterEnergy,
+        #[structopt(
+            name = "mock",
+            long = "mock",
+            number_of_values = 1,
+            help = "Path to a mock of a contract that is called, as generated by `cargo \
+                    concordium generate-mock`. Can be given multiple times."
+        )]
+        mocks:             Vec<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum RunCommand {
+    #[structopt(name = "init", about = "Initialize a module.")]
+    Init {
+        #[structopt(
+            name = "contract",
+            long = "contract",
+            short = "c",
+            help = "Name of the contract to instantiate."
+        )]
+        contract_name:        String,
+        #[structopt(
+            name = "context",
+            long = "context",
+            short = "t",
+            help = "Path to the init context file."
+        )]
+        context:              Option<PathBuf>,
+        #[structopt(
+            name = "display-state",
+            long = "display-state",
+            help = "Pretty print the contract state at the end of execution."
+        )]
+        should_display_state: bool,
+        #[structopt(
+            name = "then-update",
+            long = "then-update",
+            number_of_values = 1,
+            help = "After initializing, invoke the given entrypoint on the new instance, \
+                    optionally with a JSON parameter file given as `<entrypoint>=<file>`. Can be \
+                    given multiple times to invoke entrypoints in order. This is only supported \
+                    for V1 contracts."
+        )]
+        then_update:          Vec<UpdateStep>,
+        #[structopt(
+            name = "update-context",
+            long = "update-context",
+            help = "Path to the receive context file used by the entrypoints invoked with \
+                    --then-update."
+        )]
+        update_context:       Option<PathBuf>,
+        #[structopt(flatten)]
+        runner:               Runner,
+    },
+    #[structopt(name = "update", about = "Invoke a receive method of a module.")]
+    Receive {
+        #[structopt(
+            name = "contract",
+            long = "contract",
+            short = "c",
+            help = "Name of the contract to receive message."
+        )]
+        contract_name: String,
+        #[structopt(
+            name = "entrypoint",
+            long = "entrypoint",
+            short = "f",
+            help = "Name of the entrypoint to invoke."
+        )]
+        entrypoint:    String,
+
+        #[structopt(
+            name = "state-json",
+            long = "state-json",
+            help = "File with existing state of the contract in JSON. For V0 contracts this \
+                    requires a schema to be present either embedded or using --schema. For V1 \
+                    contracts the file contains the keys and values of the state."
+        )]
+        state_json_path:      Option<PathBuf>,
+        #[structopt(
+            name = "state-bin",
+            long = "state-bin",
+            help = "File with existing state of the contract in binary."
+        )]
+        state_bin_path:       Option<PathBuf>,
+        #[structopt(
+            name = "interrupt-responses",
+            long = "interrupt-responses",
+            help = "File with a list of JSON responses to the interrupts of V1 contracts. At each \
+                    interrupt, the execution is resumed with the next response until the call \
+                    completes or the responses run out."
+        )]
+        interrupt_responses:  Option<PathBuf>,
+        #[structopt(
+            name = "interrupt-response",
+            long = "interrupt-response",
+            number_of_values = 1,
+            help = "A JSON response to an interrupt of V1 contracts, used after the responses of \
+                    --interrupt-responses. Can be given multiple times."
+        )]
+        interrupt_response:   Vec<String>,
+        #[structopt(
+            name = "instances",
+            long = "instances",
+            help = "File with a list of contract instances of V1 modules, each with its module, \
+                    contract and state. Calls to these instances are executed on them, and the \
+                    invoked contract is resumed with the outcome."
+        )]
+        instances:            Option<PathBuf>,
+        #[structopt(
+            name = "upgrade-module",
+            long = "upgrade-module",
+            number_of_values = 1,
+            help = "A versioned module that V1 contracts can upgrade to. Upgrades to the module \
+                    are answered like on the chain, and the invocation continues. Can be given \
+                    multiple times."
+        )]
+        upgrade_modules:      Vec<PathBuf>,
+        #[structopt(
+            name = "balance",
+            long = "balance",
+            help = "Balance on the contract at the time it is invoked in CCD, such as `12.5`. \
+                    Overrides the balance in the receive context."
+        )]
+        balance:              Option<Amount>,
+        #[structopt(
+            name = "context",
+            long = "context",
+            short = "t",
+            help = "Path to the receive context file."
+        )]
+        context:              Option<PathBuf>,
+        #[structopt(
+            name = "display-state",
+            long = "display-state",
+            help = "Pretty print the contract state at the end of execution."
+        )]
+        should_display_state: bool,
+        #[structopt(flatten)]
+        runner:               Runner,
+    },
+}
+
+/// An entrypoint invoked after initializing with `run init --then-update`,
+/// given as `<entrypoint>` or `<entrypoint>=<parameter file>`.
+#[derive(Debug, Clone)]
+struct UpdateStep {
+    entrypoint: String,
+    /// The JSON parameter file.
+    parameter:  Option<PathBuf>,
+}
+
+impl std::str::FromStr for UpdateStep {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (entrypoint, parameter) = match s.split_once('=') {
+            Some((entrypoint, parameter)) => (entrypoint, Some(PathBuf::from(parameter))),
+            None => (s, None),
+        };
+        ensure!(
+            !entrypoint.is_empty(),
+            "The entrypoint to invoke is missing in '{}'.",
+            s
+        );
+        Ok(UpdateStep {
+            entrypoint: entrypoint.to_string(),
+            parameter,
+        })
+    }
+}
+
+const WARNING_STYLE: ansi_term::Color = ansi_term::Color::Yellow;
+
+/// The format of the output of `cargo concordium build`, `run` and `state
+/// stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    /// Human-readable output on stderr.
+    Human,
+    /// A JSON document on stdout.
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "human" => Ok(OutputFormat::Human),
+            "json" => Ok(OutputFormat::Json),
+            _ => bail!("Unsupported output format '{}'. Use `human` or `json`.", s),
+        }
+    }
+}
+
+/// The format of the output of `cargo concordium display-state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StateFormat {
+    /// A tree printed to stdout.
+    Tree,
+    /// A Graphviz graph in the DOT language printed to stdout.
+    Dot,
+    /// A self-contained HTML page written to a file.
+    Html,
+    /// A line of JSON for each entry, written as the state is traversed.
+    Jsonl,
+}
+
+impl std::str::FromStr for StateFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tree" => Ok(StateFormat::Tree),
+            "dot" => Ok(StateFormat::Dot),
+            "html" => Ok(StateFormat::Html),
+            "jsonl" => Ok(StateFormat::Jsonl),
+            _ => bail!(
+                "Unsupported state format '{}'. Use `tree`, `dot`, `html` or `jsonl`.",
+                s
+            ),
+        }
+    }
+}
+
+pub fn main() -> anyhow::Result<()> {
+    #[cfg(target_os = "windows")]
+    {
+        ansi_term::enable_ansi_support();
+    }
+    let success_style = ansi_term::Color::Green.bold();
+    let bold_style = ansi_term::Style::new().bold();
+
+    let cmd = {
+        let app = CargoCommand::clap()
+            .setting(AppSettings::ArgRequiredElseHelp)
+            .global_setting(AppSettings::TrailingVarArg)
+            .global_setting(AppSettings::ColoredHelp);
+        let matches = app.get_matches();
+        let CargoCommand::Concordium(cmd) = CargoCommand::from_clap(&matches);
+        cmd
+    };
+    match cmd {
+        Command::Run(run_target) => {
+            let mut run_cmd = match *run_target {
+                RunTarget::Invocation(run_cmd) => Box::new(run_cmd),
+                RunTarget::Scenario {
+                    scenario_path,
+                    energy,
+                    state_growth,
+                    events_ndjson,
+                } => {
+                    let passed =
+                        run_scenario::run(&scenario_path, &run_scenario::ScenarioOptions {
+                            energy,
+                            state_growth,
+                            events_ndjson,
+                        })?;
+                    ensure!(
+                        passed,
+                        "Some steps of the scenario did not have the expected outcome."
+                    );
+                    return Ok(());
+                }
+                RunTarget::Smoke {
+                    module_path,
+                    schema_path,
+                    init_context_path,
+                    context_path,
+                    energy,
+                    mocks,
+                } => {
+                    let (wasm_version, module) = read_versioned_module(&module_path)?;
+                    ensure!(
+                        versions::descriptor(wasm_version).supports_simulation,
+                        "Smoke tests are only supported for V1 contracts."
+                    );
+                    let read_context = |path: Option<PathBuf>| -> anyhow::Result<_> {
+                        match path {
+                            Some(path) => serde_json::from_slice(
+                                &fs::read(&path).context("Could not read the context file.")?,
+                            )
+                            .context("Could not parse the context file."),
+                            None => Ok(serde_json::json!({})),
+                        }
+                    };
+                    let init_ctx = smoke::init_context(&read_context(init_context_path)?)?;
+                    let receive_ctx = smoke::receive_context(&read_context(context_path)?)?;
+                    let exports = get_module_exports(&module, wasm_version)?;
+                    let schema = get_optional_schema(&module, wasm_version, schema_path)?;
+                    let sim = simulator(&module, energy, &mocks)?;
+                    let failures =
+                        smoke::run(&sim, &exports, schema.as_ref(), &init_ctx, &receive_ctx)?;
+                    ensure!(failures == 0, "Some calls trapped or ran out of energy.");
+                    return Ok(());
+                }
+            };
+            use_conventional_files(&mut run_cmd);
+            let runner = match *run_cmd {
+                RunCommand::Init { ref mut runner, .. } => runner,
+                RunCommand::Receive { ref mut runner, .. } => runner,
+            };
+            if let Some(nrg) = runner.energy_nrg {
+                runner.energy = InterpreterEnergy {
+                    energy: nrg.saturating_mul(cost::INTERPRETER_ENERGY_PER_NRG),
+                };
+            }
+            if let Some(micro_ccd) = runner.amount_micro {
+                runner.amount = Amount::from_micro_ccd(micro_ccd);
+            }
+            let local_chain = use_local_chain(&mut run_cmd)?;
+            let runner = match *run_cmd {
+                RunCommand::Init { ref runner, .. } => runner,
+                RunCommand::Receive { ref runner, .. } => runner,
+            };
+            let module_path = runner
+                .module
+                .as_ref()
+                .context("A module is required. Use --module.")?;
+            let (wasm_version, module) = read_versioned_module(module_path)?;
+            let descriptor = versions::descriptor(wasm_version);
+            ensure!(
+                local_chain.is_none() || descriptor.supports_simulation,
+                "The local chain is only supported for V1 contracts."
+            );
+            if runner.energy_breakdown
+                && (!descriptor.supports_simulation || matches!(*run_cmd, RunCommand::Init { .. }))
+            {
+                eprintln!(
+                    "{}",
+                    WARNING_STYLE.paint(
+                        "The energy breakdown is only supported when updating V1 contracts."
+                    )
+                );
+            }
+            if runner.profile
+                && (!descriptor.supports_simulation || matches!(*run_cmd, RunCommand::Init { .. }))
+            {
+                eprintln!(
+                    "{}",
+                    WARNING_STYLE.paint("Profiling is only supported when updating V1 contracts.")
+                );
+            }
+            if runner.record_trace.is_some()
+                && (!descriptor.supports_simulation || matches!(*run_cmd, RunCommand::Init { .. }))
+            {
+                eprintln!(
+                    "{}",
+                    WARNING_STYLE
+                        .paint("Recording traces is only supported when updating V1 contracts.")
+                );
+            }
+            if runner.trace.is_some()
+                && (!descriptor.supports_simulation || matches!(*run_cmd, RunCommand::Init { .. }))
+            {
+                eprintln!(
+                    "{}",
+                    WARNING_STYLE
+                        .paint("Tracing host calls is only supported when updating V1 contracts.")
+                );
+            }
+            if runner.events_ndjson.is_some() && !descriptor.supports_simulation {
+                eprintln!(
+                    "{}",
+                    WARNING_STYLE.paint("The event stream is only supported for V1 contracts.")
+                );
+            }
+            if runner.event_schema.is_some() && descriptor.schema_has_events {
+                eprintln!(
+                    "{}",
+                    WARNING_STYLE.paint(
+                        "The event schema only applies to V0 contracts. The logs of V1 contracts \
+                         are displayed using the event type of the module schema."
+                    )
+                );
+            }
+            if runner.crypto.is_active()
+                && (!descriptor.supports_simulation || matches!(*run_cmd, RunCommand::Init { .. }))
+            {
+                eprintln!(
+                    "{}",
+                    WARNING_STYLE.paint(
+                        "Signature verification can only be overridden when updating V1 contracts."
+                    )
+                );
+            }
+            if runner.receipt_out.is_some() && !descriptor.supports_simulation {
+                eprintln!(
+                    "{}",
+                    WARNING_STYLE.paint("Receipts are only supported for V1 contracts.")
+                );
+            }
+            if runner.bench.is_some() && !descriptor.supports_simulation {
+                eprintln!(
+                    "{}",
+                    WARNING_STYLE.paint("Benchmarks are only supported for V1 contracts.")
+                );
+            }
+            if runner.coverage.is_some() && !descriptor.supports_simulation {
+                eprintln!(
+                    "{}",
+                    WARNING_STYLE.paint("Coverage is only recorded for V1 contracts.")
+                );
+            }
+            ensure!(
+                runner.format == OutputFormat::Human || descriptor.supports_simulation,
+                "JSON output is only supported for V1 contracts."
+            );
+            ensure!(
+                runner.expect.is_none() || descriptor.supports_simulation,
+                "Expectations are only supported for V1 contracts."
+            );
+            let expectations = runner.expect.as_deref().map(expect::load).transpose()?;
+            ensure!(
+                runner.format == OutputFormat::Human
+                    || runner.events_ndjson.as_deref() != Some("-"),
+                "The event stream cannot be written to stdout together with JSON output."
+            );
+            ensure!(
+                !runner.check_determinism || descriptor.supports_simulation,
+                "Checking determinism is only supported for V1 contracts."
+            );
+            let is_flow = matches!(
+                &*run_cmd,
+                RunCommand::Init { then_update, .. } if !then_update.is_empty()
+            );
+            ensure!(
+                !is_flow || descriptor.supports_simulation,
+                "--then-update is only supported for V1 contracts."
+            );
+            ensure!(
+                !is_flow || local_chain.is_none(),
+                "--then-update cannot be combined with --chain-dir. Use `run update` with \
+                 --self-address to update instances of the local chain."
+            );
+            let versions = if runner.protocol_versions.is_empty() {
+                vec![protocol::ProtocolVersion::LATEST]
+            } else {
+                runner.protocol_versions.clone()
+            };
+            let mut report = None;
+            let mut met_expectations = true;
+            for version in versions.iter() {
+                if versions.len() > 1 {
+                    eprintln!("\n{}", bold_style.paint(format!("{}:", version)));
+                }
+                let version_report = (descriptor.run)(&run_cmd, wasm_version, &module, *version)?;
+                if let (Some(expectations), Some(version_report)) = (&expectations, &version_report)
+                {
+                    let mismatches = expectations.check(version_report);
+                    if !mismatches.is_empty() {
+                        expect::print_mismatches(&mismatches)?;
+                        met_expectations = false;
+                    }
+                }
+                report = version_report;
+            }
+            // The local chain is left unchanged by runs that are not as
+            // expected.
+            if !met_expectations {
+                if let Some(local_chain) = local_chain {
+                    local_chain.chain.discard(local_chain.address)?;
+                }
+                bail!("The invocation did not meet the expectations.");
+            }
+            if let (Some(local_chain), Some(report)) = (local_chain, report) {
+                record_in_local_chain(&run_cmd, local_chain, &report)?;
+            }
+        }
+        Command::Test {
+            package,
+            mut args,
+            seed,
+            report_html,
+            reports,
+            no_run,
+            list,
+            coverage,
+            energy_report,
+            fail_fast,
+            test_energy,
+            test_timeout,
+            module,
+            test_features,
+            no_default,
+            integration,
+            version,
+            shuffle,
+            protocol_versions,
+        } => {
+            ensure!(
+                module.is_none() || args.is_empty(),
+                "Extra arguments for `cargo build` cannot be used with `--module`."
+            );
+            if no_default {
+                args.push("--no-default-features".to_string());
+            }
+            let limits = test_runner::TestLimits {
+                energy:  test_energy,
+                timeout: test_timeout.map(Duration::from_secs),
+            };
+            let mut options = TestOptions {
+                extra_args: &args,
+                seed,
+                report_html: report_html.as_deref(),
+                reports: &reports,
+                no_run,
+                list,
+                coverage,
+                energy_report: energy_report.as_deref(),
+                fail_fast,
+                limits,
+                module: module.as_deref(),
+                features: test_features.as_deref(),
+                shuffle,
+                protocol_version: None,
+            };
+            // Without protocol versions, the tests are run once without one.
+            let protocol_versions = if protocol_versions.is_empty() {
+                vec![None]
+            } else {
+                protocol_versions.into_iter().map(Some).collect()
+            };
+            let mut success = true;
+            let mut integration_success = true;
+            for protocol_version in protocol_versions.iter().copied() {
+                if let Some(protocol_version) = protocol_version {
+                    eprintln!("\n{}", bold_style.paint(format!("{}:", protocol_version)));
+                }
+                options.protocol_version = protocol_version;
+                success &= build_and_run_wasm_test(&package, &options)
+                    .context("Could not build and run tests.")?;
+                if integration {
+                    integration_success &=
+                        run_integration_tests(&package, version, protocol_version, &args)?;
+                }
+            }
+            ensure!(success, "Test failed");
+            ensure!(integration_success, "Integration tests failed");
+        }
+        Command::Init { path } => {
+            init_concordium_project(path)
+                .context("Could not create a new Concordium smart contract project.")?;
+        }
+        Command::SchemaJSON {
+            out,
+            module_path,
+            schema_path,
+            wasm_version,
+        } => {
+            // A valid path needs to be provided when using the `--out` flag.
+            ensure!(
+                out.is_dir(),
+                "The `--out` value must point to an existing directory (expected input: \
+                 `./my/path/`)."
+            );
+
+            let schema = get_schema(module_path, schema_path, wasm_version)
+                .context("Could not get schema.")?;
+
+            write_json_schema(&out, &schema).context("Could not write JSON schema files.")?
+        }
+        Command::SchemaBase64 {
+            out,
+            module_path,
+            schema_path,
+            wasm_version,
+        } => {
+            let schema = get_schema(module_path, schema_path, wasm_version)
+                .context("Could not get schema.")?;
+
+            if out.as_path() == Path::new("-") {
+                write_schema_base64(None, &schema).context("Could not print base64 schema.")?;
+            } else {
+                // A valid path needs to be provided when using the `--out` flag.
+                if out.file_name().is_none() || out.is_dir() {
+                    anyhow::bail!(
+                        "The `--out` flag should point to an existing directory + filename \
+                         (expected input: `./my/path/base64_schema.b64`) or be `-`."
+                    );
+                }
+
+                write_schema_base64(Some(out), &schema)
+                    .context("Could not write base64 schema file.")?;
+            }
+        }
+        Command::Build {
+            schema_embed,
+            schema_out,
+            schema_json_out,
+            schema_base64_out,
+            schema_compare,
+            out,
+            out_raw,
+            module_ref_out,
+            version,
+            all_versions,
+            sbom,
+            source_url,
+            verifiable,
+            verifiable_image,
+            opt,
+            debug_names,
+            keep_sections,
+            size_report,
+            max_module_size,
+            no_cache,
+            rustflags,
+            build_std,
+            strict,
+            locked_toolchain,
+            format,
+            example,
+            bin,
+            remap_paths,
+            euro_per_energy,
+            micro_ccd_rate,
+            package,
+            workspace,
+            mut cargo_args,
+        } => {
+            if let Some(example) = example {
+                cargo_args.extend(["--example".to_string(), example]);
+            }
+            if let Some(bin) = bin {
+                cargo_args.extend(["--bin".to_string(), bin]);
+            }
+            if let Some(rustflags) = &rustflags {
+                cargo_args.extend(rustflags_args(rustflags));
+            }
+            let remappings = if remap_paths {
+                let remappings = remap::host_paths(&package)?;
+                cargo_args.extend(rustflag_list_args(&remap::rustflags(&remappings)));
+                remappings
+            } else {
+                Vec::new()
+            };
+            if build_std {
+                let rustc = toolchain::rustc_version(&package)
+                    .context("Could not determine the version of rustc.")?;
+                ensure!(
+                    rustc.contains("nightly"),
+                    "`--build-std` requires a nightly toolchain, but rustc is {}.",
+                    rustc
+                );
+                cargo_args.extend(BUILD_STD_ARGS.iter().map(|arg| arg.to_string()));
+            }
+            ensure!(
+                format == OutputFormat::Human
+                    || schema_base64_out.as_deref() != Some(Path::new("-")),
+                "`--schema-base64-out -` cannot be combined with `--format json`, since both \
+                 print to stdout."
+            );
+            let rates = euro_per_energy.zip(micro_ccd_rate);
+            let build_schema = if schema_embed {
+                SchemaBuildOptions::BuildAndEmbed
+            } else if schema_out.is_some()
+                || schema_json_out.is_some()
+                || schema_base64_out.is_some()
+                || schema_compare.is_some()
+            {
+                SchemaBuildOptions::JustBuild
+            } else {
+                SchemaBuildOptions::DoNotBuild
+            };
+            let mut options = BuildOptions {
+                version,
+                build_schema,
+                sbom,
+                source_url: source_url.as_deref(),
+                optimize: opt,
+                debug_names,
+                keep_custom_sections: &keep_sections,
+                size_report,
+                max_module_size,
+                strict,
+                use_cache: !no_cache,
+                cargo_args: &cargo_args,
+            };
+            if workspace {
+                build_workspace(
+                    &package,
+                    &options,
+                    locked_toolchain,
+                    schema_json_out,
+                    format,
+                    rates,
+                    &remappings,
+                )?;
+                return Ok(());
+            }
+            if locked_toolchain {
+                toolchain::check_locked_toolchain(&package)?;
+            }
+            if all_versions {
+                let mut summaries = Vec::new();
+                for &version in versions::ALL {
+                    let name = versions::descriptor(version).name;
+                    if format == OutputFormat::Human {
+                        eprintln!("\n{} {} module", success_style.paint("Building"), name);
+                    }
+                    options.version = version;
+                    let built = build_contract(&options, None, None, &package)
+                        .with_context(|| format!("Could not build the {} module.", name))?;
+                    remap::check_module(&built.path, &remappings)?;
+                    if let Some(module_schema) = &built.schema {
+                        if format == OutputFormat::Human {
+                            print_module_schema(module_schema);
+                        }
+                        if let Some(dir) = &schema_json_out {
+                            write_json_schema(&dir.join(name), module_schema)
+                                .context("Could not write JSON schema files.")?;
+                        }
+                    }
+                    match format {
+                        OutputFormat::Human => {
+                            eprintln!(
+                                "    {} {} module ({} B) with reference {}",
+                                success_style.paint("Finished"),
+                                name,
+                                built.size,
+                                bold_style.paint(built.module_ref.to_string())
+                            );
+                            print_deployment_cost(built.size, rates);
+                        }
+                        OutputFormat::Json => summaries.push(build_summary(&built, version, rates)),
+                    }
+                }
+                if format == OutputFormat::Json {
+                    println!("{}", serde_json::to_string_pretty(&summaries)?);
+                }
+                return Ok(());
+            }
+            if verifiable {
+                let version_name = versions::descriptor(version).name;
+                let mut args = vec!["--contract-version".to_string(), version_name.into()];
+                if schema_embed {
+                    args.push("--schema-embed".into());
+                }
+                if let Some(format) = &sbom {
+                    args.extend(["--sbom".to_string(), format.to_string()]);
+                }
+                if opt {
+                    args.push("--opt".into());
+                }
+                if let Some(size) = max_module_size {
+                    args.extend(["--max-module-size".to_string(), size.to_string()]);
+                }
+                if let Some(name) = &package.package {
+                    args.extend(["--package".to_string(), name.clone()]);
+                }
+                let work_dir = reproducible::default_work_dir(&package)?;
+                let module = reproducible::verifiable_build(
+                    verifiable_image
+                        .as_deref()
+                        .unwrap_or(reproducible::DEFAULT_IMAGE),
+                    reproducible::container_build_args(args, &cargo_args),
+                    &package,
+                    &work_dir,
+                )?;
+                let default_filename = work_dir.join("module.verifiable.wasm.v1");
+                let out = match out {
+                    Some(out) => out_path(out, &default_filename),
+                    None => default_filename,
+                };
+                if let Some(out_dir) = out.parent() {
+                    fs::create_dir_all(out_dir).context(
+                        "Unable to create directory for the resulting smart contract module.",
+                    )?;
+                }
+                fs::write(&out, &module).context("Could not write the module.")?;
+                if let Some(out_raw) = out_raw {
+                    write_raw_module(&out_raw, &module)?;
+                }
+                let module_ref = module_reference(&module);
+                if let Some(module_ref_out) = module_ref_out {
+                    write_module_reference(&module_ref_out, module_ref)?;
+                }
+                if format == OutputFormat::Json {
+                    let built = BuiltModule {
+                        path: out,
+                        size: module.len(),
+                        module_ref,
+                        schema: None,
+                    };
+                    let summary = build_summary(&built, version, rates);
+                    println!("{}", serde_json::to_string_pretty(&summary)?);
+                    return Ok(());
+                }
+                eprintln!(
+                    "    {} verifiable smart contract module {} ({} B)",
+                    success_style.paint("Finished"),
+                    out.display(),
+                    module.len()
+                );
+                eprintln!(
+                    "   Module reference: {}",
+                    bold_style.paint(module_ref.to_string())
+                );
+                print_deployment_cost(module.len(), rates);
+                return Ok(());
+            }
+            let built = build_contract(&options, out, out_raw, &package)
+                .context("Could not build smart contract.")?;
+            remap::check_module(&built.path, &remappings)?;
+            if let Some(module_schema) = &built.schema {
+                if format == OutputFormat::Human {
+                    print_module_schema(module_schema);
+                }
+                if let Some(reference) = &schema_compare {
+                    compare_schema(reference, module_schema)?;
+                }
+                let module_schema_bytes = to_bytes(module_schema);
+                if format == OutputFormat::Human {
+                    eprintln!(
+                        "\n   Total size of the module schema is {} {}",
+                        bold_style.paint(module_schema_bytes.len().to_string()),
+                        bold_style.paint("B")
+                    );
+                }
 
-pub fn main() -> anyhow::Result<()> {
-    #[cfg(target_os = "windows")]
-    {
-        ansi_term::enable_ansi_support();
-    }
-    let success_style = ansi_term::Color::Green.bold();
-    let bold_style = ansi_term::Style::new().bold();
+                if let Some(schema_out) = schema_out {
+                    // A path and a filename need to be provided when using the `--schema-out`
+                    // flag.
+                    if schema_out.file_name().is_none() || schema_out.is_dir() {
+                        anyhow::bail!(
+                            "The `--schema-out` flag requires a path and a filename (expected \
+                             input: `./my/path/schema.bin`)"
+                        );
+                    }
 
-    let cmd = {
-        let app = CargoCommand::clap()
-            .setting(AppSettings::ArgRequiredElseHelp)
-            .global_setting(AppSettings::TrailingVarArg)
-            .global_setting(AppSettings::ColoredHelp);
-        let matches = app.get_matches();
-        let CargoCommand::Concordium(cmd) = CargoCommand::from_clap(&matches);
-        cmd
-    };
-    match cmd {
-        Command::Run(run_cmd) => {
-            let runner = match *run_cmd {
-                RunCommand::Init { ref runner, .. } => runner,
-                RunCommand::Receive { ref runner, .. } => runner,
+                    if let Some(out_dir) = schema_out.parent() {
+                        fs::create_dir_all(out_dir)
+                            .context("Unable to create directory for the resulting schema.")?;
+                    }
+                    fs::write(schema_out, &module_schema_bytes)
+                        .context("Could not write schema file.")?;
+                }
+                if let Some(schema_json_out) = schema_json_out {
+                    write_json_schema(&schema_json_out, module_schema)
+                        .context("Could not write JSON schema files.")?;
+                }
+                if let Some(schema_base64_out) = schema_base64_out {
+                    if schema_base64_out.as_path() == Path::new("-") {
+                        write_schema_base64(None, module_schema)
+                            .context("Could not print base64 schema.")?;
+                    } else {
+                        if schema_base64_out.file_name().is_none() || schema_base64_out.is_dir() {
+                            anyhow::bail!(
+                                "The `--schema-base64-out` flag should point to an existing \
+                                 directory + filename (expected input: \
+                                 `./my/path/base64_schema.b64`) or be `-`."
+                            );
+                        }
+
+                        write_schema_base64(Some(schema_base64_out), module_schema)
+                            .context("Could not write base64 schema file.")?;
+                    }
+                }
+                if schema_embed && format == OutputFormat::Human {
+                    eprintln!("   Embedding schema into module.\n");
+                }
+            }
+            if let Some(module_ref_out) = module_ref_out {
+                write_module_reference(&module_ref_out, built.module_ref)?;
+            }
+            if format == OutputFormat::Json {
+                let summary = build_summary(&built, version, rates);
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+                return Ok(());
+            }
+            let size = format!("{}.{:03} kB", built.size / 1000, built.size % 1000);
+            eprintln!(
+                "    {} smart contract module {}",
+                success_style.paint("Finished"),
+                bold_style.paint(size)
+            );
+            eprintln!(
+                "   Module reference: {}",
+                bold_style.paint(built.module_ref.to_string())
+            );
+            print_deployment_cost(built.size, rates);
+        }
+        Command::Bench(BenchCommand::Compare {
+            old_module_path,
+            new_module_path,
+            scenario_path,
+            energy,
+            mocks,
+            report_html,
+        }) => {
+            let scenario = scenario::Scenario::load(&scenario_path)?;
+            let (old_version, old_module) = read_versioned_module(&old_module_path)?;
+            let (new_version, new_module) = read_versioned_module(&new_module_path)?;
+            ensure!(
+                versions::descriptor(old_version).supports_simulation
+                    && versions::descriptor(new_version).supports_simulation,
+                "Benchmarks are only supported for V1 contracts."
+            );
+            let old_schema = get_optional_schema(&old_module, old_version, None)?;
+            let new_schema = get_optional_schema(&new_module, new_version, None)?;
+            let view = |schema: &Option<VersionedModuleSchema>| {
+                schema
+                    .as_ref()
+                    .and_then(|s| schema_types::ContractSchemaView::new(s, &scenario.contract))
+                    .unwrap_or_default()
+            };
+            let (old_view, new_view) = (view(&old_schema), view(&new_schema));
+            let old_sim = simulator(&old_module, energy, &mocks)?;
+            let new_sim = simulator(&new_module, energy, &mocks)?;
+            bench::compare(
+                &scenario,
+                &bench::BenchModule {
+                    sim:    &old_sim,
+                    schema: &old_view,
+                    size:   versioned_module_bytes(&old_module, old_version).len(),
+                },
+                &bench::BenchModule {
+                    sim:    &new_sim,
+                    schema: &new_view,
+                    size:   versioned_module_bytes(&new_module, new_version).len(),
+                },
+                report_html.as_deref(),
+            )?;
+        }
+        Command::DisplayState {
+            state_bin_path,
+            wasm_version,
+            schema_path,
+            contract,
+            format,
+            out,
+            collections,
+            options,
+        } => {
+            if let Some(version) =
+                wasm_version.filter(|version| !versions::descriptor(*version).tree_state)
+            {
+                ensure!(
+                    format == StateFormat::Tree
+                        && out.is_none()
+                        && collections.is_none()
+                        && !options.is_customized(),
+                    "The state of V0 contracts is displayed as JSON, so the options for the state \
+                     of V1 contracts do not apply."
+                );
+                let schema_path =
+                    schema_path.context("The state of V0 contracts requires a schema.")?;
+                display_state_bytes(&state_bin_path, version, schema_path, contract.as_deref())?;
+            } else if schema_path.is_some() {
+                bail!(
+                    "The schema is only used for the state of V0 contracts. Use `--wasm-version \
+                     V0` to display the state of a V0 contract."
+                );
+            } else if let Some(types_path) = collections {
+                ensure!(
+                    format == StateFormat::Tree && out.is_none() && !options.is_customized(),
+                    "Grouping by collections only applies to the `tree` format without limits or \
+                     options for values."
+                );
+                let state = read_state_bin(&state_bin_path)?;
+                let types = state_types(types_path.as_deref())?;
+                let tree = collections::tree(&collections::group(&state, &[], &types)?);
+                print_tree_with(&tree, &PrintConfig::default())
+                    .context("Could not print the state as a tree.")?;
+            } else {
+                display_state_from_file(state_bin_path, &options, format, out.as_deref())?
+            }
+        }
+        Command::State(StateCommand::ToJson {
+            state_bin_path,
+            out,
+            prefix,
+            truncate_values,
+            collections,
+        }) => {
+            let state = read_state_bin(&state_bin_path)?;
+            let prefix = hex::decode(prefix.unwrap_or_default())
+                .context("Could not decode the prefix as hex.")?;
+            let json = match collections {
+                Some(types_path) => {
+                    let types = state_types(types_path.as_deref())?;
+                    serde_json::to_string_pretty(&collections::group(&state, &prefix, &types)?)?
+                }
+                None => {
+                    let mut tree = state_json::subtree(&state, &prefix)?;
+                    if let Some(max_bytes) = truncate_values {
+                        tree.truncate_values(max_bytes);
+                    }
+                    serde_json::to_string_pretty(&tree)?
+                }
+            };
+            match out {
+                Some(path) => {
+                    if let Some(out_dir) = path.parent() {
+                        fs::create_dir_all(out_dir)
+                            .context("Unable to create directory for the JSON state output.")?;
+                    }
+                    fs::write(&path, json).context("Could not write out the state.")?;
+                    eprintln!("State written to {}.", path.display());
+                }
+                None => println!("{}", json),
+            }
+        }
+        Command::State(StateCommand::Stats {
+            state_bin_path,
+            top,
+            format,
+        }) => {
+            let stats = state_stats::compute(&read_state_bin(&state_bin_path)?, top)?;
+            match format {
+                OutputFormat::Human => stats.print(),
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+            }
+        }
+        Command::State(StateCommand::Get {
+            state_bin_path,
+            key,
+            value_schema_path,
+            collections,
+            raw,
+        }) => {
+            let state = read_state_bin(&state_bin_path)?;
+            let key_bytes = hex::decode(&key).context("Could not decode the key as hex.")?;
+            let value = state_json::get(&state, &key_bytes)?
+                .with_context(|| format!("The state has no value at the key {}.", key))?;
+            if raw {
+                std::io::Write::write_all(&mut std::io::stdout(), &value)
+                    .context("Could not write the value to stdout.")?;
+            } else if let Some(path) = value_schema_path {
+                let schema = schema_types::read_type(&path)?;
+                let json = schema.to_json_string_pretty(&value).map_err(|_| {
+                    anyhow::anyhow!("Could not decode the value using the value schema.")
+                })?;
+                println!("{}", json);
+            } else if let Some(path) = collections {
+                let types = collections::StateTypes::load(&path)?;
+                let ty = types.value_type(&key_bytes).with_context(|| {
+                    format!(
+                        "The types file has no type for the value at the key {}.",
+                        key
+                    )
+                })?;
+                println!("{}", collections::decode(&value, Some(ty)));
+            } else {
+                println!("{}", hex::encode(&value));
+            }
+        }
+        Command::State(StateCommand::FromJson {
+            state_json_path,
+            out,
+        }) => {
+            let state = state_json::read(&state_json_path)?;
+            write_state_bin(&state, &out)?;
+            let entries = state_json::entries(&state)?.len();
+            eprintln!(
+                "State with {} entries written to {}.",
+                entries,
+                out.display()
+            );
+        }
+        Command::State(StateCommand::Fetch {
+            node,
+            address,
+            block,
+            out,
+        }) => {
+            let address = address.0;
+            let fetched = fetch::fetch(&node, address, block.as_deref())?;
+            write_state_bin(&fetched.state, &out)?;
+            eprintln!(
+                "State of <{},{}> with {} entries as of block {} written to {}.",
+                address.index,
+                address.subindex,
+                fetched.entries,
+                fetched.block_hash,
+                out.display()
+            );
+        }
+        Command::State(StateCommand::Set {
+            state_bin_path,
+            key,
+            value,
+            value_json,
+            value_schema_path,
+            out,
+        }) => {
+            let state = read_state_bin(&state_bin_path)?;
+            let key_bytes = hex::decode(&key).context("Could not decode the key as hex.")?;
+            let value = match (value, value_json, value_schema_path) {
+                (Some(value), _, _) => {
+                    hex::decode(value).context("Could not decode the value as hex.")?
+                }
+                (None, Some(json), Some(path)) => {
+                    let schema = schema_types::read_type(&path)?;
+                    let json: serde_json::Value =
+                        serde_json::from_str(&json).context("Could not parse the value JSON.")?;
+                    let mut bytes = Vec::new();
+                    schema
+                        .serial_value_into(&json, &mut bytes)
+                        .context("Could not encode the value using the value schema.")?;
+                    bytes
+                }
+                _ => bail!("Give the value as hex with --value or as JSON with --value-json."),
             };
-            // Expect a versioned module. The first 4 bytes are the WasmVersion.
-            let versioned_module =
-                fs::read(&runner.module).context("Could not read module file.")?;
-            let mut cursor = std::io::Cursor::new(&versioned_module[..]);
-            let wasm_version = utils::WasmVersion::read(&mut cursor)
-                .context("Could not read module version from the supplied module file.")?;
-
-            let len = {
-                let mut buf = [0u8; 4];
-                cursor
-                    .read_exact(&mut buf)
-                    .context("Could not parse supplied module.")?;
-                u32::from_be_bytes(buf)
+            let (state, replaced) = state_json::set(&state, &key_bytes, value)?;
+            let out = out.unwrap_or(state_bin_path);
+            write_state_bin(&state, &out)?;
+            eprintln!(
+                "{} the value at the key {} and wrote the state to {}.",
+                if replaced { "Replaced" } else { "Inserted" },
+                key,
+                out.display()
+            );
+        }
+        Command::State(StateCommand::Delete {
+            state_bin_path,
+            key,
+            prefix,
+            out,
+        }) => {
+            let state = read_state_bin(&state_bin_path)?;
+            let (state, removed) = match (key, prefix) {
+                (Some(key), _) => {
+                    let key_bytes =
+                        hex::decode(&key).context("Could not decode the key as hex.")?;
+                    let (state, removed) = state_json::remove(&state, |k| k == key_bytes)?;
+                    ensure!(removed > 0, "The state has no value at the key {}.", key);
+                    (state, removed)
+                }
+                (None, Some(prefix)) => {
+                    let prefix =
+                        hex::decode(&prefix).context("Could not decode the prefix as hex.")?;
+                    state_json::remove(&state, |k| k.starts_with(&prefix))?
+                }
+                (None, None) => bail!("Give the key with --key or a prefix with --prefix."),
             };
-            let module = &cursor.into_inner()[8..];
+            let out = out.unwrap_or(state_bin_path);
+            write_state_bin(&state, &out)?;
+            eprintln!(
+                "Removed {} entries and wrote the state to {}.",
+                removed,
+                out.display()
+            );
+        }
+        Command::CheckStandard {
+            module_path,
+            standards,
+            contract,
+            schema_path,
+            definitions,
+            list,
+        } => {
+            let mut registry = standards::StandardsRegistry::builtin()?;
+            for definitions_file in definitions.iter() {
+                registry.extend_from_file(definitions_file)?;
+            }
+            if list {
+                for standard in registry.iter() {
+                    println!("{}: {}", standard.identifier, standard.description);
+                }
+                return Ok(());
+            }
+            let standards = standards
+                .iter()
+                .map(|identifier| {
+                    registry.get(identifier).with_context(|| {
+                        format!(
+                            "Unknown standard '{}'. Use `--list` to see the known standards or \
+                             provide a definition using `--definitions`.",
+                            identifier
+                        )
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            // `module` is required unless `--list` is used, which is handled above.
+            let module_path = module_path.context("A module must be provided using `--module`.")?;
+            let (wasm_version, module) = read_versioned_module(&module_path)?;
+            let exports = get_module_exports(&module, wasm_version)?;
+            let schema = get_optional_schema(&module, wasm_version, schema_path)?;
+            let success =
+                standards::check_module(&standards, &exports, schema.as_ref(), contract.as_deref())
+                    .context("Could not check the module against the standards.")?;
+            ensure!(
+                success,
+                "The module does not expose the interfaces of the given standards."
+            );
+        }
+        Command::Audit {
+            module_path,
+            schema_path,
+            fail_on,
+        } => {
+            let (wasm_version, module) = read_versioned_module(&module_path)?;
+            let exports = get_module_exports(&module, wasm_version)?;
+            let schema = get_optional_schema(&module, wasm_version, schema_path)?;
+            let findings = audit::audit_module(&module, wasm_version, &exports, schema.as_ref())
+                .context("Could not audit the module.")?;
+            audit::print_findings(&findings);
+            let failing = findings.iter().filter(|f| f.severity >= fail_on).count();
             ensure!(
-                module.len() == len as usize,
-                "Could not parse the supplied module. The specified length does not match the \
-                 size of the provided data."
+                failing == 0,
+                "Found {} issue(s) of severity {} or above.",
+                failing,
+                fail_on
             );
-            match wasm_version {
-                utils::WasmVersion::V0 => handle_run_v0(*run_cmd, module)?,
-                utils::WasmVersion::V1 => handle_run_v1(*run_cmd, module)?,
+        }
+        Command::ExportIr {
+            module_path,
+            schema_path,
+            out,
+        } => {
+            let (wasm_version, module) = read_versioned_module(&module_path)?;
+            let schema = get_optional_schema(&module, wasm_version, schema_path)?;
+            let ir = ir::export_ir(&module, wasm_version, schema.as_ref())
+                .context("Could not export the module.")?;
+            let ir_string = serde_json::to_string_pretty(&ir)?;
+            if out.as_path() == Path::new("-") {
+                println!("{}", ir_string);
+            } else {
+                if let Some(out_dir) = out.parent() {
+                    fs::create_dir_all(out_dir).context(
+                        "Unable to create directory for the intermediate representation.",
+                    )?;
+                }
+                fs::write(&out, ir_string)
+                    .context("Could not write the intermediate representation.")?;
+                eprintln!("Intermediate representation written to {}.", out.display());
             }
         }
-        Command::Test { args, seed } => {
-            let success =
-                build_and_run_wasm_test(&args, seed).context("Could not build and run tests.")?;
-            ensure!(success, "Test failed");
+        Command::Coverage {
+            module_path,
+            schema_path,
+            data,
+            fail_under,
+        } => {
+            let (wasm_version, module) = read_versioned_module(&module_path)?;
+            let exports = get_module_exports(&module, wasm_version)?;
+            let schema = get_optional_schema(&module, wasm_version, schema_path)?;
+            ensure!(
+                data.exists(),
+                "The coverage data file {} does not exist.",
+                data.display()
+            );
+            let data = coverage::CoverageData::load(&data)?;
+            let percentage = coverage::print_report(&data, &exports, schema.as_ref());
+            if let Some(fail_under) = fail_under {
+                ensure!(
+                    percentage >= fail_under,
+                    "Coverage of {:.1}% is below the required {:.1}%.",
+                    percentage,
+                    fail_under
+                );
+            }
         }
-        Command::Init { path } => {
-            init_concordium_project(path)
-                .context("Could not create a new Concordium smart contract project.")?;
+        Command::ModelTest {
+            module_path,
+            model_path,
+            schema_path,
+            runs,
+            length,
+            seed,
+            energy,
+            state_growth,
+            mocks,
+        } => {
+            let (wasm_version, module) = read_versioned_module(&module_path)?;
+            ensure!(
+                versions::descriptor(wasm_version).supports_simulation,
+                "Model-based testing is only supported for V1 contracts."
+            );
+            let model: model::Model = serde_json::from_slice(
+                &fs::read(&model_path).context("Could not read the model file.")?,
+            )
+            .context("Could not parse the model file.")?;
+            let schema = get_optional_schema(&module, wasm_version, schema_path)?;
+            let view = schema
+                .as_ref()
+                .and_then(|s| schema_types::ContractSchemaView::new(s, &model.contract))
+                .unwrap_or_default();
+            let options = model::ModelTestOptions {
+                runs,
+                length,
+                seed: seed.unwrap_or_else(rand::random),
+                state_growth,
+            };
+            let sim = simulator(&module, energy, &mocks)?;
+            let success = model::run_model_tests(&model, &sim, &view, &options)?;
+            ensure!(success, "The contract diverged from the model.");
         }
-        Command::SchemaJSON {
+        Command::Fuzz {
+            module_path,
+            contract,
+            entrypoint,
+            schema_path,
+            state_bin_path,
+            state_json_path,
+            balance,
+            context_path,
+            runs,
+            seed,
+            energy,
+            energy_threshold,
+            max_amount,
+            out_dir,
+            mocks,
+            state_growth,
+        } => {
+            let (wasm_version, module) = read_versioned_module(&module_path)?;
+            ensure!(
+                versions::descriptor(wasm_version).supports_simulation,
+                "Fuzzing is only supported for V1 contracts."
+            );
+            let state = match (state_bin_path, state_json_path) {
+                (None, None) => bail!(
+                    "The state of the instance is required for fuzzing. Use either --state-bin or \
+                     --state-json."
+                ),
+                (Some(_), Some(_)) => {
+                    bail!("Only one state is allowed, choose either --state-bin or --state-json.")
+                }
+                (Some(file_path), None) => {
+                    let file = File::open(&file_path).context("Could not read state file.")?;
+                    v1::trie::PersistentState::deserialize(&mut std::io::BufReader::new(file))
+                        .context("Could not deserialize the provided state.")?
+                }
+                (None, Some(file_path)) => state_json::read(&file_path)?,
+            };
+            let base_context = match context_path {
+                Some(path) => serde_json::from_slice(
+                    &fs::read(&path).context("Could not read the context file.")?,
+                )
+                .context("Could not parse the context file.")?,
+                None => serde_json::json!({}),
+            };
+            let schema = get_optional_schema(&module, wasm_version, schema_path)?;
+            let view = schema
+                .as_ref()
+                .and_then(|s| schema_types::ContractSchemaView::new(s, &contract))
+                .unwrap_or_default();
+            let parameter_type = view
+                .receive
+                .get(entrypoint.as_str())
+                .and_then(|types| types.parameter);
+            let instance = sim::Instance {
+                contract_name: contract,
+                address: ContractAddress::new(0, 0),
+                state,
+                balance,
+            };
+            let options = fuzz::FuzzOptions {
+                runs,
+                seed: seed.unwrap_or_else(rand::random),
+                energy_threshold,
+                max_amount,
+                out_dir,
+                state_growth,
+            };
+            let sim = simulator(&module, energy, &mocks)?;
+            let passed = fuzz::fuzz_entrypoint(
+                &sim,
+                &instance,
+                &entrypoint,
+                parameter_type,
+                &base_context,
+                &options,
+            )?;
+            ensure!(
+                passed,
+                "Fuzzing found inputs that trap or use excessive energy."
+            );
+        }
+        Command::EstimateCosts {
+            module_path,
+            profile_path,
+            schema_path,
+            days,
+            samples,
+            seed,
+            energy,
+            mocks,
+        } => {
+            let (wasm_version, module) = read_versioned_module(&module_path)?;
+            ensure!(
+                versions::descriptor(wasm_version).supports_simulation,
+                "Estimating costs is only supported for V1 contracts."
+            );
+            let profile: cost::UsageProfile = serde_json::from_slice(
+                &fs::read(&profile_path).context("Could not read the usage profile.")?,
+            )
+            .context("Could not parse the usage profile.")?;
+            let schema = get_optional_schema(&module, wasm_version, schema_path)?;
+            let view = schema
+                .as_ref()
+                .and_then(|s| schema_types::ContractSchemaView::new(s, &profile.contract))
+                .unwrap_or_default();
+            let sim = simulator(&module, energy, &mocks)?;
+            cost::estimate_costs(&profile, &sim, &view, samples, days, seed)?;
+        }
+        Command::ExploreOrders {
+            module_path,
+            scenario_path,
+            schema_path,
+            max_orders,
+            seed,
+            energy,
+            mocks,
+            report_html,
+        } => {
+            let (wasm_version, module) = read_versioned_module(&module_path)?;
+            ensure!(
+                versions::descriptor(wasm_version).supports_simulation,
+                "Exploring orders is only supported for V1 contracts."
+            );
+            let scenario = scenario::Scenario::load(&scenario_path)?;
+            let schema = get_optional_schema(&module, wasm_version, schema_path)?;
+            let view = schema
+                .as_ref()
+                .and_then(|s| schema_types::ContractSchemaView::new(s, &scenario.contract))
+                .unwrap_or_default();
+            let options = order::OrderOptions {
+                max_orders,
+                seed: seed.unwrap_or_else(rand::random),
+                report_html,
+            };
+            let sim = simulator(&module, energy, &mocks)?;
+            let same = order::explore_orders(&scenario, &sim, &view, &options)?;
+            ensure!(same, "The final state depends on the order of the calls.");
+        }
+        Command::GenerateMock {
+            module_path,
+            schema_path,
+            contract,
+            index,
+            subindex,
             out,
+        } => {
+            let schema =
+                get_schema(module_path, schema_path, None).context("Could not get schema.")?;
+            let mock = mock::MockContract::generate(
+                &contract,
+                concordium_contracts_common::ContractAddress::new(index, subindex),
+                &schema,
+            )?;
+            if let Some(out_dir) = out.parent() {
+                fs::create_dir_all(out_dir).context("Unable to create directory for the mock.")?;
+            }
+            fs::write(&out, serde_json::to_vec_pretty(&mock)?)
+                .context("Could not write the mock.")?;
+            eprintln!("Mock of '{}' written to {}.", contract, out.display());
+        }
+        Command::PrintParameter {
             module_path,
             schema_path,
-            wasm_version,
+            contract,
+            entrypoint,
         } => {
-            // A valid path needs to be provided when using the `--out` flag.
+            let schema =
+                get_schema(module_path, schema_path, None).context("Could not get schema.")?;
+            let view =
+                schema_types::ContractSchemaView::new(&schema, &contract).with_context(|| {
+                    format!("The schema does not describe the contract '{}'.", contract)
+                })?;
+            let types = match &entrypoint {
+                Some(entrypoint) => view
+                    .receive
+                    .get(entrypoint.as_str())
+                    .copied()
+                    .with_context(|| {
+                        format!(
+                            "The schema does not describe the entrypoint '{}'.",
+                            entrypoint
+                        )
+                    })?,
+                None => view
+                    .init
+                    .context("The schema does not describe the init function.")?,
+            };
+            let parameter = types
+                .parameter
+                .context("The schema does not describe the type of the parameter.")?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&schema_types::json_template(parameter))?
+            );
+        }
+        Command::Replay { trace, print_all } => {
+            let trace = trace::ExecutionTrace::load(&trace)?;
+            if print_all {
+                trace::print_trace(&trace);
+            } else {
+                trace::replay(&trace)?;
+            }
+        }
+        Command::RehearseUpgrade {
+            old_module_path,
+            new_module_path,
+            state_bin_path,
+            balance,
+            rehearsal_path,
+            out_bin,
+            energy,
+            old_schema_path,
+            schema_path,
+        } => {
+            let (old_version, old_module) = read_versioned_module(&old_module_path)?;
+            let (new_version, new_module) = read_versioned_module(&new_module_path)?;
             ensure!(
-                out.is_dir(),
-                "The `--out` value must point to an existing directory (expected input: \
-                 `./my/path/`)."
+                versions::descriptor(old_version).supports_upgrade
+                    && versions::descriptor(new_version).supports_simulation,
+                "Upgrades are only supported for V1 contracts."
+            );
+            let rehearsal: upgrade::Rehearsal = serde_json::from_slice(
+                &fs::read(&rehearsal_path).context("Could not read the rehearsal file.")?,
+            )
+            .context("Could not parse the rehearsal file.")?;
+            let file = File::open(&state_bin_path).context("Could not read state file.")?;
+            let state = v1::trie::PersistentState::deserialize(&mut std::io::BufReader::new(file))
+                .context("Could not deserialize the provided state.")?;
+            let old_schema = get_optional_schema(&old_module, old_version, old_schema_path)?;
+            let new_schema = get_optional_schema(&new_module, new_version, schema_path)?;
+            let view = |schema: &Option<VersionedModuleSchema>| {
+                schema
+                    .as_ref()
+                    .and_then(|s| schema_types::ContractSchemaView::new(s, &rehearsal.contract))
+                    .unwrap_or_default()
+            };
+            let (old_view, new_view) = (view(&old_schema), view(&new_schema));
+            let input = upgrade::UpgradeInput {
+                old_module: &old_module,
+                old_schema: &old_view,
+                new_module: &new_module,
+                new_module_ref: module_reference(&versioned_module_bytes(&new_module, new_version)),
+                new_schema: &new_view,
+                state,
+                balance,
+                energy,
+            };
+            let (instance, passed) = upgrade::rehearse_upgrade(&rehearsal, input)?;
+            if let Some(out_bin) = out_bin {
+                if let Some(out_dir) = out_bin.parent() {
+                    fs::create_dir_all(out_dir)
+                        .context("Unable to create directory for the migrated state.")?;
+                }
+                fs::write(&out_bin, instance.state_bytes()?)
+                    .context("Could not write the migrated state.")?;
+                eprintln!("Migrated state written to {}.", out_bin.display());
+            }
+            ensure!(passed, "Some checks of the migrated state failed.");
+            eprintln!(
+                "{}",
+                ansi_term::Color::Green.paint("The upgrade rehearsal succeeded.")
             );
-
-            let schema = get_schema(module_path, schema_path, wasm_version)
-                .context("Could not get schema.")?;
-
-            write_json_schema(&out, &schema).context("Could not write JSON schema files.")?
         }
-        Command::SchemaBase64 {
-            out,
+        Command::VerifySource {
             module_path,
-            schema_path,
-            wasm_version,
+            module_ref,
+            node,
+            image,
+            attestation,
         } => {
-            let schema = get_schema(module_path, schema_path, wasm_version)
-                .context("Could not get schema.")?;
-
-            if out.as_path() == Path::new("-") {
-                write_schema_base64(None, &schema).context("Could not print base64 schema.")?;
-            } else {
-                // A valid path needs to be provided when using the `--out` flag.
-                if out.file_name().is_none() || out.is_dir() {
-                    anyhow::bail!(
-                        "The `--out` flag should point to an existing directory + filename \
-                         (expected input: `./my/path/base64_schema.b64`) or be `-`."
-                    );
+            let (wasm_version, module) = match (module_path, node, &module_ref) {
+                (Some(module_path), _, _) => read_versioned_module(&module_path)?,
+                (None, Some(node), Some(module_ref)) => fetch::fetch_module(&node, module_ref)?,
+                _ => bail!("Give either the module with --module, or --node and --module-ref."),
+            };
+            let versioned = versioned_module_bytes(&module, wasm_version);
+            let result = verify::verify_source(
+                &versioned,
+                &module,
+                module_ref.as_deref(),
+                image.as_deref().unwrap_or(reproducible::DEFAULT_IMAGE),
+            )?;
+            if let Some(attestation) = attestation {
+                if let Some(out_dir) = attestation.parent() {
+                    fs::create_dir_all(out_dir)
+                        .context("Unable to create directory for the attestation.")?;
                 }
-
-                write_schema_base64(Some(out), &schema)
-                    .context("Could not write base64 schema file.")?;
+                fs::write(&attestation, serde_json::to_vec_pretty(&result)?)
+                    .context("Could not write the attestation.")?;
+                eprintln!("Attestation written to {}.", attestation.display());
             }
+            ensure!(result.verified, "The source does not reproduce the module.");
         }
-        Command::Build {
-            schema_embed,
-            schema_out,
-            schema_json_out,
-            schema_base64_out,
+        Command::VerifyBuild {
+            module_path,
+            module_ref,
+            package,
+            build_args,
+        } => {
+            let reference = module_path
+                .map(|path| fs::read(path).context("Could not read the reference module."))
+                .transpose()?;
+            let matches = verify_build::verify_build(
+                reference.as_deref(),
+                module_ref.as_deref(),
+                &package,
+                &build_args,
+            )?;
+            ensure!(matches, "The rebuilt module does not match the reference.");
+        }
+        Command::Inspect {
+            module_path,
+            sbom,
             out,
-            version,
-            cargo_args,
         } => {
-            let build_schema = if schema_embed {
-                SchemaBuildOptions::BuildAndEmbed
-            } else if schema_out.is_some()
-                || schema_json_out.is_some()
-                || schema_base64_out.is_some()
-            {
-                SchemaBuildOptions::JustBuild
-            } else {
-                SchemaBuildOptions::DoNotBuild
-            };
-            let (byte_len, schema) = build_contract(version, build_schema, out, &cargo_args)
-                .context("Could not build smart contract.")?;
-            if let Some(module_schema) = &schema {
-                match module_schema {
-                    VersionedModuleSchema::V0(module_schema) => {
-                        eprintln!("\n   Module schema includes:");
-                        for (contract_name, contract_schema) in module_schema.contracts.iter() {
-                            print_contract_schema_v0(contract_name, contract_schema);
-                        }
-                    }
-                    VersionedModuleSchema::V1(module_schema) => {
-                        eprintln!("\n   Module schema includes:");
-                        for (contract_name, contract_schema) in module_schema.contracts.iter() {
-                            print_contract_schema_v1(contract_name, contract_schema);
-                        }
-                    }
-                    VersionedModuleSchema::V2(module_schema) => {
-                        eprintln!("\n   Module schema includes:");
-                        for (contract_name, contract_schema) in module_schema.contracts.iter() {
-                            print_contract_schema_v2(contract_name, contract_schema);
-                        }
-                    }
-                    VersionedModuleSchema::V3(module_schema) => {
-                        eprintln!("\n   Module schema includes:");
-                        for (contract_name, contract_schema) in module_schema.contracts.iter() {
-                            print_contract_schema_v3(contract_name, contract_schema);
-                        }
-                    }
-                };
-                let module_schema_bytes = to_bytes(module_schema);
-                eprintln!(
-                    "\n   Total size of the module schema is {} {}",
-                    bold_style.paint(module_schema_bytes.len().to_string()),
-                    bold_style.paint("B")
-                );
-
-                if let Some(schema_out) = schema_out {
-                    // A path and a filename need to be provided when using the `--schema-out`
-                    // flag.
-                    if schema_out.file_name().is_none() || schema_out.is_dir() {
-                        anyhow::bail!(
-                            "The `--schema-out` flag requires a path and a filename (expected \
-                             input: `./my/path/schema.bin`)"
-                        );
-                    }
-
-                    if let Some(out_dir) = schema_out.parent() {
+            let (wasm_version, module) = read_versioned_module(&module_path)?;
+            let sections = get_custom_sections(&module)?;
+            if sbom {
+                let (_, contents) = sections
+                    .iter()
+                    .find(|(name, _)| name == sbom::SBOM_SECTION_NAME)
+                    .context(
+                        "The module does not contain an SBOM. Build it using `cargo concordium \
+                         build --sbom`.",
+                    )?;
+                if out.as_path() == Path::new("-") {
+                    println!("{}", String::from_utf8_lossy(contents));
+                } else {
+                    if let Some(out_dir) = out.parent() {
                         fs::create_dir_all(out_dir)
-                            .context("Unable to create directory for the resulting schema.")?;
+                            .context("Unable to create directory for the SBOM.")?;
                     }
-                    fs::write(schema_out, &module_schema_bytes)
-                        .context("Could not write schema file.")?;
+                    fs::write(&out, contents).context("Could not write the SBOM.")?;
+                    eprintln!("SBOM written to {}.", out.display());
                 }
-                if let Some(schema_json_out) = schema_json_out {
-                    write_json_schema(&schema_json_out, module_schema)
-                        .context("Could not write JSON schema files.")?;
+            } else {
+                let exports = get_module_exports(&module, wasm_version)?;
+                let module_ref = module_reference(&versioned_module_bytes(&module, wasm_version));
+                println!("Module reference: {}", module_ref);
+                println!("Wasm version: {}", versions::descriptor(wasm_version).name);
+                println!("Module size: {} B", module.len());
+                if let Some((_, contents)) = sections
+                    .iter()
+                    .find(|(name, _)| name == build_info::BUILD_INFO_SECTION_NAME)
+                {
+                    let info: build_info::BuildInfo = serde_json::from_slice(contents)
+                        .context("Could not parse the build information.")?;
+                    let unknown = || "unknown".to_string();
+                    println!("Built with:");
+                    println!("  rustc: {}", info.rustc_version.unwrap_or_else(unknown));
+                    println!("  cargo-concordium: {}", info.cargo_concordium_version);
+                    println!("  features: {}", info.features.join(", "));
+                    println!("  git commit: {}", info.git_commit.unwrap_or_else(unknown));
                 }
-                if let Some(schema_base64_out) = schema_base64_out {
-                    if schema_base64_out.as_path() == Path::new("-") {
-                        write_schema_base64(None, module_schema)
-                            .context("Could not print base64 schema.")?;
-                    } else {
-                        if schema_base64_out.file_name().is_none() || schema_base64_out.is_dir() {
-                            anyhow::bail!(
-                                "The `--schema-base64-out` flag should point to an existing \
-                                 directory + filename (expected input: \
-                                 `./my/path/base64_schema.b64`) or be `-`."
-                            );
+                if let Some((_, contents)) = sections
+                    .iter()
+                    .find(|(name, _)| name == build_info::CONTRACT_METADATA_SECTION_NAME)
+                {
+                    let metadata: build_info::ContractMetadata =
+                        serde_json::from_slice(contents)
+                            .context("Could not parse the contract metadata.")?;
+                    println!("Metadata:");
+                    for (field, value) in [
+                        ("version", metadata.version),
+                        ("license", metadata.license),
+                        ("homepage", metadata.homepage),
+                        ("description", metadata.description),
+                    ] {
+                        if let Some(value) = value {
+                            println!("  {}: {}", field, value);
                         }
-
-                        write_schema_base64(Some(schema_base64_out), module_schema)
-                            .context("Could not write base64 schema file.")?;
                     }
                 }
-                if schema_embed {
-                    eprintln!("   Embedding schema into module.\n");
+                println!("Contracts:");
+                for contract in exports.contracts.iter() {
+                    println!("  - {}", contract);
+                    for entrypoint in exports.entrypoints_of(contract) {
+                        println!("      {}", entrypoint);
+                    }
+                }
+                if !sections.is_empty() {
+                    println!("Custom sections:");
+                    for (name, contents) in sections.iter() {
+                        println!("  - {} ({} B)", name, contents.len());
+                    }
                 }
             }
-            let size = format!("{}.{:03} kB", byte_len / 1000, byte_len % 1000);
+        }
+    };
+    Ok(())
+}
+
+/// Compare the schema against that of a reference, which is either a schema
+/// file or a module with an embedded schema, and print the differences.
+fn compare_schema(reference: &Path, schema: &VersionedModuleSchema) -> anyhow::Result<()> {
+    let bytes = fs::read(reference).context("Could not read the reference.")?;
+    let reference_schema = if bytes.starts_with(VERSIONED_SCHEMA_MAGIC_HASH) {
+        from_bytes::<VersionedModuleSchema>(&bytes)?
+    } else {
+        get_schema(Some(reference.to_path_buf()), None, None)
+            .context("Could not get the schema of the reference.")?
+    };
+    let changes = schema_diff::diff(&reference_schema, schema);
+    if changes.is_empty() {
+        eprintln!(
+            "\n   The schema is unchanged compared to {}.",
+            reference.display()
+        );
+    } else {
+        eprintln!(
+            "\n   The schema changed compared to {}:",
+            reference.display()
+        );
+        for change in changes.iter() {
+            change.print();
+        }
+    }
+    Ok(())
+}
+
+/// The summary of a built module printed by `cargo concordium build --format
+/// json`. The deployment cost is given in CCD if the exchange rates from NRG to
+/// euro and from euro to microCCD are given.
+fn build_summary(
+    built: &BuiltModule,
+    version: WasmVersion,
+    rates: Option<(f64, f64)>,
+) -> serde_json::Value {
+    let nrg = cost::deploy_module_nrg(built.size);
+    serde_json::json!({
+        "path": built.path,
+        "version": versions::descriptor(version).name,
+        "size": built.size,
+        "moduleReference": built.module_ref.to_string(),
+        "schema": built.schema.as_ref().map(schema_types::schema_sizes),
+        "deploymentNrg": nrg,
+        "deploymentCcd": rates.map(|(euro_per_nrg, micro_ccd_per_euro)| {
+            cost::nrg_to_ccd(nrg as f64, euro_per_nrg, micro_ccd_per_euro)
+        }),
+    })
+}
+
+/// Print the estimated cost of deploying a versioned module of the given size.
+fn print_deployment_cost(module_len: usize, rates: Option<(f64, f64)>) {
+    let bold_style = ansi_term::Style::new().bold();
+    let nrg = cost::deploy_module_nrg(module_len);
+    match rates {
+        Some((euro_per_nrg, micro_ccd_per_euro)) => eprintln!(
+            "   Deployment cost: {} NRG ({:.6} CCD)",
+            bold_style.paint(nrg.to_string()),
+            cost::nrg_to_ccd(nrg as f64, euro_per_nrg, micro_ccd_per_euro)
+        ),
+        None => eprintln!(
+            "   Deployment cost: {} NRG",
+            bold_style.paint(nrg.to_string())
+        ),
+    }
+}
+
+/// Print the contracts and their functions described by a module schema.
+fn print_module_schema(module_schema: &VersionedModuleSchema) {
+    eprintln!("\n   Module schema includes:");
+    match module_schema {
+        VersionedModuleSchema::V0(module_schema) => {
+            for (contract_name, contract_schema) in module_schema.contracts.iter() {
+                print_contract_schema_v0(contract_name, contract_schema);
+            }
+        }
+        VersionedModuleSchema::V1(module_schema) => {
+            for (contract_name, contract_schema) in module_schema.contracts.iter() {
+                print_contract_schema_v1(contract_name, contract_schema);
+            }
+        }
+        VersionedModuleSchema::V2(module_schema) => {
+            for (contract_name, contract_schema) in module_schema.contracts.iter() {
+                print_contract_schema_v2(contract_name, contract_schema);
+            }
+        }
+        VersionedModuleSchema::V3(module_schema) => {
+            for (contract_name, contract_schema) in module_schema.contracts.iter() {
+                print_contract_schema_v3(contract_name, contract_schema);
+            }
+        }
+    };
+}
+
+/// Build every member of the workspace that depends on concordium-std into
+/// its own module, and print a summary of the modules.
+fn build_workspace(
+    workspace: &PackageOptions,
+    options: &BuildOptions,
+    locked_toolchain: bool,
+    schema_json_out: Option<PathBuf>,
+    format: OutputFormat,
+    rates: Option<(f64, f64)>,
+    remappings: &[remap::Remapping],
+) -> anyhow::Result<()> {
+    let success_style = ansi_term::Color::Green.bold();
+    let bold_style = ansi_term::Style::new().bold();
+    let metadata = workspace.metadata(false)?;
+    let members = metadata
+        .packages
+        .iter()
+        .filter(|p| {
+            metadata.workspace_members.contains(&p.id)
+                && p.dependencies.iter().any(|d| d.name == "concordium-std")
+        })
+        .collect::<Vec<_>>();
+    ensure!(
+        !members.is_empty(),
+        "No member of the workspace depends on concordium-std."
+    );
+
+    let mut summary = Vec::new();
+    for member in members {
+        if format == OutputFormat::Human {
             eprintln!(
-                "    {} smart contract module {}",
-                success_style.paint("Finished"),
-                bold_style.paint(size)
-            )
+                "\n{} {}",
+                success_style.paint("Building"),
+                bold_style.paint(&member.name)
+            );
+        }
+        let package = PackageOptions {
+            package:       Some(member.name.clone()),
+            manifest_path: workspace.manifest_path.clone(),
+        };
+        if locked_toolchain {
+            toolchain::check_locked_toolchain(&package)?;
+        }
+        let built = build_contract(options, None, None, &package)
+            .with_context(|| format!("Could not build the package '{}'.", member.name))?;
+        remap::check_module(&built.path, remappings)?;
+        if let Some(module_schema) = &built.schema {
+            if format == OutputFormat::Human {
+                print_module_schema(module_schema);
+            }
+            if let Some(dir) = &schema_json_out {
+                write_json_schema(&dir.join(&member.name), module_schema)
+                    .context("Could not write JSON schema files.")?;
+            }
+        }
+        if format == OutputFormat::Human {
+            eprintln!(
+                "   Module reference: {}",
+                bold_style.paint(built.module_ref.to_string())
+            );
+            print_deployment_cost(built.size, rates);
+        }
+        summary.push((&member.name, built));
+    }
+
+    if format == OutputFormat::Json {
+        let summaries = summary
+            .iter()
+            .map(|(name, built)| {
+                let mut module = build_summary(built, options.version, rates);
+                module["package"] = serde_json::Value::from(name.as_str());
+                module
+            })
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::to_string_pretty(&summaries)?);
+        return Ok(());
+    }
+
+    eprintln!("\n{}", bold_style.paint("Workspace summary:"));
+    eprintln!(
+        "  {:<32} {:>12} {:>12}   contracts",
+        "package", "module", "schema"
+    );
+    for (name, built) in summary.iter() {
+        let (schema_len, contracts) = match &built.schema {
+            Some(schema) => (
+                format!("{} B", to_bytes(schema).len()),
+                schema_types::contract_names(schema).join(", "),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        eprintln!(
+            "  {:<32} {:>10} B {:>12}   {}",
+            name, built.size, schema_len, contracts
+        );
+    }
+    eprintln!(
+        "    {} {} smart contract modules",
+        success_style.paint("Finished"),
+        bold_style.paint(summary.len().to_string())
+    );
+    Ok(())
+}
+
+/// Read a versioned smart contract module from a file. The file is expected to
+/// contain the module as it is deployed on the chain, i.e., the Wasm version
+/// and the length of the module followed by the module itself.
+///
+/// Returns the version and the (unversioned) Wasm module.
+fn read_versioned_module(path: &Path) -> anyhow::Result<(WasmVersion, Vec<u8>)> {
+    // Expect a versioned module. The first 4 bytes are the WasmVersion.
+    let versioned_module = fs::read(path).context("Could not read module file.")?;
+    let mut cursor = std::io::Cursor::new(&versioned_module[..]);
+    let wasm_version = utils::WasmVersion::read(&mut cursor)
+        .context("Could not read module version from the supplied module file.")?;
+
+    let len = {
+        let mut buf = [0u8; 4];
+        cursor
+            .read_exact(&mut buf)
+            .context("Could not parse supplied module.")?;
+        u32::from_be_bytes(buf)
+    };
+    let module = &cursor.into_inner()[8..];
+    ensure!(
+        module.len() == len as usize,
+        "Could not parse the supplied module. The specified length does not match the size of the \
+         provided data."
+    );
+    Ok((wasm_version, module.to_vec()))
+}
+
+/// The types of the collections of a state, which are all unknown unless a
+/// types file is given.
+fn state_types(path: Option<&Path>) -> anyhow::Result<collections::StateTypes> {
+    match path {
+        Some(path) => collections::StateTypes::load(path),
+        None => Ok(collections::StateTypes::default()),
+    }
+}
+
+/// Read the state of a V1 contract in binary.
+fn read_state_bin(file_path: &Path) -> anyhow::Result<v1::trie::PersistentState> {
+    let file = File::open(file_path)
+        .with_context(|| format!("Could not read state file {}.", file_path.display()))?;
+    v1::trie::PersistentState::deserialize(&mut std::io::BufReader::new(file))
+        .context("Could not deserialize the provided state.")
+}
+
+/// Write the state in binary to the file, creating its directory if needed.
+fn write_state_bin(state: &v1::trie::PersistentState, out: &Path) -> anyhow::Result<()> {
+    if let Some(out_dir) = out.parent() {
+        fs::create_dir_all(out_dir)
+            .context("Unable to create directory for the binary state output.")?;
+    }
+    let mut out_file = File::create(out).context("Could not create file to write state into.")?;
+    state
+        .serialize(&mut v1::trie::Loader::new(&[][..]), &mut out_file)
+        .context("Could not write the state.")
+}
+
+/// Loads the state of a contract whose state is bytes, such as a V0 contract,
+/// from file and prints it as JSON using the state type of the contract in the
+/// schema.
+fn display_state_bytes(
+    file_path: &Path,
+    version: WasmVersion,
+    schema_path: PathBuf,
+    contract: Option<&str>,
+) -> anyhow::Result<()> {
+    let schema = match get_schema(None, Some(schema_path), Some(version))? {
+        VersionedModuleSchema::V0(schema) => schema,
+        _ => bail!("The state of V0 contracts requires a V0 schema."),
+    };
+    let state_type = match contract {
+        Some(name) => schema
+            .contracts
+            .get(name)
+            .with_context(|| format!("The schema has no contract '{}'.", name))?
+            .state
+            .as_ref()
+            .with_context(|| format!("The schema has no state type for '{}'.", name))?,
+        None => {
+            let mut types = schema
+                .contracts
+                .values()
+                .filter_map(|contract_schema| contract_schema.state.as_ref());
+            match (types.next(), types.next()) {
+                (Some(state_type), None) => state_type,
+                (None, _) => bail!("The schema has no state type for any contract."),
+                (Some(_), Some(_)) => bail!(
+                    "The schema has state types for more than one contract. Use `--contract` to \
+                     choose one."
+                ),
+            }
         }
-        Command::DisplayState { state_bin_path } => display_state_from_file(state_bin_path)?,
     };
+    let state = fs::read(file_path)
+        .with_context(|| format!("Could not read state file {}.", file_path.display()))?;
+    let json = state_type
+        .to_json_string_pretty(&state)
+        .map_err(|_| anyhow::anyhow!("Could not decode the state using the state type."))?;
+    println!("{}", json);
     Ok(())
 }
 
 /// Loads the contract state from file and displays it as a tree by printing to
 /// stdout.
-fn display_state_from_file(file_path: PathBuf) -> anyhow::Result<()> {
-    let file = File::open(&file_path)
-        .with_context(|| format!("Could not read state file {}.", file_path.display()))?;
-    let mut reader = std::io::BufReader::new(file);
-    let state = v1::trie::PersistentState::deserialize(&mut reader)
-        .context("Could not deserialize the provided state.")?;
+fn display_state_from_file(
+    file_path: PathBuf,
+    options: &StateDisplayOptions,
+    format: StateFormat,
+    out: Option<&Path>,
+) -> anyhow::Result<()> {
+    ensure!(
+        format != StateFormat::Tree || out.is_none(),
+        "The tree is printed to stdout, so --out requires the `dot`, `html` or `jsonl` format."
+    );
+    let state = read_state_bin(&file_path)?;
+
+    if format == StateFormat::Tree {
+        return display_state(&state, options);
+    }
+    if options.max_value_bytes.is_some() || options.hexdump || options.utf8_preview {
+        eprintln!(
+            "{}",
+            WARNING_STYLE.paint("The options for values only apply to the `tree` format.")
+        );
+    }
+    let prefix = match &options.prefix {
+        Some(prefix) => {
+            hex::decode(prefix).context("The prefix of the state to display is not hex.")?
+        }
+        None => Vec::new(),
+    };
+    if format == StateFormat::Jsonl {
+        use std::io::Write;
+        if options.depth.is_some() {
+            eprintln!(
+                "{}",
+                WARNING_STYLE.paint("The depth does not apply to the `jsonl` format.")
+            );
+        }
+        let mut writer: Box<dyn std::io::Write> = match out {
+            Some(path) => Box::new(std::io::BufWriter::new(
+                File::create(path).context("Could not create the file for the entries.")?,
+            )),
+            None => Box::new(std::io::BufWriter::new(std::io::stdout())),
+        };
+        state_json::for_each_entry(&state, &prefix, |key, value| {
+            let line = serde_json::json!({
+                "key": hex::encode(key),
+                "value": hex::encode(value),
+            });
+            writeln!(writer, "{}", line).context("Could not write an entry of the state.")
+        })?;
+        writer
+            .flush()
+            .context("Could not write the entries of the state.")?;
+        if let Some(path) = out {
+            eprintln!("Entries written to {}.", path.display());
+        }
+        return Ok(());
+    }
+    let root = state_json::subtree(&state, &prefix)?;
+    let depth = options.depth.unwrap_or(usize::MAX);
+    match (format, out) {
+        (StateFormat::Html, Some(path)) => state_html::write(&root, depth, path),
+        (StateFormat::Html, None) => bail!("The HTML explorer requires a file given with --out."),
+        (_, Some(path)) => {
+            fs::write(path, state_json::to_dot(&root, depth))
+                .context("Could not write the graph.")?;
+            eprintln!("Graph written to {}.", path.display());
+            Ok(())
+        }
+        (_, None) => {
+            print!("{}", state_json::to_dot(&root, depth));
+            Ok(())
+        }
+    }
+}
+
+/// The label of a node of the state when the display is customized.
+fn state_node_label(
+    node: &state_json::StateNode,
+    options: &StateDisplayOptions,
+) -> anyhow::Result<String> {
+    match &node.value {
+        Some(value) => {
+            let value = hex::decode(value).context("A value of the state is not hex.")?;
+            Ok(format!("{} = {}", node.key, options.value_label(&value)))
+        }
+        None => Ok(node.key.clone()),
+    }
+}
 
-    display_state(&state)
+/// Add the hexdump of the value of the node to the tree, if values are
+/// displayed as hexdumps.
+fn add_state_value_lines(
+    tree_builder: &mut TreeBuilder,
+    node: &state_json::StateNode,
+    options: &StateDisplayOptions,
+) -> anyhow::Result<()> {
+    if let Some(value) = &node.value {
+        let value = hex::decode(value).context("A value of the state is not hex.")?;
+        for line in options.value_lines(&value) {
+            tree_builder.add_empty_child(line);
+        }
+    }
+    Ok(())
 }
 
-/// Displays the contract state as a tree by printing to stdout.
-fn display_state(state: &v1::trie::PersistentState) -> Result<(), anyhow::Error> {
-    let mut loader = v1::trie::Loader::new([]);
+/// Add the children of a node of the state to the tree, up to the given depth.
+/// The children of nodes at the given depth are summarized by the number of
+/// entries below them.
+fn add_state_children(
+    tree_builder: &mut TreeBuilder,
+    node: &state_json::StateNode,
+    depth: usize,
+    options: &StateDisplayOptions,
+) -> anyhow::Result<()> {
+    if node.children.is_empty() {
+        return Ok(());
+    }
+    if depth == 0 {
+        let count = node
+            .children
+            .iter()
+            .map(state_json::StateNode::count)
+            .sum::<usize>();
+        tree_builder.add_empty_child(format!("... {} entries", count));
+        return Ok(());
+    }
+    for child in node.children.iter() {
+        tree_builder.begin_child(state_node_label(child, options)?);
+        add_state_value_lines(tree_builder, child, options)?;
+        add_state_children(tree_builder, child, depth - 1, options)?;
+        tree_builder.end_child();
+    }
+    Ok(())
+}
 
-    let mut tree_builder = TreeBuilder::new("StateRoot".into());
-    state.display_tree(&mut tree_builder, &mut loader);
-    let tree = tree_builder.build();
+/// Displays the contract state as a tree by printing to stdout, limited and
+/// rendered according to the options.
+fn display_state(
+    state: &v1::trie::PersistentState,
+    options: &StateDisplayOptions,
+) -> Result<(), anyhow::Error> {
+    let tree = if options.is_customized() {
+        let prefix = match &options.prefix {
+            Some(prefix) => {
+                hex::decode(prefix).context("The prefix of the state to display is not hex.")?
+            }
+            None => Vec::new(),
+        };
+        let root = state_json::subtree(state, &prefix)?;
+        let label = if prefix.is_empty() {
+            "StateRoot".to_string()
+        } else {
+            format!("StateRoot, prefix {}", state_node_label(&root, options)?)
+        };
+        let mut tree_builder = TreeBuilder::new(label);
+        add_state_value_lines(&mut tree_builder, &root, options)?;
+        add_state_children(
+            &mut tree_builder,
+            &root,
+            options.depth.unwrap_or(usize::MAX),
+            options,
+        )?;
+        tree_builder.build()
+    } else {
+        let mut loader = v1::trie::Loader::new([]);
+        let mut tree_builder = TreeBuilder::new("StateRoot".into());
+        state.display_tree(&mut tree_builder, &mut loader);
+        tree_builder.build()
+    };
     // We don't want to depend on some global config as it opens up for all sorts of
     // corner-case bugs since we are not in control and thus inconsistent user
     // experience.
@@ -758,8 +4505,16 @@ fn print_contract_schema_v3(
     }
 }
 
-fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
-    let (contract_name, runner, is_receive) = match run_cmd {
+/// Run an invocation of a contract of a module version that does not support
+/// simulation, such as V0. The invocation is not reported.
+fn handle_run_v0(
+    run_cmd: &RunCommand,
+    version: WasmVersion,
+    module: &[u8],
+    protocol_version: protocol::ProtocolVersion,
+) -> anyhow::Result<Option<run_report::RunReport>> {
+    let descriptor = versions::descriptor(version);
+    let (contract_name, runner, is_receive) = match *run_cmd {
         RunCommand::Init {
             ref runner,
             ref contract_name,
@@ -779,11 +4534,11 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
         let schema = if bytes.starts_with(VERSIONED_SCHEMA_MAGIC_HASH) {
             from_bytes::<VersionedModuleSchema>(&bytes)
         } else {
-            from_bytes(&bytes).map(VersionedModuleSchema::V0)
+            (descriptor.legacy_schema)(&bytes)
         };
         Some(schema.map_err(|_| anyhow::anyhow!("Could not deserialize schema file."))?)
     } else {
-        let res = utils::get_embedded_schema_v0(module);
+        let res = (descriptor.embedded_schema)(module);
         if let Err(err) = &res {
             eprintln!(
                 "{}",
@@ -813,9 +4568,27 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
         }
     });
 
+    let event_schema = runner
+        .event_schema
+        .as_deref()
+        .map(schema_types::read_type)
+        .transpose()?;
+
     let print_result = |state: v0::State, logs: v0::Logs| -> anyhow::Result<()> {
         for (i, item) in logs.iterate().enumerate() {
-            eprintln!("{}: {:?}", i, item)
+            match event_schema
+                .as_ref()
+                .map(|schema| schema.to_json_string_pretty(item))
+            {
+                Some(Ok(log_json)) => {
+                    eprintln!("The JSON representation of log {} is:\n{}", i, log_json)
+                }
+                Some(Err(error)) => eprintln!(
+                    "Event schema had an error. {:?}. The raw value of log {} is: {:?}",
+                    error, i, item
+                ),
+                None => eprintln!("{}: {:?}", i, item),
+            }
         }
         let state = &state.state;
         match (runner.ignore_state_schema, &contract_schema_state_opt) {
@@ -867,14 +4640,22 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
     let parameter = get_parameter(
         runner.parameter_bin_path.as_deref(),
         runner.parameter_json_path.as_deref(),
+        runner.parameter_inline.as_deref(),
+        runner.parameter_hex.as_deref(),
         contract_schema_opt.is_some(),
         contract_schema_func_opt,
     )
     .context("Could not get parameter.")?;
+    ensure!(
+        parameter.as_ref().len() <= protocol_version.max_parameter_size(),
+        "The parameter exceeds the maximum size of {} bytes in {}.",
+        protocol_version.max_parameter_size(),
+        protocol_version
+    );
 
-    match run_cmd {
+    match *run_cmd {
         RunCommand::Init { ref context, .. } => {
-            let init_ctx: InitContextOpt = match context {
+            let mut init_ctx: InitContextOpt = match context {
                 Some(context_file) => {
                     let ctx_content =
                         fs::read(context_file).context("Could not read init context file.")?;
@@ -882,6 +4663,7 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                 }
                 None => InitContextOpt::default(),
             };
+            init_ctx.apply(&runner.context)?;
             let name = format!("init_{}", contract_name);
             let res = v0::invoke_init_with_metering_from_source(
                 module,
@@ -889,7 +4671,7 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                 init_ctx,
                 &name,
                 parameter.as_parameter(),
-                false, // Whether number of logs should be limited. Limit removed in PV5.
+                protocol_version.limit_logs_and_return_values(),
                 runner.energy,
             )
             .context("Initialization failed due to a runtime error.")?;
@@ -903,7 +4685,7 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                     print_result(state, logs)?;
                     eprintln!(
                         "Interpreter energy spent is {}",
-                        runner.energy.subtract(remaining_energy.energy)
+                        display_energy(runner.energy.subtract(remaining_energy.energy))
                     )
                 }
                 v0::InitResult::Reject {
@@ -913,7 +4695,7 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                     eprintln!("Init call rejected with reason {}.", reason);
                     eprintln!(
                         "Interpreter energy spent is {}",
-                        runner.energy.subtract(remaining_energy.energy)
+                        display_energy(runner.energy.subtract(remaining_energy.energy))
                     )
                 }
                 v0::InitResult::OutOfEnergy => {
@@ -938,11 +4720,11 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                 }
                 None => ReceiveContextOpt::default(),
             };
+            receive_ctx.apply(&runner.context)?;
             // if the balance is set in the flag it overrides any balance that is set in the
             // context.
             if let Some(balance) = balance {
-                receive_ctx.self_balance =
-                    Some(concordium_contracts_common::Amount::from_micro_ccd(balance));
+                receive_ctx.self_balance = Some(balance);
             }
 
             // initial state of the smart contract, read from either a binary or json file.
@@ -988,8 +4770,8 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                     energy:       runner.energy,
                 },
                 &init_state,
-                u16::MAX as usize, // Max parameter size in PV5.
-                false,             // Whether to limit number of logs. Limit removed in PV5.
+                protocol_version.max_parameter_size(),
+                protocol_version.limit_logs_and_return_values(),
             )
             .context("Calling receive failed.")?;
             match res {
@@ -1037,7 +4819,7 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
 
                     eprintln!(
                         "Interpreter energy spent is {}",
-                        runner.energy.subtract(remaining_energy.energy)
+                        display_energy(runner.energy.subtract(remaining_energy.energy))
                     )
                 }
                 v0::ReceiveResult::Reject {
@@ -1047,7 +4829,7 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                     eprintln!("Receive call rejected with reason {}", reason);
                     eprintln!(
                         "Interpreter energy spent is {}",
-                        runner.energy.subtract(remaining_energy.energy)
+                        display_energy(runner.energy.subtract(remaining_energy.energy))
                     )
                 }
                 v0::ReceiveResult::OutOfEnergy => {
@@ -1056,11 +4838,33 @@ fn handle_run_v0(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
             }
         }
     }
-    Ok(())
+    Ok(None)
+}
+
+/// Run an invocation of a V1 contract, or an initialization followed by the
+/// updates given with `--then-update`, and return its report.
+fn run_v1(
+    run_cmd: &RunCommand,
+    version: WasmVersion,
+    module: &[u8],
+    protocol_version: protocol::ProtocolVersion,
+) -> anyhow::Result<Option<run_report::RunReport>> {
+    let report = match run_cmd {
+        RunCommand::Init { then_update, .. } if !then_update.is_empty() => {
+            run_flow(run_cmd, version, module, protocol_version)?
+        }
+        _ => handle_run_v1(run_cmd, version, module, protocol_version)?,
+    };
+    Ok(Some(report))
 }
 
-fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
-    let (contract_name, runner, is_receive) = match run_cmd {
+fn handle_run_v1(
+    run_cmd: &RunCommand,
+    version: WasmVersion,
+    module: &[u8],
+    protocol_version: protocol::ProtocolVersion,
+) -> anyhow::Result<run_report::RunReport> {
+    let (contract_name, runner, is_receive) = match *run_cmd {
         RunCommand::Init {
             ref runner,
             ref contract_name,
@@ -1181,7 +4985,17 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
             None => (false, None, None, None, None),
         };
 
+    let human = runner.format == OutputFormat::Human;
+    let mut report = run_report::RunReport::new(
+        contract_name,
+        is_receive.map(String::as_str),
+        protocol_version.to_string(),
+    );
+
     let print_logs = |logs: v0::Logs| {
+        if !human {
+            return;
+        }
         for (i, item) in logs.iterate().enumerate() {
             match schema_event {
                 Some(schema) => {
@@ -1213,30 +5027,52 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
 
     let print_state = |mut state: v1::trie::MutableState,
                        loader: &mut v1::trie::Loader<&[u8]>,
-                       should_display_state: bool|
-     -> anyhow::Result<()> {
+                       should_display_state: bool,
+                       previous: Option<&v1::trie::PersistentState>|
+     -> anyhow::Result<(u64, String)> {
         let mut collector = v1::trie::SizeCollector::default();
         let frozen = state.freeze(loader, &mut collector);
-        println!(
-            "\nThe contract will produce {}B of additional state that will be charged for.",
-            collector.collect()
-        );
+        let size = collector.collect();
+        let hash = receipt::state_hash(&frozen)?;
+        if human {
+            println!(
+                "\nThe contract will produce {}B of additional state that will be charged for.",
+                size
+            );
+            if let Some(previous) = previous {
+                state_diff::print(previous, &frozen, runner.diff_values)?;
+            }
+        }
         if let Some(file_path) = &runner.out_bin {
+            if let Some(out_dir) = file_path.parent() {
+                fs::create_dir_all(out_dir)
+                    .context("Unable to create directory for the binary state output.")?;
+            }
             let mut out_file = std::fs::File::create(file_path)
                 .context("Could not create file to write state into.")?;
             frozen
                 .serialize(loader, &mut out_file)
                 .context("Could not write the state.")?;
-            eprintln!("Resulting state written to {}.", file_path.display());
+            if human {
+                eprintln!("Resulting state written to {}.", file_path.display());
+            }
+        }
+        if let Some(file_path) = &runner.out_json {
+            state_json::write(&frozen, file_path)?;
+            if human {
+                eprintln!("Resulting state written to {}.", file_path.display());
+            }
         }
-        if should_display_state {
-            display_state(&frozen)?;
+        if should_display_state || runner.state_display.is_customized() {
+            display_state(&frozen, &runner.state_display)?;
         }
-        Ok(())
+        Ok((size, hash))
     };
 
     let print_return_value = |rv: ReturnValue| {
-        if let Some(schema) = schema_return_value {
+        if !human {
+            Ok(())
+        } else if let Some(schema) = schema_return_value {
             let out = schema
                 .to_json_string_pretty(&rv)
                 .map_err(|_| anyhow::anyhow!("Could not output return value in JSON"))?;
@@ -1252,7 +5088,9 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
     };
 
     let print_error = |rv: ReturnValue| {
-        if let Some(schema) = schema_error {
+        if !human {
+            Ok(())
+        } else if let Some(schema) = schema_error {
             let out = schema
                 .to_json_string_pretty(&rv)
                 .map_err(|_| anyhow::anyhow!("Could not output error value in JSON"))?;
@@ -1270,18 +5108,39 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
     let parameter = get_parameter(
         runner.parameter_bin_path.as_deref(),
         runner.parameter_json_path.as_deref(),
+        runner.parameter_inline.as_deref(),
+        runner.parameter_hex.as_deref(),
         contract_has_schema,
         schema_parameter,
     )
     .context("Could not get parameter.")?;
 
-    match run_cmd {
+    let mut receipt_inputs = receipt::ReceiptInputs {
+        context:            serde_json::Value::Null,
+        amount:             runner.amount,
+        parameter_hex:      hex::encode(parameter.as_ref()),
+        energy:             runner.energy.energy,
+        initial_state_hash: None,
+    };
+
+    let mut event_sink = runner
+        .events_ndjson
+        .as_deref()
+        .map(events::EventSink::open)
+        .transpose()?;
+    let mut coverage = runner
+        .coverage
+        .as_deref()
+        .map(coverage::CoverageData::load)
+        .transpose()?;
+
+    match *run_cmd {
         RunCommand::Init {
             ref context,
             should_display_state,
             ..
         } => {
-            let init_ctx: InitContextOpt = match context {
+            let mut init_ctx: InitContextOpt = match context {
                 Some(context_file) => {
                     let ctx_content =
                         fs::read(context_file).context("Could not read init context file.")?;
@@ -1289,6 +5148,31 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                 }
                 None => InitContextOpt::default(),
             };
+            init_ctx.apply(&runner.context)?;
+            receipt_inputs.context = serde_json::to_value(&init_ctx)?;
+            if runner.check_determinism {
+                let sim = sim::Simulator::new(module, runner.energy)?;
+                let deterministic = determinism::check_init(
+                    &sim,
+                    contract_name,
+                    init_ctx.clone(),
+                    runner.amount,
+                    parameter.as_ref(),
+                )?;
+                ensure!(deterministic, "The initialization is not deterministic.");
+            }
+            if let Some(runs) = runner.bench {
+                bench::invocation_init(
+                    module,
+                    runner.energy,
+                    runs,
+                    contract_name,
+                    &init_ctx,
+                    runner.amount,
+                    parameter.as_ref(),
+                )?;
+            }
+            let event_origin = events::EventOrigin::init(contract_name, &init_ctx);
             let name = format!("init_{}", contract_name);
             // empty initial backing store.
             let mut loader = v1::trie::Loader::new(&[][..]);
@@ -1298,15 +5182,17 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                     amount:          runner.amount,
                     parameter:       parameter.as_ref(),
                     energy:          runner.energy,
-                    support_upgrade: true, // Upgrades are supported in PV5 and onward.
+                    support_upgrade: protocol_version.support_upgrade(),
                 },
                 init_ctx,
                 &name,
                 loader,
-                false, /* Whether number of logs and size of return values should be limited.
-                        * Limits removed in PV5. */
+                protocol_version.limit_logs_and_return_values(),
             )
             .context("Initialization failed due to a runtime error.")?;
+            if let Some(coverage) = coverage.as_mut() {
+                coverage.record_init(contract_name);
+            }
             match res {
                 v1::InitResult::Success {
                     logs,
@@ -1314,46 +5200,119 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                     remaining_energy,
                     return_value,
                 } => {
-                    eprintln!("\nInit call succeeded. The following logs were produced:");
+                    if human {
+                        eprintln!("\nInit call succeeded. The following logs were produced:");
+                    }
+                    if let Some(coverage) = coverage.as_mut() {
+                        coverage.record_events(
+                            contract_name,
+                            logs.iterate().map(|e| &e[..]),
+                            schema_event,
+                        );
+                    }
+                    if let Some(sink) = event_sink.as_mut() {
+                        sink.emit_logs(&event_origin, &logs, schema_event)?;
+                    }
+                    report.add_events(&logs, schema_event);
                     print_logs(logs);
-                    print_state(state, &mut loader, should_display_state)?;
-                    eprintln!("\nThe following return value was returned:");
+                    report.state_changed = true;
+                    let (state_size, state_hash) =
+                        print_state(state, &mut loader, should_display_state, None)?;
+                    report.state_size = Some(state_size);
+                    report.state_hash = Some(state_hash);
+                    report.new_balance = Some(runner.amount);
+                    report.return_value = Some(run_report::ContractValue::new(
+                        &return_value,
+                        schema_return_value,
+                    ));
+                    let energy_used = runner.energy.subtract(remaining_energy.energy);
+                    report.set_energy_used(energy_used.energy);
+                    if human {
+                        eprintln!("\nThe following return value was returned:");
+                    }
                     print_return_value(return_value)?;
-                    eprintln!(
-                        "\nInterpreter energy spent is {}",
-                        runner.energy.subtract(remaining_energy.energy)
-                    )
+                    if human {
+                        eprintln!(
+                            "\nInterpreter energy spent is {}",
+                            display_energy(energy_used)
+                        )
+                    }
                 }
                 v1::InitResult::Reject {
                     remaining_energy,
                     reason,
                     return_value,
                 } => {
-                    eprintln!("Init call rejected with reason {}.", reason);
-                    eprintln!("\nThe following error value was returned:");
+                    if let Some(coverage) = coverage.as_mut() {
+                        coverage.record_error(contract_name, &return_value, schema_error);
+                    }
+                    let energy_used = runner.energy.subtract(remaining_energy.energy);
+                    report.outcome = run_report::Outcome::Reject;
+                    report.reject_reason = Some(reason);
+                    report.return_value =
+                        Some(run_report::ContractValue::new(&return_value, schema_error));
+                    report.set_energy_used(energy_used.energy);
+                    if human {
+                        eprintln!("Init call rejected with reason {}.", reason);
+                        eprintln!("\nThe following error value was returned:");
+                    }
                     print_error(return_value)?;
-                    eprintln!(
-                        "\nInterpreter energy spent is {}",
-                        runner.energy.subtract(remaining_energy.energy)
-                    )
+                    if human {
+                        eprintln!(
+                            "\nInterpreter energy spent is {}",
+                            display_energy(energy_used)
+                        )
+                    }
                 }
                 v1::InitResult::Trap {
                     remaining_energy,
                     error,
                 } => {
+                    // The invocation is covered even though it trapped.
+                    if let (Some(coverage), Some(path)) = (&coverage, &runner.coverage) {
+                        coverage.save(path)?;
+                    }
+                    let energy_used = runner.energy.subtract(remaining_energy.energy);
+                    report.outcome = run_report::Outcome::Trap;
+                    report.set_energy_used(energy_used.energy);
+                    report.error = Some(format!("{:#}", error));
+                    if !human {
+                        report.print()?;
+                    }
+                    save_receipt(runner, version, module, &receipt_inputs, &report)?;
+                    // A trap can be expected, so it is checked like the other
+                    // outcomes.
+                    if runner.expect.is_some() {
+                        if human {
+                            eprintln!(
+                                "Execution triggered a runtime error after spending {} \
+                                 interpreter energy: {:#}",
+                                energy_used, error
+                            );
+                        }
+                        return Ok(report);
+                    }
                     return Err(error.context(format!(
                         "Execution triggered a runtime error after spending {} interpreter energy.",
-                        runner.energy.subtract(remaining_energy.energy)
+                        energy_used
                     )));
                 }
                 v1::InitResult::OutOfEnergy => {
-                    eprintln!("Init call terminated with out of energy.")
+                    report.outcome = run_report::Outcome::OutOfEnergy;
+                    if human {
+                        eprintln!("Init call terminated with out of energy.")
+                    }
                 }
             }
         }
         RunCommand::Receive {
             ref entrypoint,
             ref state_bin_path,
+            ref state_json_path,
+            interrupt_responses: ref responses_file,
+            interrupt_response: ref inline_responses,
+            instances: ref instances_path,
+            upgrade_modules: ref upgrade_module_paths,
             balance,
             ref context,
             should_display_state,
@@ -1368,20 +5327,24 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                 }
                 None => ReceiveContextV1Opt::default(),
             };
+            receive_ctx.common.apply(&runner.context)?;
             // if the balance is set in the flag it overrides any balance that is set in the
             // context.
             if let Some(balance) = balance {
-                receive_ctx.common.self_balance =
-                    Some(concordium_contracts_common::Amount::from_micro_ccd(balance));
+                receive_ctx.common.self_balance = Some(balance);
             }
+            receipt_inputs.context = serde_json::to_value(&receive_ctx)?;
 
             // initial state of the smart contract, read from either a binary or json file.
-            let (init_state, mut loader) = match state_bin_path {
-                None => bail!(
+            let (init_state, mut loader) = match (state_bin_path, state_json_path) {
+                (None, None) => bail!(
                     "The current state is required for simulating an update to a contract \
-                     instance. Use --state-bin."
+                     instance. Use either --state-bin or --state-json."
                 ),
-                Some(file_path) => {
+                (Some(_), Some(_)) => {
+                    bail!("Only one state is allowed, choose either --state-bin or --state-json.")
+                }
+                (Some(file_path), None) => {
                     let file = File::open(&file_path).context("Could not read state file.")?;
                     let mut reader = std::io::BufReader::new(file);
                     let init_state = v1::trie::PersistentState::deserialize(&mut reader)
@@ -1392,11 +5355,46 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                     let loader = v1::trie::Loader::new(&[][..]);
                     (init_state, loader)
                 }
+                (None, Some(file_path)) => {
+                    let init_state = state_json::read(file_path)?;
+                    (init_state, v1::trie::Loader::new(&[][..]))
+                }
             };
+            let initial_state_hash = receipt::state_hash(&init_state)?;
+            // The hash is replaced if the state changes.
+            report.state_hash = Some(initial_state_hash.clone());
+            receipt_inputs.initial_state_hash = Some(initial_state_hash);
+
+            if runner.check_determinism {
+                let sim = sim::Simulator::new(module, runner.energy)?;
+                let deterministic = determinism::check_update(
+                    &sim,
+                    contract_name,
+                    entrypoint,
+                    &init_state,
+                    receive_ctx.clone(),
+                    runner.amount,
+                    parameter.as_ref(),
+                )?;
+                ensure!(deterministic, "The update is not deterministic.");
+            }
+            if let Some(runs) = runner.bench {
+                bench::invocation_update(
+                    module,
+                    runner.energy,
+                    runs,
+                    contract_name,
+                    entrypoint,
+                    &init_state,
+                    &receive_ctx,
+                    runner.amount,
+                    parameter.as_ref(),
+                )?;
+            }
 
             let artifact = concordium_wasm::utils::instantiate_with_metering(
                 &v1::ConcordiumAllowedImports {
-                    support_upgrade: true,
+                    support_upgrade: protocol_version.support_upgrade(),
                 },
                 module,
             )?;
@@ -1427,27 +5425,247 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                 }
             };
 
+            // Profile before invoking, since the artifact is consumed by the invocation.
+            let energy_profile = if runner.energy_breakdown {
+                Some(profile::profile_receive(
+                    &artifact,
+                    receive_ctx.clone(),
+                    name.as_receive_name(),
+                    runner.amount,
+                    parameter.as_ref(),
+                    runner.energy,
+                    &init_state,
+                    protocol_version.receive_params(),
+                ))
+            } else {
+                None
+            };
+            let host_function_profile = if runner.profile {
+                Some(profile::profile_host_functions(
+                    &artifact,
+                    receive_ctx.clone(),
+                    name.as_receive_name(),
+                    runner.amount,
+                    parameter.as_ref(),
+                    runner.energy,
+                    &init_state,
+                    protocol_version.receive_params(),
+                ))
+            } else {
+                None
+            };
+            if let Some(trace_path) = &runner.record_trace {
+                let (recorder, outcome) = profile::observe_receive(
+                    &artifact,
+                    receive_ctx.clone(),
+                    name.as_receive_name(),
+                    runner.amount,
+                    parameter.as_ref(),
+                    runner.energy,
+                    &init_state,
+                    protocol_version.receive_params(),
+                    trace::TraceRecorder::default(),
+                );
+                let trace = recorder.into_trace(
+                    contract_name,
+                    &name.as_receive_name().entrypoint_name().to_string(),
+                    outcome,
+                );
+                trace.save(trace_path)?;
+                eprintln!(
+                    "Recorded a trace with {} steps to {}.",
+                    trace.steps.len(),
+                    trace_path.display()
+                );
+            }
+            if let Some(trace_path) = &runner.trace {
+                let (logger, outcome) = profile::observe_receive(
+                    &artifact,
+                    receive_ctx.clone(),
+                    name.as_receive_name(),
+                    runner.amount,
+                    parameter.as_ref(),
+                    runner.energy,
+                    &init_state,
+                    protocol_version.receive_params(),
+                    trace::HostCallLogger::new(trace_path.as_deref())?,
+                );
+                let calls = logger.finish(&outcome)?;
+                if let Some(trace_path) = trace_path {
+                    eprintln!("Logged {} host calls to {}.", calls, trace_path.display());
+                }
+            }
+
+            let mut interrupt_responses = match responses_file {
+                Some(path) => interrupt::load(path)?,
+                None => Vec::new(),
+            };
+            for json in inline_responses {
+                interrupt_responses.push(interrupt::parse(json)?);
+            }
+
+            let mut local_instances = match instances_path {
+                Some(path) => Some(instances::Instances::load(path)?),
+                None => None,
+            };
+            let mut upgrade_modules = interrupt::UpgradeModules::load(upgrade_module_paths)?;
+            // The context of the calls to local instances is derived from this one.
+            let call_ctx = receive_ctx.clone();
+
+            let event_origin =
+                events::EventOrigin::receive(contract_name, entrypoint, &receive_ctx);
+            // The balance during the call, which is reduced by the amounts sent in
+            // responses to interrupts.
+            let mut balance = Amount::from_micro_ccd(
+                receive_ctx
+                    .common
+                    .self_balance
+                    .unwrap_or_else(Amount::zero)
+                    .micro_ccd()
+                    .saturating_add(runner.amount.micro_ccd()),
+            );
             let mut mutable_state = init_state.thaw();
-            let inner = mutable_state.get_inner(&mut loader);
-            let instance_state = v1::InstanceState::new(loader, inner);
-            let res = v1::invoke_receive::<_, _, _, _, ReceiveContextV1Opt, ReceiveContextV1Opt>(
-                std::sync::Arc::new(artifact),
-                receive_ctx,
-                v1::ReceiveInvocation {
-                    amount:       runner.amount,
-                    receive_name: name.as_receive_name(),
-                    parameter:    parameter.as_ref(),
-                    energy:       runner.energy,
-                },
-                instance_state,
-                v1::ReceiveParams {
-                    // These are the parameters in PV5.
-                    max_parameter_size:           u16::MAX as usize,
-                    limit_logs_and_return_values: false,
-                    support_queries:              true,
-                },
-            )
-            .context("Calling receive failed.")?;
+            let mut res = if runner.crypto.is_active() {
+                let (res, forced) = crypto::invoke_receive(
+                    &artifact,
+                    receive_ctx,
+                    name.as_receive_name(),
+                    runner.amount,
+                    parameter.as_ref(),
+                    runner.energy,
+                    &mut mutable_state,
+                    protocol_version.receive_params(),
+                    &runner.crypto,
+                )?;
+                if human {
+                    for verification in forced {
+                        eprintln!(
+                            "The signature by {} was made {}.",
+                            verification.public_key,
+                            if verification.valid {
+                                "valid"
+                            } else {
+                                "invalid"
+                            }
+                        );
+                    }
+                }
+                res
+            } else {
+                let inner = mutable_state.get_inner(&mut loader);
+                let instance_state = v1::InstanceState::new(loader, inner);
+                v1::invoke_receive::<_, _, _, _, ReceiveContextV1Opt, ReceiveContextV1Opt>(
+                    std::sync::Arc::new(artifact),
+                    receive_ctx,
+                    v1::ReceiveInvocation {
+                        amount:       runner.amount,
+                        receive_name: name.as_receive_name(),
+                        parameter:    parameter.as_ref(),
+                        energy:       runner.energy,
+                    },
+                    instance_state,
+                    protocol_version.receive_params(),
+                )
+                .context("Calling receive failed.")?
+            };
+            // Resume the call with the given responses as long as it is interrupted.
+            let mut responses = interrupt_responses.iter();
+            let mut state_changed_before = false;
+            loop {
+                let (remaining_energy, state_changed, logs, config, interrupt) = match res {
+                    v1::ReceiveResult::Interrupt {
+                        remaining_energy,
+                        state_changed,
+                        logs,
+                        config,
+                        interrupt,
+                    } => (remaining_energy, state_changed, logs, config, interrupt),
+                    _ => break,
+                };
+                let is_local_call = local_instances
+                    .as_ref()
+                    .map_or(false, |i| i.handles(&interrupt))
+                    || upgrade_modules.handles(&interrupt);
+                if !is_local_call && responses.as_slice().is_empty() {
+                    res = v1::ReceiveResult::Interrupt {
+                        remaining_energy,
+                        state_changed,
+                        logs,
+                        config,
+                        interrupt,
+                    };
+                    break;
+                }
+                if human {
+                    eprintln!(
+                        "\nReceive method was interrupted by {}. The following logs were produced \
+                         by the time of the interrupt.",
+                        sim::describe_interrupt(&interrupt)
+                    );
+                }
+                if let Some(coverage) = coverage.as_mut() {
+                    coverage.record_events(
+                        contract_name,
+                        logs.iterate().map(|e| &e[..]),
+                        schema_event,
+                    );
+                }
+                if let Some(sink) = event_sink.as_mut() {
+                    sink.emit_logs(&event_origin, &logs, schema_event)?;
+                }
+                report.add_events(&logs, schema_event);
+                print_logs(logs);
+                let local_call = match local_instances.as_mut() {
+                    Some(instances) => {
+                        instances.call(&interrupt, &call_ctx, remaining_energy, &mut balance)?
+                    }
+                    None => None,
+                };
+                let local_call = local_call.or_else(|| {
+                    let response = upgrade_modules.respond(&interrupt, contract_name, balance)?;
+                    Some((response, 0))
+                });
+                let (response, remaining_energy) = match local_call {
+                    Some((response, energy_used)) => {
+                        (response, remaining_energy.saturating_sub(energy_used))
+                    }
+                    None => {
+                        let response = responses
+                            .next()
+                            .context("No response is left for the interrupt.")?
+                            .to_invoke_response(&interrupt, &mut balance)?;
+                        if human {
+                            eprintln!("Resuming with the next response.");
+                        }
+                        (response, remaining_energy)
+                    }
+                };
+                state_changed_before |= state_changed;
+                res = v1::resume_receive(
+                    config,
+                    Some(response),
+                    InterpreterEnergy {
+                        energy: remaining_energy,
+                    },
+                    &mut mutable_state,
+                    // A response cannot change the state of the instance.
+                    false,
+                    loader,
+                )
+                .context("Resuming the call failed.")?;
+            }
+            if let Some(coverage) = coverage.as_mut() {
+                coverage.record_entrypoint(
+                    contract_name,
+                    &name.as_receive_name().entrypoint_name().to_string(),
+                );
+            }
+            if let Some(energy_profile) = energy_profile {
+                energy_profile.print();
+            }
+            if let Some(host_function_profile) = host_function_profile {
+                host_function_profile.print();
+            }
             match res {
                 v1::ReceiveResult::Success {
                     logs,
@@ -1455,35 +5673,87 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                     remaining_energy,
                     return_value,
                 } => {
-                    eprintln!("\nReceive method succeeded. The following logs were produced.");
+                    if human {
+                        eprintln!("\nReceive method succeeded. The following logs were produced.");
+                    }
+                    if let Some(coverage) = coverage.as_mut() {
+                        coverage.record_events(
+                            contract_name,
+                            logs.iterate().map(|e| &e[..]),
+                            schema_event,
+                        );
+                    }
+                    if let Some(sink) = event_sink.as_mut() {
+                        sink.emit_logs(&event_origin, &logs, schema_event)?;
+                    }
+                    report.add_events(&logs, schema_event);
                     print_logs(logs);
+                    let state_changed = state_changed || state_changed_before;
+                    report.state_changed = state_changed;
+                    report.new_balance = Some(balance);
+                    if human {
+                        eprintln!("The balance of the instance is now {}.", balance);
+                    }
                     if state_changed {
-                        print_state(mutable_state, &mut loader, should_display_state)?;
-                    } else {
+                        let (state_size, state_hash) = print_state(
+                            mutable_state,
+                            &mut loader,
+                            should_display_state,
+                            Some(&init_state),
+                        )?;
+                        report.state_size = Some(state_size);
+                        report.state_hash = Some(state_hash);
+                    } else if human {
                         eprintln!("The state of the contract did not change.");
                     }
-                    eprintln!("\nThe following return value was returned:");
+                    report.return_value = Some(run_report::ContractValue::new(
+                        &return_value,
+                        schema_return_value,
+                    ));
+                    let energy_used = runner.energy.subtract(remaining_energy);
+                    report.set_energy_used(energy_used.energy);
+                    if human {
+                        eprintln!("\nThe following return value was returned:");
+                    }
                     print_return_value(return_value)?;
-                    eprintln!(
-                        "\nInterpreter energy spent is {}",
-                        runner.energy.subtract(remaining_energy)
-                    )
+                    if human {
+                        eprintln!(
+                            "\nInterpreter energy spent is {}",
+                            display_energy(energy_used)
+                        )
+                    }
                 }
                 v1::ReceiveResult::Reject {
                     remaining_energy,
                     reason,
                     return_value,
                 } => {
-                    eprintln!("Receive call rejected with reason {}", reason);
-                    eprintln!("\nThe following error value was returned:");
+                    if let Some(coverage) = coverage.as_mut() {
+                        coverage.record_error(contract_name, &return_value, schema_error);
+                    }
+                    let energy_used = runner.energy.subtract(remaining_energy);
+                    report.outcome = run_report::Outcome::Reject;
+                    report.reject_reason = Some(reason);
+                    report.return_value =
+                        Some(run_report::ContractValue::new(&return_value, schema_error));
+                    report.set_energy_used(energy_used.energy);
+                    if human {
+                        eprintln!("Receive call rejected with reason {}", reason);
+                        eprintln!("\nThe following error value was returned:");
+                    }
                     print_error(return_value)?;
-                    eprintln!(
-                        "\nInterpreter energy spent is {}",
-                        runner.energy.subtract(remaining_energy)
-                    )
+                    if human {
+                        eprintln!(
+                            "\nInterpreter energy spent is {}",
+                            display_energy(energy_used)
+                        )
+                    }
                 }
                 v1::ReceiveResult::OutOfEnergy => {
-                    eprintln!("Receive call terminated with: out of energy.")
+                    report.outcome = run_report::Outcome::OutOfEnergy;
+                    if human {
+                        eprintln!("Receive call terminated with: out of energy.")
+                    }
                 }
                 v1::ReceiveResult::Interrupt {
                     remaining_energy,
@@ -1492,76 +5762,492 @@ fn handle_run_v1(run_cmd: RunCommand, module: &[u8]) -> anyhow::Result<()> {
                     config: _,
                     interrupt,
                 } => {
-                    eprintln!(
-                        "Receive method was interrupted. The following logs were produced by the \
-                         time of the interrupt."
-                    );
+                    if human {
+                        eprintln!(
+                            "Receive method was interrupted. The following logs were produced by \
+                             the time of the interrupt."
+                        );
+                    }
+                    if let Some(coverage) = coverage.as_mut() {
+                        coverage.record_events(
+                            contract_name,
+                            logs.iterate().map(|e| &e[..]),
+                            schema_event,
+                        );
+                    }
+                    if let Some(sink) = event_sink.as_mut() {
+                        sink.emit_logs(&event_origin, &logs, schema_event)?;
+                    }
+                    report.add_events(&logs, schema_event);
                     print_logs(logs);
+                    report.outcome = run_report::Outcome::Interrupt;
+                    let state_changed = state_changed || state_changed_before;
+                    report.state_changed = state_changed;
                     if state_changed {
-                        print_state(mutable_state, &mut loader, should_display_state)?;
-                    } else {
+                        let (state_size, state_hash) = print_state(
+                            mutable_state,
+                            &mut loader,
+                            should_display_state,
+                            Some(&init_state),
+                        )?;
+                        report.state_size = Some(state_size);
+                        report.state_hash = Some(state_hash);
+                    } else if human {
                         eprintln!("The state of the contract did not change.");
                     }
-                    match interrupt {
-                        v1::Interrupt::Transfer { to, amount } => eprintln!(
-                            "Receive call invoked a transfer of {} CCD to {}.",
-                            amount, to
-                        ),
-                        v1::Interrupt::Call {
-                            address,
-                            parameter,
-                            name,
-                            amount,
-                        } => eprintln!(
-                            "Receive call invoked contract at ({}, {}), calling method {} with \
-                             amount {} and parameter {:?}.",
-                            address.index, address.subindex, name, amount, parameter
-                        ),
-                        v1::Interrupt::Upgrade { module_ref } => eprintln!(
-                            "Receive call requested to upgrade the contract to module reference \
-                             {}.",
-                            hex::encode(module_ref.as_ref()) /* use direct hex encoding until we
-                                                              * have a proper Display
-                                                              * implementation. */
-                        ),
-
-                        v1::Interrupt::QueryAccountBalance { address } => {
-                            eprintln!("Receive call requested balance of the account {}.", address)
-                        }
+                    let energy_used = runner.energy.subtract(remaining_energy);
+                    report.set_energy_used(energy_used.energy);
+                    report.interrupt = Some(run_report::interrupt_details(&interrupt));
+                    if human {
+                        match interrupt {
+                            v1::Interrupt::Transfer { to, amount } => eprintln!(
+                                "Receive call invoked a transfer of {} CCD to {}.",
+                                amount, to
+                            ),
+                            v1::Interrupt::Call {
+                                address,
+                                parameter,
+                                name,
+                                amount,
+                            } => eprintln!(
+                                "Receive call invoked contract at ({}, {}), calling method {} \
+                                 with amount {} and parameter {:?}.",
+                                address.index, address.subindex, name, amount, parameter
+                            ),
+                            v1::Interrupt::Upgrade { module_ref } => eprintln!(
+                                "Receive call requested to upgrade the contract to module \
+                                 reference {}.",
+                                hex::encode(module_ref.as_ref()) /* use direct hex encoding
+                                                                  * until
+                                                                  * we have a proper Display
+                                                                  * implementation. */
+                            ),
 
-                        v1::Interrupt::QueryContractBalance { address } => eprintln!(
-                            "Receive call requested balance of the contract {}.",
-                            address
-                        ),
-                        v1::Interrupt::QueryExchangeRates => {
-                            eprintln!("Receive call requested exchange rates.")
+                            v1::Interrupt::QueryAccountBalance { address } => eprintln!(
+                                "Receive call requested balance of the account {}.",
+                                address
+                            ),
+
+                            v1::Interrupt::QueryContractBalance { address } => eprintln!(
+                                "Receive call requested balance of the contract {}.",
+                                address
+                            ),
+                            v1::Interrupt::QueryExchangeRates => {
+                                eprintln!("Receive call requested exchange rates.")
+                            }
                         }
+                        eprintln!(
+                            "Interpreter energy spent is {}",
+                            display_energy(energy_used)
+                        )
                     }
-                    eprintln!(
-                        "Interpreter energy spent is {}",
-                        runner.energy.subtract(remaining_energy)
-                    )
                 }
                 v1::ReceiveResult::Trap {
                     remaining_energy,
                     error,
                 } => {
+                    // The invocation is covered even though it trapped.
+                    if let (Some(coverage), Some(path)) = (&coverage, &runner.coverage) {
+                        coverage.save(path)?;
+                    }
+                    let energy_used = runner.energy.subtract(remaining_energy);
+                    report.outcome = run_report::Outcome::Trap;
+                    report.set_energy_used(energy_used.energy);
+                    report.error = Some(format!("{:#}", error));
+                    if !human {
+                        report.print()?;
+                    }
+                    save_receipt(runner, version, module, &receipt_inputs, &report)?;
+                    // A trap can be expected, so it is checked like the other
+                    // outcomes.
+                    if runner.expect.is_some() {
+                        if human {
+                            eprintln!(
+                                "Execution triggered a runtime error after spending {} \
+                                 interpreter energy: {:#}",
+                                energy_used, error
+                            );
+                        }
+                        return Ok(report);
+                    }
                     return Err(error.context(format!(
                         "Execution triggered a runtime error after spending {} interpreter energy.",
-                        runner.energy.subtract(remaining_energy)
+                        energy_used
                     )));
                 }
             }
+            // Upgrades only take effect if the call does not reject.
+            if let Some(module_ref) = upgrade_modules.upgraded_to {
+                if matches!(
+                    report.outcome,
+                    run_report::Outcome::Success | run_report::Outcome::Interrupt
+                ) {
+                    report.upgraded_to = Some(module_ref.to_string());
+                    if human {
+                        eprintln!(
+                            "\nThe instance was upgraded to module {}. Later calls use the new \
+                             module.",
+                            module_ref
+                        );
+                    }
+                }
+            }
+        }
+    }
+    if let Some(sink) = event_sink.as_mut() {
+        sink.flush()?;
+    }
+    if let (Some(coverage), Some(path)) = (&coverage, &runner.coverage) {
+        coverage.save(path)?;
+    }
+    if let Some(energy_used) = report.energy_used {
+        // Rejected invocations do not produce state that is charged for.
+        let cost = cost::InvocationCost::new(
+            energy_used,
+            report.state_size.unwrap_or(0),
+            runner.micro_ccd_per_nrg(),
+        );
+        if human {
+            cost.print();
+        }
+        report.cost = Some(cost);
+    }
+    if !human {
+        report.print()?;
+    }
+    save_receipt(runner, version, module, &receipt_inputs, &report)?;
+    Ok(report)
+}
+
+/// Write the receipt of an invocation of a contract in a module of the given
+/// version if `--receipt-out` is given.
+fn save_receipt(
+    runner: &Runner,
+    version: WasmVersion,
+    module: &[u8],
+    inputs: &receipt::ReceiptInputs,
+    report: &run_report::RunReport,
+) -> anyhow::Result<()> {
+    if let Some(path) = &runner.receipt_out {
+        let module_ref = module_reference(&versioned_module_bytes(module, version));
+        receipt::save(path, module_ref.to_string(), inputs, report)?;
+        if runner.format == OutputFormat::Human {
+            eprintln!("Receipt written to {}.", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Initialize a contract and invoke the entrypoints given with
+/// `--then-update` on the new instance. The state is passed between the
+/// invocations in a temporary file, and the balance is carried over. Options
+/// that write files apply to the last invocation only. Returns the report of
+/// the last invocation.
+fn run_flow(
+    run_cmd: &RunCommand,
+    version: WasmVersion,
+    module: &[u8],
+    protocol_version: protocol::ProtocolVersion,
+) -> anyhow::Result<run_report::RunReport> {
+    let (contract_name, context, update_context, should_display_state, then_update, runner) =
+        match run_cmd {
+            RunCommand::Init {
+                contract_name,
+                context,
+                update_context,
+                should_display_state,
+                then_update,
+                runner,
+            } => (
+                contract_name,
+                context,
+                update_context,
+                *should_display_state,
+                then_update,
+                runner,
+            ),
+            RunCommand::Receive { .. } => bail!("Only `run init` can be followed by updates."),
+        };
+    let human = runner.format == OutputFormat::Human;
+    let bold_style = ansi_term::Style::new().bold();
+    let state_file =
+        std::env::temp_dir().join(format!("cargo-concordium-flow-{}.bin", std::process::id()));
+    let intermediate_runner = Runner {
+        out_bin: Some(state_file.clone()),
+        out_json: None,
+        receipt_out: None,
+        expect: None,
+        record_trace: None,
+        events_ndjson: None,
+        // A trace logged to stderr is kept.
+        trace: runner.trace.clone().filter(Option::is_none),
+        ..runner.clone()
+    };
+    let init_cmd = RunCommand::Init {
+        contract_name: contract_name.clone(),
+        context: context.clone(),
+        should_display_state,
+        then_update: Vec::new(),
+        update_context: None,
+        runner: intermediate_runner.clone(),
+    };
+    let result = (|| -> anyhow::Result<run_report::RunReport> {
+        if human {
+            eprintln!(
+                "{}",
+                bold_style.paint(format!("Initializing '{}':", contract_name))
+            );
+        }
+        let mut report = handle_run_v1(&init_cmd, version, module, protocol_version)?;
+        ensure!(
+            matches!(report.outcome, run_report::Outcome::Success),
+            "The initialization did not succeed, so no entrypoints are invoked."
+        );
+        let mut balance = report.new_balance.unwrap_or_else(Amount::zero);
+        for (i, step) in then_update.iter().enumerate() {
+            let mut step_runner = if i + 1 == then_update.len() {
+                runner.clone()
+            } else {
+                intermediate_runner.clone()
+            };
+            step_runner.amount = Amount::zero();
+            step_runner.parameter_bin_path = None;
+            step_runner.parameter_json_path = step.parameter.clone();
+            step_runner.parameter_inline = None;
+            step_runner.parameter_hex = None;
+            // The account that initialized the instance owns it.
+            if let Some(sender) = runner.context.sender_account() {
+                step_runner.context.default_owner(sender);
+            }
+            let mut update_cmd = RunCommand::Receive {
+                contract_name: contract_name.clone(),
+                entrypoint: step.entrypoint.clone(),
+                state_json_path: None,
+                state_bin_path: Some(state_file.clone()),
+                interrupt_responses: None,
+                interrupt_response: Vec::new(),
+                instances: None,
+                upgrade_modules: Vec::new(),
+                balance: Some(balance),
+                context: update_context.clone(),
+                should_display_state,
+                runner: step_runner,
+            };
+            if human {
+                eprintln!(
+                    "\n{}",
+                    bold_style.paint(format!("Invoking '{}.{}':", contract_name, step.entrypoint))
+                );
+            }
+            use_conventional_files(&mut update_cmd);
+            report = handle_run_v1(&update_cmd, version, module, protocol_version)?;
+            if let Some(new_balance) = report.new_balance {
+                balance = new_balance;
+            }
+        }
+        Ok(report)
+    })();
+    if state_file.exists() {
+        fs::remove_file(&state_file).context("Could not remove the temporary state file.")?;
+    }
+    result
+}
+
+/// Display interpreter energy together with the NRG it amounts to.
+fn display_energy(energy: InterpreterEnergy) -> String {
+    format!(
+        "{} ({} NRG)",
+        energy,
+        energy.energy / cost::INTERPRETER_ENERGY_PER_NRG
+    )
+}
+
+/// Use the conventional context and parameter files of the package for those
+/// that are not given, noting which files are used.
+fn use_conventional_files(run_cmd: &mut RunCommand) {
+    let (context, runner, contract_name, entrypoint) = match run_cmd {
+        RunCommand::Init {
+            context,
+            runner,
+            contract_name,
+            ..
+        } => (context, runner, contract_name, None),
+        RunCommand::Receive {
+            context,
+            runner,
+            contract_name,
+            entrypoint,
+            ..
+        } => (context, runner, contract_name, Some(entrypoint.as_str())),
+    };
+    if context.is_none() {
+        if let Some(path) = discovery::context(entrypoint.is_some()) {
+            eprintln!("Using the context file {} of the package.", path.display());
+            *context = Some(path);
+        }
+    }
+    let has_parameter = runner.parameter_bin_path.is_some()
+        || runner.parameter_json_path.is_some()
+        || runner.parameter_inline.is_some()
+        || runner.parameter_hex.is_some();
+    if !has_parameter {
+        if let Some(path) = discovery::parameter(contract_name, entrypoint) {
+            eprintln!(
+                "Using the parameter file {} of the package.",
+                path.display()
+            );
+            runner.parameter_json_path = Some(path);
+        }
+    }
+}
+
+/// An invocation whose outcome is recorded in a local chain.
+struct LocalChainInvocation {
+    chain:   chain::LocalChain,
+    address: ContractAddress,
+    /// Where the resulting state is written in addition to the local chain.
+    out_bin: Option<PathBuf>,
+}
+
+/// Take the module, state, balance and owner of the updated instance from the
+/// local chain, if one is given, and write the resulting state to the chain.
+fn use_local_chain(run_cmd: &mut RunCommand) -> anyhow::Result<Option<LocalChainInvocation>> {
+    let (runner, receive) = match run_cmd {
+        RunCommand::Init { runner, .. } => (runner, None),
+        RunCommand::Receive {
+            runner,
+            contract_name,
+            state_bin_path,
+            state_json_path,
+            balance,
+            ..
+        } => (
+            runner,
+            Some((contract_name, state_bin_path, state_json_path, balance)),
+        ),
+    };
+    let chain = match &runner.chain_dir {
+        Some(chain_dir) => chain::LocalChain::open(chain_dir)?,
+        None => return Ok(None),
+    };
+    if let Some(previous) = chain.slot_time()? {
+        runner.context.advance_slot_time_from(previous)?;
+    }
+    let address = match receive {
+        None => chain.next_address()?,
+        Some((contract_name, state_bin_path, state_json_path, balance)) => {
+            let address = runner.context.self_address().context(
+                "Give the address of the instance in the local chain with --self-address.",
+            )?;
+            let record = chain.instance(address)?;
+            ensure!(
+                record.contract == *contract_name,
+                "The instance at {} is an instance of '{}', not of '{}'.",
+                address,
+                record.contract,
+                contract_name
+            );
+            ensure!(
+                runner.module.is_none() && state_bin_path.is_none() && state_json_path.is_none(),
+                "The module and state of an instance in the local chain are taken from the chain \
+                 and cannot be given."
+            );
+            runner.module = Some(chain.module_path(&record.module));
+            *state_bin_path = Some(chain.state_path(address));
+            balance.get_or_insert(record.balance);
+            if let Some(owner) = record.owner {
+                runner.context.default_owner(owner);
+            }
+            address
+        }
+    };
+    chain.prepare(address)?;
+    let out_bin = runner.out_bin.replace(chain.pending_state_path(address));
+    Ok(Some(LocalChainInvocation {
+        chain,
+        address,
+        out_bin,
+    }))
+}
+
+/// Record the instance in the local chain after a successful invocation. The
+/// local chain is unchanged by invocations that do not succeed.
+fn record_in_local_chain(
+    run_cmd: &RunCommand,
+    invocation: LocalChainInvocation,
+    report: &run_report::RunReport,
+) -> anyhow::Result<()> {
+    let LocalChainInvocation {
+        chain,
+        address,
+        out_bin,
+    } = invocation;
+    let new_balance = match (report.outcome, report.new_balance) {
+        (run_report::Outcome::Success, Some(new_balance)) => new_balance,
+        _ => {
+            chain.discard(address)?;
+            eprintln!("The invocation did not succeed, so the local chain is unchanged.");
+            return Ok(());
+        }
+    };
+    let pending = chain.pending_state_path(address);
+    if let (Some(out_bin), true) = (out_bin, pending.exists()) {
+        fs::copy(&pending, &out_bin).context("Could not write state to file.")?;
+    }
+    let record = match run_cmd {
+        RunCommand::Init {
+            runner,
+            contract_name,
+            ..
+        } => {
+            let module_path = runner.module.as_ref().context("A module is required.")?;
+            let module = fs::read(module_path).context("Could not read module file.")?;
+            chain::InstanceRecord {
+                address,
+                module: chain.add_module(&module)?,
+                contract: contract_name.clone(),
+                balance: new_balance,
+                owner: runner.context.sender_account(),
+            }
+        }
+        RunCommand::Receive {
+            upgrade_modules, ..
+        } => {
+            let mut record = chain.instance(address)?;
+            record.balance = new_balance;
+            if let Some(module_ref) = &report.upgraded_to {
+                for path in upgrade_modules {
+                    let module = fs::read(path).context("Could not read module file.")?;
+                    if module_reference(&module).to_string() == *module_ref {
+                        record.module = chain.add_module(&module)?;
+                    }
+                }
+            }
+            record
         }
+    };
+    chain.commit(&record)?;
+    let runner = match run_cmd {
+        RunCommand::Init { runner, .. } | RunCommand::Receive { runner, .. } => runner,
+    };
+    if let Some(slot_time) = runner.context.slot_time() {
+        chain.set_slot_time(slot_time)?;
     }
+    eprintln!(
+        "The instance at {} was recorded in the local chain.",
+        address
+    );
     Ok(())
 }
 
 /// Attempt to get a parameter (for either init or receive function) from the
-/// supplied paths, signalling failure if this is not possible.
+/// supplied paths or inline values, signalling failure if this is not
+/// possible.
 fn get_parameter(
     bin_path: Option<&Path>,
     json_path: Option<&Path>,
+    json_inline: Option<&str>,
+    hex_string: Option<&str>,
     has_contract_schema: bool,
     parameter_schema: Option<&Type>,
 ) -> anyhow::Result<OwnedParameter> {
@@ -1569,19 +6255,29 @@ fn get_parameter(
         Ok(OwnedParameter::new_unchecked(
             fs::read(&param_file).context("Could not read parameter-bin file.")?,
         ))
-    } else if let Some(param_file) = json_path {
+    } else if let Some(hex_string) = hex_string {
+        let bytes = hex::decode(hex_string.trim_start_matches("0x"))
+            .context("Could not decode the hex encoded parameter.")?;
+        Ok(OwnedParameter::new_unchecked(bytes))
+    } else if json_path.is_some() || json_inline.is_some() {
         if !has_contract_schema {
             bail!(
-                "No schema found for contract, a schema is required for using --parameter-json. \
-                 Either embed the schema in the module or provide it using the `--schema` option."
+                "No schema found for contract, a schema is required for JSON parameters. Either \
+                 embed the schema in the module or provide it using the `--schema` option."
             )
         } else {
             let parameter_schema = parameter_schema
                 .context("Contract schema did not contain a schema for this parameter.")?;
 
-            let file = fs::read(&param_file).context("Could not read parameter file.")?;
-            let parameter_json: serde_json::Value = serde_json::from_slice(&file)
-                .context("Could not parse the JSON in parameter-json file.")?;
+            let parameter_json: serde_json::Value = match (json_path, json_inline) {
+                (Some(param_file), _) => {
+                    let file = fs::read(&param_file).context("Could not read parameter file.")?;
+                    serde_json::from_slice(&file)
+                        .context("Could not parse the JSON in parameter-json file.")?
+                }
+                (None, json) => serde_json::from_str(json.unwrap_or_default())
+                    .context("Could not parse the inline JSON parameter.")?,
+            };
             let mut parameter_bytes = Vec::new();
             parameter_schema
                 .serial_value_into(&parameter_json, &mut parameter_bytes)
@@ -1616,26 +6312,17 @@ fn get_schema(
             }
         };
 
-        match wasm_version {
-            utils::WasmVersion::V0 => utils::get_embedded_schema_v0(module).context(
-                "Failed to get schema embedded in the module.\nPlease provide a smart contract \
-                 module with an embedded schema.",
-            )?,
-            utils::WasmVersion::V1 => utils::get_embedded_schema_v1(module).context(
-                "Failed to get schema embedded in the module.\nPlease provide a smart contract \
-                 module with an embedded schema.",
-            )?,
-        }
+        (versions::descriptor(wasm_version).embedded_schema)(module).context(
+            "Failed to get schema embedded in the module.\nPlease provide a smart contract module \
+             with an embedded schema.",
+        )?
     } else if let Some(schema_path) = schema_path {
         let bytes = fs::read(schema_path).context("Could not read schema file.")?;
 
         if bytes.starts_with(VERSIONED_SCHEMA_MAGIC_HASH) {
             from_bytes::<VersionedModuleSchema>(&bytes)?
         } else if let Some(wv) = wasm_version {
-            match wv {
-                WasmVersion::V0 => from_bytes(&bytes).map(VersionedModuleSchema::V0)?,
-                WasmVersion::V1 => from_bytes(&bytes).map(VersionedModuleSchema::V1)?,
-            }
+            (versions::descriptor(wv).legacy_schema)(&bytes)?
         } else {
             bail!(
                 "Legacy unversioned schema was supplied, but no version was provided. Use \
@@ -1648,6 +6335,36 @@ fn get_schema(
     Ok(schema)
 }
 
+/// Create a simulator for a V1 module with the mocks in the given files.
+fn simulator(
+    module: &[u8],
+    energy: InterpreterEnergy,
+    mock_paths: &[PathBuf],
+) -> anyhow::Result<sim::Simulator> {
+    let mut sim = sim::Simulator::new(module, energy)?;
+    for path in mock_paths {
+        sim.add_mock(mock::MockContract::load(path)?);
+    }
+    Ok(sim)
+}
+
+/// Get the schema from the schema file if one is supplied, and otherwise the
+/// schema embedded in the module, if any.
+fn get_optional_schema(
+    module: &[u8],
+    wasm_version: WasmVersion,
+    schema_path: Option<PathBuf>,
+) -> anyhow::Result<Option<VersionedModuleSchema>> {
+    let schema = match schema_path {
+        Some(schema_path) => Some(
+            get_schema(None, Some(schema_path), Some(wasm_version))
+                .context("Could not get schema.")?,
+        ),
+        None => (versions::descriptor(wasm_version).embedded_schema)(module).ok(),
+    };
+    Ok(schema)
+}
+
 /// Write the JSON representation of the schema into files in the `out`
 /// directory. The files are named after contract_names, except if a
 /// contract_name contains unsuitable characters. Then the counter is used to
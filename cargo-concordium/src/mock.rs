@@ -0,0 +1,171 @@
+//! Mocks of contracts that a simulated contract depends on.
+//!
+//! A mock file describes a contract instance at a given address with canned
+//! responses for each of its entrypoints. When a simulated contract calls the
+//! mock, the first response whose condition holds is used. Conditions are
+//! expressions over the parameter (`param`, decoded using the schema of the
+//! mock) and the amount sent (`amount`, in microCCD). Mock files are generated
+//! from the schema of the real contract with `cargo concordium generate-mock`
+//! and then edited.
+use crate::{
+    build::ENCODER,
+    expr::{self, Env, Value},
+    schema_types::{encode_parameter, json_template, ContractSchemaView},
+};
+use anyhow::{bail, Context};
+use base64::Engine as _;
+use concordium_contracts_common::{
+    from_bytes, schema::VersionedModuleSchema, to_bytes, Amount, ContractAddress,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+use std::{collections::BTreeMap, path::Path};
+
+/// A canned response of an entrypoint.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MockResponse {
+    /// The response is only used if this expression holds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when:         Option<String>,
+    /// Reject the call with the given error code instead of succeeding.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reject:       Option<i32>,
+    /// The value returned, encoded using the schema.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub return_value: Option<Json>,
+}
+
+/// A mocked contract instance.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MockContract {
+    pub contract:    String,
+    pub address:     ContractAddress,
+    /// The balance reported when the balance of the mock is queried.
+    #[serde(default = "Amount::zero")]
+    pub balance:     Amount,
+    /// The module schema of the mocked contract in base64.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub schema:      Option<String>,
+    /// The responses of each entrypoint, which are tried in order.
+    pub entrypoints: BTreeMap<String, Vec<MockResponse>>,
+    #[serde(skip)]
+    module_schema:   Option<VersionedModuleSchema>,
+}
+
+/// The reply of a mock to a call.
+pub enum MockReply {
+    /// The entrypoint does not exist on the mock.
+    MissingEntrypoint,
+    Return(Vec<u8>),
+    Reject {
+        code:         i32,
+        return_value: Vec<u8>,
+    },
+}
+
+impl MockContract {
+    /// Read a mock from a file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Could not read the mock {}.", path.display()))?;
+        let mut mock: MockContract = serde_json::from_slice(&bytes)
+            .with_context(|| format!("Could not parse the mock {}.", path.display()))?;
+        if let Some(schema) = &mock.schema {
+            let schema_bytes = ENCODER
+                .decode(schema)
+                .context("The schema of the mock is not valid base64.")?;
+            mock.module_schema =
+                Some(from_bytes(&schema_bytes).map_err(|_| {
+                    anyhow::anyhow!("Could not deserialize the schema of the mock.")
+                })?);
+        }
+        Ok(mock)
+    }
+
+    /// Generate a mock of a contract from its schema, with a single response
+    /// for each entrypoint that returns a template value.
+    pub fn generate(
+        contract: &str,
+        address: ContractAddress,
+        schema: &VersionedModuleSchema,
+    ) -> anyhow::Result<Self> {
+        let view = ContractSchemaView::new(schema, contract)
+            .with_context(|| format!("The schema does not describe a contract '{}'.", contract))?;
+        let entrypoints = view
+            .receive
+            .iter()
+            .map(|(name, types)| {
+                let response = MockResponse {
+                    return_value: types.return_value.map(json_template),
+                    ..MockResponse::default()
+                };
+                (name.to_string(), vec![response])
+            })
+            .collect();
+        Ok(MockContract {
+            contract: contract.to_string(),
+            address,
+            balance: Amount::zero(),
+            schema: Some(ENCODER.encode(to_bytes(schema))),
+            entrypoints,
+            module_schema: None,
+        })
+    }
+
+    fn view(&self) -> ContractSchemaView {
+        self.module_schema
+            .as_ref()
+            .and_then(|schema| ContractSchemaView::new(schema, &self.contract))
+            .unwrap_or_default()
+    }
+
+    /// Reply to a call of the given entrypoint.
+    pub fn reply(
+        &self,
+        entrypoint: &str,
+        parameter: &[u8],
+        amount: Amount,
+    ) -> anyhow::Result<MockReply> {
+        let responses = match self.entrypoints.get(entrypoint) {
+            Some(responses) => responses,
+            None => return Ok(MockReply::MissingEntrypoint),
+        };
+        let view = self.view();
+        let types = view.receive.get(entrypoint).copied().unwrap_or_default();
+        let mut env = Env::default();
+        env.set("amount", Value::Int(amount.micro_ccd().into()));
+        if let Some(ty) = types.parameter {
+            if let Ok(json) = ty.to_json(&mut std::io::Cursor::new(parameter)) {
+                env.set_json("param", &json);
+            }
+        }
+        for response in responses.iter() {
+            if let Some(when) = &response.when {
+                if !expr::eval_bool(when, &env)? {
+                    continue;
+                }
+            }
+            return match response.reject {
+                Some(code) => Ok(MockReply::Reject {
+                    code,
+                    return_value: encode_parameter(
+                        response.return_value.as_ref(),
+                        types.error.or(types.return_value),
+                    )?,
+                }),
+                None => Ok(MockReply::Return(encode_parameter(
+                    response.return_value.as_ref(),
+                    types.return_value,
+                )?)),
+            };
+        }
+        bail!(
+            "The mock '{}' has no response for the call to '{}' with parameter {}.",
+            self.contract,
+            entrypoint,
+            hex::encode(parameter)
+        )
+    }
+}
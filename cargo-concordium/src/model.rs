@@ -0,0 +1,455 @@
+//! Stateful model-based testing of contracts.
+//!
+//! A model file describes a simplified model of a contract: the initial model
+//! state, a set of commands that invoke entrypoints and update the model state,
+//! and invariants relating the model state to the values returned by view
+//! entrypoints. Random sequences of commands are run against the contract in
+//! the simulator, and a sequence for which the contract diverges from the model
+//! is shrunk to a minimal sequence that still diverges.
+use crate::{
+    context::{optional_ccd, InitContextOpt, ReceiveContextV1Opt},
+    expr::{self, Env},
+    growth::{count_entries, GrowthTracker},
+    schema_types::{encode_parameter, ContractSchemaView},
+    sim::{Instance, Outcome, Simulator},
+};
+use ansi_term::{Color, Style};
+use anyhow::{bail, Context};
+use concordium_contracts_common::{Amount, ContractAddress};
+use rand::{distributions::Uniform, rngs::SmallRng, Rng, SeedableRng};
+use serde::Deserialize;
+use serde_json::Value as Json;
+use std::collections::BTreeMap;
+
+/// How the parameter of a call is generated.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ParameterGen {
+    /// An integer in the inclusive range.
+    Range([i64; 2]),
+    /// One of the given JSON values.
+    OneOf(Vec<Json>),
+    /// Always the given JSON value.
+    Value(Json),
+}
+
+impl ParameterGen {
+    fn generate(&self, rng: &mut SmallRng) -> anyhow::Result<Json> {
+        match self {
+            ParameterGen::Range([low, high]) => {
+                anyhow::ensure!(low <= high, "Invalid parameter range [{}, {}].", low, high);
+                Ok(Json::from(rng.sample(Uniform::new_inclusive(*low, *high))))
+            }
+            ParameterGen::OneOf(values) => {
+                anyhow::ensure!(!values.is_empty(), "`oneOf` requires at least one value.");
+                Ok(values[rng.gen_range(0, values.len())].clone())
+            }
+            ParameterGen::Value(value) => Ok(value.clone()),
+        }
+    }
+}
+
+/// The initialization of the contract.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInit {
+    pub parameter: Option<Json>,
+    #[serde(default, deserialize_with = "optional_ccd")]
+    pub amount:    Option<Amount>,
+    #[serde(default)]
+    pub context:   InitContextOpt,
+}
+
+/// A command that invokes an entrypoint and updates the model.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelCommand {
+    pub name:         String,
+    pub entrypoint:   String,
+    pub parameter:    Option<ParameterGen>,
+    #[serde(default, deserialize_with = "optional_ccd")]
+    pub amount:       Option<Amount>,
+    /// The command is only chosen when this expression is true.
+    pub precondition: Option<String>,
+    /// The call is expected to succeed when this expression is true and to be
+    /// rejected otherwise. Defaults to always succeeding.
+    pub expect:       Option<String>,
+    /// Expressions giving the new values of model variables after a
+    /// successful call.
+    #[serde(default)]
+    pub update:       BTreeMap<String, String>,
+    /// A context to use instead of the context of the model.
+    pub context:      Option<ReceiveContextV1Opt>,
+}
+
+/// An invariant checked after each command by calling a view entrypoint.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelInvariant {
+    pub entrypoint: String,
+    pub parameter:  Option<Json>,
+    /// An expression over the model variables and `result`, which is bound to
+    /// the return value decoded using the schema.
+    pub check:      String,
+}
+
+/// A model of a contract.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Model {
+    pub contract:   String,
+    #[serde(default)]
+    pub init:       ModelInit,
+    /// The context used for all commands that do not specify one.
+    #[serde(default)]
+    pub context:    ReceiveContextV1Opt,
+    /// The initial values of the model variables.
+    #[serde(default)]
+    pub state:      BTreeMap<String, Json>,
+    pub commands:   Vec<ModelCommand>,
+    #[serde(default)]
+    pub invariants: Vec<ModelInvariant>,
+}
+
+/// A step of a command sequence: the index of the command and the parameter.
+#[derive(Debug, Clone)]
+struct Step {
+    command:   usize,
+    parameter: Option<Json>,
+}
+
+/// The result of executing a step.
+enum StepResult {
+    Ok,
+    /// The precondition of the command did not hold, so the sequence is not a
+    /// valid one. This happens when shrinking.
+    Invalid,
+    /// The contract diverged from the model.
+    Diverged(String),
+}
+
+/// The options for running model-based tests.
+pub struct ModelTestOptions {
+    pub runs:         u64,
+    pub length:       usize,
+    pub seed:         u64,
+    /// Whether to report entrypoints that cause unbounded state growth.
+    pub state_growth: bool,
+}
+
+/// A single execution of a command sequence against the contract and the
+/// model.
+struct Execution<'a> {
+    model:    &'a Model,
+    sim:      &'a Simulator,
+    schema:   &'a ContractSchemaView<'a>,
+    instance: Instance,
+    vars:     Env,
+}
+
+impl<'a> Execution<'a> {
+    fn new(
+        model: &'a Model,
+        sim: &'a Simulator,
+        schema: &'a ContractSchemaView<'a>,
+    ) -> anyhow::Result<Self> {
+        let parameter = encode_parameter(
+            model.init.parameter.as_ref(),
+            schema.init.and_then(|f| f.parameter),
+        )?;
+        let (result, instance) = sim.init(
+            &model.contract,
+            ContractAddress::new(0, 0),
+            model.init.context.clone(),
+            model.init.amount.unwrap_or_else(Amount::zero),
+            &parameter,
+        )?;
+        let instance = instance
+            .with_context(|| format!("Initialization of the contract {}.", result.outcome))?;
+        let mut vars = Env::default();
+        for (name, value) in model.state.iter() {
+            vars.set_json(name, value);
+        }
+        Ok(Self {
+            model,
+            sim,
+            schema,
+            instance,
+            vars,
+        })
+    }
+
+    /// The variables for evaluating expressions of a command with the given
+    /// parameter.
+    fn env_with_parameter(&self, parameter: Option<&Json>) -> Env {
+        let mut env = self.vars.clone();
+        if let Some(parameter) = parameter {
+            env.set_json("param", parameter);
+        }
+        env
+    }
+
+    /// Whether the precondition of the command holds in the current state.
+    fn enabled(&self, command: &ModelCommand, parameter: Option<&Json>) -> anyhow::Result<bool> {
+        match &command.precondition {
+            Some(precondition) => {
+                expr::eval_bool(precondition, &self.env_with_parameter(parameter))
+            }
+            None => Ok(true),
+        }
+    }
+
+    fn step(&mut self, step: &Step) -> anyhow::Result<StepResult> {
+        let model = self.model;
+        let command = &model.commands[step.command];
+        let parameter = step.parameter.as_ref();
+        if !self.enabled(command, parameter)? {
+            return Ok(StepResult::Invalid);
+        }
+        let env = self.env_with_parameter(parameter);
+        let expect_success = match &command.expect {
+            Some(expect) => expr::eval_bool(expect, &env)?,
+            None => true,
+        };
+        let parameter_type = self
+            .schema
+            .receive
+            .get(command.entrypoint.as_str())
+            .and_then(|f| f.parameter);
+        let parameter_bytes = encode_parameter(parameter, parameter_type)?;
+        let result = self.sim.update(
+            &mut self.instance,
+            &command.entrypoint,
+            command
+                .context
+                .clone()
+                .unwrap_or_else(|| model.context.clone()),
+            command.amount.unwrap_or_else(Amount::zero),
+            &parameter_bytes,
+        )?;
+        let success = result.outcome.is_success();
+        if success != expect_success {
+            return Ok(StepResult::Diverged(format!(
+                "The model expected the call to {}, but it {}.",
+                if expect_success {
+                    "succeed"
+                } else {
+                    "be rejected"
+                },
+                result.outcome
+            )));
+        }
+        if success {
+            // All updates are computed from the state before the call.
+            let mut updated = Vec::new();
+            for (name, expression) in command.update.iter() {
+                updated.push((name, expr::eval(expression, &env)?));
+            }
+            for (name, value) in updated {
+                self.vars.set(name, value);
+            }
+        }
+        self.check_invariants()
+    }
+
+    fn check_invariants(&self) -> anyhow::Result<StepResult> {
+        for invariant in self.model.invariants.iter() {
+            let types = self.schema.receive.get(invariant.entrypoint.as_str());
+            let parameter = encode_parameter(
+                invariant.parameter.as_ref(),
+                types.and_then(|f| f.parameter),
+            )?;
+            // The view is called on a copy so that it cannot affect the execution.
+            let mut instance = self.instance.clone();
+            let result = self.sim.update(
+                &mut instance,
+                &invariant.entrypoint,
+                self.model.context.clone(),
+                Amount::zero(),
+                &parameter,
+            )?;
+            let return_value = match result.outcome {
+                Outcome::Success { return_value, .. } => return_value,
+                outcome => {
+                    return Ok(StepResult::Diverged(format!(
+                        "The view '{}' {}.",
+                        invariant.entrypoint, outcome
+                    )))
+                }
+            };
+            let return_type = types.and_then(|f| f.return_value).with_context(|| {
+                format!(
+                    "A schema for the return value of '{}' is required.",
+                    invariant.entrypoint
+                )
+            })?;
+            let result_json = return_type
+                .to_json(&mut std::io::Cursor::new(&return_value[..]))
+                .map_err(|_| anyhow::anyhow!("Could not decode the return value."))?;
+            let mut env = self.vars.clone();
+            env.set_json("result", &result_json);
+            if !expr::eval_bool(&invariant.check, &env)? {
+                return Ok(StepResult::Diverged(format!(
+                    "The invariant `{}` does not hold. '{}' returned {}, and the model state is \
+                     {}.",
+                    invariant.check,
+                    invariant.entrypoint,
+                    result_json,
+                    self.describe_vars()
+                )));
+            }
+        }
+        Ok(StepResult::Ok)
+    }
+
+    fn describe_vars(&self) -> String {
+        let vars = self
+            .vars
+            .vars()
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect::<Vec<_>>();
+        format!("{{{}}}", vars.join(", "))
+    }
+}
+
+/// Execute a sequence from the start. Returns the index of the diverging step
+/// and the reason, or `None` if the sequence does not diverge or is invalid.
+fn replay(
+    model: &Model,
+    sim: &Simulator,
+    schema: &ContractSchemaView,
+    steps: &[Step],
+) -> anyhow::Result<Option<(usize, String)>> {
+    let mut execution = Execution::new(model, sim, schema)?;
+    for (i, step) in steps.iter().enumerate() {
+        match execution.step(step)? {
+            StepResult::Ok => {}
+            StepResult::Invalid => return Ok(None),
+            StepResult::Diverged(reason) => return Ok(Some((i, reason))),
+        }
+    }
+    Ok(None)
+}
+
+/// Shrink a diverging sequence by repeatedly removing steps as long as the
+/// sequence still diverges.
+fn shrink(
+    model: &Model,
+    sim: &Simulator,
+    schema: &ContractSchemaView,
+    mut steps: Vec<Step>,
+    mut divergence: (usize, String),
+) -> anyhow::Result<(Vec<Step>, (usize, String))> {
+    // Steps after the diverging one are irrelevant.
+    steps.truncate(divergence.0 + 1);
+    let mut i = 0;
+    while i < steps.len() {
+        let mut candidate = steps.clone();
+        candidate.remove(i);
+        match replay(model, sim, schema, &candidate)? {
+            Some(d) => {
+                candidate.truncate(d.0 + 1);
+                steps = candidate;
+                divergence = d;
+            }
+            None => i += 1,
+        }
+    }
+    Ok((steps, divergence))
+}
+
+/// Run random command sequences against the contract and report the first
+/// divergence from the model. Returns whether no divergence was found.
+pub fn run_model_tests(
+    model: &Model,
+    sim: &Simulator,
+    schema: &ContractSchemaView,
+    options: &ModelTestOptions,
+) -> anyhow::Result<bool> {
+    if model.commands.is_empty() {
+        bail!("The model must have at least one command.");
+    }
+    eprintln!(
+        "Running {} sequences of up to {} commands against contract '{}' (seed {}).",
+        options.runs, options.length, model.contract, options.seed
+    );
+    let mut growth = GrowthTracker::default();
+    for run in 0..options.runs {
+        let mut rng = SmallRng::seed_from_u64(options.seed.wrapping_add(run));
+        let mut execution = Execution::new(model, sim, schema)?;
+        let mut steps = Vec::new();
+        let mut divergence = None;
+        for _ in 0..options.length {
+            // Choose among the commands that are enabled with their generated
+            // parameter.
+            let mut candidates = Vec::new();
+            for (index, command) in model.commands.iter().enumerate() {
+                let parameter = command
+                    .parameter
+                    .as_ref()
+                    .map(|gen| gen.generate(&mut rng))
+                    .transpose()?;
+                if execution.enabled(command, parameter.as_ref())? {
+                    candidates.push(Step {
+                        command: index,
+                        parameter,
+                    });
+                }
+            }
+            if candidates.is_empty() {
+                break;
+            }
+            let step = candidates.swap_remove(rng.gen_range(0, candidates.len()));
+            let entries_before = if options.state_growth {
+                count_entries(&execution.instance.state)?
+            } else {
+                0
+            };
+            let result = execution.step(&step)?;
+            if options.state_growth {
+                growth.record(
+                    &model.commands[step.command].entrypoint,
+                    entries_before,
+                    count_entries(&execution.instance.state)?,
+                );
+            }
+            steps.push(step);
+            if let StepResult::Diverged(reason) = result {
+                divergence = Some((steps.len() - 1, reason));
+                break;
+            }
+        }
+        if let Some(divergence) = divergence {
+            eprintln!(
+                "{}",
+                Color::Red.paint(format!(
+                    "Sequence {} diverged from the model. Shrinking.",
+                    run
+                ))
+            );
+            let (steps, (index, reason)) = shrink(model, sim, schema, steps, divergence)?;
+            eprintln!(
+                "{}",
+                Style::new().bold().paint("Minimal diverging sequence:")
+            );
+            for (i, step) in steps.iter().enumerate() {
+                let command = &model.commands[step.command];
+                match &step.parameter {
+                    Some(parameter) => eprintln!("  {}. {} {}", i, command.name, parameter),
+                    None => eprintln!("  {}. {}", i, command.name),
+                }
+            }
+            eprintln!("Step {} diverged: {}", index, reason);
+            return Ok(false);
+        }
+    }
+    eprintln!(
+        "{}",
+        Color::Green.paint("No divergence from the model found.")
+    );
+    if options.state_growth {
+        growth.report();
+    }
+    Ok(true)
+}
@@ -0,0 +1,274 @@
+//! Exploration of the orders in which independent calls can be made.
+//!
+//! The calls of a scenario are expected to be independent of each other, and
+//! are made after the setup calls of the scenario. The calls are executed in
+//! every order (or in a random sample of orders when there are too many), and
+//! orders that lead to a different final state or balance are reported, since
+//! they reveal hidden assumptions about the order in which entrypoints are
+//! called.
+use crate::{
+    report::{state_tree, Cell, HtmlReport},
+    scenario::Scenario,
+    schema_types::ContractSchemaView,
+    sim::{Instance, Simulator},
+};
+use ansi_term::{Color, Style};
+use anyhow::ensure;
+use concordium_contracts_common::Amount;
+use rand::{rngs::SmallRng, seq::SliceRandom, SeedableRng};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// The options for exploring orders.
+pub struct OrderOptions {
+    /// Explore all orders if there are at most this many, and a random sample
+    /// of this many orders otherwise.
+    pub max_orders:  u64,
+    pub seed:        u64,
+    /// Write an HTML report of the explored orders to this directory.
+    pub report_html: Option<PathBuf>,
+}
+
+/// The final state reached by executing the calls in a given order.
+struct FinalState {
+    state_hash: [u8; 32],
+    balance:    Amount,
+}
+
+/// Advance to the next permutation in lexicographic order. Returns `false`
+/// when the permutation is the last one.
+fn next_permutation(order: &mut [usize]) -> bool {
+    let i = match (1..order.len()).rev().find(|i| order[i - 1] < order[*i]) {
+        Some(i) => i,
+        None => return false,
+    };
+    let j = (i..order.len())
+        .rev()
+        .find(|j| order[i - 1] < order[*j])
+        .expect("order[i] exists");
+    order.swap(i - 1, j);
+    order[i..].reverse();
+    true
+}
+
+/// The number of orders of `n` calls, or `None` if it exceeds `u64`.
+fn number_of_orders(n: usize) -> Option<u64> {
+    (1..=n as u64).try_fold(1u64, |acc, k| acc.checked_mul(k))
+}
+
+struct Explorer<'a> {
+    scenario: &'a Scenario,
+    sim:      &'a Simulator,
+    schema:   &'a ContractSchemaView<'a>,
+    /// The instance after the setup calls.
+    initial:  Instance,
+}
+
+impl<'a> Explorer<'a> {
+    /// Execute the calls in the given order. Returns the final state, the
+    /// instance in that state, and the calls that did not succeed.
+    fn execute(&self, order: &[usize]) -> anyhow::Result<(FinalState, Instance, Vec<usize>)> {
+        let mut instance = self.initial.clone();
+        let mut failed = Vec::new();
+        for index in order.iter() {
+            let call = &self.scenario.calls[*index];
+            let result = self
+                .scenario
+                .call(self.sim, self.schema, &mut instance, call)?;
+            if !result.outcome.is_success() {
+                failed.push(*index);
+            }
+        }
+        let final_state = FinalState {
+            state_hash: Sha256::digest(&instance.state_bytes()?).into(),
+            balance:    instance.balance,
+        };
+        Ok((final_state, instance, failed))
+    }
+
+    fn describe_order(&self, order: &[usize]) -> String {
+        order
+            .iter()
+            .map(|i| self.scenario.calls[*i].name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// An equivalence class of orders that lead to the same final state.
+struct Class {
+    final_state: FinalState,
+    /// The first order found that leads to this state.
+    example:     Vec<usize>,
+    /// The instance in the final state, which is kept for the report.
+    instance:    Instance,
+    /// The calls that failed in the example order.
+    failed:      Vec<usize>,
+    count:       u64,
+}
+
+/// Execute the independent calls of the scenario in different orders and
+/// report orders that lead to different final states. Returns whether all
+/// explored orders lead to the same final state.
+pub fn explore_orders(
+    scenario: &Scenario,
+    sim: &Simulator,
+    schema: &ContractSchemaView,
+    options: &OrderOptions,
+) -> anyhow::Result<bool> {
+    ensure!(
+        scenario.calls.len() > 1,
+        "At least two calls are required to explore orders."
+    );
+    ensure!(
+        options.max_orders > 0,
+        "At least one order must be explored."
+    );
+    let explorer = Explorer {
+        scenario,
+        sim,
+        schema,
+        initial: scenario.setup(sim, schema)?,
+    };
+
+    let mut classes: Vec<Class> = Vec::new();
+    let mut record = |order: &[usize]| -> anyhow::Result<()> {
+        let (final_state, instance, failed) = explorer.execute(order)?;
+        match classes.iter_mut().find(|c| {
+            c.final_state.state_hash == final_state.state_hash
+                && c.final_state.balance == final_state.balance
+        }) {
+            Some(class) => class.count += 1,
+            None => classes.push(Class {
+                final_state,
+                example: order.to_vec(),
+                instance,
+                failed,
+                count: 1,
+            }),
+        }
+        Ok(())
+    };
+
+    let mut order = (0..scenario.calls.len()).collect::<Vec<_>>();
+    let total = number_of_orders(order.len());
+    match total {
+        Some(total) if total <= options.max_orders => {
+            eprintln!("Exploring all {} orders of {} calls.", total, order.len());
+            loop {
+                record(&order)?;
+                if !next_permutation(&mut order) {
+                    break;
+                }
+            }
+        }
+        _ => {
+            eprintln!(
+                "Exploring {} random orders of {} calls (seed {}).",
+                options.max_orders,
+                order.len(),
+                options.seed
+            );
+            let mut rng = SmallRng::seed_from_u64(options.seed);
+            record(&order)?;
+            for _ in 1..options.max_orders {
+                order.shuffle(&mut rng);
+                record(&order)?;
+            }
+        }
+    }
+
+    if let Some(dir) = &options.report_html {
+        write_report(&explorer, &classes, dir)?;
+    }
+    if classes.len() == 1 {
+        eprintln!(
+            "{}",
+            Color::Green.paint("All explored orders lead to the same state and balance.")
+        );
+        return Ok(true);
+    }
+    eprintln!(
+        "{}",
+        Color::Red.paint(format!(
+            "The explored orders lead to {} different final states.",
+            classes.len()
+        ))
+    );
+    for (i, class) in classes.iter().enumerate() {
+        eprintln!(
+            "{}",
+            Style::new().bold().paint(format!(
+                "State {} (hash {}, balance {} CCD), reached by {} orders, e.g.:",
+                i,
+                hex::encode(&class.final_state.state_hash[..8]),
+                class.final_state.balance,
+                class.count
+            ))
+        );
+        eprintln!("  {}", explorer.describe_order(&class.example));
+        if !class.failed.is_empty() {
+            eprintln!(
+                "  where these calls did not succeed: {}",
+                explorer.describe_order(&class.failed)
+            );
+        }
+    }
+    Ok(false)
+}
+
+/// Write an HTML report of the final states and the orders leading to them.
+fn write_report(explorer: &Explorer, classes: &[Class], dir: &Path) -> anyhow::Result<()> {
+    let mut report = HtmlReport::new(&format!(
+        "Orders of calls to {}",
+        explorer.scenario.contract
+    ));
+    report.result(
+        classes.len() == 1,
+        &format!(
+            "The explored orders lead to {} different final states.",
+            classes.len()
+        ),
+    );
+    report.heading("Calls");
+    let calls = explorer
+        .scenario
+        .calls
+        .iter()
+        .map(|call| {
+            vec![
+                Cell::Text(call.name().to_string()),
+                Cell::Text(call.entrypoint.clone()),
+                Cell::Text(
+                    call.parameter
+                        .as_ref()
+                        .map(|p| p.to_string())
+                        .unwrap_or_default(),
+                ),
+                Cell::Number(call.amount.map(|a| a.to_string()).unwrap_or_default()),
+            ]
+        })
+        .collect::<Vec<_>>();
+    report.table(&["name", "entrypoint", "parameter", "amount"], &calls);
+    for (i, class) in classes.iter().enumerate() {
+        report.heading(&format!(
+            "State {} (hash {}, balance {} CCD), reached by {} orders",
+            i,
+            hex::encode(&class.final_state.state_hash[..8]),
+            class.final_state.balance,
+            class.count
+        ));
+        report.paragraph(&format!(
+            "For example: {}",
+            explorer.describe_order(&class.example)
+        ));
+        if !class.failed.is_empty() {
+            report.paragraph(&format!(
+                "where these calls did not succeed: {}",
+                explorer.describe_order(&class.failed)
+            ));
+        }
+        report.preformatted(&state_tree(&class.instance.state)?);
+    }
+    report.write(dir)
+}
@@ -0,0 +1,494 @@
+//! Attribution of interpreter energy to the different kinds of work a contract
+//! performs.
+//!
+//! The attribution is done by executing the invocation a second time with a
+//! host that wraps the host of the engine and observes the energy spent in
+//! each host function call. Pure Wasm execution is charged by the metering
+//! calls injected into the module, so those are attributed to execution.
+//!
+//! Only receive functions of V1 contracts are observed. Tests use the test
+//! infrastructure of `concordium-std` rather than the host functions of the
+//! chain, so all the energy they use is execution and is reported by
+//! `test_energy` instead.
+use crate::context::ReceiveContextV1Opt;
+use ansi_term::Style;
+use concordium_contracts_common::{Amount, ReceiveName};
+use concordium_smart_contract_engine::{
+    constants, v0,
+    v1::{self, trie::PersistentState, CommonFunc, ImportFunc, ProcessedImports, ReceiveOnlyFunc},
+    InterpreterEnergy,
+};
+use concordium_wasm::{
+    artifact::{Artifact, CompiledFunction},
+    machine::{ExecutionOutcome, Host, RunResult, RuntimeStack, Value},
+};
+use std::collections::BTreeMap;
+
+/// The kinds of work energy is attributed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EnergyCategory {
+    /// Execution of Wasm instructions, including function calls.
+    Execution,
+    /// Allocation of memory.
+    Memory,
+    /// Reading and writing contract state.
+    State,
+    /// Logging events.
+    Logging,
+    /// Invoking other contracts, transferring CCD, querying the chain and
+    /// upgrading.
+    Invocations,
+    /// All other host functions, e.g., accessing the parameter and the context
+    /// or cryptographic primitives.
+    Other,
+}
+
+impl std::fmt::Display for EnergyCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnergyCategory::Execution => write!(f, "execution"),
+            EnergyCategory::Memory => write!(f, "memory"),
+            EnergyCategory::State => write!(f, "state operations"),
+            EnergyCategory::Logging => write!(f, "logging"),
+            EnergyCategory::Invocations => write!(f, "invocations/queries"),
+            EnergyCategory::Other => write!(f, "other host functions"),
+        }
+    }
+}
+
+/// Determine the category of a host function.
+fn categorize(f: &ProcessedImports) -> EnergyCategory {
+    match f.tag {
+        ImportFunc::ChargeEnergy | ImportFunc::TrackCall | ImportFunc::TrackReturn => {
+            EnergyCategory::Execution
+        }
+        ImportFunc::ChargeMemoryAlloc => EnergyCategory::Memory,
+        ImportFunc::Common(CommonFunc::LogEvent) => EnergyCategory::Logging,
+        ImportFunc::Common(
+            CommonFunc::StateLookupEntry
+            | CommonFunc::StateCreateEntry
+            | CommonFunc::StateDeleteEntry
+            | CommonFunc::StateDeletePrefix
+            | CommonFunc::StateIteratePrefix
+            | CommonFunc::StateIteratorNext
+            | CommonFunc::StateIteratorDelete
+            | CommonFunc::StateIteratorKeySize
+            | CommonFunc::StateIteratorKeyRead
+            | CommonFunc::StateEntryRead
+            | CommonFunc::StateEntryWrite
+            | CommonFunc::StateEntrySize
+            | CommonFunc::StateEntryResize,
+        ) => EnergyCategory::State,
+        ImportFunc::ReceiveOnly(ReceiveOnlyFunc::Invoke | ReceiveOnlyFunc::Upgrade) => {
+            EnergyCategory::Invocations
+        }
+        _ => EnergyCategory::Other,
+    }
+}
+
+/// Energy spent and number of host function calls per category.
+#[derive(Debug, Default)]
+pub struct EnergyProfile {
+    pub categories: BTreeMap<EnergyCategory, (u64, u64)>,
+}
+
+impl EnergyProfile {
+    fn record(&mut self, category: EnergyCategory, energy: u64) {
+        let entry = self.categories.entry(category).or_insert((0, 0));
+        entry.0 += energy;
+        entry.1 += 1;
+    }
+
+    /// The total energy attributed to any category.
+    pub fn total(&self) -> u64 { self.categories.values().map(|(energy, _)| energy).sum() }
+
+    /// Print the breakdown as a table.
+    pub fn print(&self) {
+        let total = self.total();
+        eprintln!("\n{}", Style::new().bold().paint("Energy breakdown:"));
+        for (category, (energy, calls)) in self.categories.iter() {
+            let share = if total == 0 {
+                0.0
+            } else {
+                100.0 * *energy as f64 / total as f64
+            };
+            eprintln!(
+                "  {:<22} {:>12} ({:>5.1}%) in {} host calls",
+                category.to_string(),
+                energy,
+                share,
+                calls
+            );
+        }
+    }
+}
+
+/// The name of a host function as imported by contracts, e.g.,
+/// `state_lookup_entry`, or of the metering function injected into the module.
+pub fn host_function_name(f: &ProcessedImports) -> String {
+    let name = match &f.tag {
+        ImportFunc::Common(func) => format!("{:?}", func),
+        ImportFunc::ReceiveOnly(func) => format!("{:?}", func),
+        tag => format!("{:?}", tag),
+    };
+    let mut snake_case = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 {
+                snake_case.push('_');
+            }
+            snake_case.push(c.to_ascii_lowercase());
+        } else {
+            snake_case.push(c);
+        }
+    }
+    snake_case
+}
+
+/// Energy spent and number of calls per host function, together with the
+/// calls of the functions of the contract.
+///
+/// The metering injected into the module does not identify the function of
+/// the contract that is executing, so the energy of executing Wasm
+/// instructions is reported in total, and calls of contract functions are
+/// counted together with the maximum call depth.
+#[derive(Debug, Default)]
+pub struct HostFunctionProfile {
+    /// The category, energy and number of calls of each host function.
+    functions:      BTreeMap<String, (EnergyCategory, u64, u64)>,
+    /// The energy charged for executing Wasm instructions.
+    execution:      u64,
+    /// The energy charged for the initial memory.
+    initial_memory: u64,
+    contract_calls: u64,
+    depth:          u64,
+    max_depth:      u64,
+}
+
+impl HostFunctionProfile {
+    /// Print the host functions sorted by the energy spent in them.
+    pub fn print(&self) {
+        let total = self.execution
+            + self.initial_memory
+            + self
+                .functions
+                .values()
+                .map(|(_, energy, _)| energy)
+                .sum::<u64>();
+        let share = |energy: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                100.0 * energy as f64 / total as f64
+            }
+        };
+        let mut rows = self
+            .functions
+            .iter()
+            .map(|(name, (category, energy, calls))| {
+                (
+                    name.as_str(),
+                    category.to_string(),
+                    *energy,
+                    calls.to_string(),
+                )
+            })
+            .collect::<Vec<_>>();
+        rows.push((
+            "Wasm instructions",
+            EnergyCategory::Execution.to_string(),
+            self.execution,
+            "-".into(),
+        ));
+        if self.initial_memory > 0 {
+            rows.push((
+                "initial memory",
+                EnergyCategory::Memory.to_string(),
+                self.initial_memory,
+                "-".into(),
+            ));
+        }
+        rows.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(b.0)));
+        eprintln!("\n{}", Style::new().bold().paint("Profile:"));
+        eprintln!(
+            "  {:<28} {:<22} {:>12} {:>8} {:>8}",
+            "function", "category", "energy", "share", "calls"
+        );
+        for (name, category, energy, calls) in rows {
+            eprintln!(
+                "  {:<28} {:<22} {:>12} {:>7.1}% {:>8}",
+                name,
+                category,
+                energy,
+                share(energy),
+                calls
+            );
+        }
+        eprintln!(
+            "Contract functions were called {} times with a maximum call depth of {}.",
+            self.contract_calls, self.max_depth
+        );
+    }
+}
+
+/// Hosts that allow inspecting the remaining energy.
+pub trait HasRemainingEnergy {
+    fn remaining_energy(&self) -> u64;
+}
+
+impl<'a, BackingStore, ParamType, Ctx> HasRemainingEnergy
+    for v1::ReceiveHost<'a, BackingStore, ParamType, Ctx>
+{
+    fn remaining_energy(&self) -> u64 { self.energy.energy }
+}
+
+/// Observers of the host function calls made during an execution.
+pub trait HostObserver {
+    /// Called when the initial memory of the instance is allocated, with the
+    /// energy spent on it.
+    fn initial_memory(&mut self, energy: u64);
+
+    /// Called before a host function is called, with the memory as the host
+    /// function sees it and the remaining energy.
+    fn before_call(&mut self, _f: &ProcessedImports, _memory: &[u8], _remaining_energy: u64) {}
+
+    /// Called after a host function has returned, with the energy spent by it.
+    fn after_call(&mut self, f: &ProcessedImports, energy: u64);
+
+    /// Whether the observer is shown the arguments and results of host
+    /// functions. Reading them requires popping them off the stack and pushing
+    /// them back, so it is only done when needed.
+    fn observes_arguments(&self) -> bool { false }
+
+    /// Called after [`before_call`](Self::before_call) with the arguments of
+    /// the host functions listed in [`host_function_arity`], if the observer
+    /// observes arguments. Arguments of type `i32` are in the low 32 bits.
+    fn arguments(&mut self, _f: &ProcessedImports, _memory: &[u8], _arguments: &[u64]) {}
+
+    /// Called before [`after_call`](Self::after_call) with the result of the
+    /// host functions listed in [`host_function_arity`], if the observer
+    /// observes arguments and the call was not interrupted.
+    fn returned(&mut self, _f: &ProcessedImports, _result: u64) {}
+}
+
+/// The number of arguments of the host functions whose arguments and results
+/// can be observed. All of them return a single value.
+pub fn host_function_arity(f: &ProcessedImports) -> Option<usize> {
+    let arity = match f.tag {
+        ImportFunc::Common(CommonFunc::StateLookupEntry)
+        | ImportFunc::Common(CommonFunc::StateCreateEntry)
+        | ImportFunc::Common(CommonFunc::StateDeleteEntry)
+        | ImportFunc::Common(CommonFunc::StateDeletePrefix)
+        | ImportFunc::Common(CommonFunc::StateIteratePrefix)
+        | ImportFunc::Common(CommonFunc::StateEntryResize)
+        | ImportFunc::Common(CommonFunc::LogEvent) => 2,
+        ImportFunc::Common(CommonFunc::StateIteratorNext)
+        | ImportFunc::Common(CommonFunc::StateIteratorDelete)
+        | ImportFunc::Common(CommonFunc::StateIteratorKeySize)
+        | ImportFunc::Common(CommonFunc::StateEntrySize)
+        | ImportFunc::ReceiveOnly(ReceiveOnlyFunc::Upgrade) => 1,
+        ImportFunc::Common(CommonFunc::StateIteratorKeyRead)
+        | ImportFunc::Common(CommonFunc::StateEntryRead)
+        | ImportFunc::Common(CommonFunc::StateEntryWrite) => 4,
+        ImportFunc::ReceiveOnly(ReceiveOnlyFunc::Invoke) => 3,
+        _ => return None,
+    };
+    Some(arity)
+}
+
+impl HostObserver for EnergyProfile {
+    fn initial_memory(&mut self, energy: u64) { self.record(EnergyCategory::Memory, energy); }
+
+    fn after_call(&mut self, f: &ProcessedImports, energy: u64) {
+        self.record(categorize(f), energy);
+    }
+}
+
+impl HostObserver for HostFunctionProfile {
+    fn initial_memory(&mut self, energy: u64) { self.initial_memory += energy; }
+
+    fn after_call(&mut self, f: &ProcessedImports, energy: u64) {
+        match f.tag {
+            ImportFunc::ChargeEnergy => self.execution += energy,
+            ImportFunc::TrackCall => {
+                self.execution += energy;
+                self.contract_calls += 1;
+                self.depth += 1;
+                self.max_depth = self.max_depth.max(self.depth);
+            }
+            ImportFunc::TrackReturn => {
+                self.execution += energy;
+                self.depth = self.depth.saturating_sub(1);
+            }
+            _ => {
+                let entry =
+                    self.functions
+                        .entry(host_function_name(f))
+                        .or_insert((categorize(f), 0, 0));
+                entry.1 += energy;
+                entry.2 += 1;
+            }
+        }
+    }
+}
+
+/// A host that delegates to another host and reports each host function call
+/// to an observer.
+pub struct ObservingHost<H, O> {
+    pub inner:    H,
+    pub observer: O,
+}
+
+impl<H: Host<ProcessedImports> + HasRemainingEnergy, O: HostObserver> Host<ProcessedImports>
+    for ObservingHost<H, O>
+{
+    type Interrupt = H::Interrupt;
+
+    fn tick_initial_memory(&mut self, num_pages: u32) -> RunResult<()> {
+        let before = self.inner.remaining_energy();
+        let result = self.inner.tick_initial_memory(num_pages);
+        let spent = before.saturating_sub(self.inner.remaining_energy());
+        self.observer.initial_memory(spent);
+        result
+    }
+
+    fn call(
+        &mut self,
+        f: &ProcessedImports,
+        memory: &mut Vec<u8>,
+        stack: &mut RuntimeStack,
+    ) -> RunResult<Option<Self::Interrupt>> {
+        let before = self.inner.remaining_energy();
+        self.observer.before_call(f, memory, before);
+        let arity = if self.observer.observes_arguments() {
+            host_function_arity(f)
+        } else {
+            None
+        };
+        if let Some(arity) = arity {
+            // The last argument is on top of the stack. The values are pushed
+            // back unchanged, so the host function sees the same stack.
+            let mut arguments = (0..arity)
+                .map(|_| unsafe { stack.pop_u64() })
+                .collect::<Vec<_>>();
+            arguments.reverse();
+            for argument in arguments.iter() {
+                stack.push_value(*argument);
+            }
+            self.observer.arguments(f, memory, &arguments);
+        }
+        let result = self.inner.call(f, memory, stack);
+        if arity.is_some() && matches!(result, Ok(None)) {
+            let value = unsafe { stack.pop_u64() };
+            stack.push_value(value);
+            self.observer.returned(f, value);
+        }
+        let spent = before.saturating_sub(self.inner.remaining_energy());
+        self.observer.after_call(f, spent);
+        result
+    }
+}
+
+/// Execute a receive function with a host that reports to the given observer,
+/// and return the observer together with a description of the outcome. The
+/// state is not modified. The execution stops at the first interrupt, so host
+/// calls after an interrupt are not observed.
+#[allow(clippy::too_many_arguments)]
+pub fn observe_receive<O: HostObserver>(
+    artifact: &Artifact<ProcessedImports, CompiledFunction>,
+    receive_ctx: ReceiveContextV1Opt,
+    receive_name: ReceiveName,
+    amount: Amount,
+    parameter: &[u8],
+    energy: InterpreterEnergy,
+    state: &PersistentState,
+    params: v1::ReceiveParams,
+    observer: O,
+) -> (O, String) {
+    let mut loader = v1::trie::Loader::new(&[][..]);
+    let mut mutable_state = state.thaw();
+    let inner = mutable_state.get_inner(&mut loader);
+    let instance_state = v1::InstanceState::new(loader, inner);
+    let mut host = ObservingHost {
+        inner: v1::ReceiveHost {
+            energy,
+            stateless: v1::StateLessReceiveHost {
+                activation_frames: constants::MAX_ACTIVATION_FRAMES,
+                logs: v0::Logs::new(),
+                return_value: Vec::new(),
+                parameters: vec![parameter],
+                receive_ctx,
+                params,
+            },
+            state: instance_state,
+        },
+        observer,
+    };
+    // A trap or running out of energy is still a valid observation of the work
+    // done until that point, so it is only described.
+    let outcome = match artifact.run(&mut host, receive_name.get_chain_name(), &[Value::I64(
+        amount.micro_ccd() as i64,
+    )]) {
+        Ok(ExecutionOutcome::Success { .. }) => "finished".to_string(),
+        Ok(ExecutionOutcome::Interrupted { .. }) => "interrupted".to_string(),
+        Err(e) => format!("trapped: {}", e),
+    };
+    (host.observer, outcome)
+}
+
+/// Execute a receive function with a profiling host and return the energy
+/// profile. The state is not modified. The execution stops at the first
+/// interrupt, so energy spent after an interrupt is not included.
+#[allow(clippy::too_many_arguments)]
+pub fn profile_receive(
+    artifact: &Artifact<ProcessedImports, CompiledFunction>,
+    receive_ctx: ReceiveContextV1Opt,
+    receive_name: ReceiveName,
+    amount: Amount,
+    parameter: &[u8],
+    energy: InterpreterEnergy,
+    state: &PersistentState,
+    params: v1::ReceiveParams,
+) -> EnergyProfile {
+    // The outcome is ignored since it is reported by the actual invocation.
+    let (profile, _) = observe_receive(
+        artifact,
+        receive_ctx,
+        receive_name,
+        amount,
+        parameter,
+        energy,
+        state,
+        params,
+        EnergyProfile::default(),
+    );
+    profile
+}
+
+/// Execute a receive function with a profiling host and return the energy
+/// and calls per host function. Like for [`profile_receive`], the state is
+/// not modified and the execution stops at the first interrupt.
+#[allow(clippy::too_many_arguments)]
+pub fn profile_host_functions(
+    artifact: &Artifact<ProcessedImports, CompiledFunction>,
+    receive_ctx: ReceiveContextV1Opt,
+    receive_name: ReceiveName,
+    amount: Amount,
+    parameter: &[u8],
+    energy: InterpreterEnergy,
+    state: &PersistentState,
+    params: v1::ReceiveParams,
+) -> HostFunctionProfile {
+    let (profile, _) = observe_receive(
+        artifact,
+        receive_ctx,
+        receive_name,
+        amount,
+        parameter,
+        energy,
+        state,
+        params,
+        HostFunctionProfile::default(),
+    );
+    profile
+}
@@ -0,0 +1,81 @@
+//! Protocol versions that `cargo concordium run` and `cargo concordium test`
+//! can emulate.
+//!
+//! The interpreter has a few settings that depend on the protocol version of
+//! the chain, such as whether contracts can upgrade themselves or query the
+//! chain, and how large parameters, logs and return values can be. V1
+//! contracts are supported from protocol version 4. The limits of V0
+//! contracts did not change before protocol version 5, so protocol version 4
+//! also stands for the earlier versions for them.
+use concordium_smart_contract_engine::v1;
+use std::{fmt, str::FromStr};
+
+/// A protocol version that supports V1 contracts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProtocolVersion {
+    P4,
+    P5,
+    P6,
+}
+
+impl ProtocolVersion {
+    /// The version used when none is given.
+    pub const LATEST: ProtocolVersion = ProtocolVersion::P6;
+
+    /// Whether contracts can upgrade their module.
+    pub fn support_upgrade(self) -> bool { self >= ProtocolVersion::P5 }
+
+    /// Whether the number of logs and the size of return values are limited.
+    pub fn limit_logs_and_return_values(self) -> bool { self < ProtocolVersion::P5 }
+
+    /// The largest parameter of a call, which was limited to 1024 bytes
+    /// before protocol version 5.
+    pub fn max_parameter_size(self) -> usize {
+        if self >= ProtocolVersion::P5 {
+            u16::MAX as usize
+        } else {
+            1024
+        }
+    }
+
+    /// The parameters of receive functions in this protocol version.
+    pub fn receive_params(self) -> v1::ReceiveParams {
+        v1::ReceiveParams {
+            max_parameter_size:           self.max_parameter_size(),
+            limit_logs_and_return_values: self.limit_logs_and_return_values(),
+            support_queries:              self >= ProtocolVersion::P5,
+        }
+    }
+
+    /// The number of the protocol version.
+    pub fn number(self) -> u8 {
+        match self {
+            ProtocolVersion::P4 => 4,
+            ProtocolVersion::P5 => 5,
+            ProtocolVersion::P6 => 6,
+        }
+    }
+}
+
+impl FromStr for ProtocolVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        let number = lower.trim_start_matches("pv").trim_start_matches('p');
+        match number {
+            "4" => Ok(ProtocolVersion::P4),
+            "5" => Ok(ProtocolVersion::P5),
+            "6" => Ok(ProtocolVersion::P6),
+            _ => anyhow::bail!(
+                "Unsupported protocol version '{}'. V1 contracts are supported in protocol \
+                 versions 4 to 6.",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "PV{}", self.number()) }
+}
@@ -0,0 +1,69 @@
+//! Receipts of invocations made by `cargo concordium run --receipt-out`.
+//!
+//! A receipt is a self-contained JSON record of a single invocation: the
+//! module, the context, amount, parameter and energy it was invoked with, the
+//! hash of the state it started from, and the report of what happened,
+//! including the hash of the resulting state. Receipts can be attached to bug
+//! reports to show exactly which simulation was run.
+use crate::run_report::RunReport;
+use anyhow::Context;
+use concordium_contracts_common::Amount;
+use concordium_smart_contract_engine::v1;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// The SHA-256 hash of the serialized state, hex encoded.
+pub fn state_hash(state: &v1::trie::PersistentState) -> anyhow::Result<String> {
+    let mut loader = v1::trie::Loader::new(&[][..]);
+    let mut bytes = Vec::new();
+    state
+        .serialize(&mut loader, &mut bytes)
+        .context("Could not serialize the state.")?;
+    Ok(hex::encode(Sha256::digest(&bytes)))
+}
+
+/// The inputs of an invocation, which are recorded in its receipt.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptInputs {
+    /// The init or receive context after applying the command line options.
+    /// Sender policies are hex encoded in `senderPoliciesHex`.
+    pub context:            Value,
+    pub amount:             Amount,
+    pub parameter_hex:      String,
+    /// The interpreter energy the invocation was given.
+    pub energy:             u64,
+    /// The hash of the state an update started from.
+    pub initial_state_hash: Option<String>,
+}
+
+/// The receipt of an invocation.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Receipt<'a> {
+    cargo_concordium_version: &'static str,
+    module_reference:         String,
+    #[serde(flatten)]
+    inputs:                   &'a ReceiptInputs,
+    #[serde(flatten)]
+    report:                   &'a RunReport,
+}
+
+/// Write the receipt of an invocation to a file.
+pub fn save(
+    path: &Path,
+    module_reference: String,
+    inputs: &ReceiptInputs,
+    report: &RunReport,
+) -> anyhow::Result<()> {
+    let receipt = Receipt {
+        cargo_concordium_version: env!("CARGO_PKG_VERSION"),
+        module_reference,
+        inputs,
+        report,
+    };
+    std::fs::write(path, serde_json::to_vec_pretty(&receipt)?)
+        .with_context(|| format!("Could not write the receipt to {}.", path.display()))
+}
@@ -0,0 +1,95 @@
+//! Remapping of the paths of the build machine in modules.
+//!
+//! rustc embeds the paths of source files in panic messages and debug
+//! information, so that modules built in different directories differ.
+//! `cargo concordium build --remap-paths` makes rustc replace the workspace
+//! root, the cargo home and the sysroot of the toolchain with fixed prefixes
+//! and checks that no path of the build machine is left in the module.
+use crate::{build::PackageOptions, toolchain};
+use anyhow::{ensure, Context};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// A path of the build machine and the fixed prefix it is replaced with.
+pub struct Remapping {
+    pub from: String,
+    pub to:   &'static str,
+}
+
+/// The paths of the build machine that end up in the modules of the package.
+pub fn host_paths(package_options: &PackageOptions) -> anyhow::Result<Vec<Remapping>> {
+    let metadata = package_options.metadata(true)?;
+    let mut remappings = vec![Remapping {
+        from: metadata.workspace_root.to_string(),
+        to:   "/workspace",
+    }];
+    let cargo_home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")));
+    if let Some(cargo_home) = cargo_home {
+        remappings.push(Remapping {
+            from: cargo_home.to_string_lossy().into_owned(),
+            to:   "/cargo",
+        });
+    }
+    let sysroot = toolchain::rustc_sysroot(package_options)
+        .context("Could not determine the sysroot of rustc.")?;
+    remappings.push(Remapping {
+        from: sysroot,
+        to:   "/rustc",
+    });
+    Ok(remappings)
+}
+
+/// The rustc flags that apply the remappings.
+pub fn rustflags(remappings: &[Remapping]) -> Vec<String> {
+    remappings
+        .iter()
+        .map(|remapping| format!("--remap-path-prefix={}={}", remapping.from, remapping.to))
+        .collect()
+}
+
+/// Check that the module written to `path` contains none of the remapped
+/// paths, nor the home directory of the user. Nothing is checked if there are
+/// no remappings.
+pub fn check_module(path: &Path, remappings: &[Remapping]) -> anyhow::Result<()> {
+    if remappings.is_empty() {
+        return Ok(());
+    }
+    let module = fs::read(path).context("Could not read the module.")?;
+    let home = env::var("HOME").ok().filter(|home| home.len() > 1);
+    let leaked = remappings
+        .iter()
+        .map(|remapping| remapping.from.as_str())
+        .chain(home.as_deref())
+        .filter_map(|path| leaked_string(&module, path.as_bytes()))
+        .collect::<Vec<_>>();
+    ensure!(
+        leaked.is_empty(),
+        "The module contains paths of the build machine after remapping:\n  {}",
+        leaked.join("\n  ")
+    );
+    Ok(())
+}
+
+/// The printable string around the first occurrence of `path` in the module.
+fn leaked_string(module: &[u8], path: &[u8]) -> Option<String> {
+    if path.is_empty() {
+        return None;
+    }
+    let pos = module
+        .windows(path.len())
+        .position(|window| window == path)?;
+    let is_printable = |byte: &u8| byte.is_ascii_graphic() || *byte == b' ';
+    let start = module[..pos]
+        .iter()
+        .rposition(|byte| !is_printable(byte))
+        .map_or(0, |i| i + 1);
+    let end = module[pos..]
+        .iter()
+        .position(|byte| !is_printable(byte))
+        .map_or(module.len(), |i| pos + i);
+    Some(String::from_utf8_lossy(&module[start..end]).into_owned())
+}
@@ -0,0 +1,150 @@
+//! Self-contained HTML reports of test, benchmark and scenario runs.
+//!
+//! A report is a single `index.html` file with inline styles and no scripts
+//! or external resources, so that it can be attached to release candidates
+//! and audit submissions and viewed offline.
+use anyhow::Context;
+use concordium_smart_contract_engine::v1;
+use ptree::{write_tree_with, PrintConfig, TreeBuilder};
+use std::{fmt::Write as _, fs, path::Path};
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em \
+                     auto;max-width:70em;color:#222}table{border-collapse:collapse;margin:1em \
+                     0}th,td{border:1px solid #ccc;padding:.3em \
+                     .6em;text-align:left;vertical-align:top}th{background:#f4f4f4}td.\
+                     num{text-align:right}.ok{color:#1a7f37;font-weight:bold}.failed{color:#\
+                     cf222e;font-weight:bold}.bar{background:#4c8bf5;height:1em}pre{background:#\
+                     f6f8fa;padding:1em;overflow-x:auto}";
+
+/// Escape text for inclusion in HTML.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The state as the tree shown by `cargo concordium display-state`.
+pub fn state_tree(state: &v1::trie::PersistentState) -> anyhow::Result<String> {
+    let mut loader = v1::trie::Loader::new([]);
+    let mut tree_builder = TreeBuilder::new("StateRoot".into());
+    state.display_tree(&mut tree_builder, &mut loader);
+    let mut out = Vec::new();
+    write_tree_with(&tree_builder.build(), &mut out, &PrintConfig::default())
+        .context("Could not write the state as a tree.")?;
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// A cell of a table. Numbers are aligned to the right.
+pub enum Cell {
+    Text(String),
+    Number(String),
+    /// An outcome shown as `ok` or `FAILED`.
+    Status(bool),
+}
+
+/// An HTML report consisting of a sequence of sections.
+pub struct HtmlReport {
+    title: String,
+    body:  String,
+}
+
+impl HtmlReport {
+    pub fn new(title: &str) -> Self {
+        HtmlReport {
+            title: title.to_string(),
+            body:  String::new(),
+        }
+    }
+
+    pub fn heading(&mut self, heading: &str) {
+        let _ = write!(self.body, "<h2>{}</h2>", escape(heading));
+    }
+
+    pub fn paragraph(&mut self, text: &str) {
+        let _ = write!(self.body, "<p>{}</p>", escape(text));
+    }
+
+    /// The overall result of the run.
+    pub fn result(&mut self, passed: bool, text: &str) {
+        let _ = write!(
+            self.body,
+            "<p><span class=\"{}\">{}</span> {}</p>",
+            if passed { "ok" } else { "failed" },
+            if passed { "ok" } else { "FAILED" },
+            escape(text)
+        );
+    }
+
+    pub fn table(&mut self, headers: &[&str], rows: &[Vec<Cell>]) {
+        self.body.push_str("<table><tr>");
+        for header in headers {
+            let _ = write!(self.body, "<th>{}</th>", escape(header));
+        }
+        self.body.push_str("</tr>");
+        for row in rows {
+            self.body.push_str("<tr>");
+            for cell in row {
+                let _ = match cell {
+                    Cell::Text(text) => write!(self.body, "<td>{}</td>", escape(text)),
+                    Cell::Number(number) => {
+                        write!(self.body, "<td class=\"num\">{}</td>", escape(number))
+                    }
+                    Cell::Status(true) => write!(self.body, "<td class=\"ok\">ok</td>"),
+                    Cell::Status(false) => write!(self.body, "<td class=\"failed\">FAILED</td>"),
+                };
+            }
+            self.body.push_str("</tr>");
+        }
+        self.body.push_str("</table>");
+    }
+
+    /// A horizontal bar chart of the given values, scaled to the largest.
+    pub fn bar_chart(&mut self, bars: &[(String, f64)]) {
+        let max = bars.iter().map(|(_, value)| *value).fold(0.0, f64::max);
+        self.body.push_str("<table>");
+        for (label, value) in bars {
+            let width = if max > 0.0 { 100.0 * value / max } else { 0.0 };
+            let _ = write!(
+                self.body,
+                "<tr><td>{}</td><td class=\"num\">{:.0}</td><td style=\"width:30em\"><div \
+                 class=\"bar\" style=\"width:{:.1}%\"></div></td></tr>",
+                escape(label),
+                value,
+                width
+            );
+        }
+        self.body.push_str("</table>");
+    }
+
+    pub fn preformatted(&mut self, text: &str) {
+        let _ = write!(self.body, "<pre>{}</pre>", escape(text));
+    }
+
+    /// Write the report to `index.html` in the directory, which is created if
+    /// it does not exist.
+    pub fn write(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(dir).context("Could not create the report directory.")?;
+        let path = dir.join("index.html");
+        let html = format!(
+            "<!DOCTYPE html><html><head><meta \
+             charset=\"utf-8\"><title>{title}</title><style>{}</style></head><body><h1>{title}</\
+             h1><p>Generated by cargo-concordium {}.</p>{}</body></html>\n",
+            STYLE,
+            env!("CARGO_PKG_VERSION"),
+            self.body,
+            title = escape(&self.title),
+        );
+        fs::write(&path, html).context("Could not write the report.")?;
+        eprintln!("Report written to {}.", path.display());
+        Ok(())
+    }
+}
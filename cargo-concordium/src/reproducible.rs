@@ -0,0 +1,150 @@
+//! Verifiable builds in a pinned container image.
+//!
+//! `cargo concordium build --verifiable` archives the committed source of the
+//! git repository, builds the package from the archive inside a container of a
+//! pinned image, and embeds the build recipe into the module. The recipe
+//! consists of the image, the SHA256 hash of the source archive, the path of
+//! the package in the archive and the build arguments, which is everything
+//! needed to rebuild the module byte for byte from public sources.
+use crate::{
+    build::{append_custom_section, PackageOptions},
+    verify::command_output,
+};
+use anyhow::{ensure, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// The image used for verifiable builds unless another one is given. It
+/// contains a pinned version of rustc, the Wasm target and cargo-concordium.
+pub const DEFAULT_IMAGE: &str = "docker.io/concordium/verifiable-sc:1.70.0";
+
+/// Name of the custom section that contains the build recipe.
+pub const RECIPE_SECTION_NAME: &str = "concordium-build-recipe";
+
+/// Everything needed to reproduce a verifiable build.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildRecipe {
+    /// The container image the module was built in.
+    pub image:                 String,
+    /// The SHA256 hash of the source archive produced by `git archive`.
+    pub source_archive_sha256: String,
+    /// The commit the source archive was made from.
+    pub commit:                String,
+    /// The path of the package relative to the root of the archive.
+    pub path:                  String,
+    /// The value of `SOURCE_DATE_EPOCH`, which is the time of the commit.
+    pub source_date_epoch:     String,
+    /// The arguments of `cargo concordium build` inside the container.
+    pub build_args:            Vec<String>,
+}
+
+/// Build the package in the container and return the versioned module with
+/// the embedded recipe. The source archive and the module built in the
+/// container are placed in `work_dir`.
+pub fn verifiable_build(
+    image: &str,
+    build_args: Vec<String>,
+    package_options: &PackageOptions,
+    work_dir: &Path,
+) -> anyhow::Result<Vec<u8>> {
+    which::which("docker").context("Verifiable builds require docker to be installed.")?;
+    let git = || {
+        let mut command = Command::new("git");
+        if let Some(dir) = package_options.directory() {
+            command.current_dir(dir);
+        }
+        command
+    };
+    let changes = command_output(git().args(&["status", "--porcelain"]))
+        .context("Verifiable builds require the package to be in a git repository.")?;
+    ensure!(
+        changes.is_empty(),
+        "The working tree has uncommitted changes, which are not part of the source archive. \
+         Commit or stash them first."
+    );
+    let commit = command_output(git().args(&["rev-parse", "HEAD"]))?;
+    let path = command_output(git().args(&["rev-parse", "--show-prefix"]))?;
+    let source_date_epoch = command_output(git().args(&["log", "-1", "--format=%ct"]))?;
+
+    fs::create_dir_all(work_dir).context("Could not create the verifiable build directory.")?;
+    let work_dir = fs::canonicalize(work_dir)?;
+    let archive = git()
+        .args(&["archive", "--format=tar", "HEAD"])
+        .stderr(Stdio::inherit())
+        .output()
+        .context("Could not archive the source.")?;
+    ensure!(archive.status.success(), "Could not archive the source.");
+    fs::write(work_dir.join("source.tar"), &archive.stdout)
+        .context("Could not write the source archive.")?;
+    let out = work_dir.join("module.wasm.v1");
+    if out.exists() {
+        fs::remove_file(&out).context("Could not remove the previously built module.")?;
+    }
+
+    let recipe = BuildRecipe {
+        image: image.to_string(),
+        source_archive_sha256: hex::encode(Sha256::digest(&archive.stdout)),
+        commit,
+        path,
+        source_date_epoch,
+        build_args,
+    };
+    eprintln!("Building {} in {}.", recipe.commit, recipe.image);
+    let mut module = build_in_container(&recipe, &work_dir, &out)?;
+    append_custom_section(
+        &mut module,
+        RECIPE_SECTION_NAME,
+        &serde_json::to_vec(&recipe)?,
+    )?;
+    Ok(module)
+}
+
+/// Run the build described by the recipe in a container, with the source
+/// archive `source.tar` in `work_dir`, which is mounted into the container.
+/// Returns the module built.
+fn build_in_container(
+    recipe: &BuildRecipe,
+    work_dir: &Path,
+    out: &Path,
+) -> anyhow::Result<Vec<u8>> {
+    let script = "set -e; mkdir -p /build; tar -xf /verifiable/source.tar -C /build; cd \
+                  \"/build/$1\"; shift; exec cargo concordium build \"$@\"";
+    let status = Command::new("docker")
+        .args(&["run", "--rm", "--volume"])
+        .arg(format!("{}:/verifiable", work_dir.display()))
+        .arg("--env")
+        .arg(format!("SOURCE_DATE_EPOCH={}", recipe.source_date_epoch))
+        .arg(&recipe.image)
+        .args(&["sh", "-c", script, "sh", recipe.path.as_str()])
+        .args(&recipe.build_args)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("Could not run docker.")?;
+    ensure!(status.success(), "The build in the container failed.");
+    fs::read(out).context("Could not read the module built in the container.")
+}
+
+/// The arguments of `cargo concordium build` in the container, which write the
+/// module to the mounted directory and require the dependencies to be exactly
+/// those in `Cargo.lock`.
+pub fn container_build_args(mut args: Vec<String>, cargo_args: &[String]) -> Vec<String> {
+    args.extend(["--out", "/verifiable/module.wasm.v1", "--", "--locked"].map(String::from));
+    args.extend_from_slice(cargo_args);
+    args
+}
+
+/// The default directory for the source archive and the built module.
+pub fn default_work_dir(package_options: &PackageOptions) -> anyhow::Result<PathBuf> {
+    let metadata = package_options.metadata(true)?;
+    Ok(PathBuf::from(format!(
+        "{}/concordium/verifiable",
+        metadata.target_directory
+    )))
+}
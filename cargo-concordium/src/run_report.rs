@@ -0,0 +1,159 @@
+//! The JSON output of `cargo concordium run --format json`.
+//!
+//! Instead of the human-readable description of an invocation on stderr, a
+//! single JSON document describing it is printed to stdout, so that tools
+//! wrapping cargo-concordium do not have to parse the human-readable output.
+use crate::cost::{InvocationCost, INTERPRETER_ENERGY_PER_NRG};
+use concordium_contracts_common::{schema::Type, Amount};
+use concordium_smart_contract_engine::{v0, v1};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// How an invocation ended.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Outcome {
+    Success,
+    Reject,
+    Trap,
+    OutOfEnergy,
+    Interrupt,
+}
+
+/// A value produced by a contract, such as an event or a return value.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContractValue {
+    /// The value as hex encoded bytes.
+    pub raw_hex: String,
+    /// The value decoded using the schema, if one is available and the value
+    /// could be decoded.
+    pub decoded: Option<Value>,
+}
+
+impl ContractValue {
+    pub fn new(bytes: &[u8], schema: Option<&Type>) -> Self {
+        ContractValue {
+            raw_hex: hex::encode(bytes),
+            decoded: schema
+                .and_then(|schema| schema.to_json(&mut std::io::Cursor::new(bytes)).ok()),
+        }
+    }
+}
+
+/// The description of a single invocation.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunReport {
+    pub contract:         String,
+    /// The invoked entrypoint, or `None` for init functions.
+    pub entrypoint:       Option<String>,
+    pub protocol_version: String,
+    pub outcome:          Outcome,
+    /// The reason of a rejection.
+    pub reject_reason:    Option<i32>,
+    /// The return value, or the error value of a rejection.
+    pub return_value:     Option<ContractValue>,
+    pub events:           Vec<ContractValue>,
+    pub state_changed:    bool,
+    /// The number of bytes of additional state that will be charged for, if
+    /// the state changed.
+    pub state_size:       Option<u64>,
+    /// The SHA-256 hash of the serialized state after the invocation, which is
+    /// the hash of the initial state of an update that did not change it.
+    pub state_hash:       Option<String>,
+    /// The interpreter energy used, which is unknown when running out of
+    /// energy, since all of it is used then.
+    pub energy_used:      Option<u64>,
+    /// The energy used in NRG.
+    pub energy_used_nrg:  Option<u64>,
+    /// The estimated cost of the invocation.
+    pub cost:             Option<InvocationCost>,
+    /// The module the instance was upgraded to.
+    pub upgraded_to:      Option<String>,
+    /// The balance of the instance after a successful invocation.
+    pub new_balance:      Option<Amount>,
+    /// The details of the operation that interrupted the execution.
+    pub interrupt:        Option<Value>,
+    /// The runtime error of a trap.
+    pub error:            Option<String>,
+}
+
+impl RunReport {
+    pub fn new(contract: &str, entrypoint: Option<&str>, protocol_version: String) -> Self {
+        RunReport {
+            contract: contract.to_string(),
+            entrypoint: entrypoint.map(str::to_string),
+            protocol_version,
+            outcome: Outcome::Success,
+            reject_reason: None,
+            return_value: None,
+            events: Vec::new(),
+            state_changed: false,
+            state_size: None,
+            state_hash: None,
+            energy_used: None,
+            energy_used_nrg: None,
+            cost: None,
+            upgraded_to: None,
+            new_balance: None,
+            interrupt: None,
+            error: None,
+        }
+    }
+
+    /// Add the logged events, decoded using the event schema if given.
+    pub fn add_events(&mut self, logs: &v0::Logs, schema: Option<&Type>) {
+        self.events.extend(
+            logs.iterate()
+                .map(|item| ContractValue::new(&item[..], schema)),
+        );
+    }
+
+    pub fn set_energy_used(&mut self, energy: u64) {
+        self.energy_used = Some(energy);
+        self.energy_used_nrg = Some(energy / INTERPRETER_ENERGY_PER_NRG);
+    }
+
+    /// Print the report to stdout.
+    pub fn print(&self) -> anyhow::Result<()> {
+        println!("{}", serde_json::to_string_pretty(self)?);
+        Ok(())
+    }
+}
+
+/// Describe the operation that interrupted an execution.
+pub fn interrupt_details(interrupt: &v1::Interrupt) -> Value {
+    match interrupt {
+        v1::Interrupt::Transfer { to, amount } => json!({
+            "kind": "transfer",
+            "to": to.to_string(),
+            "amount": amount.micro_ccd(),
+        }),
+        v1::Interrupt::Call {
+            address,
+            parameter,
+            name,
+            amount,
+        } => json!({
+            "kind": "call",
+            "address": address,
+            "entrypoint": name.to_string(),
+            "amount": amount.micro_ccd(),
+            "parameter": hex::encode(parameter),
+        }),
+        v1::Interrupt::Upgrade { module_ref } => json!({
+            "kind": "upgrade",
+            "moduleReference": hex::encode(module_ref.as_ref()),
+        }),
+        v1::Interrupt::QueryAccountBalance { address } => json!({
+            "kind": "queryAccountBalance",
+            "address": address.to_string(),
+        }),
+        v1::Interrupt::QueryContractBalance { address } => json!({
+            "kind": "queryContractBalance",
+            "address": address,
+        }),
+        v1::Interrupt::QueryExchangeRates => json!({ "kind": "queryExchangeRates" }),
+    }
+}
@@ -0,0 +1,615 @@
+//! Scenarios of calls to several contract instances, run with
+//! `cargo concordium run scenario`.
+//!
+//! A scenario file, written in YAML or JSON, declares named accounts, modules
+//! and existing instances, followed by an ordered list of steps that
+//! initialize or update instances. The steps are executed one after the other
+//! by the simulator, so each step sees the state and balances left by the
+//! previous steps, and the outcome of each step is checked against the
+//! expected outcome. The balance of an instance follows the amounts it is sent
+//! and the transfers it makes, which credit the receiving accounts. Steps can
+//! set the slot time or advance it by a duration, and later steps keep it, so
+//! time-locked logic can be tested without a context for every step.
+//!
+//! Unlike the scenarios of `explore-orders` and `bench`, which describe calls
+//! to a single instance, these scenarios can involve any number of instances
+//! and accounts. Calls between the instances are not executed, and end the
+//! step with an interrupt.
+//!
+//! With `--state-growth`, the number of state entries added by each update is
+//! recorded, and entrypoints that add entries in most of their calls are
+//! reported after the steps. With `--events-ndjson`, the events of the steps
+//! are written as NDJSON records with the number of the step they belong to.
+use crate::{
+    context::{ccd, optional_ccd, InitContextOpt, ReceiveContextV1Opt, SlotTimeArg},
+    events::{EventOrigin, EventSink},
+    growth::{count_entries, GrowthTracker},
+    run_report::ContractValue,
+    schema_types::{encode_parameter, ContractSchemaView, FunctionTypes},
+    sim::{Instance, Outcome, Simulator},
+    versions,
+};
+use ansi_term::Color;
+use anyhow::{ensure, Context};
+use concordium_contracts_common::{
+    schema::{Type, VersionedModuleSchema},
+    AccountAddress, Amount, ContractAddress, SlotTime,
+};
+use concordium_smart_contract_engine::{v1::trie::PersistentState, InterpreterEnergy};
+use serde::Deserialize;
+use serde_json::Value as Json;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+/// The kinds of outcomes a step can be expected to have.
+const OUTCOMES: [&str; 6] = [
+    "success",
+    "reject",
+    "interrupt",
+    "upgrade",
+    "outOfEnergy",
+    "trap",
+];
+
+/// An account that sends transactions in the scenario.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountSpec {
+    address: AccountAddress,
+    /// The balance of the account, which is reduced by the amounts it sends
+    /// and increased by the transfers it receives. Amounts are not checked if
+    /// no balance is given.
+    #[serde(default, deserialize_with = "optional_ccd")]
+    balance: Option<Amount>,
+}
+
+/// An instance that exists before the first step.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InstanceSpec {
+    /// The name the instance is referred to by in the steps.
+    name:     String,
+    /// The name of the module of the instance.
+    module:   String,
+    contract: String,
+    /// The state in binary, as written by `--out-bin`, relative to the
+    /// scenario file.
+    state:    PathBuf,
+    #[serde(default = "Amount::zero", deserialize_with = "ccd")]
+    balance:  Amount,
+    /// The name of the account that owns the instance.
+    owner:    String,
+}
+
+/// The initialization of a new instance.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InitStep {
+    module:    String,
+    contract:  String,
+    /// The name the new instance is referred to by in later steps.
+    instance:  String,
+    parameter: Option<Json>,
+    #[serde(default, deserialize_with = "optional_ccd")]
+    amount:    Option<Amount>,
+    /// The name of the account that initializes the instance.
+    sender:    String,
+    /// A context to use instead of the init context of the scenario.
+    context:   Option<InitContextOpt>,
+}
+
+/// A call to an entrypoint of an instance.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateStep {
+    instance:   String,
+    entrypoint: String,
+    parameter:  Option<Json>,
+    #[serde(default, deserialize_with = "optional_ccd")]
+    amount:     Option<Amount>,
+    /// The name of the account that sends the transaction.
+    sender:     String,
+    /// A context to use instead of the context of the scenario.
+    context:    Option<ReceiveContextV1Opt>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum StepKind {
+    Init(InitStep),
+    Update(UpdateStep),
+}
+
+/// The expected outcome of a step.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct Expectation {
+    #[serde(default = "Expectation::default_outcome")]
+    outcome:       String,
+    reject_reason: Option<i32>,
+    /// The return value, or the error value of a rejection. It is compared
+    /// with the value decoded using the schema, or with the hex encoded value.
+    return_value:  Option<Json>,
+    /// The balance of the instance after the step.
+    #[serde(default, deserialize_with = "optional_ccd")]
+    balance:       Option<Amount>,
+}
+
+impl Expectation {
+    fn default_outcome() -> String { "success".into() }
+}
+
+impl Default for Expectation {
+    fn default() -> Self {
+        Expectation {
+            outcome:       Expectation::default_outcome(),
+            reject_reason: None,
+            return_value:  None,
+            balance:       None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Step {
+    /// An optional name used when reporting.
+    name:      Option<String>,
+    /// The slot time of the step and the following steps, either a point in
+    /// time or a duration such as `+1d` that advances the slot time.
+    slot_time: Option<SlotTimeArg>,
+    #[serde(flatten)]
+    kind:      StepKind,
+    /// Steps are expected to succeed if no expectation is given.
+    #[serde(default)]
+    expect:    Expectation,
+}
+
+/// A scenario of calls to several instances.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ScenarioFile {
+    #[serde(default)]
+    accounts:     BTreeMap<String, AccountSpec>,
+    /// The versioned V1 modules by name, relative to the scenario file.
+    modules:      BTreeMap<String, PathBuf>,
+    #[serde(default)]
+    instances:    Vec<InstanceSpec>,
+    /// The context used for all initializations that do not specify one.
+    #[serde(default)]
+    init_context: InitContextOpt,
+    /// The context used for all updates that do not specify one.
+    #[serde(default)]
+    context:      ReceiveContextV1Opt,
+    steps:        Vec<Step>,
+}
+
+/// The options for running a scenario.
+pub struct ScenarioOptions {
+    /// The interpreter energy each call is given.
+    pub energy:        InterpreterEnergy,
+    /// Whether to report entrypoints that cause unbounded state growth.
+    pub state_growth:  bool,
+    /// Where to write the events of the steps, as for `EventSink::open`.
+    pub events_ndjson: Option<String>,
+}
+
+/// Write the events of a successful step to the sink.
+fn emit_events(
+    sink: Option<&mut EventSink>,
+    origin: &EventOrigin,
+    outcome: &Outcome,
+    event_schema: Option<&Type>,
+) -> anyhow::Result<()> {
+    if let (Some(sink), Outcome::Success { events, .. }) = (sink, outcome) {
+        sink.emit_events(origin, events, event_schema)?;
+    }
+    Ok(())
+}
+
+/// A module together with its simulator and schema.
+struct ScenarioModule {
+    sim:    Simulator,
+    schema: Option<VersionedModuleSchema>,
+}
+
+impl ScenarioModule {
+    fn view(&self, contract_name: &str) -> ContractSchemaView {
+        self.schema
+            .as_ref()
+            .and_then(|schema| ContractSchemaView::new(schema, contract_name))
+            .unwrap_or_default()
+    }
+}
+
+/// An instance during the scenario.
+struct ScenarioInstance {
+    module:   String,
+    instance: Instance,
+    owner:    AccountAddress,
+}
+
+/// Read a scenario, as YAML if the file has a `.yaml` or `.yml` extension and
+/// as JSON otherwise.
+fn load(path: &Path) -> anyhow::Result<ScenarioFile> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Could not read the scenario {}.", path.display()))?;
+    let scenario: ScenarioFile = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_slice(&bytes)
+            .with_context(|| format!("Could not parse the scenario {}.", path.display()))?,
+        _ => serde_json::from_slice(&bytes)
+            .with_context(|| format!("Could not parse the scenario {}.", path.display()))?,
+    };
+    ensure!(!scenario.steps.is_empty(), "The scenario has no steps.");
+    for (i, step) in scenario.steps.iter().enumerate() {
+        ensure!(
+            OUTCOMES.contains(&step.expect.outcome.as_str()),
+            "Step {} expects the unknown outcome '{}'. The outcome is one of {}.",
+            i + 1,
+            step.expect.outcome,
+            OUTCOMES.join(", ")
+        );
+    }
+    Ok(scenario)
+}
+
+/// The account with the given name.
+fn account<'a>(
+    accounts: &'a mut BTreeMap<String, AccountSpec>,
+    name: &str,
+) -> anyhow::Result<&'a mut AccountSpec> {
+    accounts
+        .get_mut(name)
+        .with_context(|| format!("Unknown account '{}'.", name))
+}
+
+/// Why an account cannot send the amount, if it cannot.
+fn insufficient_funds(account: &AccountSpec, name: &str, amount: Amount) -> Option<String> {
+    match account.balance {
+        Some(balance) if balance.micro_ccd() < amount.micro_ccd() => Some(format!(
+            "the account '{}' cannot send {} with a balance of {}",
+            name, amount, balance
+        )),
+        _ => None,
+    }
+}
+
+/// Reduce the balance of an account by an amount it sent.
+fn debit(account: &mut AccountSpec, amount: Amount) {
+    if let Some(balance) = account.balance.as_mut() {
+        *balance = Amount::from_micro_ccd(balance.micro_ccd() - amount.micro_ccd());
+    }
+}
+
+/// Increase the balances of the accounts that received transfers.
+fn credit(accounts: &mut BTreeMap<String, AccountSpec>, outcome: &Outcome) {
+    if let Outcome::Success { transfers, .. } = outcome {
+        for (to, amount) in transfers {
+            if let Some(balance) = accounts
+                .values_mut()
+                .find(|a| a.address == *to)
+                .and_then(|a| a.balance.as_mut())
+            {
+                *balance = Amount::from_micro_ccd(balance.micro_ccd() + amount.micro_ccd());
+            }
+        }
+    }
+}
+
+/// The ways in which the outcome and the balance of the instance after the
+/// step, if it exists, differ from the expected outcome.
+fn check(
+    expect: &Expectation,
+    outcome: &Outcome,
+    types: FunctionTypes,
+    balance: Option<Amount>,
+) -> Vec<String> {
+    if outcome.kind() != expect.outcome {
+        return vec![format!("expected the outcome '{}'", expect.outcome)];
+    }
+    let mut mismatches = Vec::new();
+    if let Some(expected) = expect.balance {
+        match balance {
+            Some(balance) if balance == expected => (),
+            Some(balance) => mismatches.push(format!(
+                "expected the balance {} of the instance, but it is {}",
+                expected, balance
+            )),
+            None => mismatches.push("expected a balance, but there is no instance".into()),
+        }
+    }
+    if let (Some(expected), Outcome::Reject { reason, .. }) = (expect.reject_reason, outcome) {
+        if expected != *reason {
+            mismatches.push(format!("expected the reject reason {}", expected));
+        }
+    }
+    if let Some(expected) = &expect.return_value {
+        let (bytes, ty) = match outcome {
+            Outcome::Success { return_value, .. } => (return_value, types.return_value),
+            Outcome::Reject { return_value, .. } => (return_value, types.error),
+            _ => return vec!["expected a return value".into()],
+        };
+        let value = ContractValue::new(bytes, ty);
+        if value.decoded.as_ref() != Some(expected)
+            && expected.as_str() != Some(value.raw_hex.as_str())
+        {
+            let actual = value
+                .decoded
+                .map_or(value.raw_hex, |decoded| decoded.to_string());
+            mismatches.push(format!(
+                "expected the return value {}, but got {}",
+                expected, actual
+            ));
+        }
+    }
+    mismatches
+}
+
+/// Run the scenario in the file with the options. Returns whether all steps
+/// had the expected outcome.
+pub fn run(path: &Path, options: &ScenarioOptions) -> anyhow::Result<bool> {
+    let ScenarioFile {
+        mut accounts,
+        modules: module_paths,
+        instances: instance_specs,
+        init_context,
+        context,
+        steps,
+    } = load(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    let mut modules = BTreeMap::new();
+    for (name, module_path) in module_paths {
+        let (version, module) = crate::read_versioned_module(&dir.join(&module_path))
+            .with_context(|| format!("Could not load the module '{}'.", name))?;
+        ensure!(
+            versions::descriptor(version).supports_simulation,
+            "The module '{}' is not a V1 module. Scenarios are only supported for V1 contracts.",
+            name
+        );
+        let schema = crate::get_optional_schema(&module, version, None)?;
+        modules.insert(name, ScenarioModule {
+            sim: Simulator::new(&module, options.energy)?,
+            schema,
+        });
+    }
+
+    // Instances are given consecutive indices in the order they are created.
+    let mut next_index = 0;
+    let mut instances = BTreeMap::new();
+    for spec in instance_specs {
+        ensure!(
+            modules.contains_key(&spec.module),
+            "The instance '{}' has the unknown module '{}'.",
+            spec.name,
+            spec.module
+        );
+        ensure!(
+            !instances.contains_key(&spec.name),
+            "The instance '{}' is given twice.",
+            spec.name
+        );
+        let owner = account(&mut accounts, &spec.owner)?.address;
+        let state_file = std::fs::File::open(dir.join(&spec.state))
+            .with_context(|| format!("Could not read the state of '{}'.", spec.name))?;
+        let state = PersistentState::deserialize(&mut std::io::BufReader::new(state_file))
+            .with_context(|| format!("Could not deserialize the state of '{}'.", spec.name))?;
+        let instance = Instance {
+            contract_name: spec.contract,
+            address: ContractAddress::new(next_index, 0),
+            state,
+            balance: spec.balance,
+        };
+        next_index += 1;
+        instances.insert(spec.name, ScenarioInstance {
+            module: spec.module,
+            instance,
+            owner,
+        });
+    }
+
+    let mut event_sink = options
+        .events_ndjson
+        .as_deref()
+        .map(EventSink::open)
+        .transpose()?;
+    let mut failed = 0;
+    let mut growth = GrowthTracker::default();
+    // The slot time set by the last step that set one, which is kept by the
+    // following steps.
+    let mut clock: Option<SlotTime> = None;
+    for (i, step) in steps.iter().enumerate() {
+        let title = match &step.name {
+            Some(name) => format!("Step {} ({})", i + 1, name),
+            None => format!("Step {}", i + 1),
+        };
+        // The slot time of the step, with the slot time of the context if it
+        // does not set one and no earlier step did.
+        let slot_time = match (step.slot_time, clock) {
+            (Some(SlotTimeArg::Advance(_)), None) => step.slot_time,
+            (Some(SlotTimeArg::Advance(duration)), Some(now)) => Some(SlotTimeArg::At(
+                now.checked_add(duration)
+                    .with_context(|| format!("The slot time of step {} is out of range.", i + 1))?,
+            )),
+            (Some(at), _) => Some(at),
+            (None, now) => now.map(SlotTimeArg::At),
+        };
+        let (description, result, types, balance) = match &step.kind {
+            StepKind::Init(init) => {
+                let module = modules.get(&init.module).with_context(|| {
+                    format!("Step {} uses the unknown module '{}'.", i + 1, init.module)
+                })?;
+                ensure!(
+                    !instances.contains_key(&init.instance),
+                    "Step {} creates the instance '{}', which already exists.",
+                    i + 1,
+                    init.instance
+                );
+                let address = ContractAddress::new(next_index, 0);
+                let description = format!(
+                    "init '{}' as '{}' at {}",
+                    init.contract, init.instance, address
+                );
+                let types = module.view(&init.contract).init.unwrap_or_default();
+                let amount = init.amount.unwrap_or_else(Amount::zero);
+                let sender = account(&mut accounts, &init.sender)?;
+                if let Some(reason) = insufficient_funds(sender, &init.sender, amount) {
+                    failed += 1;
+                    eprintln!("{}: {} was not executed: {}.", title, description, reason);
+                    continue;
+                }
+                let parameter = encode_parameter(init.parameter.as_ref(), types.parameter)
+                    .with_context(|| {
+                        format!("Could not encode the parameter of step {}.", i + 1)
+                    })?;
+                let mut ctx = init.context.clone().unwrap_or_else(|| init_context.clone());
+                ctx.set_init_origin(sender.address);
+                if let Some(slot_time) = slot_time {
+                    clock = Some(ctx.advance_slot_time(slot_time).with_context(|| {
+                        format!("Could not set the slot time of step {}.", i + 1)
+                    })?);
+                }
+                let mut origin = EventOrigin::init(&init.contract, &ctx);
+                origin.step = i + 1;
+                origin.contract_address = Some(address);
+                let (result, instance) =
+                    module
+                        .sim
+                        .init(&init.contract, address, ctx, amount, &parameter)?;
+                emit_events(
+                    event_sink.as_mut(),
+                    &origin,
+                    &result.outcome,
+                    module.view(&init.contract).event,
+                )?;
+                let balance = instance.as_ref().map(|instance| instance.balance);
+                if let Some(instance) = instance {
+                    debit(sender, amount);
+                    next_index += 1;
+                    instances.insert(init.instance.clone(), ScenarioInstance {
+                        module: init.module.clone(),
+                        instance,
+                        owner: sender.address,
+                    });
+                }
+                (description, result, types, balance)
+            }
+            StepKind::Update(update) => {
+                let local = instances.get_mut(&update.instance).with_context(|| {
+                    format!(
+                        "Step {} uses the unknown instance '{}'.",
+                        i + 1,
+                        update.instance
+                    )
+                })?;
+                let module = &modules[&local.module];
+                let description = format!(
+                    "update '{}' at {} with '{}'",
+                    update.instance, local.instance.address, update.entrypoint
+                );
+                let types = module
+                    .view(&local.instance.contract_name)
+                    .receive
+                    .get(update.entrypoint.as_str())
+                    .copied()
+                    .unwrap_or_default();
+                let amount = update.amount.unwrap_or_else(Amount::zero);
+                let sender = account(&mut accounts, &update.sender)?;
+                if let Some(reason) = insufficient_funds(sender, &update.sender, amount) {
+                    failed += 1;
+                    eprintln!("{}: {} was not executed: {}.", title, description, reason);
+                    continue;
+                }
+                let parameter = encode_parameter(update.parameter.as_ref(), types.parameter)
+                    .with_context(|| {
+                        format!("Could not encode the parameter of step {}.", i + 1)
+                    })?;
+                let mut ctx = update.context.clone().unwrap_or_else(|| context.clone());
+                ctx.set_sender_account(sender.address);
+                ctx.set_owner(local.owner);
+                if let Some(slot_time) = slot_time {
+                    clock = Some(ctx.advance_slot_time(slot_time).with_context(|| {
+                        format!("Could not set the slot time of step {}.", i + 1)
+                    })?);
+                }
+                let entries_before = if options.state_growth {
+                    count_entries(&local.instance.state)?
+                } else {
+                    0
+                };
+                let contract_name = local.instance.contract_name.clone();
+                let mut origin = EventOrigin::receive(&contract_name, &update.entrypoint, &ctx);
+                origin.step = i + 1;
+                origin.contract_address = Some(local.instance.address);
+                let result = module.sim.update(
+                    &mut local.instance,
+                    &update.entrypoint,
+                    ctx,
+                    amount,
+                    &parameter,
+                )?;
+                emit_events(
+                    event_sink.as_mut(),
+                    &origin,
+                    &result.outcome,
+                    module.view(&contract_name).event,
+                )?;
+                if options.state_growth {
+                    // Entrypoints of different contracts are told apart.
+                    growth.record(
+                        &format!("{}.{}", contract_name, update.entrypoint),
+                        entries_before,
+                        count_entries(&local.instance.state)?,
+                    );
+                }
+                if result.outcome.is_success() {
+                    debit(sender, amount);
+                }
+                (description, result, types, Some(local.instance.balance))
+            }
+        };
+        credit(&mut accounts, &result.outcome);
+        let mismatches = check(&step.expect, &result.outcome, types, balance);
+        let status = if mismatches.is_empty() {
+            Color::Green.paint("ok")
+        } else {
+            failed += 1;
+            Color::Red.paint("FAILED")
+        };
+        eprintln!(
+            "{}: {} {} using {} interpreter energy and {} B of new state: {}",
+            title, description, result.outcome, result.energy_used, result.additional_state, status
+        );
+        for mismatch in mismatches {
+            eprintln!("    {}", mismatch);
+        }
+    }
+
+    if let Some(sink) = event_sink.as_mut() {
+        sink.flush()?;
+    }
+    if options.state_growth {
+        growth.report();
+    }
+    if failed == 0 {
+        eprintln!(
+            "{}",
+            Color::Green.paint(format!(
+                "All {} steps had the expected outcome.",
+                steps.len()
+            ))
+        );
+    } else {
+        eprintln!(
+            "{}",
+            Color::Red.paint(format!(
+                "{} of {} steps did not have the expected outcome.",
+                failed,
+                steps.len()
+            ))
+        );
+    }
+    Ok(failed == 0)
+}
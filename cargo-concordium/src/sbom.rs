@@ -0,0 +1,270 @@
+//! Generation of a software bill of materials (SBOM) for a smart contract
+//! package from the cargo metadata.
+//!
+//! The SBOM can be embedded into the module at build time as a custom section,
+//! so that the exact dependency versions that went into a deployed module can
+//! be audited later.
+use crate::build::PackageOptions;
+use anyhow::Context;
+use cargo_metadata::{DependencyKind, Metadata, Package, PackageId};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    str::FromStr,
+};
+
+/// Name of the custom section that contains the SBOM.
+pub const SBOM_SECTION_NAME: &str = "concordium-sbom";
+
+/// The supported SBOM formats.
+#[derive(Debug, Clone, Copy)]
+pub enum SbomFormat {
+    /// CycloneDX 1.4 in JSON.
+    CycloneDx,
+    /// SPDX 2.3 in JSON.
+    Spdx,
+}
+
+impl FromStr for SbomFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cyclonedx" => Ok(SbomFormat::CycloneDx),
+            "spdx" => Ok(SbomFormat::Spdx),
+            _ => anyhow::bail!(
+                "Unsupported SBOM format '{}'. Use `cyclonedx` or `spdx`.",
+                s
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for SbomFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SbomFormat::CycloneDx => write!(f, "cyclonedx"),
+            SbomFormat::Spdx => write!(f, "spdx"),
+        }
+    }
+}
+
+/// The package URL identifying a crate.
+fn purl(package: &Package) -> String { format!("pkg:cargo/{}@{}", package.name, package.version) }
+
+/// The root package and the packages it depends on, excluding development
+/// dependencies, together with the direct dependencies of each package.
+struct DependencyGraph<'a> {
+    root:     &'a Package,
+    packages: BTreeMap<&'a PackageId, (&'a Package, BTreeSet<&'a PackageId>)>,
+}
+
+fn dependency_graph<'a>(
+    metadata: &'a Metadata,
+    root: &'a Package,
+) -> anyhow::Result<DependencyGraph<'a>> {
+    let resolve = metadata
+        .resolve
+        .as_ref()
+        .context("Cargo did not resolve the dependencies.")?;
+    let packages_by_id = metadata
+        .packages
+        .iter()
+        .map(|p| (&p.id, p))
+        .collect::<BTreeMap<_, _>>();
+    let nodes = resolve
+        .nodes
+        .iter()
+        .map(|n| (&n.id, n))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut packages = BTreeMap::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(&root.id);
+    while let Some(id) = queue.pop_front() {
+        if packages.contains_key(id) {
+            continue;
+        }
+        let package = packages_by_id
+            .get(id)
+            .with_context(|| format!("Package {} is missing from the cargo metadata.", id))?;
+        let deps = nodes
+            .get(id)
+            .map(|node| {
+                node.deps
+                    .iter()
+                    .filter(|dep| {
+                        dep.dep_kinds
+                            .iter()
+                            .any(|info| info.kind != DependencyKind::Development)
+                    })
+                    .map(|dep| &dep.pkg)
+                    .collect::<BTreeSet<_>>()
+            })
+            .unwrap_or_default();
+        queue.extend(deps.iter().copied());
+        packages.insert(id, (*package, deps));
+    }
+    Ok(DependencyGraph { root, packages })
+}
+
+/// Convert a number of seconds since the unix epoch to an RFC 3339 timestamp
+/// in UTC.
+pub fn rfc3339_from_unix(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // Conversion from days to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// The creation time of the SBOM. `SOURCE_DATE_EPOCH` is respected so that
+/// reproducible builds produce identical modules.
+fn creation_time() -> String {
+    let secs = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs())
+        });
+    rfc3339_from_unix(secs)
+}
+
+fn cyclonedx(graph: &DependencyGraph) -> Value {
+    let component = |package: &Package, ty: &str| {
+        let mut value = json!({
+            "type": ty,
+            "bom-ref": purl(package),
+            "name": package.name,
+            "version": package.version.to_string(),
+            "purl": purl(package),
+        });
+        if let Some(license) = &package.license {
+            value["licenses"] = json!([{ "expression": license }]);
+        }
+        value
+    };
+    let components = graph
+        .packages
+        .values()
+        .filter(|(package, _)| package.id != graph.root.id)
+        .map(|(package, _)| component(package, "library"))
+        .collect::<Vec<_>>();
+    let dependencies = graph
+        .packages
+        .values()
+        .map(|(package, deps)| {
+            json!({
+                "ref": purl(package),
+                "dependsOn": deps
+                    .iter()
+                    .filter_map(|id| graph.packages.get(id))
+                    .map(|(dep, _)| purl(dep))
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.4",
+        "version": 1,
+        "metadata": {
+            "timestamp": creation_time(),
+            "tools": [{ "name": "cargo-concordium", "version": env!("CARGO_PKG_VERSION") }],
+            "component": component(graph.root, "application"),
+        },
+        "components": components,
+        "dependencies": dependencies,
+    })
+}
+
+fn spdx(graph: &DependencyGraph) -> Value {
+    let spdx_ids = graph
+        .packages
+        .keys()
+        .enumerate()
+        .map(|(i, id)| (*id, format!("SPDXRef-Package-{}", i)))
+        .collect::<BTreeMap<_, _>>();
+    let packages = graph
+        .packages
+        .iter()
+        .map(|(id, (package, _))| {
+            json!({
+                "SPDXID": spdx_ids[id],
+                "name": package.name,
+                "versionInfo": package.version.to_string(),
+                "downloadLocation": "NOASSERTION",
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": package.license.as_deref().unwrap_or("NOASSERTION"),
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": purl(package),
+                }],
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": spdx_ids[&graph.root.id],
+    })];
+    for (id, (_, deps)) in graph.packages.iter() {
+        for dep in deps.iter().filter(|dep| spdx_ids.contains_key(dep)) {
+            relationships.push(json!({
+                "spdxElementId": spdx_ids[id],
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": spdx_ids[dep],
+            }));
+        }
+    }
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}-{}", graph.root.name, graph.root.version),
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/{}-{}",
+            graph.root.name, graph.root.version
+        ),
+        "creationInfo": {
+            "created": creation_time(),
+            "creators": [format!("Tool: cargo-concordium-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+/// Generate an SBOM in the given format for the selected package.
+pub fn generate_sbom(
+    format: SbomFormat,
+    package_options: &PackageOptions,
+) -> anyhow::Result<Vec<u8>> {
+    let metadata = package_options.metadata(false)?;
+    let graph = dependency_graph(&metadata, package_options.package(&metadata)?)?;
+    let sbom = match format {
+        SbomFormat::CycloneDx => cyclonedx(&graph),
+        SbomFormat::Spdx => spdx(&graph),
+    };
+    Ok(serde_json::to_vec(&sbom)?)
+}
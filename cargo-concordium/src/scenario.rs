@@ -0,0 +1,138 @@
+//! Scenario files describing calls to a single contract instance.
+//!
+//! A scenario consists of the initialization of an instance, setup calls and
+//! the calls of interest. How the calls are executed depends on the command
+//! using the scenario, e.g., `explore-orders` executes the calls in different
+//! orders, and `bench compare` executes them on two versions of a module.
+use crate::{
+    context::{optional_ccd, InitContextOpt, ReceiveContextV1Opt},
+    schema_types::{encode_parameter, ContractSchemaView},
+    sim::{CallResult, Instance, Simulator},
+};
+use anyhow::Context;
+use concordium_contracts_common::{Amount, ContractAddress};
+use serde::Deserialize;
+use serde_json::Value as Json;
+use std::path::Path;
+
+/// A call to an entrypoint of the instance.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioCall {
+    /// An optional name used when reporting, defaults to the entrypoint.
+    pub name:       Option<String>,
+    pub entrypoint: String,
+    pub parameter:  Option<Json>,
+    #[serde(default, deserialize_with = "optional_ccd")]
+    pub amount:     Option<Amount>,
+    /// A context to use instead of the context of the scenario.
+    pub context:    Option<ReceiveContextV1Opt>,
+}
+
+impl ScenarioCall {
+    pub fn name(&self) -> &str { self.name.as_deref().unwrap_or(&self.entrypoint) }
+}
+
+/// The initialization of the instance.
+#[derive(Default, Deserialize)]
+pub struct ScenarioInit {
+    pub parameter: Option<Json>,
+    #[serde(default, deserialize_with = "optional_ccd")]
+    pub amount:    Option<Amount>,
+    #[serde(default)]
+    pub context:   InitContextOpt,
+}
+
+/// A scenario of calls to an instance of a contract.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Scenario {
+    pub contract: String,
+    #[serde(default)]
+    pub init:     ScenarioInit,
+    /// The context used for all calls that do not specify one.
+    #[serde(default)]
+    pub context:  ReceiveContextV1Opt,
+    /// Calls made in the given order before the calls of interest.
+    #[serde(default)]
+    pub setup:    Vec<ScenarioCall>,
+    /// The calls of interest.
+    pub calls:    Vec<ScenarioCall>,
+}
+
+impl Scenario {
+    /// Read a scenario, as YAML if the file has a `.yaml` or `.yml` extension
+    /// and as JSON otherwise.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Could not read the scenario {}.", path.display()))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml") | Some("yml") => serde_yaml::from_slice(&bytes)
+                .with_context(|| format!("Could not parse the scenario {}.", path.display())),
+            _ => serde_json::from_slice(&bytes)
+                .with_context(|| format!("Could not parse the scenario {}.", path.display())),
+        }
+    }
+
+    /// Initialize the instance. Fails if the initialization does not succeed.
+    pub fn init(
+        &self,
+        sim: &Simulator,
+        schema: &ContractSchemaView,
+    ) -> anyhow::Result<(CallResult, Instance)> {
+        let parameter = encode_parameter(
+            self.init.parameter.as_ref(),
+            schema.init.and_then(|f| f.parameter),
+        )?;
+        let (result, instance) = sim.init(
+            &self.contract,
+            ContractAddress::new(0, 0),
+            self.init.context.clone(),
+            self.init.amount.unwrap_or_else(Amount::zero),
+            &parameter,
+        )?;
+        let instance = instance
+            .with_context(|| format!("Initialization of the contract {}.", result.outcome))?;
+        Ok((result, instance))
+    }
+
+    /// Make a call to the instance.
+    pub fn call(
+        &self,
+        sim: &Simulator,
+        schema: &ContractSchemaView,
+        instance: &mut Instance,
+        call: &ScenarioCall,
+    ) -> anyhow::Result<CallResult> {
+        let parameter = encode_parameter(
+            call.parameter.as_ref(),
+            schema
+                .receive
+                .get(call.entrypoint.as_str())
+                .and_then(|f| f.parameter),
+        )
+        .with_context(|| format!("Could not encode the parameter of '{}'.", call.name()))?;
+        sim.update(
+            instance,
+            &call.entrypoint,
+            call.context.clone().unwrap_or_else(|| self.context.clone()),
+            call.amount.unwrap_or_else(Amount::zero),
+            &parameter,
+        )
+    }
+
+    /// Initialize the instance and make the setup calls, which must succeed.
+    pub fn setup(&self, sim: &Simulator, schema: &ContractSchemaView) -> anyhow::Result<Instance> {
+        let (_, mut instance) = self.init(sim, schema)?;
+        for call in self.setup.iter() {
+            let result = self.call(sim, schema, &mut instance, call)?;
+            anyhow::ensure!(
+                result.outcome.is_success(),
+                "The setup call '{}' {}.",
+                call.name(),
+                result.outcome
+            );
+        }
+        Ok(instance)
+    }
+}
@@ -0,0 +1,133 @@
+//! Comparison of two module schemas, e.g., of a new build against the schema
+//! of the deployed module, to catch accidental changes of the interface of a
+//! contract before deployment.
+use crate::schema_types::{contract_names, type_to_description, ContractSchemaView, FunctionTypes};
+use ansi_term::Color;
+use concordium_contracts_common::schema::{Type, VersionedModuleSchema};
+use std::collections::BTreeSet;
+
+/// A difference between two schemas.
+pub enum Change {
+    Added(String),
+    Removed(String),
+    /// The type of something changed from the first to the second
+    /// description.
+    Changed(String, String, String),
+}
+
+impl Change {
+    pub fn print(&self) {
+        match self {
+            Change::Added(what) => eprintln!("     {} {}", Color::Green.bold().paint("+"), what),
+            Change::Removed(what) => eprintln!("     {} {}", Color::Red.bold().paint("-"), what),
+            Change::Changed(what, old, new) => {
+                eprintln!("     {} {}", Color::Yellow.bold().paint("~"), what);
+                eprintln!("         old: {}", old);
+                eprintln!("         new: {}", new);
+            }
+        }
+    }
+}
+
+/// Describe a type that may be absent for the output.
+fn describe(ty: Option<&Type>) -> String {
+    match ty {
+        Some(ty) => type_to_description(ty).to_string(),
+        None => "none".to_string(),
+    }
+}
+
+/// Compare a type in the old and the new schema.
+fn compare_type(what: String, old: Option<&Type>, new: Option<&Type>, changes: &mut Vec<Change>) {
+    if old != new {
+        changes.push(Change::Changed(what, describe(old), describe(new)));
+    }
+}
+
+/// Compare the types of an init function or an entrypoint.
+fn compare_function(
+    what: &str,
+    old: Option<&FunctionTypes>,
+    new: Option<&FunctionTypes>,
+    changes: &mut Vec<Change>,
+) {
+    match (old, new) {
+        (None, None) => {}
+        (None, Some(_)) => changes.push(Change::Added(what.to_string())),
+        (Some(_), None) => changes.push(Change::Removed(what.to_string())),
+        (Some(old), Some(new)) => {
+            compare_type(
+                format!("{} parameter", what),
+                old.parameter,
+                new.parameter,
+                changes,
+            );
+            compare_type(
+                format!("{} return value", what),
+                old.return_value,
+                new.return_value,
+                changes,
+            );
+            compare_type(format!("{} error", what), old.error, new.error, changes);
+        }
+    }
+}
+
+/// Compute the differences between the old and the new schema, covering the
+/// contracts, their init functions and entrypoints, and their state and event
+/// types.
+pub fn diff(old: &VersionedModuleSchema, new: &VersionedModuleSchema) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let contracts = contract_names(old)
+        .into_iter()
+        .chain(contract_names(new))
+        .collect::<BTreeSet<_>>();
+    for contract in contracts {
+        let (old_view, new_view) = match (
+            ContractSchemaView::new(old, contract),
+            ContractSchemaView::new(new, contract),
+        ) {
+            (Some(old_view), Some(new_view)) => (old_view, new_view),
+            (None, _) => {
+                changes.push(Change::Added(format!("contract '{}'", contract)));
+                continue;
+            }
+            (_, None) => {
+                changes.push(Change::Removed(format!("contract '{}'", contract)));
+                continue;
+            }
+        };
+        compare_type(
+            format!("'{}' state", contract),
+            old_view.state,
+            new_view.state,
+            &mut changes,
+        );
+        compare_type(
+            format!("'{}' event", contract),
+            old_view.event,
+            new_view.event,
+            &mut changes,
+        );
+        compare_function(
+            &format!("init function of '{}'", contract),
+            old_view.init.as_ref(),
+            new_view.init.as_ref(),
+            &mut changes,
+        );
+        let entrypoints = old_view
+            .receive
+            .keys()
+            .chain(new_view.receive.keys())
+            .collect::<BTreeSet<_>>();
+        for entrypoint in entrypoints {
+            compare_function(
+                &format!("entrypoint '{}.{}'", contract, entrypoint),
+                old_view.receive.get(entrypoint),
+                new_view.receive.get(entrypoint),
+                &mut changes,
+            );
+        }
+    }
+    changes
+}
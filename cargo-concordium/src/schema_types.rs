@@ -0,0 +1,457 @@
+//! Version independent views of schemas and structural JSON descriptions of
+//! schema types.
+use crate::build::{ModuleExports, ENCODER};
+use anyhow::Context;
+use base64::Engine as _;
+use concordium_contracts_common::{
+    from_bytes,
+    schema::{Fields, SizeLength, Type, VersionedModuleSchema},
+    to_bytes,
+};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, path::Path};
+
+/// The types of a single init or receive function described by a schema.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FunctionTypes<'a> {
+    pub parameter:    Option<&'a Type>,
+    pub return_value: Option<&'a Type>,
+    pub error:        Option<&'a Type>,
+}
+
+/// The schema of a single contract, independent of the version of the module
+/// schema it comes from. Types that are not supported by the schema version
+/// are `None`.
+#[derive(Debug, Default)]
+pub struct ContractSchemaView<'a> {
+    /// The type of the contract state. Only present in V0 schemas.
+    pub state:   Option<&'a Type>,
+    /// The types of the init function.
+    pub init:    Option<FunctionTypes<'a>>,
+    /// The types of each entrypoint described by the schema.
+    pub receive: BTreeMap<&'a str, FunctionTypes<'a>>,
+    /// The type of events logged by the contract. Only present in V3 schemas.
+    pub event:   Option<&'a Type>,
+}
+
+impl<'a> ContractSchemaView<'a> {
+    /// Extract the schema of the given contract from a module schema.
+    /// Returns `None` if the module schema does not contain the contract.
+    pub fn new(schema: &'a VersionedModuleSchema, contract_name: &str) -> Option<Self> {
+        let mut view = ContractSchemaView::default();
+        match schema {
+            VersionedModuleSchema::V0(module_schema) => {
+                let contract_schema = module_schema.contracts.get(contract_name)?;
+                view.state = contract_schema.state.as_ref();
+                view.init = contract_schema.init.as_ref().map(|ty| FunctionTypes {
+                    parameter: Some(ty),
+                    ..FunctionTypes::default()
+                });
+                for (name, ty) in contract_schema.receive.iter() {
+                    view.receive.insert(name.as_str(), FunctionTypes {
+                        parameter: Some(ty),
+                        ..FunctionTypes::default()
+                    });
+                }
+            }
+            VersionedModuleSchema::V1(module_schema) => {
+                let contract_schema = module_schema.contracts.get(contract_name)?;
+                view.init = contract_schema.init.as_ref().map(|func| FunctionTypes {
+                    parameter:    func.parameter(),
+                    return_value: func.return_value(),
+                    error:        None,
+                });
+                for (name, func) in contract_schema.receive.iter() {
+                    view.receive.insert(name.as_str(), FunctionTypes {
+                        parameter:    func.parameter(),
+                        return_value: func.return_value(),
+                        error:        None,
+                    });
+                }
+            }
+            VersionedModuleSchema::V2(module_schema) => {
+                let contract_schema = module_schema.contracts.get(contract_name)?;
+                view.init = contract_schema.init.as_ref().map(|func| FunctionTypes {
+                    parameter:    func.parameter(),
+                    return_value: func.return_value(),
+                    error:        func.error(),
+                });
+                for (name, func) in contract_schema.receive.iter() {
+                    view.receive.insert(name.as_str(), FunctionTypes {
+                        parameter:    func.parameter(),
+                        return_value: func.return_value(),
+                        error:        func.error(),
+                    });
+                }
+            }
+            VersionedModuleSchema::V3(module_schema) => {
+                let contract_schema = module_schema.contracts.get(contract_name)?;
+                view.init = contract_schema.init.as_ref().map(|func| FunctionTypes {
+                    parameter:    func.parameter(),
+                    return_value: func.return_value(),
+                    error:        func.error(),
+                });
+                for (name, func) in contract_schema.receive.iter() {
+                    view.receive.insert(name.as_str(), FunctionTypes {
+                        parameter:    func.parameter(),
+                        return_value: func.return_value(),
+                        error:        func.error(),
+                    });
+                }
+                view.event = contract_schema.event();
+            }
+        }
+        Some(view)
+    }
+}
+
+/// Get the names of all contracts described by a module schema.
+pub fn contract_names(schema: &VersionedModuleSchema) -> Vec<&str> {
+    match schema {
+        VersionedModuleSchema::V0(module_schema) => {
+            module_schema.contracts.keys().map(String::as_str).collect()
+        }
+        VersionedModuleSchema::V1(module_schema) => {
+            module_schema.contracts.keys().map(String::as_str).collect()
+        }
+        VersionedModuleSchema::V2(module_schema) => {
+            module_schema.contracts.keys().map(String::as_str).collect()
+        }
+        VersionedModuleSchema::V3(module_schema) => {
+            module_schema.contracts.keys().map(String::as_str).collect()
+        }
+    }
+}
+
+/// The size of the serialized types of a function.
+fn function_size(func: &FunctionTypes) -> usize {
+    [func.parameter, func.return_value, func.error]
+        .iter()
+        .flatten()
+        .map(|ty| to_bytes(*ty).len())
+        .sum()
+}
+
+/// The sizes in bytes of the serialized module schema and of the types of each
+/// contract, its init function and its entrypoints, as JSON.
+pub fn schema_sizes(schema: &VersionedModuleSchema) -> Value {
+    let mut contracts = serde_json::Map::new();
+    for contract in contract_names(schema) {
+        let view = match ContractSchemaView::new(schema, contract) {
+            Some(view) => view,
+            None => continue,
+        };
+        let entrypoints = view
+            .receive
+            .iter()
+            .map(|(name, func)| (name.to_string(), Value::from(function_size(func))))
+            .collect::<serde_json::Map<_, _>>();
+        contracts.insert(
+            contract.to_string(),
+            json!({
+                "state": view.state.map(|ty| to_bytes(ty).len()),
+                "event": view.event.map(|ty| to_bytes(ty).len()),
+                "init": view.init.as_ref().map(function_size),
+                "entrypoints": entrypoints,
+            }),
+        );
+    }
+    json!({
+        "total": to_bytes(schema).len(),
+        "contracts": contracts,
+    })
+}
+
+/// Describe the init functions and entrypoints exported by a module that the
+/// schema has no types for. An entrypoint without any types is included, since
+/// it almost always means that its schema was forgotten.
+pub fn schema_gaps(schema: &VersionedModuleSchema, exports: &ModuleExports) -> Vec<String> {
+    let mut gaps = Vec::new();
+    for contract in exports.contracts.iter() {
+        let view = match ContractSchemaView::new(schema, contract) {
+            Some(view) => view,
+            None => {
+                gaps.push(format!("The contract '{}' is not in the schema.", contract));
+                continue;
+            }
+        };
+        if view.init.is_none() {
+            gaps.push(format!(
+                "The init function of '{}' has no schema.",
+                contract
+            ));
+        }
+        for entrypoint in exports.entrypoints_of(contract) {
+            match view.receive.get(entrypoint.as_str()) {
+                None => gaps.push(format!(
+                    "The entrypoint '{}.{}' is not in the schema.",
+                    contract, entrypoint
+                )),
+                Some(types) if types.parameter.is_none() && types.return_value.is_none() => gaps
+                    .push(format!(
+                        "The entrypoint '{}.{}' has neither a parameter nor a return value schema.",
+                        contract, entrypoint
+                    )),
+                Some(_) => {}
+            }
+        }
+    }
+    gaps
+}
+
+/// Describe the size of a length prefix by the number of bits used for it.
+fn size_length_bits(size_len: &SizeLength) -> u32 {
+    match size_len {
+        SizeLength::U8 => 8,
+        SizeLength::U16 => 16,
+        SizeLength::U32 => 32,
+        SizeLength::U64 => 64,
+    }
+}
+
+/// Describe the fields of a struct or an enum variant.
+fn fields_to_description(fields: &Fields) -> Value {
+    match fields {
+        Fields::Named(fields) => json!({
+            "fields": "named",
+            "items": fields
+                .iter()
+                .map(|(name, ty)| json!({ "name": name, "type": type_to_description(ty) }))
+                .collect::<Vec<_>>(),
+        }),
+        Fields::Unnamed(fields) => json!({
+            "fields": "unnamed",
+            "items": fields.iter().map(type_to_description).collect::<Vec<_>>(),
+        }),
+        Fields::None => json!({ "fields": "none" }),
+    }
+}
+
+/// Describe a schema type as a JSON value. Every type is described by an
+/// object with a `type` field naming the type constructor, and additional
+/// fields for the arguments of the constructor, e.g., the length prefix size
+/// of a list and the type of its elements.
+pub fn type_to_description(ty: &Type) -> Value {
+    match ty {
+        Type::Unit => json!({ "type": "unit" }),
+        Type::Bool => json!({ "type": "bool" }),
+        Type::U8 => json!({ "type": "u8" }),
+        Type::U16 => json!({ "type": "u16" }),
+        Type::U32 => json!({ "type": "u32" }),
+        Type::U64 => json!({ "type": "u64" }),
+        Type::U128 => json!({ "type": "u128" }),
+        Type::I8 => json!({ "type": "i8" }),
+        Type::I16 => json!({ "type": "i16" }),
+        Type::I32 => json!({ "type": "i32" }),
+        Type::I64 => json!({ "type": "i64" }),
+        Type::I128 => json!({ "type": "i128" }),
+        Type::Amount => json!({ "type": "amount" }),
+        Type::AccountAddress => json!({ "type": "accountAddress" }),
+        Type::ContractAddress => json!({ "type": "contractAddress" }),
+        Type::Timestamp => json!({ "type": "timestamp" }),
+        Type::Duration => json!({ "type": "duration" }),
+        Type::Pair(left, right) => json!({
+            "type": "pair",
+            "left": type_to_description(left),
+            "right": type_to_description(right),
+        }),
+        Type::List(size_len, elem) => json!({
+            "type": "list",
+            "lengthBits": size_length_bits(size_len),
+            "element": type_to_description(elem),
+        }),
+        Type::Set(size_len, elem) => json!({
+            "type": "set",
+            "lengthBits": size_length_bits(size_len),
+            "element": type_to_description(elem),
+        }),
+        Type::Map(size_len, key, value) => json!({
+            "type": "map",
+            "lengthBits": size_length_bits(size_len),
+            "key": type_to_description(key),
+            "value": type_to_description(value),
+        }),
+        Type::Array(len, elem) => json!({
+            "type": "array",
+            "length": len,
+            "element": type_to_description(elem),
+        }),
+        Type::Struct(fields) => json!({
+            "type": "struct",
+            "fields": fields_to_description(fields),
+        }),
+        Type::Enum(variants) => json!({
+            "type": "enum",
+            "variants": variants
+                .iter()
+                .enumerate()
+                .map(|(tag, (name, fields))| json!({
+                    "tag": tag,
+                    "name": name,
+                    "fields": fields_to_description(fields),
+                }))
+                .collect::<Vec<_>>(),
+        }),
+        Type::TaggedEnum(variants) => json!({
+            "type": "enum",
+            "variants": variants
+                .iter()
+                .map(|(tag, (name, fields))| json!({
+                    "tag": tag,
+                    "name": name,
+                    "fields": fields_to_description(fields),
+                }))
+                .collect::<Vec<_>>(),
+        }),
+        Type::String(size_len) => json!({
+            "type": "string",
+            "lengthBits": size_length_bits(size_len),
+        }),
+        Type::ContractName(size_len) => json!({
+            "type": "contractName",
+            "lengthBits": size_length_bits(size_len),
+        }),
+        Type::ReceiveName(size_len) => json!({
+            "type": "receiveName",
+            "lengthBits": size_length_bits(size_len),
+        }),
+        Type::ULeb128(max_bytes) => json!({
+            "type": "uleb128",
+            "maxBytes": max_bytes,
+        }),
+        Type::ILeb128(max_bytes) => json!({
+            "type": "ileb128",
+            "maxBytes": max_bytes,
+        }),
+        Type::ByteList(size_len) => json!({
+            "type": "byteList",
+            "lengthBits": size_length_bits(size_len),
+        }),
+        Type::ByteArray(len) => json!({
+            "type": "byteArray",
+            "length": len,
+        }),
+    }
+}
+
+/// An example account address used in templates.
+pub const TEMPLATE_ACCOUNT_ADDRESS: &str = "3kBx2h5Y2veb4hZgAJWPrr8RyQESKm5TjzF3ti1QQ4VSYLwK1G";
+
+/// A template value of the fields of a struct or an enum variant.
+fn fields_template(fields: &Fields) -> Value {
+    match fields {
+        Fields::Named(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, ty)| (name.clone(), json_template(ty)))
+                .collect(),
+        ),
+        Fields::Unnamed(fields) => fields.iter().map(json_template).collect(),
+        Fields::None => json!([]),
+    }
+}
+
+/// A template value of an enum with the given variant.
+fn variant_template(variant: Option<&(String, Fields)>) -> Value {
+    let mut object = serde_json::Map::new();
+    if let Some((name, fields)) = variant {
+        object.insert(name.clone(), fields_template(fields));
+    }
+    Value::Object(object)
+}
+
+/// A template JSON value of the given type, i.e., a value in the JSON format
+/// of the schema with zero values, empty strings, a single element in
+/// collections, and the first variant of enums. It is meant to be edited by
+/// the user.
+pub fn json_template(ty: &Type) -> Value {
+    match ty {
+        Type::Unit => json!([]),
+        Type::Bool => json!(false),
+        Type::U8 | Type::U16 | Type::U32 | Type::U64 => json!(0),
+        Type::I8 | Type::I16 | Type::I32 | Type::I64 => json!(0),
+        Type::U128 | Type::I128 | Type::ULeb128(_) | Type::ILeb128(_) => json!("0"),
+        Type::Amount => json!("0"),
+        Type::AccountAddress => json!(TEMPLATE_ACCOUNT_ADDRESS),
+        Type::ContractAddress => json!({ "index": 0, "subindex": 0 }),
+        Type::Timestamp => json!("1970-01-01T00:00:00Z"),
+        Type::Duration => json!("0s"),
+        Type::Pair(left, right) => json!([json_template(left), json_template(right)]),
+        Type::List(_, elem) | Type::Set(_, elem) => json!([json_template(elem)]),
+        Type::Map(_, key, value) => json!([[json_template(key), json_template(value)]]),
+        Type::Array(len, elem) => Value::Array(
+            std::iter::repeat(json_template(elem))
+                .take(*len as usize)
+                .collect(),
+        ),
+        Type::Struct(fields) => fields_template(fields),
+        Type::Enum(variants) => variant_template(variants.first()),
+        Type::TaggedEnum(variants) => variant_template(variants.values().next()),
+        Type::String(_) => json!(""),
+        Type::ContractName(_) => json!({ "contract": "" }),
+        Type::ReceiveName(_) => json!({ "contract": "", "func": "" }),
+        Type::ByteList(_) => json!(""),
+        Type::ByteArray(len) => json!("00".repeat(*len as usize)),
+    }
+}
+
+/// The names of the variants of an enum type. Returns an empty list for types
+/// that are not enums.
+pub fn variant_names(ty: &Type) -> Vec<&str> {
+    match ty {
+        Type::Enum(variants) => variants.iter().map(|(name, _)| name.as_str()).collect(),
+        Type::TaggedEnum(variants) => variants.values().map(|(name, _)| name.as_str()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Determine the name of the variant of an enum type that the given serialized
+/// value is an instance of. Returns `None` if the type is not an enum or the
+/// tag is not a valid one.
+pub fn variant_of<'a>(ty: &'a Type, bytes: &[u8]) -> Option<&'a str> {
+    match ty {
+        Type::Enum(variants) => {
+            // The tag is a single byte if there are at most 256 variants, and four
+            // bytes in little endian otherwise.
+            let tag = if variants.len() <= 256 {
+                usize::from(*bytes.first()?)
+            } else {
+                let mut buf = [0u8; 4];
+                buf.copy_from_slice(bytes.get(..4)?);
+                u32::from_le_bytes(buf) as usize
+            };
+            variants.get(tag).map(|(name, _)| name.as_str())
+        }
+        Type::TaggedEnum(variants) => variants.get(bytes.first()?).map(|(name, _)| name.as_str()),
+        _ => None,
+    }
+}
+
+/// Read a schema type from a file, either serialized or as base64 like the
+/// types in the JSON schema written by `build --schema-json-out`.
+pub fn read_type(path: &Path) -> anyhow::Result<Type> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Could not read the schema type {}.", path.display()))?;
+    let base64 = std::str::from_utf8(&bytes)
+        .ok()
+        .and_then(|text| ENCODER.decode(text.trim().trim_end_matches('=')).ok());
+    let bytes = base64.unwrap_or(bytes);
+    from_bytes(&bytes)
+        .map_err(|_| anyhow::anyhow!("Could not deserialize the schema type {}.", path.display()))
+}
+
+/// Encode an optional JSON parameter using the given schema type. No parameter
+/// is encoded as the empty parameter.
+pub fn encode_parameter(json: Option<&Value>, ty: Option<&Type>) -> anyhow::Result<Vec<u8>> {
+    match json {
+        None => Ok(Vec::new()),
+        Some(json) => {
+            let ty =
+                ty.context("A schema for the parameter is required to encode it from JSON.")?;
+            let mut bytes = Vec::new();
+            ty.serial_value_into(json, &mut bytes)
+                .context("Could not encode the parameter using the schema.")?;
+            Ok(bytes)
+        }
+    }
+}
@@ -0,0 +1,520 @@
+//! In-process simulation of sequences of calls to V1 contract instances.
+//!
+//! Unlike `cargo concordium run`, which simulates a single invocation with the
+//! state read from and written to files, the simulator keeps the state and
+//! balance of each instance in memory and threads them through the calls.
+//! Calls to and balance queries of mocked contracts are answered by the mocks,
+//! and execution is resumed with their response. Transfers to accounts succeed
+//! if the instance has enough CCD, so the balance of an instance follows the
+//! amounts it receives and sends. An upgrade of an instance succeeds if it is
+//! to the module the simulator was told to allow with `allow_upgrade`, and the
+//! call is then resumed in the old module, as on the chain.
+use crate::{
+    context::{InitContextOpt, ReceiveContextV1Opt},
+    mock::{MockContract, MockReply},
+};
+use anyhow::Context;
+use concordium_contracts_common::{
+    to_bytes, AccountAddress, Amount, ContractAddress, ModuleReference, OwnedEntrypointName,
+    OwnedReceiveName, ReceiveName,
+};
+use concordium_smart_contract_engine::{
+    v1::{self, trie::PersistentState, ProcessedImports},
+    InterpreterEnergy,
+};
+use concordium_wasm::artifact::{Artifact, CompiledFunction};
+use std::sync::Arc;
+
+/// How a call ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Success {
+        return_value: Vec<u8>,
+        events:       Vec<Vec<u8>>,
+        /// The transfers to accounts made by the call.
+        transfers:    Vec<(AccountAddress, Amount)>,
+    },
+    Reject {
+        reason:       i32,
+        return_value: Vec<u8>,
+    },
+    /// The call was interrupted, e.g., to call another contract, which the
+    /// simulator does not handle. The state is not updated.
+    Interrupted(String),
+    /// The call was interrupted to upgrade the instance to a module that the
+    /// simulator does not allow. The state is not updated.
+    Upgrade(ModuleReference),
+    OutOfEnergy,
+    Trap(String),
+}
+
+impl Outcome {
+    pub fn is_success(&self) -> bool { matches!(self, Outcome::Success { .. }) }
+
+    /// A short name of the kind of outcome.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Outcome::Success { .. } => "success",
+            Outcome::Reject { .. } => "reject",
+            Outcome::Interrupted(_) => "interrupt",
+            Outcome::Upgrade(_) => "upgrade",
+            Outcome::OutOfEnergy => "outOfEnergy",
+            Outcome::Trap(_) => "trap",
+        }
+    }
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Success { .. } => write!(f, "succeeded"),
+            Outcome::Reject { reason, .. } => write!(f, "rejected with reason {}", reason),
+            Outcome::Interrupted(interrupt) => write!(f, "was interrupted by {}", interrupt),
+            Outcome::Upgrade(module_ref) => {
+                write!(f, "was interrupted by an upgrade to module {}", module_ref)
+            }
+            Outcome::OutOfEnergy => write!(f, "ran out of energy"),
+            Outcome::Trap(error) => write!(f, "trapped: {}", error),
+        }
+    }
+}
+
+/// The result of a simulated call.
+#[derive(Debug, Clone)]
+pub struct CallResult {
+    pub outcome:          Outcome,
+    /// The interpreter energy used by the call.
+    pub energy_used:      u64,
+    /// The number of bytes of additional state produced by the call, which
+    /// is charged for.
+    pub additional_state: u64,
+    /// The module the call upgraded the instance to. The upgrade is also
+    /// recorded for calls that fail after it, although it then does not take
+    /// effect.
+    pub upgraded:         Option<ModuleReference>,
+}
+
+/// A simulated contract instance.
+#[derive(Clone)]
+pub struct Instance {
+    pub contract_name: String,
+    pub address:       ContractAddress,
+    pub state:         PersistentState,
+    pub balance:       Amount,
+}
+
+impl Instance {
+    /// The serialized state of the instance, which is the same for equal
+    /// states.
+    pub fn state_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut loader = v1::trie::Loader::new(&[][..]);
+        let mut out = Vec::new();
+        self.state
+            .serialize(&mut loader, &mut out)
+            .context("Could not serialize the state.")?;
+        Ok(out)
+    }
+}
+
+/// A simulator for the contracts in a single V1 module.
+pub struct Simulator {
+    module:     Vec<u8>,
+    artifact:   Arc<Artifact<ProcessedImports, CompiledFunction>>,
+    /// The interpreter energy each call is given.
+    pub energy: InterpreterEnergy,
+    /// Mocks of the contracts that the simulated contracts call.
+    mocks:      Vec<MockContract>,
+    /// The module that instances may upgrade to.
+    upgrade_to: Option<ModuleReference>,
+}
+
+/// Describe an interrupt.
+pub fn describe_interrupt(interrupt: &v1::Interrupt) -> String {
+    match interrupt {
+        v1::Interrupt::Transfer { to, amount } => format!("a transfer of {} CCD to {}", amount, to),
+        v1::Interrupt::Call { address, name, .. } => format!(
+            "a call to {} on <{}, {}>",
+            name, address.index, address.subindex
+        ),
+        v1::Interrupt::Upgrade { module_ref } => format!("an upgrade to module {}", module_ref),
+        v1::Interrupt::QueryAccountBalance { address } => {
+            format!("a query of the balance of account {}", address)
+        }
+        v1::Interrupt::QueryContractBalance { address } => {
+            format!("a query of the balance of contract {}", address)
+        }
+        v1::Interrupt::QueryExchangeRates => "a query of the exchange rates".to_string(),
+    }
+}
+
+impl Simulator {
+    /// Create a simulator for the given (unversioned) V1 module.
+    pub fn new(module: &[u8], energy: InterpreterEnergy) -> anyhow::Result<Self> {
+        let artifact = concordium_wasm::utils::instantiate_with_metering(
+            &v1::ConcordiumAllowedImports {
+                support_upgrade: true,
+            },
+            module,
+        )
+        .context("Could not instantiate the module.")?;
+        Ok(Self {
+            module: module.to_vec(),
+            artifact: Arc::new(artifact),
+            energy,
+            mocks: Vec::new(),
+            upgrade_to: None,
+        })
+    }
+
+    /// Register a mock that answers calls to its address.
+    pub fn add_mock(&mut self, mock: MockContract) { self.mocks.push(mock); }
+
+    /// Let instances upgrade to the given module. The upgrade is reported to
+    /// the contract as successful and the call continues in the old module.
+    pub fn allow_upgrade(&mut self, module_ref: ModuleReference) {
+        self.upgrade_to = Some(module_ref);
+    }
+
+    /// The response to an interrupt if it concerns a mock, given the current
+    /// balance of the calling instance, which is updated if CCD is sent to
+    /// the mock.
+    fn mock_response(
+        &self,
+        interrupt: &v1::Interrupt,
+        balance: &mut Amount,
+    ) -> anyhow::Result<Option<v1::InvokeResponse>> {
+        let response = match interrupt {
+            v1::Interrupt::Call {
+                address,
+                parameter,
+                name,
+                amount,
+            } => {
+                let mock = match self.mocks.iter().find(|m| m.address == *address) {
+                    Some(mock) => mock,
+                    None => return Ok(None),
+                };
+                if amount.micro_ccd() > balance.micro_ccd() {
+                    return Ok(Some(v1::InvokeResponse::Failure {
+                        kind: v1::InvokeFailure::InsufficientAmount,
+                    }));
+                }
+                match mock.reply(&name.to_string(), parameter, *amount)? {
+                    MockReply::MissingEntrypoint => v1::InvokeResponse::Failure {
+                        kind: v1::InvokeFailure::NonExistentEntrypoint,
+                    },
+                    MockReply::Return(data) => {
+                        *balance = Amount::from_micro_ccd(balance.micro_ccd() - amount.micro_ccd());
+                        v1::InvokeResponse::Success {
+                            new_balance: *balance,
+                            data:        Some(data),
+                        }
+                    }
+                    MockReply::Reject { code, return_value } => v1::InvokeResponse::Failure {
+                        kind: v1::InvokeFailure::ContractReject {
+                            code,
+                            data: return_value,
+                        },
+                    },
+                }
+            }
+            v1::Interrupt::QueryContractBalance { address } => {
+                match self.mocks.iter().find(|m| m.address == *address) {
+                    Some(mock) => v1::InvokeResponse::Success {
+                        new_balance: *balance,
+                        data:        Some(to_bytes(&mock.balance)),
+                    },
+                    None => return Ok(None),
+                }
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(response))
+    }
+
+    /// The response to a transfer to an account, which is assumed to exist.
+    /// The balance of the calling instance is reduced by the amount and the
+    /// transfer is recorded.
+    fn transfer(
+        to: AccountAddress,
+        amount: Amount,
+        balance: &mut Amount,
+        transfers: &mut Vec<(AccountAddress, Amount)>,
+    ) -> v1::InvokeResponse {
+        if amount.micro_ccd() > balance.micro_ccd() {
+            return v1::InvokeResponse::Failure {
+                kind: v1::InvokeFailure::InsufficientAmount,
+            };
+        }
+        *balance = Amount::from_micro_ccd(balance.micro_ccd() - amount.micro_ccd());
+        transfers.push((to, amount));
+        v1::InvokeResponse::Success {
+            new_balance: *balance,
+            data:        None,
+        }
+    }
+
+    /// Initialize an instance of a contract. The instance is returned if the
+    /// initialization succeeded.
+    pub fn init(
+        &self,
+        contract_name: &str,
+        address: ContractAddress,
+        ctx: InitContextOpt,
+        amount: Amount,
+        parameter: &[u8],
+    ) -> anyhow::Result<(CallResult, Option<Instance>)> {
+        let name = format!("init_{}", contract_name);
+        let mut loader = v1::trie::Loader::new(&[][..]);
+        let res = v1::invoke_init_with_metering_from_source(
+            v1::InvokeFromSourceCtx {
+                source: &self.module,
+                amount,
+                parameter,
+                energy: self.energy,
+                support_upgrade: true,
+            },
+            ctx,
+            &name,
+            loader,
+            false,
+        )
+        .context("Initialization failed due to a runtime error.")?;
+        let result = match res {
+            v1::InitResult::Success {
+                logs,
+                mut state,
+                remaining_energy,
+                return_value,
+            } => {
+                let mut collector = v1::trie::SizeCollector::default();
+                let state = state.freeze(&mut loader, &mut collector);
+                let instance = Instance {
+                    contract_name: contract_name.to_string(),
+                    address,
+                    state,
+                    balance: amount,
+                };
+                let result = CallResult {
+                    outcome:          Outcome::Success {
+                        return_value,
+                        events: logs.iterate().map(|e| e[..].to_vec()).collect(),
+                        transfers: Vec::new(),
+                    },
+                    energy_used:      self.energy.subtract(remaining_energy.energy).energy,
+                    additional_state: collector.collect(),
+                    upgraded:         None,
+                };
+                return Ok((result, Some(instance)));
+            }
+            v1::InitResult::Reject {
+                remaining_energy,
+                reason,
+                return_value,
+            } => CallResult {
+                outcome:          Outcome::Reject {
+                    reason,
+                    return_value,
+                },
+                energy_used:      self.energy.subtract(remaining_energy.energy).energy,
+                additional_state: 0,
+                upgraded:         None,
+            },
+            v1::InitResult::Trap {
+                remaining_energy,
+                error,
+            } => CallResult {
+                outcome:          Outcome::Trap(format!("{:#}", error)),
+                energy_used:      self.energy.subtract(remaining_energy.energy).energy,
+                additional_state: 0,
+                upgraded:         None,
+            },
+            v1::InitResult::OutOfEnergy => CallResult {
+                outcome:          Outcome::OutOfEnergy,
+                energy_used:      self.energy.energy,
+                additional_state: 0,
+                upgraded:         None,
+            },
+        };
+        Ok((result, None))
+    }
+
+    /// Invoke an entrypoint of an instance. The state and balance of the
+    /// instance are updated if the call succeeds.
+    pub fn update(
+        &self,
+        instance: &mut Instance,
+        entrypoint: &str,
+        mut ctx: ReceiveContextV1Opt,
+        amount: Amount,
+        parameter: &[u8],
+    ) -> anyhow::Result<CallResult> {
+        let name = format!("{}.{}", instance.contract_name, entrypoint);
+        ReceiveName::is_valid_receive_name(&name)
+            .map_err(|e| anyhow::anyhow!("Invalid contract or receive function name: {}", e))?;
+        let name = OwnedReceiveName::new_unchecked(name);
+        ctx.set_instance(
+            instance.address,
+            instance.balance,
+            OwnedEntrypointName::new_unchecked(entrypoint.to_string()),
+        );
+
+        let mut loader = v1::trie::Loader::new(&[][..]);
+        let mut mutable_state = instance.state.thaw();
+        let inner = mutable_state.get_inner(&mut loader);
+        let instance_state = v1::InstanceState::new(loader, inner);
+        let mut res = v1::invoke_receive::<_, _, _, _, ReceiveContextV1Opt, ReceiveContextV1Opt>(
+            Arc::clone(&self.artifact),
+            ctx,
+            v1::ReceiveInvocation {
+                amount,
+                receive_name: name.as_receive_name(),
+                parameter,
+                energy: self.energy,
+            },
+            instance_state,
+            v1::ReceiveParams {
+                // These are the parameters in PV5.
+                max_parameter_size:           u16::MAX as usize,
+                limit_logs_and_return_values: false,
+                support_queries:              true,
+            },
+        )
+        .context("Calling receive failed.")?;
+        // The balance during the call, which includes the amount sent to the
+        // instance and is reduced by amounts sent to mocks and accounts.
+        let mut balance = Amount::from_micro_ccd(
+            instance
+                .balance
+                .micro_ccd()
+                .saturating_add(amount.micro_ccd()),
+        );
+        // Events, state changes and transfers before interrupts that were answered.
+        let mut events = Vec::new();
+        let mut transfers = Vec::new();
+        let mut state_changed_before = false;
+        let mut upgraded = None;
+        loop {
+            let (remaining_energy, state_changed, logs, config, interrupt) = match res {
+                v1::ReceiveResult::Interrupt {
+                    remaining_energy,
+                    state_changed,
+                    logs,
+                    config,
+                    interrupt,
+                } => (remaining_energy, state_changed, logs, config, interrupt),
+                _ => break,
+            };
+            let response = match &interrupt {
+                v1::Interrupt::Transfer { to, amount } => {
+                    Some(Self::transfer(*to, *amount, &mut balance, &mut transfers))
+                }
+                v1::Interrupt::Upgrade { module_ref } if Some(*module_ref) == self.upgrade_to => {
+                    upgraded = Some(*module_ref);
+                    Some(v1::InvokeResponse::Success {
+                        new_balance: balance,
+                        data:        None,
+                    })
+                }
+                interrupt => self.mock_response(interrupt, &mut balance)?,
+            };
+            match response {
+                Some(response) => {
+                    events.extend(logs.iterate().map(|e| e[..].to_vec()));
+                    state_changed_before |= state_changed;
+                    res = v1::resume_receive(
+                        config,
+                        Some(response),
+                        InterpreterEnergy {
+                            energy: remaining_energy,
+                        },
+                        &mut mutable_state,
+                        // A mock cannot change the state of the instance.
+                        false,
+                        v1::trie::Loader::new(&[][..]),
+                    )
+                    .context("Resuming the call failed.")?;
+                }
+                None => {
+                    res = v1::ReceiveResult::Interrupt {
+                        remaining_energy,
+                        state_changed,
+                        logs,
+                        config,
+                        interrupt,
+                    };
+                    break;
+                }
+            }
+        }
+        let result = match res {
+            v1::ReceiveResult::Success {
+                logs,
+                state_changed,
+                remaining_energy,
+                return_value,
+            } => {
+                let mut additional_state = 0;
+                if state_changed || state_changed_before {
+                    let mut collector = v1::trie::SizeCollector::default();
+                    instance.state = mutable_state.freeze(&mut loader, &mut collector);
+                    additional_state = collector.collect();
+                }
+                instance.balance = balance;
+                events.extend(logs.iterate().map(|e| e[..].to_vec()));
+                CallResult {
+                    outcome: Outcome::Success {
+                        return_value,
+                        events,
+                        transfers,
+                    },
+                    energy_used: self.energy.subtract(remaining_energy).energy,
+                    additional_state,
+                    upgraded,
+                }
+            }
+            v1::ReceiveResult::Reject {
+                remaining_energy,
+                reason,
+                return_value,
+            } => CallResult {
+                outcome: Outcome::Reject {
+                    reason,
+                    return_value,
+                },
+                energy_used: self.energy.subtract(remaining_energy).energy,
+                additional_state: 0,
+                upgraded,
+            },
+            v1::ReceiveResult::Interrupt {
+                remaining_energy,
+                interrupt,
+                ..
+            } => CallResult {
+                outcome: match interrupt {
+                    v1::Interrupt::Upgrade { module_ref } => Outcome::Upgrade(module_ref),
+                    interrupt => Outcome::Interrupted(describe_interrupt(&interrupt)),
+                },
+                energy_used: self.energy.subtract(remaining_energy).energy,
+                additional_state: 0,
+                upgraded,
+            },
+            v1::ReceiveResult::OutOfEnergy => CallResult {
+                outcome: Outcome::OutOfEnergy,
+                energy_used: self.energy.energy,
+                additional_state: 0,
+                upgraded,
+            },
+            v1::ReceiveResult::Trap {
+                remaining_energy,
+                error,
+            } => CallResult {
+                outcome: Outcome::Trap(format!("{:#}", error)),
+                energy_used: self.energy.subtract(remaining_energy).energy,
+                additional_state: 0,
+                upgraded,
+            },
+        };
+        Ok(result)
+    }
+}
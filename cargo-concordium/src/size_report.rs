@@ -0,0 +1,134 @@
+//! A breakdown of the size of a module by section and by function, shown by
+//! `cargo concordium build --size-report`, so that contract authors can see
+//! where the bytes go when a module approaches the size limit of the chain.
+use crate::build::read_leb128_u32;
+use anyhow::{ensure, Context};
+use concordium_wasm::types::{ExportDescription, Module};
+use std::collections::BTreeMap;
+
+/// The number of functions listed in the report.
+const LARGEST_FUNCTIONS: usize = 15;
+
+/// The name of a section with the given id, as used in the report.
+fn section_name(id: u8) -> &'static str {
+    match id {
+        0 => "custom",
+        1 => "type",
+        2 => "import",
+        3 => "function",
+        4 => "table",
+        5 => "memory",
+        6 => "global",
+        7 => "export",
+        8 => "start",
+        9 => "element",
+        10 => "code",
+        11 => "data",
+        12 => "data count",
+        _ => "unknown",
+    }
+}
+
+/// The sizes of the sections and functions of a module.
+pub struct SizeReport {
+    /// The name and the size of each section, including its header.
+    sections:  Vec<(String, usize)>,
+    /// The name and the size of the body of each function defined in the
+    /// module, largest first.
+    functions: Vec<(String, usize)>,
+    /// The size of the Wasm module.
+    total:     usize,
+}
+
+impl SizeReport {
+    /// Analyze the Wasm module `wasm` (without the version prefix), where
+    /// `module` is the parsed module, which provides the names of the exported
+    /// functions.
+    pub fn new(wasm: &[u8], module: &Module) -> anyhow::Result<Self> {
+        ensure!(
+            wasm.len() >= 8 && wasm[..4] == *b"\0asm",
+            "Not a Wasm module."
+        );
+        let num_imports = module.import.imports.len();
+        let mut names = BTreeMap::new();
+        for export in &module.export.exports {
+            if let ExportDescription::Func { index } = export.description {
+                names.insert(index as usize, export.name.as_ref().to_string());
+            }
+        }
+
+        let mut sections = Vec::new();
+        let mut functions = Vec::new();
+        let mut pos = 8;
+        while pos < wasm.len() {
+            let start = pos;
+            let id = wasm[pos];
+            pos += 1;
+            let len = read_leb128_u32(wasm, &mut pos)? as usize;
+            let contents = wasm
+                .get(pos..pos + len)
+                .context("Section exceeds the module.")?;
+            pos += len;
+            let name = if id == 0 {
+                let mut name_pos = 0;
+                let name_len = read_leb128_u32(contents, &mut name_pos)? as usize;
+                let name = contents
+                    .get(name_pos..name_pos + name_len)
+                    .context("Malformed custom section name.")?;
+                format!("custom '{}'", String::from_utf8_lossy(name))
+            } else {
+                section_name(id).to_string()
+            };
+            sections.push((name, pos - start));
+
+            if id == 10 {
+                let mut code_pos = 0;
+                let count = read_leb128_u32(contents, &mut code_pos)?;
+                for i in 0..count as usize {
+                    let body_len = read_leb128_u32(contents, &mut code_pos)? as usize;
+                    code_pos += body_len;
+                    let index = num_imports + i;
+                    let name = names
+                        .get(&index)
+                        .cloned()
+                        .unwrap_or_else(|| format!("function[{}]", index));
+                    functions.push((name, body_len));
+                }
+            }
+        }
+        functions.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(SizeReport {
+            sections,
+            functions,
+            total: wasm.len(),
+        })
+    }
+
+    /// Print the report to stderr.
+    pub fn print(&self) {
+        let bold_style = ansi_term::Style::new().bold();
+        let percentage = |size: usize| 100.0 * size as f64 / self.total as f64;
+        eprintln!("\n   {}", bold_style.paint("Size by section:"));
+        for (name, size) in self.sections.iter() {
+            eprintln!(
+                "     {:<40} {:>10} B {:>6.1}%",
+                name,
+                size,
+                percentage(*size)
+            );
+        }
+        eprintln!(
+            "\n   {} (of {} defined)",
+            bold_style.paint("Largest functions:"),
+            self.functions.len()
+        );
+        for (name, size) in self.functions.iter().take(LARGEST_FUNCTIONS) {
+            eprintln!(
+                "     {:<40} {:>10} B {:>6.1}%",
+                name,
+                size,
+                percentage(*size)
+            );
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Smoke tests of every entrypoint of a module, run with `cargo concordium run
+//! smoke`.
+//!
+//! Each contract of the module is initialized, and each of its entrypoints is
+//! then called on a copy of the new instance, so that the calls do not affect
+//! each other. Parameters are the templates of the schema, or empty if the
+//! schema has no type for them. Made up parameters are often rejected, so the
+//! outcomes are only reported, and only traps and running out of energy count
+//! as failures. This gives a quick signal after large refactors.
+use crate::{
+    build::ModuleExports,
+    context::{InitContextOpt, ReceiveContextV1Opt},
+    schema_types::{encode_parameter, json_template, ContractSchemaView, TEMPLATE_ACCOUNT_ADDRESS},
+    sim::{Outcome, Simulator},
+};
+use ansi_term::Color;
+use anyhow::Context;
+use concordium_contracts_common::{schema::VersionedModuleSchema, Amount, ContractAddress};
+use serde_json::{json, Value};
+
+/// The slot time used unless the context has one.
+const SLOT_TIME: &str = "2023-01-01T00:00:00Z";
+
+/// The value with the fields of the base added to or replacing those of the
+/// defaults, recursively for objects.
+fn merge(defaults: Value, base: &Value) -> Value {
+    match (defaults, base) {
+        (Value::Object(mut defaults), Value::Object(base)) => {
+            for (key, value) in base {
+                let merged = match defaults.remove(key) {
+                    Some(default) => merge(default, value),
+                    None => value.clone(),
+                };
+                defaults.insert(key.clone(), merged);
+            }
+            Value::Object(defaults)
+        }
+        (_, base) => base.clone(),
+    }
+}
+
+/// The init context in JSON with the fields it does not have filled in, so
+/// that contracts reading them do not trap.
+pub fn init_context(base: &Value) -> anyhow::Result<InitContextOpt> {
+    let defaults = json!({
+        "metadata": { "slotTime": SLOT_TIME },
+        "initOrigin": TEMPLATE_ACCOUNT_ADDRESS,
+        "senderPolicies": [],
+    });
+    serde_json::from_value(merge(defaults, base)).context("Could not parse the init context.")
+}
+
+/// The receive context in JSON with the fields it does not have filled in.
+/// The address, balance and entrypoint are set by the simulator.
+pub fn receive_context(base: &Value) -> anyhow::Result<ReceiveContextV1Opt> {
+    let defaults = json!({
+        "metadata": { "slotTime": SLOT_TIME },
+        "invoker": TEMPLATE_ACCOUNT_ADDRESS,
+        "sender": { "type": "account", "address": TEMPLATE_ACCOUNT_ADDRESS },
+        "owner": TEMPLATE_ACCOUNT_ADDRESS,
+        "senderPolicies": [],
+    });
+    serde_json::from_value(merge(defaults, base)).context("Could not parse the receive context.")
+}
+
+/// The outcome colored by whether it is a failure.
+fn paint(outcome: &Outcome) -> String {
+    let color = match outcome {
+        Outcome::Success { .. } => Color::Green,
+        Outcome::OutOfEnergy | Outcome::Trap(_) => Color::Red,
+        _ => Color::Yellow,
+    };
+    color.paint(outcome.to_string()).to_string()
+}
+
+/// Initialize each contract of the module and call each of its entrypoints,
+/// printing the outcomes. Returns the number of calls that trapped or ran out
+/// of energy.
+pub fn run(
+    sim: &Simulator,
+    exports: &ModuleExports,
+    schema: Option<&VersionedModuleSchema>,
+    init_ctx: &InitContextOpt,
+    receive_ctx: &ReceiveContextV1Opt,
+) -> anyhow::Result<usize> {
+    let is_failure = |outcome: &Outcome| matches!(outcome, Outcome::OutOfEnergy | Outcome::Trap(_));
+    let mut failures = 0;
+    let mut calls = 0;
+    for contract in exports.contracts.iter() {
+        let view = schema
+            .and_then(|schema| ContractSchemaView::new(schema, contract))
+            .unwrap_or_default();
+        eprintln!("Contract '{}':", contract);
+        let template = view
+            .init
+            .and_then(|types| types.parameter)
+            .map(json_template);
+        let parameter = encode_parameter(template.as_ref(), view.init.and_then(|t| t.parameter))
+            .with_context(|| format!("Could not encode the init parameter of '{}'.", contract))?;
+        let (result, instance) = sim.init(
+            contract,
+            ContractAddress::new(0, 0),
+            init_ctx.clone(),
+            Amount::zero(),
+            &parameter,
+        )?;
+        calls += 1;
+        if is_failure(&result.outcome) {
+            failures += 1;
+        }
+        eprintln!(
+            "  {:<32} {} using {} interpreter energy",
+            "init",
+            paint(&result.outcome),
+            result.energy_used
+        );
+        let entrypoints = exports.entrypoints_of(contract);
+        let instance = match instance {
+            Some(instance) => instance,
+            None => {
+                if !entrypoints.is_empty() {
+                    eprintln!(
+                        "  The {} entrypoints are not called since the initialization did not \
+                         succeed.",
+                        entrypoints.len()
+                    );
+                }
+                continue;
+            }
+        };
+        for entrypoint in entrypoints.iter() {
+            let parameter_type = view
+                .receive
+                .get(entrypoint.as_str())
+                .and_then(|types| types.parameter);
+            let template = parameter_type.map(json_template);
+            let parameter = encode_parameter(template.as_ref(), parameter_type)
+                .with_context(|| format!("Could not encode the parameter of '{}'.", entrypoint))?;
+            let mut copy = instance.clone();
+            let result = sim.update(
+                &mut copy,
+                entrypoint,
+                receive_ctx.clone(),
+                Amount::zero(),
+                &parameter,
+            )?;
+            calls += 1;
+            if is_failure(&result.outcome) {
+                failures += 1;
+            }
+            let name = if entrypoint.is_empty() {
+                "(fallback)"
+            } else {
+                entrypoint.as_str()
+            };
+            eprintln!(
+                "  {:<32} {} using {} interpreter energy",
+                name,
+                paint(&result.outcome),
+                result.energy_used
+            );
+        }
+    }
+    if failures == 0 {
+        eprintln!(
+            "{}",
+            Color::Green.paint(format!("None of the {} calls trapped.", calls))
+        );
+    } else {
+        eprintln!(
+            "{}",
+            Color::Red.paint(format!(
+                "{} of {} calls trapped or ran out of energy.",
+                failures, calls
+            ))
+        );
+    }
+    Ok(failures)
+}
@@ -0,0 +1,267 @@
+//! A registry of Concordium interface standards (CIS-0, CIS-2, ...) and
+//! functionality for checking that the contracts in a module expose their
+//! interface.
+//!
+//! Only the interface is checked: the presence of the entrypoints, the presence
+//! of their parameter and return value types in the schema, and the tags and
+//! names of the events in the event schema. The types themselves and the
+//! behavior of the entrypoints, e.g., that `supports` answers queries or that
+//! `transfer` logs a transfer event, are not checked.
+//!
+//! The built-in definitions are stored in `standards.json` in the same format
+//! as is accepted for user supplied definition files, so new standards can be
+//! checked without a new release of the tool.
+use crate::{build::ModuleExports, schema_types::ContractSchemaView};
+use ansi_term::{Color, Style};
+use anyhow::Context;
+use concordium_contracts_common::schema::{Type, VersionedModuleSchema};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+/// The definitions of the standards that are known to the tool without any
+/// additional definition files.
+const BUILTIN_STANDARDS: &str = include_str!("standards.json");
+
+/// The definition of a standard as a set of requirements a contract must
+/// satisfy to conform to it.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StandardDefinition {
+    /// The identifier of the standard, e.g., `CIS-2`.
+    pub identifier:  String,
+    /// A human readable description of the standard.
+    #[serde(default)]
+    pub description: String,
+    /// Entrypoints that must be exposed by the contract.
+    #[serde(default)]
+    pub entrypoints: Vec<EntrypointRequirement>,
+    /// Events that the contract must be able to log. These are checked against
+    /// the event schema of the contract.
+    #[serde(default)]
+    pub events:      Vec<EventRequirement>,
+}
+
+/// A required entrypoint together with the parts of its signature that must
+/// be described by the schema.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EntrypointRequirement {
+    /// Name of the entrypoint.
+    pub name:         String,
+    /// Whether the schema must include a parameter type for the entrypoint.
+    #[serde(default)]
+    pub parameter:    bool,
+    /// Whether the schema must include a return value type for the entrypoint.
+    #[serde(default)]
+    pub return_value: bool,
+}
+
+/// A required event, identified by the tag of its variant in the event type.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct EventRequirement {
+    /// The tag of the event, i.e., the first byte of the serialized event.
+    pub tag:  u8,
+    /// The name of the event variant in the event schema.
+    pub name: String,
+}
+
+/// Standard definitions indexed by their identifier.
+#[derive(Debug, Default)]
+pub struct StandardsRegistry {
+    standards: BTreeMap<String, StandardDefinition>,
+}
+
+impl StandardsRegistry {
+    /// Construct a registry containing the built-in standard definitions.
+    pub fn builtin() -> anyhow::Result<Self> {
+        let mut registry = Self::default();
+        registry
+            .extend_from_str(BUILTIN_STANDARDS)
+            .context("Could not parse the built-in standard definitions.")?;
+        Ok(registry)
+    }
+
+    /// Add definitions from a JSON string. The string must contain either a
+    /// single definition or a list of definitions. Definitions with an
+    /// identifier that is already in the registry replace the existing one.
+    fn extend_from_str(&mut self, source: &str) -> anyhow::Result<()> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Definitions {
+            Many(Vec<StandardDefinition>),
+            One(StandardDefinition),
+        }
+        let definitions = match serde_json::from_str(source)? {
+            Definitions::Many(definitions) => definitions,
+            Definitions::One(definition) => vec![definition],
+        };
+        for definition in definitions {
+            self.standards
+                .insert(definition.identifier.to_uppercase(), definition);
+        }
+        Ok(())
+    }
+
+    /// Add definitions from a JSON file.
+    pub fn extend_from_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let source = fs::read_to_string(path).with_context(|| {
+            format!(
+                "Could not read standard definitions file {}.",
+                path.display()
+            )
+        })?;
+        self.extend_from_str(&source).with_context(|| {
+            format!(
+                "Could not parse standard definitions file {}.",
+                path.display()
+            )
+        })
+    }
+
+    /// Look up a standard by its identifier. The lookup is case-insensitive.
+    pub fn get(&self, identifier: &str) -> Option<&StandardDefinition> {
+        self.standards.get(&identifier.to_uppercase())
+    }
+
+    /// Iterate over all the standards in the registry.
+    pub fn iter(&self) -> impl Iterator<Item = &StandardDefinition> { self.standards.values() }
+}
+
+/// Check the interface of a single contract against a standard, returning the
+/// list of requirements that are not met. An empty list means the contract
+/// exposes the interface of the standard.
+pub fn check_contract(
+    standard: &StandardDefinition,
+    entrypoints: &BTreeSet<String>,
+    schema: Option<&ContractSchemaView>,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    for requirement in standard.entrypoints.iter() {
+        if !entrypoints.contains(&requirement.name) {
+            problems.push(format!("Missing entrypoint '{}'.", requirement.name));
+            continue;
+        }
+        // Signature requirements can only be checked if there is a schema.
+        if let Some(schema) = schema {
+            match schema.receive.get(requirement.name.as_str()) {
+                Some(types) => {
+                    if requirement.parameter && types.parameter.is_none() {
+                        problems.push(format!(
+                            "The schema has no parameter type for entrypoint '{}'.",
+                            requirement.name
+                        ));
+                    }
+                    if requirement.return_value && types.return_value.is_none() {
+                        problems.push(format!(
+                            "The schema has no return value type for entrypoint '{}'.",
+                            requirement.name
+                        ));
+                    }
+                }
+                None if requirement.parameter || requirement.return_value => {
+                    problems.push(format!(
+                        "The schema does not describe entrypoint '{}'.",
+                        requirement.name
+                    ));
+                }
+                None => (),
+            }
+        }
+    }
+    if !standard.events.is_empty() {
+        match schema.and_then(|s| s.event) {
+            Some(Type::TaggedEnum(variants)) => {
+                for event in standard.events.iter() {
+                    match variants.get(&event.tag) {
+                        Some((name, _)) if name == &event.name => (),
+                        Some((name, _)) => problems.push(format!(
+                            "Event with tag {} is named '{}', expected '{}'.",
+                            event.tag, name, event.name
+                        )),
+                        None => problems.push(format!(
+                            "Missing event '{}' with tag {}.",
+                            event.name, event.tag
+                        )),
+                    }
+                }
+            }
+            Some(_) => problems.push(
+                "The event schema is not a tagged enum, so the events of the standard cannot be \
+                 checked."
+                    .into(),
+            ),
+            None => problems.push("The contract has no event schema.".into()),
+        }
+    }
+    problems
+}
+
+/// Check the interface of the contracts in a module against the given
+/// standards and print the results. If `contract_name` is given only that
+/// contract is checked.
+///
+/// Returns whether all checked contracts expose the interfaces of all the
+/// standards.
+pub fn check_module(
+    standards: &[&StandardDefinition],
+    exports: &ModuleExports,
+    schema: Option<&VersionedModuleSchema>,
+    contract_name: Option<&str>,
+) -> anyhow::Result<bool> {
+    let contracts: Vec<&str> = match contract_name {
+        Some(name) => {
+            anyhow::ensure!(
+                exports.contracts.contains(name),
+                "The module does not contain a contract named '{}'.",
+                name
+            );
+            vec![name]
+        }
+        None => exports.contracts.iter().map(String::as_str).collect(),
+    };
+    if schema.is_none() {
+        eprintln!(
+            "{}",
+            crate::WARNING_STYLE.paint(
+                "No schema found for the module. Only the presence of entrypoints will be checked."
+            )
+        );
+    }
+    eprintln!(
+        "{}",
+        Style::new().italic().paint(
+            "Only the interfaces of the standards are checked, not the behavior of the \
+             entrypoints."
+        )
+    );
+    let mut all_ok = true;
+    for contract in contracts {
+        eprintln!("Contract '{}':", Style::new().bold().paint(contract));
+        let entrypoints = exports.entrypoints_of(contract);
+        let schema_view = schema.and_then(|s| ContractSchemaView::new(s, contract));
+        for standard in standards {
+            let problems = check_contract(standard, &entrypoints, schema_view.as_ref());
+            if problems.is_empty() {
+                eprintln!(
+                    "  - {} ... {}",
+                    standard.identifier,
+                    Color::Green.bold().paint("ok")
+                );
+            } else {
+                all_ok = false;
+                eprintln!(
+                    "  - {} ... {}",
+                    standard.identifier,
+                    Color::Red.bold().paint("FAILED")
+                );
+                for problem in problems {
+                    eprintln!("    {}", Style::new().italic().paint(problem));
+                }
+            }
+        }
+    }
+    Ok(all_ok)
+}
@@ -0,0 +1,76 @@
+//! The changes an update made to the state of a V1 contract.
+//!
+//! Instead of only noting that the state changed, the entries that were added,
+//! removed or modified are listed, so that it is easy to check that an
+//! entrypoint touched exactly the entries it should.
+use crate::state_json;
+use concordium_smart_contract_engine::v1::trie::PersistentState;
+use std::collections::BTreeSet;
+
+/// The number of bytes of a value shown in a preview.
+const PREVIEW_BYTES: usize = 16;
+
+/// The start of the value as hex.
+fn preview(value: &[u8]) -> String {
+    if value.len() > PREVIEW_BYTES {
+        format!("{}...", hex::encode(&value[..PREVIEW_BYTES]))
+    } else {
+        hex::encode(value)
+    }
+}
+
+/// Print the entries that differ between the states, with the sizes of their
+/// values and, if `show_values` is set, a preview of the values.
+pub fn print(
+    before: &PersistentState,
+    after: &PersistentState,
+    show_values: bool,
+) -> anyhow::Result<()> {
+    let before = state_json::entries(before)?;
+    let after = state_json::entries(after)?;
+    let keys = before.keys().chain(after.keys()).collect::<BTreeSet<_>>();
+    let (mut added, mut removed, mut modified) = (0, 0, 0);
+    eprintln!("\nChanges to the state:");
+    for key in keys {
+        let key_hex = hex::encode(key);
+        match (before.get(key), after.get(key)) {
+            (None, Some(new)) => {
+                added += 1;
+                if show_values {
+                    eprintln!("  + {} ({} B) = {}", key_hex, new.len(), preview(new));
+                } else {
+                    eprintln!("  + {} ({} B)", key_hex, new.len());
+                }
+            }
+            (Some(old), None) => {
+                removed += 1;
+                if show_values {
+                    eprintln!("  - {} ({} B) = {}", key_hex, old.len(), preview(old));
+                } else {
+                    eprintln!("  - {} ({} B)", key_hex, old.len());
+                }
+            }
+            (Some(old), Some(new)) if old != new => {
+                modified += 1;
+                if show_values {
+                    eprintln!(
+                        "  ~ {} ({} B -> {} B) = {} -> {}",
+                        key_hex,
+                        old.len(),
+                        new.len(),
+                        preview(old),
+                        preview(new)
+                    );
+                } else {
+                    eprintln!("  ~ {} ({} B -> {} B)", key_hex, old.len(), new.len());
+                }
+            }
+            _ => {}
+        }
+    }
+    eprintln!(
+        "{} added, {} removed and {} modified entries.",
+        added, removed, modified
+    );
+    Ok(())
+}
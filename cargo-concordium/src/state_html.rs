@@ -0,0 +1,107 @@
+//! A self-contained HTML explorer of the state of a V1 contract, written by
+//! `cargo concordium display-state --format html`.
+//!
+//! The state is shown as a tree of collapsible nodes, and a small inline
+//! script searches the keys and expands the nodes leading to the matches. Like
+//! the reports, the file has no external resources, so it can be shared and
+//! viewed offline, and it stays usable for states with many thousands of
+//! entries, unlike the tree printed to the console.
+use crate::{report::escape, state_json::StateNode};
+use anyhow::Context;
+use std::{fmt::Write as _, fs, path::Path};
+
+const STYLE: &str =
+    "body{font-family:sans-serif;margin:2em \
+     auto;max-width:70em;color:#222}ul{list-style:none;padding-left:1.2em;margin:\
+     0}code{font-family:monospace;word-break:break-all}summary{cursor:pointer}.key{color:#0550ae}.\
+     value{color:#116329}.meta{color:#6e7781}.hit>details>summary \
+     .key,.hit>.key{background:#fff8c5}input{font-family:monospace;width:30em;padding:.3em}";
+
+/// Show the nodes whose key contains the search text together with the nodes
+/// leading to them, and hide the others. The items are visited from the last
+/// to the first so that the children of a node are visited before it.
+const SCRIPT: &str = "const items=[...document.querySelectorAll('li[data-key]')].reverse();const \
+                      search=document.getElementById('search');const \
+                      matches=document.getElementById('matches');search.addEventListener('input',\
+                      ()=>{const q=search.value.trim().toLowerCase();let n=0;for(const li of \
+                      items){const own=q!==''&&li.dataset.key.includes(q);const \
+                      child=[...li.querySelectorAll(':scope>details>ul>li')].some(c=>c.dataset.\
+                      show==='1');const \
+                      show=q===''||own||child;li.dataset.show=show?'1':'0';li.style.display=show?'\
+                      ':'none';li.classList.toggle('hit',own);if(own){n++;}const \
+                      d=li.querySelector(':scope>details');if(d&&q!==''){d.open=child;}}matches.\
+                      textContent=q===''?'':n+' matching keys';});";
+
+/// The fragment of the key, the value and its size, and the number of entries
+/// below the node.
+fn label(node: &StateNode, fragment: &str) -> String {
+    let mut label = format!("<code class=\"key\">{}</code>", escape(fragment));
+    if let Some(value) = &node.value {
+        let _ = write!(
+            label,
+            " = <code class=\"value\">{}</code> <span class=\"meta\">({} B)</span>",
+            escape(value),
+            value.len() / 2
+        );
+    }
+    if !node.children.is_empty() {
+        let _ = write!(
+            label,
+            " <span class=\"meta\">[{} entries]</span>",
+            node.count()
+        );
+    }
+    label
+}
+
+/// Add the node and, up to the depth, its children as an item of a list.
+fn add_node(out: &mut String, node: &StateNode, fragment: &str, depth: usize, open: bool) {
+    let _ = write!(out, "<li data-key=\"{}\">", escape(&node.key));
+    if node.children.is_empty() || depth == 0 {
+        out.push_str(&label(node, fragment));
+    } else {
+        let _ = write!(
+            out,
+            "<details{}><summary>{}</summary><ul>",
+            if open { " open" } else { "" },
+            label(node, fragment)
+        );
+        for child in node.children.iter() {
+            // Keys are hex encoded, so the fragment starts after the characters
+            // of the key of the node.
+            add_node(out, child, &child.key[node.key.len()..], depth - 1, false);
+        }
+        out.push_str("</ul></details>");
+    }
+    out.push_str("</li>");
+}
+
+/// Write the tree as an HTML explorer to the file. The children of nodes at
+/// the given depth are left out and only counted.
+pub fn write(root: &StateNode, depth: usize, path: &Path) -> anyhow::Result<()> {
+    let mut tree = String::new();
+    let root_label = if root.key.is_empty() {
+        "(root)".to_string()
+    } else {
+        format!("(prefix {})", root.key)
+    };
+    add_node(&mut tree, root, &root_label, depth, true);
+    let html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Contract \
+         state</title><style>{}</style></head><body><h1>Contract state</h1><p>{} entries. \
+         Generated by cargo-concordium {}.</p><p><input id=\"search\" type=\"search\" \
+         placeholder=\"Search keys as hex\"> <span id=\"matches\" \
+         class=\"meta\"></span></p><ul>{}</ul><script>{}</script></body></html>\n",
+        STYLE,
+        root.count(),
+        env!("CARGO_PKG_VERSION"),
+        tree,
+        SCRIPT
+    );
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).context("Could not create the directory of the explorer.")?;
+    }
+    fs::write(path, html).context("Could not write the state explorer.")?;
+    eprintln!("State explorer written to {}.", path.display());
+    Ok(())
+}
@@ -0,0 +1,385 @@
+//! The state of V1 contracts as JSON.
+//!
+//! The state of a V1 contract is a trie of byte keys and values, which does
+//! not have a schema. It is written as a tree of nodes with hex encoded keys
+//! and values, where the key of each node is a prefix of the keys of its
+//! children, so that the structure of the state is kept while it can be
+//! inspected and diffed without binary tooling.
+//!
+//! The tree can also be rendered as a Graphviz graph, which shows how the
+//! entries are laid out in the trie.
+//!
+//! Long values can be truncated for inspection, in which case the length of
+//! the full value is given with `valueLength`. Such a state cannot be read
+//! back.
+//!
+//! A state is read from the same format, or from a list of nodes with a key and
+//! a value. Keys and values are given either as hex, as a typed value such as
+//! `{ "u64": 5 }` or `{ "string": "abc" }` that is serialized like in
+//! contracts, or as a list of these, which are concatenated.
+use anyhow::{anyhow, bail, Context};
+use concordium_smart_contract_engine::v1::{self, trie::PersistentState};
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, convert::TryFrom, fs, path::Path};
+
+/// A node of the state. Nodes without a value only group their children.
+#[derive(Debug, Serialize)]
+pub struct StateNode {
+    /// The full key of the node, hex encoded.
+    pub key:          String,
+    /// The value stored at the key, hex encoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value:        Option<String>,
+    /// The number of bytes of a value that was truncated.
+    #[serde(rename = "valueLength", skip_serializing_if = "Option::is_none")]
+    pub value_length: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children:     Vec<StateNode>,
+}
+
+/// Call the function with the key and value of each entry of the state with a
+/// key that starts with the prefix, in the order of the keys. The entries are
+/// visited one at a time, so they are not all copied.
+pub fn for_each_entry(
+    state: &PersistentState,
+    prefix: &[u8],
+    mut f: impl FnMut(&[u8], &[u8]) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut loader = v1::trie::Loader::new(&[][..]);
+    let mut mutable_state = state.thaw();
+    let inner = mutable_state.get_inner(&mut loader);
+    let iter = inner
+        .iter(&mut loader, prefix)
+        .map_err(|_| anyhow!("Could not iterate over the state."))?;
+    if let Some(mut iter) = iter {
+        while let Some(entry) = inner
+            .next(&mut loader, &mut iter, &mut v1::trie::EmptyCounter)
+            .map_err(|_| anyhow!("Could not iterate over the state."))?
+        {
+            inner
+                .with_entry(entry, &mut loader, |value| f(iter.get_key(), value))
+                .context("An entry of the state has no value.")??;
+        }
+    }
+    Ok(())
+}
+
+/// The entries of the state, ordered by key.
+pub fn entries(state: &PersistentState) -> anyhow::Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+    let mut entries = BTreeMap::new();
+    for_each_entry(state, &[], |key, value| {
+        entries.insert(key.to_vec(), value.to_vec());
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+/// The value stored at the key, if there is one. Only the path to the key is
+/// loaded, so this is fast even for large states.
+pub fn get(state: &PersistentState, key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut loader = v1::trie::Loader::new(&[][..]);
+    let mut mutable_state = state.thaw();
+    let inner = mutable_state.get_inner(&mut loader);
+    match inner.get_entry(&mut loader, key) {
+        Some(entry) => {
+            let value = inner
+                .with_entry(entry, &mut loader, |value| value.to_vec())
+                .context("The entry of the key has no value.")?;
+            Ok(Some(value))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The state with the value at the key inserted, or replaced if the key has a
+/// value, together with whether a value was replaced.
+pub fn set(
+    state: &PersistentState,
+    key: &[u8],
+    value: Vec<u8>,
+) -> anyhow::Result<(PersistentState, bool)> {
+    let replaced = get(state, key)?.is_some();
+    let mut loader = v1::trie::Loader::new(&[][..]);
+    let mut mutable_state = state.thaw();
+    let inner = mutable_state.get_inner(&mut loader);
+    inner.insert(&mut loader, key, value).map_err(|_| {
+        anyhow!(
+            "Could not insert the key {} into the state.",
+            hex::encode(key)
+        )
+    })?;
+    let mut collector = v1::trie::SizeCollector::default();
+    Ok((mutable_state.freeze(&mut loader, &mut collector), replaced))
+}
+
+/// The state without the entries whose keys satisfy the predicate, together
+/// with the number of entries removed.
+pub fn remove(
+    state: &PersistentState,
+    mut predicate: impl FnMut(&[u8]) -> bool,
+) -> anyhow::Result<(PersistentState, usize)> {
+    let mut entries = entries(state)?;
+    let before = entries.len();
+    entries.retain(|key, _| !predicate(key));
+    let removed = before - entries.len();
+    Ok((from_entries(entries)?, removed))
+}
+
+impl StateNode {
+    /// The number of entries in the subtree rooted at the node.
+    pub fn count(&self) -> usize {
+        let children = self.children.iter().map(StateNode::count).sum::<usize>();
+        usize::from(self.value.is_some()) + children
+    }
+
+    /// Keep only the first bytes of the values in the subtree that are longer
+    /// than the given number of bytes, marking them with `...`.
+    pub fn truncate_values(&mut self, max_bytes: usize) {
+        if let Some(value) = self.value.as_mut() {
+            if value.len() > 2 * max_bytes {
+                self.value_length = Some(value.len() / 2);
+                value.truncate(2 * max_bytes);
+                value.push_str("...");
+            }
+        }
+        for child in self.children.iter_mut() {
+            child.truncate_values(max_bytes);
+        }
+    }
+}
+
+/// The length of the longest common prefix of two keys.
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count()
+}
+
+/// The node with the given key of entries ordered by key, which all start with
+/// the key.
+fn node(key: &[u8], entries: &[(&[u8], &[u8])]) -> StateNode {
+    let (value, mut rest) = match entries.first() {
+        Some((entry_key, value)) if entry_key.len() == key.len() => {
+            (Some(hex::encode(value)), &entries[1..])
+        }
+        _ => (None, entries),
+    };
+    let mut children = Vec::new();
+    // Group the remaining entries by the byte following the key. Since they are
+    // ordered, the common prefix of a group is that of its first and last key.
+    while let Some((first, _)) = rest.first() {
+        let byte = first[key.len()];
+        let end = rest
+            .iter()
+            .position(|(k, _)| k[key.len()] != byte)
+            .unwrap_or(rest.len());
+        let (group, remaining) = rest.split_at(end);
+        let (last, _) = group[group.len() - 1];
+        children.push(node(&first[..common_prefix(first, last)], group));
+        rest = remaining;
+    }
+    StateNode {
+        key: hex::encode(key),
+        value,
+        value_length: None,
+        children,
+    }
+}
+
+/// The entries of the state with keys that start with the prefix, as a tree
+/// rooted at the prefix.
+pub fn subtree(state: &PersistentState, prefix: &[u8]) -> anyhow::Result<StateNode> {
+    let entries = entries(state)?;
+    let entries = entries
+        .iter()
+        .filter(|(key, _)| key.starts_with(prefix))
+        .map(|(key, value)| (&key[..], &value[..]))
+        .collect::<Vec<_>>();
+    Ok(node(prefix, &entries))
+}
+
+/// The state as a tree rooted at the empty key.
+pub fn to_tree(state: &PersistentState) -> anyhow::Result<StateNode> { subtree(state, &[]) }
+
+/// Add a node and, up to the depth, its children to the Graphviz graph.
+/// Returns the identifier of the node.
+fn add_dot_node(
+    out: &mut String,
+    node: &StateNode,
+    label: &str,
+    depth: usize,
+    next_id: &mut usize,
+) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let (kind, shape) = match (node.value.is_some(), node.children.is_empty()) {
+        (true, true) => ("leaf", "ellipse"),
+        (true, false) => ("branch with value", "box"),
+        (false, _) => ("branch", "box"),
+    };
+    let mut text = format!("{}\\n{}", label, kind);
+    if let Some(value) = &node.value {
+        text.push_str(&format!("\\n{} B", value.len() / 2));
+    }
+    out.push_str(&format!(
+        "    n{} [label=\"{}\", shape={}];\n",
+        id, text, shape
+    ));
+    if depth == 0 {
+        if !node.children.is_empty() {
+            let count = node.children.iter().map(StateNode::count).sum::<usize>();
+            out.push_str(&format!(
+                "    n{}_rest [label=\"{} entries\", shape=plaintext];\n    n{} -> n{}_rest \
+                 [style=dashed];\n",
+                id, count, id, id
+            ));
+        }
+        return id;
+    }
+    for child in node.children.iter() {
+        // Keys are hex encoded, so the fragment starts after the characters of
+        // the key of the node.
+        let fragment = &child.key[node.key.len()..];
+        let child_id = add_dot_node(out, child, fragment, depth - 1, next_id);
+        out.push_str(&format!("    n{} -> n{};\n", id, child_id));
+    }
+    id
+}
+
+/// The tree as a Graphviz graph. Each node is labelled with the fragment of
+/// the key it adds to the key of its parent, whether it is a branch or a leaf
+/// and the size of its value. The children of nodes at the given depth are
+/// summarized by the number of entries below them.
+pub fn to_dot(root: &StateNode, depth: usize) -> String {
+    let mut out = String::from("digraph state {\n    node [fontname=\"monospace\"];\n");
+    let label = if root.key.is_empty() {
+        "root".to_string()
+    } else {
+        format!("root\\nprefix {}", root.key)
+    };
+    add_dot_node(&mut out, root, &label, depth, &mut 0);
+    out.push_str("}\n");
+    out
+}
+
+/// Write the state as JSON.
+pub fn write(state: &PersistentState, path: &Path) -> anyhow::Result<()> {
+    let tree = to_tree(state)?;
+    if let Some(out_dir) = path.parent() {
+        fs::create_dir_all(out_dir)
+            .context("Unable to create directory for the JSON state output.")?;
+    }
+    fs::write(path, serde_json::to_vec_pretty(&tree)?).context("Could not write out the state.")
+}
+
+/// Serialize a typed value.
+fn encode_typed(ty: &str, value: &Value) -> anyhow::Result<Vec<u8>> {
+    let unsigned = || {
+        value
+            .as_u64()
+            .with_context(|| format!("Expected an unsigned integer for '{}'.", ty))
+    };
+    let signed = || {
+        value
+            .as_i64()
+            .with_context(|| format!("Expected an integer for '{}'.", ty))
+    };
+    let bytes = match ty {
+        "u8" => u8::try_from(unsigned()?)?.to_le_bytes().to_vec(),
+        "u16" => u16::try_from(unsigned()?)?.to_le_bytes().to_vec(),
+        "u32" => u32::try_from(unsigned()?)?.to_le_bytes().to_vec(),
+        "u64" => unsigned()?.to_le_bytes().to_vec(),
+        "i8" => i8::try_from(signed()?)?.to_le_bytes().to_vec(),
+        "i16" => i16::try_from(signed()?)?.to_le_bytes().to_vec(),
+        "i32" => i32::try_from(signed()?)?.to_le_bytes().to_vec(),
+        "i64" => signed()?.to_le_bytes().to_vec(),
+        "bool" => vec![value.as_bool().context("Expected a boolean for 'bool'.")? as u8],
+        "string" => {
+            let string = value.as_str().context("Expected a string for 'string'.")?;
+            let mut bytes = u32::try_from(string.len())?.to_le_bytes().to_vec();
+            bytes.extend_from_slice(string.as_bytes());
+            bytes
+        }
+        _ => bail!(
+            "Unknown type '{}'. Supported types are u8, u16, u32, u64, i8, i16, i32, i64, bool \
+             and string.",
+            ty
+        ),
+    };
+    Ok(bytes)
+}
+
+/// Serialize a key or value given as hex, a typed value or a list of these.
+fn encode(value: &Value) -> anyhow::Result<Vec<u8>> {
+    match value {
+        Value::String(hex) => {
+            hex::decode(hex).with_context(|| format!("Could not decode '{}' as hex.", hex))
+        }
+        Value::Array(parts) => {
+            let mut bytes = Vec::new();
+            for part in parts {
+                bytes.extend(encode(part)?);
+            }
+            Ok(bytes)
+        }
+        Value::Object(typed) if typed.len() == 1 => {
+            let (ty, value) = typed.iter().next().context("Expected a typed value.")?;
+            encode_typed(ty, value)
+        }
+        _ => bail!("Expected hex, a typed value such as {{\"u64\": 5}} or a list of these."),
+    }
+}
+
+/// Collect the entries of a node or a list of nodes.
+fn collect(node: &Value, entries: &mut BTreeMap<Vec<u8>, Vec<u8>>) -> anyhow::Result<()> {
+    match node {
+        Value::Array(nodes) => {
+            for node in nodes {
+                collect(node, entries)?;
+            }
+        }
+        Value::Object(fields) => {
+            if let Some(value) = fields.get("value").filter(|value| !value.is_null()) {
+                let key = encode(
+                    fields
+                        .get("key")
+                        .context("A node with a value has no key.")?,
+                )?;
+                let value = encode(value)?;
+                if entries.insert(key.clone(), value).is_some() {
+                    bail!("The key {} occurs more than once.", hex::encode(key));
+                }
+            }
+            if let Some(children) = fields.get("children") {
+                collect(children, entries)?;
+            }
+        }
+        _ => bail!("Expected a node of the state or a list of nodes."),
+    }
+    Ok(())
+}
+
+/// Build a state from its entries.
+pub fn from_entries(entries: BTreeMap<Vec<u8>, Vec<u8>>) -> anyhow::Result<PersistentState> {
+    let mut loader = v1::trie::Loader::new(&[][..]);
+    let mut mutable_state = v1::trie::MutableState::initial_state();
+    let inner = mutable_state.get_inner(&mut loader);
+    for (key, value) in entries {
+        inner.insert(&mut loader, &key, value).map_err(|_| {
+            anyhow!(
+                "Could not insert the key {} into the state.",
+                hex::encode(&key)
+            )
+        })?;
+    }
+    let mut collector = v1::trie::SizeCollector::default();
+    Ok(mutable_state.freeze(&mut loader, &mut collector))
+}
+
+/// Read a state from JSON.
+pub fn read(path: &Path) -> anyhow::Result<PersistentState> {
+    let file = fs::read(path).context("Could not read state file.")?;
+    let json: Value = serde_json::from_slice(&file).context("Could not parse state JSON.")?;
+    let mut entries = BTreeMap::new();
+    collect(&json, &mut entries).context("Could not read the state from JSON.")?;
+    from_entries(entries)
+}
@@ -0,0 +1,117 @@
+//! Statistics of the state of a V1 contract, printed by `cargo concordium
+//! state stats`.
+//!
+//! The state of an instance is charged for by its size, so the number of
+//! entries and the bytes of their keys and values show what a contract pays
+//! for. The depth and branching of the trie show how the entries are laid out,
+//! and the largest values show where most of the bytes are.
+use crate::state_json::{self, StateNode};
+use concordium_smart_contract_engine::v1::trie::PersistentState;
+use serde::Serialize;
+
+/// A value of the state together with its key.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LargeValue {
+    /// The hex encoded key.
+    pub key:  String,
+    /// The number of bytes of the value.
+    pub size: usize,
+}
+
+/// The statistics of a state.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateStats {
+    pub entries:          usize,
+    pub key_bytes:        usize,
+    pub value_bytes:      usize,
+    pub max_key_length:   usize,
+    /// The largest number of nodes on a path from the root of the trie.
+    pub max_depth:        usize,
+    /// The number of nodes with children.
+    pub branches:         usize,
+    /// The largest number of children of a node.
+    pub max_children:     usize,
+    /// The average number of children of the nodes with children.
+    pub average_children: f64,
+    /// The largest values, largest first.
+    pub largest_values:   Vec<LargeValue>,
+}
+
+/// The depth of the subtree rooted at the node, and the number of nodes with
+/// children, their total number of children and the largest number.
+fn shape(node: &StateNode) -> (usize, usize, usize, usize) {
+    let mut depth = 0;
+    let (mut branches, mut children, mut max_children) = (0, 0, 0);
+    if !node.children.is_empty() {
+        branches = 1;
+        children = node.children.len();
+        max_children = node.children.len();
+    }
+    for child in node.children.iter() {
+        let (child_depth, child_branches, child_children, child_max) = shape(child);
+        depth = depth.max(child_depth);
+        branches += child_branches;
+        children += child_children;
+        max_children = max_children.max(child_max);
+    }
+    (depth + 1, branches, children, max_children)
+}
+
+/// Compute the statistics of the state, with the given number of largest
+/// values.
+pub fn compute(state: &PersistentState, top: usize) -> anyhow::Result<StateStats> {
+    let entries = state_json::entries(state)?;
+    let (max_depth, branches, children, max_children) = shape(&state_json::to_tree(state)?);
+    let mut largest_values = entries
+        .iter()
+        .map(|(key, value)| LargeValue {
+            key:  hex::encode(key),
+            size: value.len(),
+        })
+        .collect::<Vec<_>>();
+    largest_values.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.key.cmp(&b.key)));
+    largest_values.truncate(top);
+    Ok(StateStats {
+        entries: entries.len(),
+        key_bytes: entries.keys().map(Vec::len).sum(),
+        value_bytes: entries.values().map(Vec::len).sum(),
+        max_key_length: entries.keys().map(Vec::len).max().unwrap_or(0),
+        max_depth,
+        branches,
+        max_children,
+        average_children: if branches == 0 {
+            0.0
+        } else {
+            children as f64 / branches as f64
+        },
+        largest_values,
+    })
+}
+
+impl StateStats {
+    /// Print the statistics to stdout.
+    pub fn print(&self) {
+        println!("Entries:               {}", self.entries);
+        println!("Key bytes:             {} B", self.key_bytes);
+        println!("Value bytes:           {} B", self.value_bytes);
+        println!(
+            "Total bytes:           {} B",
+            self.key_bytes + self.value_bytes
+        );
+        println!("Longest key:           {} B", self.max_key_length);
+        println!("Depth of the trie:     {}", self.max_depth);
+        println!("Branching nodes:       {}", self.branches);
+        println!(
+            "Children per branch:   {:.2} on average, {} at most",
+            self.average_children, self.max_children
+        );
+        if !self.largest_values.is_empty() {
+            println!("Largest values:");
+            for value in self.largest_values.iter() {
+                println!("  {:>8} B  {}", value.size, value.key);
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Checks of `cargo concordium build --strict` for constructs that the chain
+//! accepts, but which are almost always mistakes.
+//!
+//! Floating point instructions are not checked here, since validation of the
+//! module already rejects them.
+use crate::build::read_leb128_u32;
+use anyhow::{bail, Context};
+use concordium_contracts_common::{ContractName, ReceiveName};
+use concordium_wasm::types::{ExportDescription, Module};
+
+/// Data segments larger than this are reported. Such segments usually stem
+/// from large constants, e.g., tables or embedded files.
+const MAX_DATA_SEGMENT_SIZE: usize = 64 * 1024;
+
+/// Describe the suspicious constructs in the Wasm module `wasm` (without the
+/// version prefix), where `module` is the parsed module.
+pub fn suspicious_constructs(wasm: &[u8], module: &Module) -> anyhow::Result<Vec<String>> {
+    let mut findings = Vec::new();
+    for export in &module.export.exports {
+        if let ExportDescription::Func { .. } = export.description {
+            let name = export.name.as_ref();
+            if ContractName::new(name).is_err() && ReceiveName::new(name).is_err() {
+                findings.push(format!(
+                    "The function '{}' is exported, but is neither an init nor a receive function.",
+                    name
+                ));
+            }
+        }
+    }
+
+    let mut pos = 8;
+    while pos < wasm.len() {
+        let id = wasm[pos];
+        pos += 1;
+        let len = read_leb128_u32(wasm, &mut pos)? as usize;
+        let contents = wasm
+            .get(pos..pos + len)
+            .context("Section exceeds the module.")?;
+        pos += len;
+        match id {
+            8 => findings.push(
+                "The module has a start function, which runs whenever an instance is invoked."
+                    .to_string(),
+            ),
+            11 => {
+                for (index, size) in data_segment_sizes(contents)?.into_iter().enumerate() {
+                    if size > MAX_DATA_SEGMENT_SIZE {
+                        findings.push(format!(
+                            "The data segment {} is {} B, which is larger than {} B.",
+                            index, size, MAX_DATA_SEGMENT_SIZE
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(findings)
+}
+
+/// Get the sizes of the segments in the contents of a data section.
+fn data_segment_sizes(contents: &[u8]) -> anyhow::Result<Vec<usize>> {
+    let mut pos = 0;
+    let count = read_leb128_u32(contents, &mut pos)?;
+    let mut sizes = Vec::new();
+    for _ in 0..count {
+        // The memory index followed by the offset, which is a constant
+        // expression consisting of `i32.const` or `global.get` and `end`.
+        read_leb128_u32(contents, &mut pos)?;
+        match contents.get(pos) {
+            Some(0x41) | Some(0x23) => pos += 1,
+            _ => bail!("Unsupported offset of a data segment."),
+        }
+        read_leb128_u32(contents, &mut pos)?;
+        if contents.get(pos) != Some(&0x0b) {
+            bail!("Unsupported offset of a data segment.");
+        }
+        pos += 1;
+        let size = read_leb128_u32(contents, &mut pos)? as usize;
+        pos += size;
+        sizes.push(size);
+    }
+    Ok(sizes)
+}
@@ -0,0 +1,247 @@
+//! Coverage of the functions of a contract by its tests.
+//!
+//! `cargo concordium test --coverage` instruments the test module with a
+//! counter at the start of each function of the package, which is a call to
+//! the host function `hit` of the module `concordium_coverage` with the number
+//! of the function. The counters are injected into the parsed module before
+//! the metering of the chain, so the tests are run only once, and the test
+//! runner records the functions each test executes. Functions are identified
+//! by the name section of the test module, so functions that the compiler
+//! inlined are not measured.
+use crate::build::{read_leb128_u32, TEST_EXPORT_PREFIX};
+use ansi_term::{Color, Style};
+use anyhow::Context;
+use concordium_wasm::{
+    parse::{parse_custom, Skeleton},
+    types::{
+        ExportDescription, FunctionType, Import, ImportDescription, Module, OpCode, ValueType,
+    },
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+/// The module of the host function that counts the execution of functions.
+pub const COVERAGE_MODULE: &str = "concordium_coverage";
+
+/// The name of the host function that counts the execution of functions.
+pub const COVERAGE_HIT: &str = "hit";
+
+/// The coverage of a single function.
+pub struct FunctionCoverage {
+    pub name:       String,
+    /// The tests that execute the function.
+    pub covered_by: Vec<String>,
+}
+
+/// Read a name prefixed by its length.
+fn read_name<'a>(bytes: &'a [u8], pos: &mut usize) -> anyhow::Result<&'a [u8]> {
+    let len = read_leb128_u32(bytes, pos)? as usize;
+    let name = bytes
+        .get(*pos..*pos + len)
+        .context("Malformed name in the module.")?;
+    *pos += len;
+    Ok(name)
+}
+
+/// The names of the functions from the `name` custom section, by index.
+fn function_names(skeleton: &Skeleton) -> anyhow::Result<BTreeMap<u32, String>> {
+    let mut names = BTreeMap::new();
+    for section in skeleton.custom.iter() {
+        let section = parse_custom(section).context("Could not parse custom section.")?;
+        if section.name.as_ref() != "name" {
+            continue;
+        }
+        let contents = section.contents;
+        let mut pos = 0;
+        while pos < contents.len() {
+            let subsection = contents[pos];
+            pos += 1;
+            let len = read_leb128_u32(contents, &mut pos)? as usize;
+            let end = pos + len;
+            if subsection == 1 {
+                for _ in 0..read_leb128_u32(contents, &mut pos)? {
+                    let index = read_leb128_u32(contents, &mut pos)?;
+                    let name = read_name(contents, &mut pos)?;
+                    names.insert(index, String::from_utf8_lossy(name).into_owned());
+                }
+            }
+            pos = end;
+        }
+    }
+    Ok(names)
+}
+
+/// Remove the hash that rustc appends to the names of functions.
+fn without_hash(name: &str) -> &str {
+    match name.rsplit_once("::h") {
+        Some((path, hash)) if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            path
+        }
+        _ => name,
+    }
+}
+
+/// Increment a function index that refers to `from` or a later function, to
+/// make room for a function imported at `from`.
+fn shift(index: &mut u32, from: u32) {
+    if *index >= from {
+        *index += 1;
+    }
+}
+
+/// Import the host function `hit` as the last imported function, which shifts
+/// the indices of the functions defined in the module by one. Returns the
+/// index of the imported function.
+fn import_hit(module: &mut Module) -> u32 {
+    let hit = module.import.imports.len() as u32;
+    let ty = FunctionType {
+        parameters: vec![ValueType::I32],
+        result:     None,
+    };
+    let type_idx = match module.ty.types.iter().position(|t| **t == ty) {
+        Some(index) => index as u32,
+        None => {
+            module.ty.types.push(Rc::new(ty));
+            module.ty.types.len() as u32 - 1
+        }
+    };
+    module.import.imports.push(Import {
+        mod_name:    COVERAGE_MODULE.into(),
+        item_name:   COVERAGE_HIT.into(),
+        description: ImportDescription::Func { type_idx },
+    });
+    for code in module.code.impls.iter_mut() {
+        for instr in code.expr.instrs.iter_mut() {
+            if let OpCode::Call(index) = instr {
+                shift(index, hit);
+            }
+        }
+    }
+    for export in module.export.exports.iter_mut() {
+        if let ExportDescription::Func { index } = &mut export.description {
+            shift(index, hit);
+        }
+    }
+    for element in module.element.elements.iter_mut() {
+        for index in element.inits.iter_mut() {
+            shift(index, hit);
+        }
+    }
+    if let Some(index) = module.start.start.as_mut() {
+        shift(index, hit);
+    }
+    hit
+}
+
+/// Inject a counter at the start of each function of the crate with the given
+/// name into the validated test module, excluding the tests themselves. The
+/// counter of a function calls `hit` with the position of the function in the
+/// returned list of names.
+pub fn instrument(
+    skeleton: &Skeleton,
+    module: &mut Module,
+    crate_name: &str,
+) -> anyhow::Result<Vec<String>> {
+    let names = function_names(skeleton)?;
+    anyhow::ensure!(
+        !names.is_empty(),
+        "The test module has no names of functions, which coverage requires. Make sure that debug \
+         information is not stripped from release builds."
+    );
+    let imported = module.import.imports.len() as u32;
+    let tests = module
+        .export
+        .exports
+        .iter()
+        .filter(|export| export.name.as_ref().starts_with(TEST_EXPORT_PREFIX))
+        .filter_map(|export| match export.description {
+            ExportDescription::Func { index } => Some(index),
+            _ => None,
+        })
+        .collect::<BTreeSet<_>>();
+    let prefix = format!("{}::", crate_name);
+    let functions = names
+        .iter()
+        .filter(|(index, name)| {
+            **index >= imported
+                && !tests.contains(*index)
+                && name.contains(&prefix)
+                && !name.contains("::tests::")
+        })
+        .collect::<Vec<_>>();
+
+    let hit = import_hit(module);
+    let mut measured = Vec::with_capacity(functions.len());
+    for (counter, (index, name)) in functions.into_iter().enumerate() {
+        let code = module
+            .code
+            .impls
+            .get_mut((index - imported) as usize)
+            .context("The name section refers to a function that does not exist.")?;
+        code.expr
+            .instrs
+            .splice(0..0, [OpCode::I32Const(counter as i32), OpCode::Call(hit)]);
+        measured.push(without_hash(name).to_string());
+    }
+    Ok(measured)
+}
+
+/// Combine the functions executed by each test into the coverage of each
+/// function, where `functions` are the names returned by [`instrument`].
+pub fn coverage<'a>(
+    functions: &[String],
+    executed: impl IntoIterator<Item = (&'a str, &'a BTreeSet<u32>)>,
+) -> Vec<FunctionCoverage> {
+    let mut coverage = functions
+        .iter()
+        .map(|name| FunctionCoverage {
+            name:       name.clone(),
+            covered_by: Vec::new(),
+        })
+        .collect::<Vec<_>>();
+    for (test, counters) in executed {
+        for counter in counters {
+            if let Some(function) = coverage.get_mut(*counter as usize) {
+                function.covered_by.push(test.to_string());
+            }
+        }
+    }
+    coverage.sort_by(|a, b| a.name.cmp(&b.name));
+    coverage
+}
+
+/// Print the covered and uncovered functions and return the percentage of
+/// covered functions.
+pub fn print_report(coverage: &[FunctionCoverage]) -> f64 {
+    eprintln!("\n{}", Style::new().bold().paint("Function coverage:"));
+    for function in coverage.iter() {
+        if function.covered_by.is_empty() {
+            eprintln!("  {} {}", Color::Red.paint("not covered"), function.name);
+        } else {
+            eprintln!(
+                "  {} {} ({} tests)",
+                Color::Green.paint("covered    "),
+                function.name,
+                function.covered_by.len()
+            );
+        }
+    }
+    let covered = coverage
+        .iter()
+        .filter(|function| !function.covered_by.is_empty())
+        .count();
+    let percentage = if coverage.is_empty() {
+        100.0
+    } else {
+        100.0 * covered as f64 / coverage.len() as f64
+    };
+    eprintln!(
+        "{} of {} functions covered ({:.1}%).",
+        covered,
+        coverage.len(),
+        percentage
+    );
+    percentage
+}
@@ -0,0 +1,36 @@
+//! Interpreter energy used by each test of `cargo concordium test`.
+//!
+//! The test runner of the engine does not meter energy, so with
+//! `--energy-report` the tests are run one at a time from the module
+//! instrumented with metering, within the limits given for tests. Only the
+//! execution of Wasm instructions is counted, since the host functions of the
+//! test infrastructure do not exist on the chain.
+use anyhow::Context;
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// The energy used by each test of a test module, by name.
+#[derive(Debug, Serialize)]
+pub struct TestEnergy {
+    /// The seed used for randomized tests.
+    pub seed:  u64,
+    pub tests: BTreeMap<String, u64>,
+}
+
+impl TestEnergy {
+    pub fn print(&self) {
+        eprintln!("\nInterpreter energy per test:");
+        for (name, energy) in self.tests.iter() {
+            eprintln!("  {:<48} {:>14}", name, energy);
+        }
+    }
+
+    /// Write the energy per test as JSON.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Unable to create directory for the energy report.")?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+            .context("Could not write the energy report.")
+    }
+}
@@ -0,0 +1,155 @@
+//! Machine-readable reports of `cargo concordium test` for CI systems.
+//!
+//! Reports are requested with `--report <format>:<path>`, where the format is
+//! either `junit` for JUnit XML, which most CI dashboards ingest, or `json`.
+use crate::report::escape;
+use anyhow::Context;
+use serde::Serialize;
+use std::{fmt::Write as _, fs, path::PathBuf, str::FromStr, time::Duration};
+
+/// The supported report formats.
+#[derive(Debug, Clone, Copy)]
+pub enum TestReportFormat {
+    Junit,
+    Json,
+}
+
+/// A report to write, given as `<format>:<path>`.
+#[derive(Debug, Clone)]
+pub struct TestReport {
+    pub format: TestReportFormat,
+    pub path:   PathBuf,
+}
+
+impl FromStr for TestReport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, path) = s.split_once(':').context(
+            "Expected a report of the form `<format>:<path>`, e.g., `junit:report.xml`.",
+        )?;
+        let format = match format.to_lowercase().as_str() {
+            "junit" => TestReportFormat::Junit,
+            "json" => TestReportFormat::Json,
+            _ => anyhow::bail!(
+                "Unsupported report format '{}'. Use `junit` or `json`.",
+                format
+            ),
+        };
+        anyhow::ensure!(!path.is_empty(), "The path of the report is missing.");
+        Ok(TestReport {
+            format,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// The outcome of a single test.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestOutcome {
+    pub name:     String,
+    /// The error if the test failed.
+    pub error:    Option<String>,
+    /// The seed that reproduces the failure of a randomized test.
+    pub seed:     Option<u64>,
+    /// The time taken by the test, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<f64>,
+}
+
+/// The results of a test run.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestRun {
+    /// The name of the tested package.
+    pub suite:    String,
+    /// The seed used for randomized tests.
+    pub seed:     u64,
+    /// The time taken by running all tests in the module, in seconds.
+    pub duration: f64,
+    pub tests:    Vec<TestOutcome>,
+}
+
+impl TestRun {
+    pub fn new(suite: &str, seed: u64, duration: Duration, tests: Vec<TestOutcome>) -> Self {
+        TestRun {
+            suite: suite.to_string(),
+            seed,
+            duration: duration.as_secs_f64(),
+            tests,
+        }
+    }
+
+    fn failures(&self) -> usize {
+        self.tests
+            .iter()
+            .filter(|test| test.error.is_some())
+            .count()
+    }
+
+    /// Render the run as JUnit XML.
+    fn junit(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuites tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+            self.tests.len(),
+            self.failures(),
+            self.duration
+        );
+        let _ = writeln!(
+            xml,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" time=\"{:.3}\">",
+            escape(&self.suite),
+            self.tests.len(),
+            self.failures(),
+            self.duration
+        );
+        let _ = writeln!(
+            xml,
+            "    <properties>\n      <property name=\"seed\" value=\"{}\"/>\n    </properties>",
+            self.seed
+        );
+        for test in self.tests.iter() {
+            let _ = write!(
+                xml,
+                "    <testcase name=\"{}\" classname=\"{}\"",
+                escape(&test.name),
+                escape(&self.suite)
+            );
+            if let Some(duration) = test.duration {
+                let _ = write!(xml, " time=\"{:.3}\"", duration);
+            }
+            match &test.error {
+                None => xml.push_str("/>\n"),
+                Some(error) => {
+                    let _ = writeln!(
+                        xml,
+                        ">\n      <failure message=\"{}\">{}</failure>",
+                        escape(error),
+                        escape(error)
+                    );
+                    if let Some(seed) = test.seed {
+                        let _ = writeln!(xml, "      <system-out>Seed: {}</system-out>", seed);
+                    }
+                    xml.push_str("    </testcase>\n");
+                }
+            }
+        }
+        xml.push_str("  </testsuite>\n</testsuites>\n");
+        xml
+    }
+
+    /// Write the run in the format of the report.
+    pub fn write(&self, report: &TestReport) -> anyhow::Result<()> {
+        let contents = match report.format {
+            TestReportFormat::Junit => self.junit(),
+            TestReportFormat::Json => serde_json::to_string_pretty(self)?,
+        };
+        if let Some(dir) = report.path.parent() {
+            fs::create_dir_all(dir).context("Unable to create directory for the test report.")?;
+        }
+        fs::write(&report.path, contents).context("Could not write the test report.")
+    }
+}
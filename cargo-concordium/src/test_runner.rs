@@ -0,0 +1,195 @@
+//! Running the tests of a test module one at a time.
+//!
+//! The engine runs all tests of a module in one go and only then returns the
+//! results. When `cargo concordium test` must observe single tests, such as
+//! for stopping at the first failure, it runs them one at a time from the
+//! module instrumented with the metering of the chain instead. The host
+//! counts the energy charged by the instrumentation, records whether a test
+//! uses randomness, and delegates all other calls to the test host of the
+//! engine. The host also stops tests that exceed the limits on energy and
+//! time given with `--test-energy` and `--test-timeout`, and records the
+//! functions a test executes when the module is instrumented for coverage.
+use crate::{
+    build::{test_exports, TEST_EXPORT_PREFIX},
+    test_coverage::{self, COVERAGE_MODULE},
+};
+use anyhow::{bail, Context};
+use concordium_smart_contract_engine::utils::TestHost;
+use concordium_wasm::{
+    artifact::{Artifact, ArtifactNamedImport, CompiledFunction},
+    machine::{Host, RunResult, RuntimeStack},
+    parse::parse_skeleton,
+    validate::validate_module,
+};
+use rand::{rngs::SmallRng, SeedableRng};
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+/// Limits for a single test. A test that exceeds one of them fails.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TestLimits {
+    /// The maximum interpreter energy of a test.
+    pub energy:  Option<u64>,
+    /// The maximum time a test can run for.
+    pub timeout: Option<Duration>,
+}
+
+/// A test host that counts the energy charged by the metering instrumentation.
+struct RunnerHost {
+    inner:      TestHost,
+    energy:     u64,
+    randomized: bool,
+    covered:    BTreeSet<u32>,
+    limits:     TestLimits,
+    start:      Instant,
+}
+
+impl Host<ArtifactNamedImport> for RunnerHost {
+    type Interrupt = <TestHost as Host<ArtifactNamedImport>>::Interrupt;
+
+    fn tick_initial_memory(&mut self, num_pages: u32) -> RunResult<()> {
+        self.inner.tick_initial_memory(num_pages)
+    }
+
+    fn call(
+        &mut self,
+        f: &ArtifactNamedImport,
+        memory: &mut Vec<u8>,
+        stack: &mut RuntimeStack,
+    ) -> RunResult<Option<Self::Interrupt>> {
+        if f.get_mod_name() == COVERAGE_MODULE {
+            self.covered.insert(unsafe { stack.pop_u32() });
+            return Ok(None);
+        }
+        if f.get_mod_name() != "concordium_metering" {
+            if f.get_item_name() == "get_random" {
+                self.randomized = true;
+            }
+            return self.inner.call(f, memory, stack);
+        }
+        match f.get_item_name() {
+            "account_energy" => {
+                self.energy += unsafe { stack.pop_u64() };
+                if let Some(limit) = self.limits.energy {
+                    if self.energy > limit {
+                        bail!("Test exceeded the energy limit of {}.", limit);
+                    }
+                }
+                // Energy is charged for each block of instructions, so this
+                // also stops tests that loop forever.
+                if let Some(timeout) = self.limits.timeout {
+                    if self.start.elapsed() > timeout {
+                        bail!("Test exceeded the timeout of {} s.", timeout.as_secs_f64());
+                    }
+                }
+            }
+            // The number of pages to allocate is left on the stack as the
+            // result.
+            "account_memory" | "track_call" | "track_return" => {}
+            name => bail!("Unsupported metering function '{}'.", name),
+        }
+        Ok(None)
+    }
+}
+
+/// The result of running a single test.
+pub struct TestResult {
+    /// The error if the test failed.
+    pub error:      Option<String>,
+    /// Whether the test used randomness.
+    pub randomized: bool,
+    /// The interpreter energy used by the test, until it failed if it did.
+    pub energy:     u64,
+    /// The counters of the functions the test executed, if the module is
+    /// instrumented for coverage.
+    pub covered:    BTreeSet<u32>,
+}
+
+/// The instantiated test module.
+pub struct TestRunner {
+    artifact:  Artifact<ArtifactNamedImport, CompiledFunction>,
+    seed:      u64,
+    limits:    TestLimits,
+    /// The names of the tests in the module.
+    pub tests: Vec<String>,
+}
+
+impl TestRunner {
+    pub fn new(wasm: &[u8], seed: u64, limits: TestLimits) -> anyhow::Result<Self> {
+        let (runner, _) = Self::instantiate(wasm, seed, limits, None)?;
+        Ok(runner)
+    }
+
+    /// Instantiate the test module with a counter in each function of the
+    /// crate with the given name. Returns the runner together with the names
+    /// of the measured functions, which the counters in
+    /// [`TestResult::covered`] are positions in.
+    pub fn with_coverage(
+        wasm: &[u8],
+        seed: u64,
+        limits: TestLimits,
+        crate_name: &str,
+    ) -> anyhow::Result<(Self, Vec<String>)> {
+        Self::instantiate(wasm, seed, limits, Some(crate_name))
+    }
+
+    fn instantiate(
+        wasm: &[u8],
+        seed: u64,
+        limits: TestLimits,
+        coverage_of: Option<&str>,
+    ) -> anyhow::Result<(Self, Vec<String>)> {
+        let skeleton =
+            parse_skeleton(wasm).context("Could not parse the skeleton of the test module.")?;
+        let mut module = validate_module(&TestHost::new(SmallRng::seed_from_u64(seed)), &skeleton)
+            .context("Could not validate the test module.")?;
+        // The counters are injected before the metering, so that they are
+        // charged for like any other instructions.
+        let functions = match coverage_of {
+            Some(crate_name) => test_coverage::instrument(&skeleton, &mut module, crate_name)?,
+            None => Vec::new(),
+        };
+        module
+            .inject_metering()
+            .context("Could not inject the metering into the test module.")?;
+        let artifact = module
+            .compile::<ArtifactNamedImport>()
+            .context("Could not compile the test module.")?;
+        let runner = TestRunner {
+            artifact,
+            seed,
+            limits,
+            tests: test_exports(wasm)?
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect(),
+        };
+        Ok((runner, functions))
+    }
+
+    /// Run the test with the given name. Each test gets a random number
+    /// generator seeded with the seed of the runner.
+    pub fn run(&self, name: &str) -> TestResult {
+        let mut host = RunnerHost {
+            inner:      TestHost::new(SmallRng::seed_from_u64(self.seed)),
+            energy:     0,
+            randomized: false,
+            covered:    BTreeSet::new(),
+            limits:     self.limits,
+            start:      Instant::now(),
+        };
+        let error = self
+            .artifact
+            .run(&mut host, &format!("{}{}", TEST_EXPORT_PREFIX, name), &[])
+            .err()
+            .map(|error| error.to_string());
+        TestResult {
+            error,
+            randomized: host.randomized,
+            energy: host.energy,
+            covered: host.covered,
+        }
+    }
+}
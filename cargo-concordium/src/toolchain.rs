@@ -0,0 +1,133 @@
+//! Pinning of the toolchain used to build a contract.
+//!
+//! A project can pin the exact versions of rustc, the Wasm target and
+//! concordium-std it is built with in its `Cargo.toml`:
+//!
+//! ```toml
+//! [package.metadata.concordium.toolchain]
+//! rustc = "1.62.0"
+//! wasm-target = "wasm32-unknown-unknown"
+//! concordium-std = "5.1.0"
+//! ```
+//!
+//! `cargo concordium build --locked-toolchain` refuses to build if the
+//! environment deviates from the pins, so that modules are only produced by
+//! the toolchain the project was reviewed and verified with.
+use crate::{build::PackageOptions, verify::command_output};
+use anyhow::{bail, Context};
+use cargo_metadata::Package;
+use serde::Deserialize;
+use std::process::Command;
+
+/// The versions pinned by a project. Versions that are not given are not
+/// checked.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ToolchainPins {
+    /// The version of rustc, as shown by `rustc --version`, e.g., `1.62.0`.
+    pub rustc:          Option<String>,
+    /// The Wasm target that must be installed.
+    pub wasm_target:    Option<String>,
+    /// The version of concordium-std that the package must resolve to.
+    pub concordium_std: Option<String>,
+}
+
+/// Read the pins of the package from its `Cargo.toml`.
+fn read_pins(package: &Package) -> anyhow::Result<ToolchainPins> {
+    let pins = package
+        .metadata
+        .get("concordium")
+        .and_then(|concordium| concordium.get("toolchain"))
+        .with_context(|| {
+            format!(
+                "The package '{}' does not pin a toolchain. Add a \
+                 [package.metadata.concordium.toolchain] table to its Cargo.toml.",
+                package.name
+            )
+        })?;
+    serde_json::from_value(pins.clone()).context("Could not parse the pinned toolchain.")
+}
+
+/// Create a command that runs in the directory of the package, so that the
+/// toolchain selected by rustup for that directory is used.
+fn command_in_package(program: &str, package_options: &PackageOptions) -> Command {
+    let mut command = Command::new(program);
+    if let Some(dir) = package_options.directory() {
+        command.current_dir(dir);
+    }
+    command
+}
+
+/// The version of the rustc that cargo uses, e.g., `1.62.0` for
+/// `rustc 1.62.0 (a8314ef7d 2022-06-27)`.
+pub fn rustc_version(package_options: &PackageOptions) -> anyhow::Result<String> {
+    let output = command_output(command_in_package("rustc", package_options).arg("--version"))?;
+    output
+        .split_whitespace()
+        .nth(1)
+        .map(String::from)
+        .with_context(|| format!("Unexpected output of rustc --version: {}", output))
+}
+
+/// The sysroot of the rustc that cargo uses, which contains the sources of the
+/// standard library.
+pub fn rustc_sysroot(package_options: &PackageOptions) -> anyhow::Result<String> {
+    command_output(command_in_package("rustc", package_options).args(&["--print", "sysroot"]))
+}
+
+/// Check that the environment matches the toolchain pinned by the selected
+/// package. Fails with a list of the deviations otherwise.
+pub fn check_locked_toolchain(package_options: &PackageOptions) -> anyhow::Result<()> {
+    let metadata = package_options.metadata(false)?;
+    let pins = read_pins(package_options.package(&metadata)?)?;
+    let mut deviations = Vec::new();
+
+    if let Some(pinned) = &pins.rustc {
+        let actual =
+            rustc_version(package_options).context("Could not determine the version of rustc.")?;
+        if &actual != pinned {
+            deviations.push(format!("rustc is {}, but {} is pinned.", actual, pinned));
+        }
+    }
+
+    if let Some(target) = &pins.wasm_target {
+        let installed = command_output(command_in_package("rustup", package_options).args(&[
+            "target",
+            "list",
+            "--installed",
+        ]))
+        .context("Could not list the installed targets using rustup.")?;
+        if !installed.lines().any(|line| line.trim() == target) {
+            deviations.push(format!(
+                "The target {} is not installed. Install it with `rustup target add {}`.",
+                target, target
+            ));
+        }
+    }
+
+    if let Some(pinned) = &pins.concordium_std {
+        let resolved = metadata
+            .packages
+            .iter()
+            .filter(|package| package.name == "concordium-std")
+            .map(|package| package.version.to_string())
+            .collect::<Vec<_>>();
+        if resolved.is_empty() {
+            deviations.push("The package does not depend on concordium-std.".to_string());
+        } else if resolved.iter().any(|version| version != pinned) {
+            deviations.push(format!(
+                "concordium-std resolves to {}, but {} is pinned.",
+                resolved.join(", "),
+                pinned
+            ));
+        }
+    }
+
+    if !deviations.is_empty() {
+        bail!(
+            "The environment deviates from the pinned toolchain:\n  - {}",
+            deviations.join("\n  - ")
+        );
+    }
+    Ok(())
+}
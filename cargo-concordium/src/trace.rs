@@ -0,0 +1,666 @@
+//! Recording of execution traces and stepping through them after the fact.
+//!
+//! A trace consists of a step for each host function call of an execution,
+//! with the energy at that point, the changes to the linear memory since the
+//! previous step and the changes to the contract state made by the call. The
+//! memory at any step is reconstructed by replaying the changes from the start,
+//! which allows stepping both forwards and backwards through an execution.
+//!
+//! State changes are identified by the keys they concern. Host functions refer
+//! to entries and iterators by identifiers, so the keys of the entries and the
+//! prefixes of the iterators are tracked from the calls that created them.
+//!
+//! Host function calls can also be logged as they happen, with their arguments
+//! and results, which is less detailed but does not require a replay.
+use crate::profile::{host_function_name, HostObserver};
+use ansi_term::Style;
+use anyhow::{bail, Context};
+use concordium_smart_contract_engine::v1::{
+    CommonFunc, ImportFunc, ProcessedImports, ReceiveOnlyFunc,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{BufRead, Write},
+    path::Path,
+};
+
+/// Runs of unchanged bytes shorter than this are included in a memory change
+/// to keep the number of changes down.
+const MAX_GAP: usize = 8;
+
+/// A contiguous change to the linear memory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemoryChange {
+    pub offset: usize,
+    /// The new contents, hex encoded.
+    pub bytes:  String,
+}
+
+/// A change to the contract state made by a host function call. Keys,
+/// prefixes and data are hex encoded.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum StateChange {
+    /// An entry was created, or emptied if it existed.
+    Create { key: String },
+    /// Data was written to an entry at the given offset.
+    Write {
+        key:    String,
+        offset: u32,
+        data:   String,
+    },
+    /// An entry was resized.
+    Resize { key: String, size: u32 },
+    /// An entry was deleted.
+    Delete { key: String },
+    /// All entries with the prefix were deleted.
+    DeletePrefix { prefix: String },
+    /// The entry an iterator over the prefix was at was deleted.
+    DeleteAtIterator { prefix: String },
+}
+
+impl std::fmt::Display for StateChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateChange::Create { key } => write!(f, "create {}", key),
+            StateChange::Write { key, offset, data } => {
+                write!(f, "write {} at offset {}: {}", key, offset, data)
+            }
+            StateChange::Resize { key, size } => write!(f, "resize {} to {} B", key, size),
+            StateChange::Delete { key } => write!(f, "delete {}", key),
+            StateChange::DeletePrefix { prefix } => write!(f, "delete prefix {}", prefix),
+            StateChange::DeleteAtIterator { prefix } => {
+                write!(f, "delete the entry of an iterator over prefix {}", prefix)
+            }
+        }
+    }
+}
+
+/// A single host function call.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceStep {
+    /// The host function that was called.
+    pub function:         String,
+    /// The interpreter energy remaining before the call.
+    pub remaining_energy: u64,
+    /// The interpreter energy spent by the call.
+    pub energy:           u64,
+    /// The size of the linear memory when the call was made.
+    pub memory_size:      usize,
+    /// Changes to the memory since the previous step.
+    pub memory_changes:   Vec<MemoryChange>,
+    /// Changes to the state made by the call.
+    #[serde(default)]
+    pub state_changes:    Vec<StateChange>,
+}
+
+/// A recorded execution.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionTrace {
+    pub contract_name: String,
+    pub entrypoint:    String,
+    /// A description of how the execution ended.
+    pub outcome:       String,
+    pub steps:         Vec<TraceStep>,
+}
+
+impl ExecutionTrace {
+    /// Write the trace to a file.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context("Unable to create directory for the trace.")?;
+        }
+        fs::write(path, serde_json::to_vec(self)?).context("Could not write the trace.")
+    }
+
+    /// Read a trace from a file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let bytes = fs::read(path).context("Could not read the trace.")?;
+        serde_json::from_slice(&bytes).context("Could not parse the trace.")
+    }
+
+    /// Reconstruct the memory at the given step by replaying the changes of
+    /// all steps up to and including it.
+    pub fn memory_at(&self, step: usize) -> anyhow::Result<Vec<u8>> {
+        let mut memory = Vec::new();
+        for s in self.steps.iter().take(step + 1) {
+            memory.resize(s.memory_size, 0);
+            for change in s.memory_changes.iter() {
+                let bytes =
+                    hex::decode(&change.bytes).context("Invalid memory change in trace.")?;
+                let end = change.offset + bytes.len();
+                if end > memory.len() {
+                    bail!("Memory change in trace is out of bounds.");
+                }
+                memory[change.offset..end].copy_from_slice(&bytes);
+            }
+        }
+        Ok(memory)
+    }
+}
+
+/// Compute the changes from the old to the new memory. Memory that is not
+/// present in the old memory is considered to be zero, since that is how
+/// memory is initialized when it grows.
+fn memory_changes(old: &[u8], new: &[u8]) -> Vec<MemoryChange> {
+    let unchanged = |i: usize| old.get(i).copied().unwrap_or(0) == new[i];
+    let mut changes = Vec::new();
+    let mut i = 0;
+    while i < new.len() {
+        if unchanged(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let mut end = i + 1;
+        let mut gap = 0;
+        while end < new.len() && gap < MAX_GAP {
+            if unchanged(end) {
+                gap += 1;
+            } else {
+                gap = 0;
+            }
+            end += 1;
+        }
+        let end = end - gap;
+        changes.push(MemoryChange {
+            offset: start,
+            bytes:  hex::encode(&new[start..end]),
+        });
+        i = end;
+    }
+    changes
+}
+
+/// A state operation whose arguments have been observed and whose result is
+/// awaited.
+enum PendingOperation {
+    /// A lookup or creation of the entry with the key, hex encoded.
+    Entry { key: String, create: bool },
+    /// The creation of an iterator over the prefix, hex encoded.
+    Iterator { prefix: String },
+    /// A change that is made if the result satisfies the condition.
+    Change {
+        change:    StateChange,
+        succeeded: fn(u64) -> bool,
+    },
+}
+
+/// An observer that records a step for each host function call.
+#[derive(Default)]
+pub struct TraceRecorder {
+    memory:    Vec<u8>,
+    steps:     Vec<TraceStep>,
+    /// The keys of the entries by their identifiers.
+    entries:   BTreeMap<u64, String>,
+    /// The prefixes of the iterators by their identifiers.
+    iterators: BTreeMap<u64, String>,
+    pending:   Option<PendingOperation>,
+}
+
+impl TraceRecorder {
+    /// Finish the recording.
+    pub fn into_trace(
+        self,
+        contract_name: &str,
+        entrypoint: &str,
+        outcome: String,
+    ) -> ExecutionTrace {
+        ExecutionTrace {
+            contract_name: contract_name.to_string(),
+            entrypoint: entrypoint.to_string(),
+            outcome,
+            steps: self.steps,
+        }
+    }
+}
+
+impl HostObserver for TraceRecorder {
+    fn initial_memory(&mut self, _energy: u64) {}
+
+    fn before_call(&mut self, f: &ProcessedImports, memory: &[u8], remaining_energy: u64) {
+        let changes = memory_changes(&self.memory, memory);
+        self.memory.clear();
+        self.memory.extend_from_slice(memory);
+        self.steps.push(TraceStep {
+            function: format!("{:?}", f.tag),
+            remaining_energy,
+            energy: 0,
+            memory_size: memory.len(),
+            memory_changes: changes,
+            state_changes: Vec::new(),
+        });
+    }
+
+    fn observes_arguments(&self) -> bool { true }
+
+    fn arguments(&mut self, f: &ProcessedImports, memory: &[u8], arguments: &[u64]) {
+        let arg = |i: usize| arguments.get(i).copied().unwrap_or_default();
+        let bytes = |start: u64, length: u64| match memory_bytes(memory, start, length) {
+            Some(bytes) => hex::encode(bytes),
+            None => "<out of bounds>".to_string(),
+        };
+        let entry = |entries: &BTreeMap<u64, String>, entry: u64| {
+            entries
+                .get(&entry)
+                .cloned()
+                .unwrap_or_else(|| format!("<entry {}>", entry))
+        };
+        self.pending = match f.tag {
+            ImportFunc::Common(CommonFunc::StateLookupEntry) => Some(PendingOperation::Entry {
+                key:    bytes(arg(0), arg(1)),
+                create: false,
+            }),
+            ImportFunc::Common(CommonFunc::StateCreateEntry) => Some(PendingOperation::Entry {
+                key:    bytes(arg(0), arg(1)),
+                create: true,
+            }),
+            ImportFunc::Common(CommonFunc::StateIteratePrefix) => {
+                Some(PendingOperation::Iterator {
+                    prefix: bytes(arg(0), arg(1)),
+                })
+            }
+            // Deletions return 2 if something was deleted.
+            ImportFunc::Common(CommonFunc::StateDeleteEntry) => Some(PendingOperation::Change {
+                change:    StateChange::Delete {
+                    key: bytes(arg(0), arg(1)),
+                },
+                succeeded: |result| result as u32 == 2,
+            }),
+            ImportFunc::Common(CommonFunc::StateDeletePrefix) => Some(PendingOperation::Change {
+                change:    StateChange::DeletePrefix {
+                    prefix: bytes(arg(0), arg(1)),
+                },
+                succeeded: |result| result as u32 == 2,
+            }),
+            ImportFunc::Common(CommonFunc::StateIteratorDelete) => Some(PendingOperation::Change {
+                change:    StateChange::DeleteAtIterator {
+                    prefix: self
+                        .iterators
+                        .get(&arg(0))
+                        .cloned()
+                        .unwrap_or_else(|| format!("<iterator {}>", arg(0))),
+                },
+                succeeded: |result| result as u32 == 2,
+            }),
+            // A write returns the number of bytes written, or `u32::MAX` if
+            // the entry no longer exists.
+            ImportFunc::Common(CommonFunc::StateEntryWrite) => Some(PendingOperation::Change {
+                change:    StateChange::Write {
+                    key:    entry(&self.entries, arg(0)),
+                    offset: arg(3) as u32,
+                    data:   bytes(arg(1), arg(2)),
+                },
+                succeeded: |result| result as u32 != u32::MAX,
+            }),
+            // A resize returns 1 if it succeeded.
+            ImportFunc::Common(CommonFunc::StateEntryResize) => Some(PendingOperation::Change {
+                change:    StateChange::Resize {
+                    key:  entry(&self.entries, arg(0)),
+                    size: arg(1) as u32,
+                },
+                succeeded: |result| result as u32 == 1,
+            }),
+            _ => None,
+        };
+    }
+
+    fn returned(&mut self, _f: &ProcessedImports, result: u64) {
+        // Lookups, creations and iterations return `u64::MAX` if they failed.
+        let change = match self.pending.take() {
+            Some(PendingOperation::Entry { key, create }) if result != u64::MAX => {
+                self.entries.insert(result, key.clone());
+                if create {
+                    Some(StateChange::Create { key })
+                } else {
+                    None
+                }
+            }
+            Some(PendingOperation::Iterator { prefix }) if result != u64::MAX => {
+                self.iterators.insert(result, prefix);
+                None
+            }
+            Some(PendingOperation::Change { change, succeeded }) if succeeded(result) => {
+                Some(change)
+            }
+            _ => None,
+        };
+        if let (Some(change), Some(step)) = (change, self.steps.last_mut()) {
+            step.state_changes.push(change);
+        }
+    }
+
+    fn after_call(&mut self, _f: &ProcessedImports, energy: u64) {
+        // The result is not observed if the call was interrupted.
+        self.pending = None;
+        if let Some(step) = self.steps.last_mut() {
+            step.energy = energy;
+        }
+    }
+}
+
+/// Data longer than this is truncated in the log of host calls.
+const MAX_LOGGED_BYTES: usize = 64;
+
+/// An observer that writes a line for each host function call as it happens,
+/// with its arguments, the keys and data they refer to, its result and the
+/// energy. The calls injected into the module for metering are not logged.
+pub struct HostCallLogger {
+    out:       Box<dyn Write>,
+    /// The number of host function calls logged so far.
+    calls:     usize,
+    /// The description of the current call, if it is logged.
+    current:   Option<String>,
+    /// The interpreter energy remaining before the current call.
+    remaining: u64,
+    /// The first error writing the log, which is reported when finishing.
+    error:     Option<std::io::Error>,
+}
+
+impl HostCallLogger {
+    /// Log to the given file, or to stderr if there is none.
+    pub fn new(path: Option<&Path>) -> anyhow::Result<Self> {
+        let out: Box<dyn Write> = match path {
+            Some(path) => Box::new(std::io::BufWriter::new(
+                fs::File::create(path).with_context(|| {
+                    format!("Could not create the trace file {}.", path.display())
+                })?,
+            )),
+            None => Box::new(std::io::stderr()),
+        };
+        Ok(HostCallLogger {
+            out,
+            calls: 0,
+            current: None,
+            remaining: 0,
+            error: None,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.error.is_none() {
+            if let Err(e) = writeln!(self.out, "{}", line) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    /// Finish the log with a description of the outcome.
+    pub fn finish(mut self, outcome: &str) -> anyhow::Result<usize> {
+        self.write_line(&format!(
+            "The execution {} after {} host calls.",
+            outcome, self.calls
+        ));
+        if let Some(e) = self.error.take() {
+            return Err(e).context("Could not write the trace.");
+        }
+        self.out.flush().context("Could not write the trace.")?;
+        Ok(self.calls)
+    }
+}
+
+/// A region of the memory given by arguments of a host function, if it is
+/// within the memory.
+fn memory_bytes(memory: &[u8], start: u64, length: u64) -> Option<&[u8]> {
+    let start = start as u32 as usize;
+    let length = length as u32 as usize;
+    memory.get(start..start.saturating_add(length))
+}
+
+/// Hex encode a region of the memory, truncated to [`MAX_LOGGED_BYTES`].
+fn memory_region(memory: &[u8], start: u64, length: u64) -> String {
+    match memory_bytes(memory, start, length) {
+        Some(bytes) if bytes.len() > MAX_LOGGED_BYTES => {
+            format!(
+                "{}... ({} B)",
+                hex::encode(&bytes[..MAX_LOGGED_BYTES]),
+                bytes.len()
+            )
+        }
+        Some(bytes) => hex::encode(bytes),
+        None => "<out of bounds>".to_string(),
+    }
+}
+
+/// Describe the arguments of a host function listed in
+/// [`host_function_arity`](crate::profile::host_function_arity).
+fn describe_arguments(f: &ProcessedImports, memory: &[u8], arguments: &[u64]) -> String {
+    let arg = |i: usize| arguments.get(i).copied().unwrap_or_default();
+    let int = |i: usize| arg(i) as u32;
+    match f.tag {
+        ImportFunc::Common(CommonFunc::StateLookupEntry)
+        | ImportFunc::Common(CommonFunc::StateCreateEntry)
+        | ImportFunc::Common(CommonFunc::StateDeleteEntry) => {
+            format!("key = {}", memory_region(memory, arg(0), arg(1)))
+        }
+        ImportFunc::Common(CommonFunc::StateDeletePrefix)
+        | ImportFunc::Common(CommonFunc::StateIteratePrefix) => {
+            format!("prefix = {}", memory_region(memory, arg(0), arg(1)))
+        }
+        ImportFunc::Common(CommonFunc::StateIteratorNext)
+        | ImportFunc::Common(CommonFunc::StateIteratorDelete)
+        | ImportFunc::Common(CommonFunc::StateIteratorKeySize) => format!("iterator = {}", arg(0)),
+        ImportFunc::Common(CommonFunc::StateIteratorKeyRead) => {
+            format!(
+                "iterator = {}, length = {}, offset = {}",
+                arg(0),
+                int(2),
+                int(3)
+            )
+        }
+        ImportFunc::Common(CommonFunc::StateEntryRead) => {
+            format!(
+                "entry = {}, length = {}, offset = {}",
+                arg(0),
+                int(2),
+                int(3)
+            )
+        }
+        ImportFunc::Common(CommonFunc::StateEntryWrite) => format!(
+            "entry = {}, offset = {}, data = {}",
+            arg(0),
+            int(3),
+            memory_region(memory, arg(1), arg(2))
+        ),
+        ImportFunc::Common(CommonFunc::StateEntrySize) => format!("entry = {}", arg(0)),
+        ImportFunc::Common(CommonFunc::StateEntryResize) => {
+            format!("entry = {}, new size = {}", arg(0), int(1))
+        }
+        ImportFunc::Common(CommonFunc::LogEvent) => {
+            format!("event = {}", memory_region(memory, arg(0), arg(1)))
+        }
+        ImportFunc::ReceiveOnly(ReceiveOnlyFunc::Invoke) => {
+            let kind = match int(0) {
+                0 => "transfer".to_string(),
+                1 => "call".to_string(),
+                2 => "query account balance".to_string(),
+                3 => "query contract balance".to_string(),
+                4 => "query exchange rates".to_string(),
+                tag => format!("unknown ({})", tag),
+            };
+            format!(
+                "kind = {}, payload = {}",
+                kind,
+                memory_region(memory, arg(1), arg(2))
+            )
+        }
+        ImportFunc::ReceiveOnly(ReceiveOnlyFunc::Upgrade) => {
+            format!("module = {}", memory_region(memory, arg(0), 32))
+        }
+        _ => String::new(),
+    }
+}
+
+impl HostObserver for HostCallLogger {
+    fn initial_memory(&mut self, energy: u64) {
+        self.write_line(&format!("initial memory (spent {})", energy));
+    }
+
+    fn before_call(&mut self, f: &ProcessedImports, _memory: &[u8], remaining_energy: u64) {
+        self.current = match f.tag {
+            ImportFunc::ChargeEnergy
+            | ImportFunc::TrackCall
+            | ImportFunc::TrackReturn
+            | ImportFunc::ChargeMemoryAlloc => None,
+            _ => Some(format!("{}()", host_function_name(f))),
+        };
+        self.remaining = remaining_energy;
+    }
+
+    fn observes_arguments(&self) -> bool { true }
+
+    fn arguments(&mut self, f: &ProcessedImports, memory: &[u8], arguments: &[u64]) {
+        if let Some(current) = &mut self.current {
+            current.pop();
+            current.push_str(&describe_arguments(f, memory, arguments));
+            current.push(')');
+        }
+    }
+
+    fn returned(&mut self, _f: &ProcessedImports, result: u64) {
+        if let Some(current) = &mut self.current {
+            current.push_str(&format!(" -> {}", result));
+        }
+    }
+
+    fn after_call(&mut self, _f: &ProcessedImports, energy: u64) {
+        if let Some(current) = self.current.take() {
+            let line = format!(
+                "[{}] {} (remaining energy {}, spent {})",
+                self.calls, current, self.remaining, energy
+            );
+            self.write_line(&line);
+            self.calls += 1;
+        }
+    }
+}
+
+/// Print a summary of a step.
+fn print_step(trace: &ExecutionTrace, index: usize) {
+    let step = &trace.steps[index];
+    let changed: usize = step.memory_changes.iter().map(|c| c.bytes.len() / 2).sum();
+    println!(
+        "{} {} (remaining energy {}, spent {}, {} B of memory changed since the previous step)",
+        Style::new()
+            .bold()
+            .paint(format!("[{}/{}]", index, trace.steps.len() - 1)),
+        step.function,
+        step.remaining_energy,
+        step.energy,
+        changed
+    );
+    for change in step.state_changes.iter() {
+        println!("  state: {}", change);
+    }
+}
+
+/// Print the given range of memory as hex, 16 bytes per line.
+fn print_memory(memory: &[u8], offset: usize, len: usize) {
+    let end = memory.len().min(offset.saturating_add(len));
+    if offset >= end {
+        println!("The range is outside the memory of {} B.", memory.len());
+        return;
+    }
+    for (i, chunk) in memory[offset..end].chunks(16).enumerate() {
+        println!("{:08x}  {}", offset + 16 * i, hex::encode(chunk));
+    }
+}
+
+const REPLAY_HELP: &str = "Commands:
+  n, <enter>       go to the next step
+  p                go to the previous step
+  g <step>         go to the given step
+  c                show the memory changes of the current step
+  m <offset> <len> show memory at the current step (offset may be hex with 0x)
+  q                quit";
+
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn print_header(trace: &ExecutionTrace) {
+    println!(
+        "Trace of '{}.{}' with {} steps, which {}.",
+        trace.contract_name,
+        trace.entrypoint,
+        trace.steps.len(),
+        trace.outcome
+    );
+}
+
+/// Print all steps of a trace.
+pub fn print_trace(trace: &ExecutionTrace) {
+    print_header(trace);
+    for index in 0..trace.steps.len() {
+        print_step(trace, index);
+    }
+}
+
+/// Interactively step forwards and backwards through a trace.
+pub fn replay(trace: &ExecutionTrace) -> anyhow::Result<()> {
+    print_header(trace);
+    if trace.steps.is_empty() {
+        return Ok(());
+    }
+    println!("{}", REPLAY_HELP);
+    let stdin = std::io::stdin();
+    let mut current = 0;
+    print_step(trace, current);
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words.as_slice() {
+            [] | ["n"] => {
+                if current + 1 < trace.steps.len() {
+                    current += 1;
+                } else {
+                    println!("This is the last step.");
+                }
+            }
+            ["p"] => {
+                if current > 0 {
+                    current -= 1;
+                } else {
+                    println!("This is the first step.");
+                }
+            }
+            ["g", step] => match parse_number(step) {
+                Some(step) if step < trace.steps.len() => current = step,
+                _ => println!("Invalid step."),
+            },
+            ["c"] => {
+                for change in trace.steps[current].memory_changes.iter() {
+                    println!("{:08x}  {}", change.offset, change.bytes);
+                }
+                continue;
+            }
+            ["m", offset, len] => {
+                match (parse_number(offset), parse_number(len)) {
+                    (Some(offset), Some(len)) => {
+                        print_memory(&trace.memory_at(current)?, offset, len)
+                    }
+                    _ => println!("Invalid offset or length."),
+                }
+                continue;
+            }
+            ["q"] => return Ok(()),
+            _ => {
+                println!("{}", REPLAY_HELP);
+                continue;
+            }
+        }
+        print_step(trace, current);
+    }
+}
@@ -0,0 +1,212 @@
+//! Rehearsal of upgrading a contract instance to a new module.
+//!
+//! A rehearsal starts from the state of an instance of the old module. It
+//! optionally calls the upgrade entrypoint of the old module and checks that it
+//! requests an upgrade to the new module and then completes, as the call
+//! continues in the old module after the upgrade. It then calls the migration
+//! entrypoint of the new module on the resulting state, and finally checks the
+//! migrated state by calling view entrypoints of the new module.
+use crate::{
+    context::{optional_ccd, ReceiveContextV1Opt},
+    expr::{self, Env},
+    schema_types::{encode_parameter, ContractSchemaView},
+    sim::{CallResult, Instance, Outcome, Simulator},
+};
+use ansi_term::{Color, Style};
+use anyhow::{bail, Context};
+use concordium_contracts_common::{Amount, ContractAddress, ModuleReference};
+use concordium_smart_contract_engine::{v1::trie::PersistentState, InterpreterEnergy};
+use serde::Deserialize;
+use serde_json::Value as Json;
+
+/// A call made during the rehearsal.
+#[derive(Deserialize)]
+pub struct RehearsalCall {
+    pub entrypoint: String,
+    pub parameter:  Option<Json>,
+    #[serde(default, deserialize_with = "optional_ccd")]
+    pub amount:     Option<Amount>,
+    /// A context to use instead of the context of the rehearsal.
+    pub context:    Option<ReceiveContextV1Opt>,
+}
+
+/// A check of the migrated state using a view entrypoint.
+#[derive(Deserialize)]
+pub struct RehearsalCheck {
+    pub entrypoint: String,
+    pub parameter:  Option<Json>,
+    /// An expression over `result`, which is bound to the return value
+    /// decoded using the schema. If absent, the call only has to succeed.
+    pub check:      Option<String>,
+}
+
+/// A description of an upgrade rehearsal.
+#[derive(Deserialize)]
+pub struct Rehearsal {
+    pub contract:  String,
+    /// The context used for all calls that do not specify one.
+    #[serde(default)]
+    pub context:   ReceiveContextV1Opt,
+    /// The call to the old module that triggers the upgrade.
+    pub upgrade:   Option<RehearsalCall>,
+    /// The call to the new module that migrates the state.
+    pub migration: Option<RehearsalCall>,
+    #[serde(default)]
+    pub checks:    Vec<RehearsalCheck>,
+}
+
+/// The modules and the state of the instance being upgraded.
+pub struct UpgradeInput<'a> {
+    pub old_module:     &'a [u8],
+    pub old_schema:     &'a ContractSchemaView<'a>,
+    pub new_module:     &'a [u8],
+    pub new_module_ref: ModuleReference,
+    pub new_schema:     &'a ContractSchemaView<'a>,
+    pub state:          PersistentState,
+    pub balance:        Amount,
+    pub energy:         InterpreterEnergy,
+}
+
+fn call(
+    sim: &Simulator,
+    schema: &ContractSchemaView,
+    rehearsal: &Rehearsal,
+    instance: &mut Instance,
+    call: &RehearsalCall,
+) -> anyhow::Result<CallResult> {
+    let parameter = encode_parameter(
+        call.parameter.as_ref(),
+        schema
+            .receive
+            .get(call.entrypoint.as_str())
+            .and_then(|f| f.parameter),
+    )?;
+    let result = sim.update(
+        instance,
+        &call.entrypoint,
+        call.context
+            .clone()
+            .unwrap_or_else(|| rehearsal.context.clone()),
+        call.amount.unwrap_or_else(Amount::zero),
+        &parameter,
+    )?;
+    eprintln!(
+        "  '{}' {} using {} interpreter energy.",
+        call.entrypoint, result.outcome, result.energy_used
+    );
+    Ok(result)
+}
+
+/// Run the rehearsal. Returns the migrated instance if the upgrade and
+/// migration succeeded, and whether all checks passed.
+pub fn rehearse_upgrade(
+    rehearsal: &Rehearsal,
+    input: UpgradeInput,
+) -> anyhow::Result<(Instance, bool)> {
+    let mut instance = Instance {
+        contract_name: rehearsal.contract.clone(),
+        address:       ContractAddress::new(0, 0),
+        state:         input.state,
+        balance:       input.balance,
+    };
+
+    if let Some(upgrade) = &rehearsal.upgrade {
+        eprintln!("{}", Style::new().bold().paint("Upgrade:"));
+        let mut sim = Simulator::new(input.old_module, input.energy)
+            .context("Could not load the old module.")?;
+        sim.allow_upgrade(input.new_module_ref);
+        let result = call(&sim, input.old_schema, rehearsal, &mut instance, upgrade)?;
+        match (result.outcome, result.upgraded) {
+            (Outcome::Success { .. }, Some(_)) => {}
+            (Outcome::Success { .. }, None) => {
+                bail!("The upgrade entrypoint succeeded without requesting an upgrade.")
+            }
+            (Outcome::Upgrade(module_ref), _) => bail!(
+                "The old module requested an upgrade to {}, but the new module is {}.",
+                module_ref,
+                input.new_module_ref
+            ),
+            (outcome, Some(_)) => bail!(
+                "The upgrade entrypoint requested the upgrade, but then {}.",
+                outcome
+            ),
+            (outcome, None) => {
+                bail!(
+                    "The upgrade entrypoint {} instead of requesting an upgrade.",
+                    outcome
+                )
+            }
+        }
+    }
+
+    let sim =
+        Simulator::new(input.new_module, input.energy).context("Could not load the new module.")?;
+    if let Some(migration) = &rehearsal.migration {
+        eprintln!("{}", Style::new().bold().paint("Migration:"));
+        let outcome = call(&sim, input.new_schema, rehearsal, &mut instance, migration)?.outcome;
+        if !outcome.is_success() {
+            bail!("The migration {}.", outcome);
+        }
+    }
+
+    if rehearsal.checks.is_empty() {
+        return Ok((instance, true));
+    }
+    eprintln!("{}", Style::new().bold().paint("Checks:"));
+    let mut passed = true;
+    for check in rehearsal.checks.iter() {
+        let types = input.new_schema.receive.get(check.entrypoint.as_str());
+        let parameter =
+            encode_parameter(check.parameter.as_ref(), types.and_then(|f| f.parameter))?;
+        // Checks are made on a copy so that they cannot affect each other.
+        let result = sim.update(
+            &mut instance.clone(),
+            &check.entrypoint,
+            rehearsal.context.clone(),
+            Amount::zero(),
+            &parameter,
+        )?;
+        let description = check.check.as_deref().unwrap_or("succeeds");
+        let failure = match (result.outcome, &check.check) {
+            (Outcome::Success { .. }, None) => None,
+            (Outcome::Success { return_value, .. }, Some(expression)) => {
+                let return_type = types.and_then(|f| f.return_value).with_context(|| {
+                    format!(
+                        "A schema for the return value of '{}' is required.",
+                        check.entrypoint
+                    )
+                })?;
+                let result_json = return_type
+                    .to_json(&mut std::io::Cursor::new(&return_value[..]))
+                    .map_err(|_| anyhow::anyhow!("Could not decode the return value."))?;
+                let mut env = Env::default();
+                env.set_json("result", &result_json);
+                if expr::eval_bool(expression, &env)? {
+                    None
+                } else {
+                    Some(format!("returned {}", result_json))
+                }
+            }
+            (outcome, _) => Some(outcome.to_string()),
+        };
+        match failure {
+            None => eprintln!(
+                "  - '{}' {} ... {}",
+                check.entrypoint,
+                description,
+                Color::Green.bold().paint("ok")
+            ),
+            Some(failure) => {
+                passed = false;
+                eprintln!(
+                    "  - '{}' {} ... {}: {}",
+                    check.entrypoint,
+                    description,
+                    Color::Red.bold().paint("FAILED"),
+                    failure
+                );
+            }
+        }
+    }
+    Ok((instance, passed))
+}
@@ -0,0 +1,314 @@
+//! Links from modules to the source they were built from, and verification
+//! that a module is reproduced by rebuilding that source.
+//!
+//! When building with `--source-url` the URL of the git repository, the commit
+//! and the build settings are embedded into the module as a custom section.
+//! Anybody with the module can then clone the repository at that commit,
+//! rebuild it with the same settings, and check that the result is
+//! byte-for-byte identical to the module. The rebuild runs in a container of a
+//! pinned image, since the source is untrusted and the result only proves
+//! something if the build is hermetic.
+use crate::{
+    build::{get_custom_sections, module_reference, PackageOptions},
+    sbom::{rfc3339_from_unix, SbomFormat},
+    versions,
+};
+use ansi_term::Color;
+use anyhow::{ensure, Context};
+use concordium_smart_contract_engine::utils::WasmVersion;
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+/// Name of the custom section that contains the source link.
+pub const SOURCE_SECTION_NAME: &str = "concordium-source";
+
+/// The settings that affect the bytes of a built module.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildSettings {
+    /// The version of cargo-concordium that built the module.
+    pub cargo_concordium_version: String,
+    /// Either `V0` or `V1`.
+    pub contract_version:         String,
+    pub schema_embed:             bool,
+    pub sbom:                     Option<String>,
+    /// Whether the module was optimized using wasm-opt.
+    #[serde(default)]
+    pub optimize:                 bool,
+    /// The value of `SOURCE_DATE_EPOCH`, which affects the SBOM.
+    pub source_date_epoch:        Option<String>,
+    /// The workspace member that was built, if one was selected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package:                  Option<String>,
+    /// The extra arguments passed to `cargo build`.
+    pub cargo_args:               Vec<String>,
+}
+
+/// A link from a module to the source it was built from.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceLink {
+    /// The URL of the git repository.
+    pub source_url: String,
+    /// The commit that was built.
+    pub commit:     String,
+    /// The path of the package relative to the root of the repository.
+    pub path:       String,
+    pub build:      BuildSettings,
+}
+
+/// The result of verifying a module against its source.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Attestation {
+    pub module_reference: String,
+    pub source:           SourceLink,
+    /// Whether the rebuilt module is identical to the verified module.
+    pub verified:         bool,
+    /// The version of cargo-concordium that performed the verification.
+    pub verifier_version: String,
+    /// The container image the source was rebuilt in.
+    pub image:            String,
+    /// The version of rustc used for rebuilding.
+    pub rustc_version:    String,
+    pub time:             String,
+}
+
+/// Run a command and return its trimmed standard output if it succeeds.
+pub fn command_output(command: &mut Command) -> anyhow::Result<String> {
+    let output = command
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("Could not run {:?}.", command))?;
+    ensure!(output.status.success(), "{:?} failed.", command);
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Create the source link for building the package in the current directory
+/// with the given settings. The working tree must be clean, since uncommitted
+/// changes could not be reproduced from the commit.
+pub fn source_link(
+    source_url: String,
+    version: WasmVersion,
+    schema_embed: bool,
+    sbom: Option<SbomFormat>,
+    optimize: bool,
+    package_options: &PackageOptions,
+    cargo_args: &[String],
+) -> anyhow::Result<SourceLink> {
+    // Run git in the directory of the package, which is the one linked to.
+    let git = || {
+        let mut command = Command::new("git");
+        if let Some(dir) = package_options.directory() {
+            command.current_dir(dir);
+        }
+        command
+    };
+    let changes = command_output(git().args(&["status", "--porcelain"]))
+        .context("Embedding a source link requires the package to be in a git repository.")?;
+    ensure!(
+        changes.is_empty(),
+        "The working tree has uncommitted changes, which cannot be reproduced from the source \
+         link. Commit or stash them first."
+    );
+    let commit = command_output(git().args(&["rev-parse", "HEAD"]))?;
+    let path = command_output(git().args(&["rev-parse", "--show-prefix"]))?;
+    Ok(SourceLink {
+        source_url,
+        commit,
+        path,
+        build: BuildSettings {
+            cargo_concordium_version: env!("CARGO_PKG_VERSION").to_string(),
+            contract_version: versions::descriptor(version).name.to_string(),
+            schema_embed,
+            sbom: sbom.map(|format| format.to_string()),
+            optimize,
+            source_date_epoch: env::var("SOURCE_DATE_EPOCH").ok(),
+            package: package_options.package.clone(),
+            cargo_args: cargo_args.to_vec(),
+        },
+    })
+}
+
+/// Get the source link embedded in a module, if any.
+pub fn get_source_link(module: &[u8]) -> anyhow::Result<Option<SourceLink>> {
+    let sections = get_custom_sections(module)?;
+    match sections
+        .iter()
+        .find(|(name, _)| name == SOURCE_SECTION_NAME)
+    {
+        Some((_, contents)) => Ok(Some(
+            serde_json::from_slice(contents).context("Could not parse the source link.")?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Clone the source into `dir` and rebuild it with the recorded settings in a
+/// container of the given image. The build scripts and procedural macros of
+/// the source thus do not run on the host, and the toolchain is the one pinned
+/// by the image rather than whatever is installed locally. The clone is copied
+/// within the container so that the build does not write to it. Returns the
+/// bytes of the rebuilt versioned module.
+fn rebuild(link: &SourceLink, dir: &Path, image: &str) -> anyhow::Result<Vec<u8>> {
+    which::which("docker").context("Verifying the source requires docker to be installed.")?;
+    if dir.exists() {
+        fs::remove_dir_all(dir).context("Could not remove the previous verification directory.")?;
+    }
+    fs::create_dir_all(dir).context("Could not create the verification directory.")?;
+    let dir = fs::canonicalize(dir)?;
+    let source = dir.join("source");
+    eprintln!("Cloning {} at {}.", link.source_url, link.commit);
+    command_output(
+        Command::new("git")
+            .arg("clone")
+            .arg("--quiet")
+            .arg(&link.source_url)
+            .arg(&source),
+    )
+    .context("Could not clone the source.")?;
+    command_output(Command::new("git").arg("-C").arg(&source).args(&[
+        "checkout",
+        "--quiet",
+        &link.commit,
+    ]))
+    .context("Could not check out the commit.")?;
+
+    eprintln!("Rebuilding {} in {}.", link.commit, image);
+    let script = "set -e; cp -R /verifiable/source /build; cd \"/build/$1\"; shift; exec cargo \
+                  concordium build \"$@\"";
+    let mut command = Command::new("docker");
+    command
+        .args(&["run", "--rm", "--volume"])
+        .arg(format!("{}:/verifiable", dir.display()));
+    if let Some(epoch) = &link.build.source_date_epoch {
+        command
+            .arg("--env")
+            .arg(format!("SOURCE_DATE_EPOCH={}", epoch));
+    }
+    let status = command
+        .arg(image)
+        .args(&["sh", "-c", script, "sh", link.path.as_str()])
+        .args(&["--out", "/verifiable/module.wasm.v1"])
+        .args(build_args(link))
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("Could not run docker.")?;
+    ensure!(status.success(), "Rebuilding the source failed.");
+    fs::read(dir.join("module.wasm.v1")).context("Could not read the rebuilt module.")
+}
+
+/// The arguments of `cargo concordium build`, other than the output, that
+/// reproduce the settings recorded in the source link, ending with the extra
+/// cargo arguments.
+pub fn build_args(link: &SourceLink) -> Vec<String> {
+    let mut args = vec![
+        "--contract-version".to_string(),
+        link.build.contract_version.clone(),
+        "--source-url".to_string(),
+        link.source_url.clone(),
+    ];
+    if link.build.schema_embed {
+        args.push("--schema-embed".into());
+    }
+    if let Some(sbom) = &link.build.sbom {
+        args.extend(["--sbom".to_string(), sbom.clone()]);
+    }
+    if link.build.optimize {
+        args.push("--opt".into());
+    }
+    if let Some(package) = &link.build.package {
+        args.extend(["--package".to_string(), package.clone()]);
+    }
+    args.push("--".into());
+    args.extend_from_slice(&link.build.cargo_args);
+    args
+}
+
+/// Verify that the versioned module is reproduced by rebuilding the source it
+/// links to. If an expected module reference is given, the module must have
+/// that reference.
+pub fn verify_source(
+    versioned_module: &[u8],
+    module: &[u8],
+    expected_reference: Option<&str>,
+    image: &str,
+) -> anyhow::Result<Attestation> {
+    let module_ref = module_reference(versioned_module).to_string();
+    if let Some(expected) = expected_reference {
+        ensure!(
+            expected == module_ref,
+            "The module has reference {}, but {} was expected.",
+            module_ref,
+            expected
+        );
+    }
+    let link = get_source_link(module)?.context(
+        "The module does not contain a source link. Build it using `cargo concordium build \
+         --source-url <url>`.",
+    )?;
+    if link.build.cargo_concordium_version != env!("CARGO_PKG_VERSION") {
+        eprintln!(
+            "{}",
+            crate::WARNING_STYLE.paint(format!(
+                "Warning: The module was built with cargo-concordium {}, but this is version {}. \
+                 The rebuilt module might differ for that reason.",
+                link.build.cargo_concordium_version,
+                env!("CARGO_PKG_VERSION")
+            ))
+        );
+    }
+    let dir: PathBuf = env::temp_dir().join(format!("cargo-concordium-verify-{}", module_ref));
+    let rebuilt = rebuild(&link, &dir, image)?;
+    let verified = rebuilt == versioned_module;
+    let rustc_version = command_output(
+        Command::new("docker")
+            .args(&["run", "--rm", image])
+            .args(&["rustc", "--version"]),
+    )
+    .unwrap_or_else(|_| "unknown".to_string());
+    if verified {
+        eprintln!(
+            "{}",
+            Color::Green.paint(format!(
+                "The module {} is reproduced by building {} at {}.",
+                module_ref, link.source_url, link.commit
+            ))
+        );
+    } else {
+        let rebuilt_ref = module_reference(&rebuilt);
+        eprintln!(
+            "{}",
+            Color::Red.paint(format!(
+                "The rebuilt module has reference {}, which differs from {}. Building in a \
+                 different image than the module was built in, or absolute paths of the build \
+                 machine embedded in the module, can cause this.",
+                rebuilt_ref, module_ref
+            ))
+        );
+    }
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    if let Err(e) = fs::remove_dir_all(&dir) {
+        eprintln!(
+            "Could not remove the verification directory {}: {}",
+            dir.display(),
+            e
+        );
+    }
+    Ok(Attestation {
+        module_reference: module_ref,
+        source: link,
+        verified,
+        verifier_version: env!("CARGO_PKG_VERSION").to_string(),
+        image: image.to_string(),
+        rustc_version,
+        time: rfc3339_from_unix(secs),
+    })
+}
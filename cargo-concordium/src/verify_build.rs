@@ -0,0 +1,295 @@
+//! Verification that the local source reproduces a given module.
+//!
+//! `cargo concordium verify-build` rebuilds the package with the settings the
+//! reference module was built with and compares the result to the reference
+//! byte for byte. The settings are taken from the build recipe of a verifiable
+//! build or from the source link embedded in the reference, unless build
+//! arguments are given. If the modules differ, their sections and embedded
+//! schemas are compared to narrow down where the difference comes from.
+use crate::{
+    build::{get_custom_sections, module_reference, read_leb128_u32, PackageOptions},
+    reproducible::{self, BuildRecipe, RECIPE_SECTION_NAME},
+    schema_diff,
+    verify::{self, SourceLink},
+};
+use ansi_term::Color;
+use anyhow::{ensure, Context};
+use concordium_contracts_common::{from_bytes, schema::VersionedModuleSchema};
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs,
+    process::{Command, Stdio},
+};
+
+/// Name of the custom section that contains the embedded schema.
+const SCHEMA_SECTION_NAME: &str = "concordium-schema";
+
+/// How the reference module was built, as far as it is recorded in it.
+enum Settings {
+    Recipe(BuildRecipe),
+    SourceLink(SourceLink),
+    Unknown,
+}
+
+/// Get the settings recorded in the Wasm module of the reference.
+fn recorded_settings(module: &[u8]) -> anyhow::Result<Settings> {
+    let sections = get_custom_sections(module)?;
+    if let Some((_, contents)) = sections
+        .iter()
+        .find(|(name, _)| name == RECIPE_SECTION_NAME)
+    {
+        let recipe =
+            serde_json::from_slice(contents).context("Could not parse the build recipe.")?;
+        return Ok(Settings::Recipe(recipe));
+    }
+    match verify::get_source_link(module)? {
+        Some(link) => Ok(Settings::SourceLink(link)),
+        None => Ok(Settings::Unknown),
+    }
+}
+
+/// Rebuild the package with the current cargo-concordium executable, passing
+/// it the given build arguments. Returns the versioned module.
+fn rebuild(
+    package_options: &PackageOptions,
+    build_args: &[String],
+    source_date_epoch: Option<&str>,
+) -> anyhow::Result<Vec<u8>> {
+    let metadata = package_options.metadata(true)?;
+    let out_dir = metadata
+        .target_directory
+        .as_std_path()
+        .join("concordium")
+        .join("verify-build");
+    fs::create_dir_all(&out_dir).context("Could not create the directory for the rebuild.")?;
+    let out = out_dir.join("module.wasm");
+    if out.exists() {
+        fs::remove_file(&out).context("Could not remove the previously rebuilt module.")?;
+    }
+    let mut command = Command::new(env::current_exe().context("Could not find cargo-concordium.")?);
+    if let Some(dir) = package_options.directory() {
+        command.current_dir(dir);
+    }
+    command
+        .args(&["concordium", "build", "--out"])
+        .arg(&out)
+        .args(build_args);
+    if let Some(epoch) = source_date_epoch {
+        command.env("SOURCE_DATE_EPOCH", epoch);
+    }
+    let status = command
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()
+        .context("Could not run cargo-concordium.")?;
+    ensure!(status.success(), "Rebuilding the source failed.");
+    fs::read(&out).context("Could not read the rebuilt module.")
+}
+
+/// A section of a Wasm module.
+struct Section {
+    /// The kind of the section, and the name for custom sections.
+    label: String,
+    size:  usize,
+    hash:  Vec<u8>,
+}
+
+/// Split a Wasm module into its sections.
+fn sections(wasm: &[u8]) -> anyhow::Result<Vec<Section>> {
+    const KINDS: [&str; 13] = [
+        "custom",
+        "type",
+        "import",
+        "function",
+        "table",
+        "memory",
+        "global",
+        "export",
+        "start",
+        "element",
+        "code",
+        "data",
+        "data count",
+    ];
+    let mut sections = Vec::new();
+    let mut pos = 8;
+    while pos < wasm.len() {
+        let id = wasm[pos];
+        pos += 1;
+        let len = read_leb128_u32(wasm, &mut pos)? as usize;
+        let contents = wasm
+            .get(pos..pos + len)
+            .context("Section exceeds the module.")?;
+        pos += len;
+        let kind = KINDS.get(usize::from(id)).copied().unwrap_or("unknown");
+        let label = if id == 0 {
+            let mut name_pos = 0;
+            let name_len = read_leb128_u32(contents, &mut name_pos)? as usize;
+            let name = contents
+                .get(name_pos..name_pos + name_len)
+                .context("Malformed custom section.")?;
+            format!("custom '{}'", String::from_utf8_lossy(name))
+        } else {
+            kind.to_string()
+        };
+        sections.push(Section {
+            label,
+            size: len,
+            hash: Sha256::digest(contents).to_vec(),
+        });
+    }
+    Ok(sections)
+}
+
+/// Print how the sections and the embedded schemas of the Wasm modules of the
+/// reference and the rebuilt module differ.
+fn print_differences(reference: &[u8], rebuilt: &[u8]) -> anyhow::Result<()> {
+    let reference_sections = sections(reference)?;
+    let rebuilt_sections = sections(rebuilt)?;
+    eprintln!(
+        "\n  {:<32} {:>12} {:>12}",
+        "section", "reference", "rebuilt"
+    );
+    let mut labels = reference_sections
+        .iter()
+        .map(|s| &s.label)
+        .collect::<Vec<_>>();
+    for section in rebuilt_sections.iter() {
+        if !labels.contains(&&section.label) {
+            labels.push(&section.label);
+        }
+    }
+    for label in labels {
+        let old = reference_sections.iter().find(|s| &s.label == label);
+        let new = rebuilt_sections.iter().find(|s| &s.label == label);
+        let size =
+            |section: Option<&Section>| section.map_or("-".to_string(), |s| s.size.to_string());
+        let status = match (old, new) {
+            (Some(old), Some(new)) if old.hash == new.hash => Color::Green.paint("identical"),
+            (Some(_), Some(_)) => Color::Red.paint("differs"),
+            (Some(_), None) => Color::Red.paint("missing"),
+            (None, _) => Color::Red.paint("added"),
+        };
+        eprintln!(
+            "  {:<32} {:>12} {:>12}   {}",
+            label,
+            size(old),
+            size(new),
+            status
+        );
+    }
+
+    let schema = |wasm: &[u8]| -> anyhow::Result<Option<VersionedModuleSchema>> {
+        let sections = get_custom_sections(wasm)?;
+        match sections
+            .iter()
+            .find(|(name, _)| name == SCHEMA_SECTION_NAME)
+        {
+            Some((_, contents)) => Ok(Some(
+                from_bytes(contents).context("Could not parse the embedded schema.")?,
+            )),
+            None => Ok(None),
+        }
+    };
+    match (schema(reference)?, schema(rebuilt)?) {
+        (Some(old), Some(new)) => {
+            let changes = schema_diff::diff(&old, &new);
+            if changes.is_empty() {
+                eprintln!("\n   The embedded schemas are identical.");
+            } else {
+                eprintln!("\n   The embedded schemas differ:");
+                for change in changes.iter() {
+                    change.print();
+                }
+            }
+        }
+        (Some(_), None) => eprintln!("\n   Only the reference has an embedded schema."),
+        (None, Some(_)) => eprintln!("\n   Only the rebuilt module has an embedded schema."),
+        (None, None) => {}
+    }
+    Ok(())
+}
+
+/// Rebuild the package and check that the result matches the reference,
+/// given either as a versioned module or as a module reference, or both.
+/// Returns whether the rebuilt module matches.
+pub fn verify_build(
+    reference_module: Option<&[u8]>,
+    reference_ref: Option<&str>,
+    package_options: &PackageOptions,
+    build_args: &[String],
+) -> anyhow::Result<bool> {
+    if let (Some(module), Some(expected)) = (reference_module, reference_ref) {
+        let module_ref = module_reference(module).to_string();
+        ensure!(
+            module_ref == expected,
+            "The reference module has reference {}, but {} was given.",
+            module_ref,
+            expected
+        );
+    }
+    let settings = match reference_module {
+        Some(module) if build_args.is_empty() => recorded_settings(&module[8..])?,
+        _ => Settings::Unknown,
+    };
+    let rebuilt = match settings {
+        Settings::Recipe(recipe) => {
+            eprintln!(
+                "Rebuilding with the recipe of the verifiable build in {}.",
+                recipe.image
+            );
+            let work_dir = reproducible::default_work_dir(package_options)?;
+            reproducible::verifiable_build(
+                &recipe.image,
+                recipe.build_args,
+                package_options,
+                &work_dir,
+            )?
+        }
+        Settings::SourceLink(link) => {
+            eprintln!("Rebuilding with the settings of the source link.");
+            let epoch = link.build.source_date_epoch.as_deref();
+            rebuild(package_options, &verify::build_args(&link), epoch)?
+        }
+        Settings::Unknown => {
+            let mut args = package_options.cargo_args();
+            args.extend_from_slice(build_args);
+            rebuild(package_options, &args, None)?
+        }
+    };
+
+    let rebuilt_ref = module_reference(&rebuilt);
+    let matches = match reference_module {
+        Some(module) => module == rebuilt.as_slice(),
+        None => reference_ref == Some(rebuilt_ref.to_string().as_str()),
+    };
+    if matches {
+        eprintln!(
+            "{}",
+            Color::Green.paint(format!(
+                "The rebuilt module {} matches the reference byte for byte.",
+                rebuilt_ref
+            ))
+        );
+        return Ok(true);
+    }
+    eprintln!(
+        "{}",
+        Color::Red.paint(format!(
+            "The rebuilt module has reference {}, which differs from the reference.",
+            rebuilt_ref
+        ))
+    );
+    if let Some(module) = reference_module {
+        eprintln!(
+            "   Reference: {} B, rebuilt: {} B",
+            module.len(),
+            rebuilt.len()
+        );
+        if module.get(..4) != rebuilt.get(..4) {
+            eprintln!("   The Wasm versions of the modules differ.");
+        }
+        print_differences(&module[8..], &rebuilt[8..])?;
+    }
+    Ok(false)
+}
@@ -0,0 +1,129 @@
+//! The capabilities of each version of smart contract modules.
+//!
+//! Everything that differs between module versions is described by a
+//! [`VersionDescriptor`], so that supporting a new module version amounts to
+//! adding a descriptor for it, extending [`descriptor`] and listing it in
+//! [`ALL`].
+use crate::{protocol::ProtocolVersion, run_report::RunReport, RunCommand};
+use concordium_contracts_common::{from_bytes, schema::VersionedModuleSchema, ParseResult};
+use concordium_smart_contract_engine::{
+    utils::{self, WasmVersion},
+    v0, v1, ExecResult,
+};
+use concordium_wasm::{parse::Skeleton, types::Module, validate::validate_module};
+
+/// The properties of a module version.
+pub struct VersionDescriptor {
+    /// The name used in the output and on the command line, e.g., `V1`.
+    pub name:                  &'static str,
+    /// The extension of the module files built by `cargo concordium build`.
+    pub extension:             &'static str,
+    /// The version number that versioned modules start with.
+    pub tag:                   u32,
+    /// Whether all exported functions must be init or receive functions.
+    pub only_contract_exports: bool,
+    /// Whether contracts can invoke other contracts and query balances,
+    /// which interrupts their execution.
+    pub supports_invoke:       bool,
+    /// Whether contracts can upgrade their module.
+    pub supports_upgrade:      bool,
+    /// Whether instances can be simulated by the commands built on the
+    /// simulator, e.g., `model-test` and `explore-orders`, and whether
+    /// invocations with `run` can be observed, e.g., for JSON output, receipts,
+    /// traces and the energy breakdown.
+    pub supports_simulation:   bool,
+    /// Whether the module schema contains the type of the events, so that no
+    /// separate event schema is needed to decode logs.
+    pub schema_has_events:     bool,
+    /// Whether the state of instances is a tree of keys and values, rather
+    /// than bytes only the contract interprets.
+    pub tree_state:            bool,
+    /// The imports of which at least one is needed to transfer CCD.
+    pub transfer_imports:      &'static [&'static str],
+    /// The largest module, without the version prefix, the chain accepts.
+    pub max_module_size:       usize,
+    /// Validate a module, checking that it only uses the allowed imports.
+    pub validate:              fn(&Skeleton) -> anyhow::Result<Module>,
+    /// Generate the schema by running the schema functions of a module built
+    /// with the `build-schema` feature.
+    pub generate_schema:       fn(&[u8]) -> ExecResult<VersionedModuleSchema>,
+    /// Get the schema embedded in a module.
+    pub embedded_schema:       fn(&[u8]) -> ExecResult<VersionedModuleSchema>,
+    /// Parse a legacy schema file, which does not contain its version.
+    pub legacy_schema:         fn(&[u8]) -> ParseResult<VersionedModuleSchema>,
+    /// Run an invocation with `cargo concordium run` using the interpreter
+    /// settings of the protocol version. Returns the report of the invocation
+    /// if the version supports simulation.
+    pub(crate) run:
+        fn(&RunCommand, WasmVersion, &[u8], ProtocolVersion) -> anyhow::Result<Option<RunReport>>,
+}
+
+fn validate_v0(skeleton: &Skeleton) -> anyhow::Result<Module> {
+    Ok(validate_module(&v0::ConcordiumAllowedImports, skeleton)?)
+}
+
+fn validate_v1(skeleton: &Skeleton) -> anyhow::Result<Module> {
+    Ok(validate_module(
+        &v1::ConcordiumAllowedImports {
+            support_upgrade: true,
+        },
+        skeleton,
+    )?)
+}
+
+fn legacy_schema_v0(bytes: &[u8]) -> ParseResult<VersionedModuleSchema> {
+    from_bytes(bytes).map(VersionedModuleSchema::V0)
+}
+
+fn legacy_schema_v1(bytes: &[u8]) -> ParseResult<VersionedModuleSchema> {
+    from_bytes(bytes).map(VersionedModuleSchema::V1)
+}
+
+static V0: VersionDescriptor = VersionDescriptor {
+    name:                  "V0",
+    extension:             "v0",
+    tag:                   0,
+    only_contract_exports: true,
+    supports_invoke:       false,
+    supports_upgrade:      false,
+    supports_simulation:   false,
+    schema_has_events:     false,
+    tree_state:            false,
+    transfer_imports:      &["simple_transfer", "send"],
+    max_module_size:       65536,
+    validate:              validate_v0,
+    generate_schema:       utils::generate_contract_schema_v0,
+    embedded_schema:       utils::get_embedded_schema_v0,
+    legacy_schema:         legacy_schema_v0,
+    run:                   crate::handle_run_v0,
+};
+
+static V1: VersionDescriptor = VersionDescriptor {
+    name:                  "V1",
+    extension:             "v1",
+    tag:                   1,
+    only_contract_exports: false,
+    supports_invoke:       true,
+    supports_upgrade:      true,
+    supports_simulation:   true,
+    schema_has_events:     true,
+    tree_state:            true,
+    transfer_imports:      &["invoke"],
+    max_module_size:       524288,
+    validate:              validate_v1,
+    generate_schema:       utils::generate_contract_schema_v3,
+    embedded_schema:       utils::get_embedded_schema_v1,
+    legacy_schema:         legacy_schema_v1,
+    run:                   crate::run_v1,
+};
+
+/// All module versions, from the oldest.
+pub const ALL: &[WasmVersion] = &[WasmVersion::V0, WasmVersion::V1];
+
+/// Get the descriptor of a module version.
+pub fn descriptor(version: WasmVersion) -> &'static VersionDescriptor {
+    match version {
+        WasmVersion::V0 => &V0,
+        WasmVersion::V1 => &V1,
+    }
+}